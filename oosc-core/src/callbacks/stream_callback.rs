@@ -1,10 +1,16 @@
 use std::sync::{Arc, Mutex};
 
 use crate::{
-    core::synthesizer::Synthesizer,
+    core::{
+        synthesizer::Synthesizer,
+        transport::{Transport, TransportState},
+    },
     error::Error,
-    midi::{mediator::MidiEventReceiver, playback::MidiPlayback},
-    utils::SharedMutex,
+    midi::{
+        arpeggiator::Arpeggiator, mediator::MidiEventReceiver, output::MidiClockOutput,
+        playback::MidiPlayback, sequencer::StepSequencer,
+    },
+    utils::{Shared, SharedMutex},
 };
 
 use super::StreamCallback;
@@ -37,14 +43,101 @@ pub struct MidiStreamCallback(
 );
 
 impl StreamCallback for MidiStreamCallback {
+    fn process_stream(
+        &mut self,
+        data: &mut [f32],
+        _time: f32,
+        sample_rate: f32,
+    ) -> std::result::Result<(), Error> {
+        let mut playback = self.0.lock().unwrap();
+        let delta_time = (data.len() / 2) as f32 / sample_rate;
+        playback.process_events(delta_time, self.1.clone())?;
+        Ok(())
+    }
+}
+
+// Plays arpeggiator steps which are due in this buffer
+pub struct ArpeggiatorStreamCallback(pub SharedMutex<Arpeggiator>);
+
+impl StreamCallback for ArpeggiatorStreamCallback {
+    fn process_stream(
+        &mut self,
+        data: &mut [f32],
+        _time: f32,
+        sample_rate: f32,
+    ) -> std::result::Result<(), Error> {
+        let delta_time = (data.len() / 2) as f32 / sample_rate;
+        self.0.lock().unwrap().advance(delta_time)
+    }
+}
+
+// Plays sequencer steps, should run after transport callback
+pub struct SequencerStreamCallback(pub SharedMutex<StepSequencer>);
+
+impl StreamCallback for SequencerStreamCallback {
     fn process_stream(
         &mut self,
         _data: &mut [f32],
-        time: f32,
+        _time: f32,
         _sample_rate: f32,
     ) -> std::result::Result<(), Error> {
-        let mut playback = self.0.lock().unwrap();
-        playback.process_events(time, self.1.clone())?;
+        self.0.lock().unwrap().advance()
+    }
+}
+
+// Moves transport position and makes SMF playback follow transport state changes
+pub struct TransportStreamCallback {
+    transport: Shared<Transport>,
+    playback: SharedMutex<dyn MidiPlayback>,
+    clock: Option<SharedMutex<MidiClockOutput>>,
+    last_state: TransportState,
+}
+
+impl TransportStreamCallback {
+    pub fn new(transport: Shared<Transport>, playback: SharedMutex<dyn MidiPlayback>) -> Self {
+        let last_state = transport.read().unwrap().state();
+        Self {
+            transport,
+            playback,
+            clock: None,
+            last_state,
+        }
+    }
+
+    pub fn set_clock(&mut self, clock: SharedMutex<MidiClockOutput>) -> &mut Self {
+        self.clock = Some(clock);
+        self
+    }
+}
+
+impl StreamCallback for TransportStreamCallback {
+    fn process_stream(
+        &mut self,
+        data: &mut [f32],
+        _time: f32,
+        sample_rate: f32,
+    ) -> std::result::Result<(), Error> {
+        let mut transport = self.transport.write().unwrap();
+        let mut playback = self.playback.lock().unwrap();
+        let restarted = transport.take_restart();
+        if restarted {
+            playback.rewind();
+        }
+        let state = transport.state();
+        if state != self.last_state {
+            match state {
+                TransportState::Playing => playback.play(),
+                TransportState::Stopped => playback.pause(),
+            };
+            self.last_state = state;
+        }
+        if transport.is_external_clock() {
+            playback.set_bpm(transport.bpm());
+        }
+        transport.advance((data.len() / 2) as f32 / sample_rate);
+        if let Some(clock) = &self.clock {
+            clock.lock().unwrap().update(&transport, restarted);
+        }
         Ok(())
     }
 }
@@ -0,0 +1,541 @@
+use std::any::Any;
+
+use crate::{
+    error::Error,
+    utils::{
+        adsr_envelope::ADSREnvelope,
+        consts::PI_2M,
+        convert::semitones_to_freq_coefficient,
+        evaluate::Modulation,
+        fft::harmonics_to_table,
+        interpolation::interpolate_linear,
+        make_shared,
+        sample_buffer::SampleBuffer,
+        Shared,
+    },
+};
+
+use super::{
+    note::{Expression, Note, NoteEventReceiver},
+    oscillator::Oscillator,
+    parameter::{
+        NamedParameter, NamedParametersContainer, PanParameter, SharedParameter, ValueParameter,
+        VolumeParameter,
+    },
+    velocity::VelocityResponse,
+    voices::{envelope_value_at, Voice, Voices},
+};
+
+pub const MAX_PARTIALS: usize = 64;
+const TABLE_SIZE: usize = 2048;
+// Tables band-limited to 1, 2, 4 .. MAX_PARTIALS harmonics
+const TABLE_LEVELS: usize = MAX_PARTIALS.trailing_zeros() as usize + 1;
+
+#[derive(Clone)]
+pub struct Partial {
+    amplitude: SharedParameter<f32>,
+    ratio: SharedParameter<f32>,
+    phase: SharedParameter<f32>,
+    envelope: Option<Shared<ADSREnvelope>>,
+}
+
+impl Partial {
+    fn new(number: usize, amplitude: f32) -> Self {
+        Self {
+            amplitude: make_shared(ValueParameter::new(amplitude, (0.0, 1.0))),
+            ratio: make_shared(ValueParameter::new(
+                number as f32,
+                (0.5, MAX_PARTIALS as f32),
+            )),
+            phase: make_shared(ValueParameter::new(0.0, (0.0, 1.0))),
+            envelope: None,
+        }
+    }
+
+    pub fn amplitude(&self) -> SharedParameter<f32> {
+        self.amplitude.clone()
+    }
+
+    // Frequency relative to played note
+    pub fn ratio(&self) -> SharedParameter<f32> {
+        self.ratio.clone()
+    }
+
+    // Start phase in cycles
+    pub fn phase(&self) -> SharedParameter<f32> {
+        self.phase.clone()
+    }
+
+    pub fn envelope(&self) -> Option<Shared<ADSREnvelope>> {
+        self.envelope.clone()
+    }
+
+    fn values(&self) -> (f32, f32, f32) {
+        (
+            self.amplitude.read().unwrap().get_value(),
+            self.ratio.read().unwrap().get_value(),
+            self.phase.read().unwrap().get_value(),
+        )
+    }
+
+    // Integer ratio without own envelope, such partial is summed into shared table
+    fn harmonic(&self) -> Option<usize> {
+        let ratio = self.ratio.read().unwrap().get_value();
+        (self.envelope.is_none() && ratio.fract() == 0.0).then_some(ratio as usize)
+    }
+}
+
+struct AdditiveVoice {
+    note: Note,
+    // Phases of partials rendered one by one, indexed as partials
+    phases: Vec<f32>,
+}
+
+impl Voice for AdditiveVoice {
+    fn note(&self) -> &Note {
+        &self.note
+    }
+
+    fn note_mut(&mut self) -> &mut Note {
+        &mut self.note
+    }
+}
+
+// Sum of sine partials. Harmonic partials are rendered by inverse FFT into tables,
+// inharmonic ones and ones with own envelope are evaluated per voice
+pub struct AdditiveOscillator {
+    buffer: SampleBuffer,
+    envelope: Shared<ADSREnvelope>,
+    partials: Vec<Partial>,
+    voices: Voices<AdditiveVoice>,
+    tables: Vec<Vec<f32>>,
+    snapshot: Vec<(f32, f32, f32, bool)>,
+    normalize: f32,
+    pitch_bend: f32,
+    pitch_bend_range: SharedParameter<i32>,
+    pan: Shared<PanParameter>,
+    gain: Shared<VolumeParameter>,
+    parameters_f32: Vec<NamedParameter<f32>>,
+    parameters_i32: Vec<NamedParameter<i32>>,
+    velocity: VelocityResponse,
+}
+
+impl AdditiveOscillator {
+    pub fn envelope(&self) -> Shared<ADSREnvelope> {
+        self.envelope.clone()
+    }
+
+    pub fn partials(&self) -> &[Partial] {
+        &self.partials
+    }
+
+    pub fn set_partial(
+        &mut self,
+        index: usize,
+        amplitude: f32,
+        ratio: f32,
+        phase: f32,
+    ) -> Result<&mut Self, Error> {
+        let partial = self
+            .partials
+            .get(index)
+            .ok_or(format!("No partial {}", index))?;
+        partial.amplitude.write().unwrap().set_value(amplitude);
+        partial.ratio.write().unwrap().set_value(ratio);
+        partial.phase.write().unwrap().set_value(phase);
+        Ok(self)
+    }
+
+    // Own envelope replaces oscillator envelope for the partial
+    pub fn set_partial_envelope(
+        &mut self,
+        index: usize,
+        envelope: Option<ADSREnvelope>,
+    ) -> Result<&mut Self, Error> {
+        let partial = self
+            .partials
+            .get_mut(index)
+            .ok_or(format!("No partial {}", index))?;
+        partial.envelope = envelope.map(make_shared);
+        Ok(self)
+    }
+
+    pub fn pitch_bend_range(&self) -> SharedParameter<i32> {
+        self.pitch_bend_range.clone()
+    }
+
+    pub fn pan(&self) -> Shared<PanParameter> {
+        self.pan.clone()
+    }
+
+    pub fn gain(&self) -> SharedParameter<f32> {
+        self.gain.clone()
+    }
+
+    pub fn velocity(&self) -> &VelocityResponse {
+        &self.velocity
+    }
+
+    pub fn velocity_mut(&mut self) -> &mut VelocityResponse {
+        &mut self.velocity
+    }
+
+    pub fn is_playing(&self, note: u32) -> bool {
+        self.voices.is_playing(note)
+    }
+
+    // Rebuilds tables only when partials changed since the last buffer
+    fn update_tables(&mut self) -> Result<(), Error> {
+        let snapshot = self
+            .partials
+            .iter()
+            .map(|p| {
+                let (amplitude, ratio, phase) = p.values();
+                (amplitude, ratio, phase, p.envelope.is_some())
+            })
+            .collect::<Vec<_>>();
+        if snapshot == self.snapshot {
+            return Ok(());
+        }
+        let harmonics = self
+            .partials
+            .iter()
+            .zip(snapshot.iter())
+            .filter_map(|(p, (amplitude, _, phase, _))| Some((p.harmonic()?, *amplitude, *phase)))
+            .collect::<Vec<_>>();
+        self.tables = (0..TABLE_LEVELS)
+            .map(|level| {
+                let limit = 1 << level;
+                let harmonics = harmonics
+                    .iter()
+                    .filter(|(k, _, _)| *k <= limit)
+                    .copied()
+                    .collect::<Vec<_>>();
+                harmonics_to_table(&harmonics, TABLE_SIZE)
+            })
+            .collect::<Result<_, _>>()?;
+        let total = snapshot.iter().map(|s| s.0).sum::<f32>();
+        self.normalize = 1.0 / total.max(1.0);
+        self.snapshot = snapshot;
+        Ok(())
+    }
+}
+
+fn table_value(table: &[f32], phase: f32) -> f32 {
+    let position = phase * TABLE_SIZE as f32;
+    let index = position as usize % TABLE_SIZE;
+    interpolate_linear(
+        table[index],
+        table[(index + 1) % TABLE_SIZE],
+        position.fract(),
+    )
+}
+
+impl Oscillator for AdditiveOscillator {
+    fn evaluate(&mut self, size: usize, delta_time: f32) -> Result<(), Error> {
+        self.update_tables()?;
+        let envelope = self.envelope.clone();
+        let envelope = envelope.read().unwrap();
+        let envelopes = self
+            .partials
+            .iter()
+            .map(|p| p.envelope.as_ref().map(|e| e.read().unwrap()))
+            .collect::<Vec<_>>();
+        // Voice lasts until the longest of envelopes is released
+        self.voices.remove_released_after(|release| {
+            envelopes
+                .iter()
+                .flatten()
+                .map(|e| e.end_after(release))
+                .fold(envelope.end_after(release), f32::max)
+        });
+        let direct = self
+            .partials
+            .iter()
+            .zip(self.snapshot.iter())
+            .enumerate()
+            .filter(|(_, (p, _))| p.harmonic().is_none())
+            .map(|(i, (_, (amplitude, ratio, _, _)))| (i, *amplitude, *ratio))
+            .collect::<Vec<_>>();
+        let nyquist = 0.5 / delta_time;
+        let bend_range = self.pitch_bend_range.read().unwrap().get_value();
+        let bend = semitones_to_freq_coefficient(self.pitch_bend * bend_range as f32);
+        let mut pan = self.pan.write().unwrap();
+        let gain = self.gain.read().unwrap().linear * self.normalize;
+        let tables = &self.tables;
+
+        (0..size).try_for_each(|i| -> Result<(), Error> {
+            pan.next_value(delta_time)?;
+            let (left, right) = pan.polar;
+            let mut sample = 0.0;
+            self.voices.iter_mut().for_each(|voice| {
+                let note = &mut voice.note;
+                let freq = note.frequency
+                    * bend
+                    * semitones_to_freq_coefficient(note.expression.pitch_bend);
+                // Highest partial ratio below Nyquist frequency
+                let limit = nyquist / freq;
+                let level = envelope_value_at(note.play_time, note, &envelope);
+                let mut value = 0.0;
+                if limit >= 1.0 {
+                    let table = (limit.log2() as usize).min(TABLE_LEVELS - 1);
+                    value += table_value(&tables[table], note.phase) * level;
+                }
+                direct.iter().for_each(|(index, amplitude, ratio)| {
+                    let phase = &mut voice.phases[*index];
+                    if *ratio < limit {
+                        let level = envelopes[*index]
+                            .as_ref()
+                            .map_or(level, |e| envelope_value_at(note.play_time, note, e));
+                        value += amplitude * level * (PI_2M * *phase).sin();
+                    }
+                    *phase = (*phase + freq * ratio * delta_time).fract();
+                });
+                sample += value * self.velocity.gain(note.velocity);
+                note.phase = (note.phase + freq * delta_time).fract();
+                note.play_time += delta_time;
+            });
+            self.buffer.set_at(0, i, sample * gain * left)?;
+            self.buffer.set_at(1, i, sample * gain * right)
+        })
+    }
+
+    fn get_buffer_mut(&mut self) -> &mut SampleBuffer {
+        &mut self.buffer
+    }
+
+    fn get_buffer(&self) -> &SampleBuffer {
+        &self.buffer
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn parameters(&mut self) -> Option<&mut dyn NamedParametersContainer> {
+        Some(self)
+    }
+
+    fn velocity_response(&mut self) -> Option<&mut VelocityResponse> {
+        Some(&mut self.velocity)
+    }
+
+    fn shared_envelope(&self) -> Option<Shared<ADSREnvelope>> {
+        Some(self.envelope.clone())
+    }
+}
+
+impl NamedParametersContainer for AdditiveOscillator {
+    fn name(&self) -> Option<&'static str> {
+        Some("Additive")
+    }
+
+    fn parameters_f32(&self) -> Option<&[NamedParameter<f32>]> {
+        Some(&self.parameters_f32)
+    }
+
+    fn parameters_i32(&self) -> Option<&[NamedParameter<i32>]> {
+        Some(&self.parameters_i32)
+    }
+}
+
+impl NoteEventReceiver for AdditiveOscillator {
+    fn note_on(&mut self, note: Note) -> Result<(), Error> {
+        let phases = self
+            .partials
+            .iter()
+            .map(|p| p.phase.read().unwrap().get_value())
+            .collect();
+        self.voices.note_on(AdditiveVoice { note, phases });
+        Ok(())
+    }
+
+    fn note_off(&mut self, note: u32) -> Result<(), Error> {
+        self.voices.note_off(note);
+        Ok(())
+    }
+
+    fn member_note_off(&mut self, channel: u8, note: u32) -> Result<(), Error> {
+        self.voices.member_note_off(channel, note);
+        Ok(())
+    }
+
+    fn release_all(&mut self) {
+        self.voices.release_all();
+    }
+
+    fn pitch_bend(&mut self, value: f32) {
+        self.pitch_bend = value;
+    }
+
+    fn note_expression(&mut self, channel: u8, note: u32, expression: Expression) {
+        self.voices.note_expression(channel, note, expression);
+    }
+
+    fn sustain(&mut self, hold: bool) -> Result<(), Error> {
+        self.voices.sustain(hold);
+        Ok(())
+    }
+
+    fn sostenuto(&mut self, hold: bool) -> Result<(), Error> {
+        self.voices.sostenuto(hold);
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct AdditiveOscillatorBuilder {
+    buffer: Option<SampleBuffer>,
+    envelope: Option<ADSREnvelope>,
+    amplitudes: Vec<f32>,
+}
+
+impl AdditiveOscillatorBuilder {
+    pub fn new() -> Self {
+        Self {
+            buffer: None,
+            envelope: None,
+            amplitudes: vec![],
+        }
+    }
+
+    pub fn set_buffer(&mut self, buffer: SampleBuffer) -> &mut Self {
+        self.buffer = Some(buffer);
+        self
+    }
+
+    pub fn set_envelope(&mut self, envelope: ADSREnvelope) -> &mut Self {
+        self.envelope = Some(envelope);
+        self
+    }
+
+    // Amplitudes of first harmonics, sine wave if not set
+    pub fn set_amplitudes(&mut self, amplitudes: &[f32]) -> &mut Self {
+        self.amplitudes = amplitudes.to_vec();
+        self
+    }
+
+    pub fn build(&mut self) -> Result<AdditiveOscillator, Error> {
+        let buffer = self.buffer.take().ok_or(Error::Specify("samples buffer"))?;
+        let envelope = make_shared(self.envelope.take().ok_or(Error::Specify("envelope"))?);
+        if self.amplitudes.len() > MAX_PARTIALS {
+            return Err(format!("Additive oscillator has up to {} partials", MAX_PARTIALS).into());
+        }
+        let partials = (0..MAX_PARTIALS)
+            .map(|i| {
+                let default = if i == 0 { 1.0 } else { 0.0 };
+                Partial::new(i + 1, *self.amplitudes.get(i).unwrap_or(&default))
+            })
+            .collect();
+        let pan = make_shared(PanParameter::default());
+        let gain = make_shared(VolumeParameter::default());
+        let velocity = VelocityResponse::default();
+        let parameters_f32 = vec![
+            NamedParameter::new(pan.clone(), "Pan"),
+            NamedParameter::new(gain.clone(), "Gain"),
+            NamedParameter::new(velocity.sensitivity(), "Velocity"),
+        ];
+        let pitch_bend_range: SharedParameter<i32> = make_shared(ValueParameter::new(2, (0, 24)));
+        let parameters_i32 = vec![NamedParameter::new(pitch_bend_range.clone(), "Bend")];
+        let mut osc = AdditiveOscillator {
+            buffer,
+            envelope,
+            partials,
+            voices: Voices::default(),
+            tables: vec![],
+            snapshot: vec![],
+            normalize: 1.0,
+            pitch_bend: 0.0,
+            pitch_bend_range,
+            pan,
+            gain,
+            parameters_f32,
+            parameters_i32,
+            velocity,
+        };
+        osc.update_tables()?;
+        Ok(osc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_approx_eq::assert_approx_eq;
+
+    use crate::{
+        core::{
+            note::{Note, NoteEventReceiver},
+            oscillator::Oscillator,
+        },
+        utils::{
+            adsr_envelope::{ADSREnvelope, ADSREnvelopeBuilder},
+            sample_buffer::SampleBufferBuilder,
+        },
+    };
+
+    use super::{AdditiveOscillator, AdditiveOscillatorBuilder, TABLE_SIZE};
+
+    fn build_additive(amplitudes: &[f32]) -> AdditiveOscillator {
+        let buffer = SampleBufferBuilder::new()
+            .set_channels(2)
+            .set_samples(10)
+            .build()
+            .unwrap();
+        AdditiveOscillatorBuilder::new()
+            .set_buffer(buffer)
+            .set_envelope(ADSREnvelope::default())
+            .set_amplitudes(amplitudes)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_additive_tables() {
+        let mut additive = build_additive(&[1.0, 0.0, 0.5]);
+        assert_approx_eq!(additive.normalize, 1.0 / 1.5);
+        // First table has only fundamental, third harmonic is added from the third one
+        let quarter = TABLE_SIZE / 4;
+        assert_approx_eq!(additive.tables[0][quarter], 1.0, 1e-4);
+        assert_approx_eq!(additive.tables[1][quarter], 1.0, 1e-4);
+        assert_approx_eq!(additive.tables[2][quarter], 0.5, 1e-4);
+
+        additive.set_partial(1, 1.0, 2.0, 0.25).unwrap();
+        additive.update_tables().unwrap();
+        assert_approx_eq!(additive.tables[2][0], 1.0, 1e-4);
+        assert!(additive.set_partial(64, 1.0, 1.0, 0.0).is_err());
+    }
+
+    #[test]
+    fn test_additive_partials() {
+        let delta_time = 1.0 / 44100.0;
+        let mut additive = build_additive(&[1.0]);
+        additive.set_partial(1, 1.0, 1.5, 0.5).unwrap();
+        additive.note_on(Note::from(69)).unwrap();
+        additive.evaluate(10, delta_time).unwrap();
+        let voice = &additive.voices.notes[0];
+        assert_approx_eq!(voice.note.phase, 4400.0 * delta_time, 1e-4);
+        assert_approx_eq!(voice.phases[1], 0.5 + 6600.0 * delta_time, 1e-4);
+        assert_ne!(additive.get_buffer().at(0, 5).unwrap(), 0.0);
+
+        let envelope = ADSREnvelopeBuilder::new()
+            .attack(0.1, 1.0)
+            .unwrap()
+            .decay(0.1, 1.0)
+            .unwrap()
+            .sustain(0.1, 1.0)
+            .unwrap()
+            .release(10.0)
+            .unwrap()
+            .build()
+            .unwrap();
+        additive.set_partial_envelope(0, Some(envelope)).unwrap();
+        additive.note_off(69).unwrap();
+        assert!(!additive.is_playing(69));
+        additive.evaluate(10, 1.0).unwrap();
+        assert_eq!(additive.voices.release_notes.len(), 1);
+    }
+}
@@ -0,0 +1,144 @@
+use crate::{error::Error, utils::make_shared};
+
+use super::parameter::{NamedParameter, NamedParametersContainer, SharedParameter, ValueParameter};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CrossModulationKind {
+    // Linear FM, index scales frequency deviation relative to carrier frequency
+    Frequency,
+    // Index is phase deviation in cycles
+    Phase,
+    // Index blends dry carrier with carrier multiplied by modulator
+    Ring,
+    // Unipolar amplitude modulation, index is depth
+    Amplitude,
+    // Carrier phase is reset on every modulator cycle, index is not used
+    Sync,
+}
+
+// Output of single note of modulating oscillator during last evaluate
+#[derive(Clone, Default)]
+pub struct VoiceSignal {
+    // MPE member channel of note, voices of the same key on other channels are separate
+    pub channel: Option<u8>,
+    pub note: u32,
+    pub samples: Vec<f32>,
+    // Set where first voice of note finished its cycle
+    pub wraps: Vec<bool>,
+}
+
+#[derive(Clone, Default)]
+pub struct VoiceSignals {
+    pub voices: Vec<VoiceSignal>,
+}
+
+impl VoiceSignals {
+    pub fn clear(&mut self) {
+        self.voices.clear();
+    }
+
+    pub fn position(&self, channel: Option<u8>, note: u32) -> Option<usize> {
+        self.voices
+            .iter()
+            .position(|v| v.channel == channel && v.note == note)
+    }
+
+    pub fn sample(&self, voice: usize, index: usize) -> (f32, bool) {
+        self.voices
+            .get(voice)
+            .map(|v| {
+                (
+                    v.samples.get(index).copied().unwrap_or(0.0),
+                    v.wraps.get(index).copied().unwrap_or(false),
+                )
+            })
+            .unwrap_or((0.0, false))
+    }
+}
+
+// Signals of source oscillator passed to target before its evaluate
+#[derive(Clone)]
+pub struct Modulator {
+    pub kind: CrossModulationKind,
+    pub index: f32,
+    pub signals: VoiceSignals,
+}
+
+// Route between two oscillators of the same part, indices are positions in part
+pub struct CrossModulation {
+    pub source: usize,
+    pub target: usize,
+    pub kind: CrossModulationKind,
+    index: SharedParameter<f32>,
+    parameters_f32: Vec<NamedParameter<f32>>,
+}
+
+impl CrossModulation {
+    pub fn new(source: usize, target: usize, kind: CrossModulationKind, index: f32) -> Self {
+        let index = make_shared(ValueParameter::new(index, (0.0, 10.0)));
+        let parameters_f32 = vec![NamedParameter::new(index.clone(), "Index")];
+        Self {
+            source,
+            target,
+            kind,
+            index,
+            parameters_f32,
+        }
+    }
+
+    pub fn index(&self) -> SharedParameter<f32> {
+        self.index.clone()
+    }
+}
+
+impl NamedParametersContainer for CrossModulation {
+    fn name(&self) -> Option<&'static str> {
+        Some("Modulation")
+    }
+
+    fn parameters_f32(&self) -> Option<&[NamedParameter<f32>]> {
+        Some(&self.parameters_f32)
+    }
+}
+
+// Order of evaluation where every source goes before its targets
+pub fn evaluation_order(count: usize, routes: &[CrossModulation]) -> Result<Vec<usize>, Error> {
+    let mut order = vec![];
+    let mut done = vec![false; count];
+    while order.len() < count {
+        let ready = (0..count).find(|&i| {
+            !done[i]
+                && routes
+                    .iter()
+                    .filter(|r| r.target == i)
+                    .all(|r| done[r.source])
+        });
+        match ready {
+            Some(i) => {
+                done[i] = true;
+                order.push(i);
+            }
+            None => return Err("Cross modulation routes form a cycle".into()),
+        }
+    }
+    Ok(order)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{evaluation_order, CrossModulation, CrossModulationKind};
+
+    #[test]
+    fn test_evaluation_order() {
+        let routes = vec![
+            CrossModulation::new(2, 0, CrossModulationKind::Frequency, 1.0),
+            CrossModulation::new(1, 2, CrossModulationKind::Sync, 0.0),
+        ];
+        assert_eq!(evaluation_order(3, &routes).unwrap(), vec![1, 2, 0]);
+        let routes = vec![
+            CrossModulation::new(0, 1, CrossModulationKind::Ring, 1.0),
+            CrossModulation::new(1, 0, CrossModulationKind::Ring, 1.0),
+        ];
+        assert!(evaluation_order(2, &routes).is_err());
+    }
+}
@@ -1,25 +1,512 @@
-use crate::utils::{consts::PI_2M, evaluate::Evaluate, make_shared};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use crate::{
+    error::Error,
+    utils::{
+        consts::PI_2M, cubic_bezier::CubicBezierCurve, evaluate::Evaluate, make_shared,
+        random::Random, Shared,
+    },
+};
 
 use super::{
-    parameter::{SharedParameter, ValueParameter},
+    parameter::{NamedParameter, NamedParametersContainer, SharedParameter, ValueParameter},
+    transport::Transport,
     waveshape::WaveShape,
+    wavetable::WaveTable,
 };
 
+pub enum LfoShape {
+    Wave(WaveShape),
+    // New random value every cycle
+    SampleAndHold,
+    // Random values of cycles joined by smooth steps
+    SmoothRandom,
+    // One cycle of custom shape
+    Table(Shared<WaveTable>),
+    // Curve from x 0 to 1 is one cycle, y 0..1 is the whole range of LFO
+    Curve(Shared<CubicBezierCurve>),
+}
+
+// Cycle lengths in beats selectable by sync parameter, from 1/32 note to four bars
+pub const SYNC_DIVISIONS: [f32; 8] = [0.125, 0.25, 0.5, 1.0, 2.0, 4.0, 8.0, 16.0];
+
+// Random shapes of LFOs without own seed repeat the same values every run
+const DEFAULT_SEED: u32 = 0x2545_F491;
+
+impl LfoShape {
+    // Value of shape parameter, custom table and curve share the last one
+    pub fn index(&self) -> i32 {
+        match self {
+            LfoShape::Wave(WaveShape::Sin) => 0,
+            LfoShape::Wave(WaveShape::Triangle) => 1,
+            LfoShape::Wave(WaveShape::Saw) => 2,
+            LfoShape::Wave(WaveShape::Square | WaveShape::SquareFourier(_)) => 3,
+            LfoShape::SampleAndHold => 4,
+            LfoShape::SmoothRandom => 5,
+            LfoShape::Table(_) | LfoShape::Curve(_) => 6,
+        }
+    }
+
+    fn from_index(index: i32) -> Option<Self> {
+        match index {
+            0 => Some(LfoShape::Wave(WaveShape::Sin)),
+            1 => Some(LfoShape::Wave(WaveShape::Triangle)),
+            2 => Some(LfoShape::Wave(WaveShape::Saw)),
+            3 => Some(LfoShape::Wave(WaveShape::Square)),
+            4 => Some(LfoShape::SampleAndHold),
+            5 => Some(LfoShape::SmoothRandom),
+            _ => None,
+        }
+    }
+}
+
+impl From<WaveShape> for LfoShape {
+    fn from(value: WaveShape) -> Self {
+        LfoShape::Wave(value)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LfoPolarity {
+    // 0..1, modulation containers multiply their sources
+    Unipolar,
+    Bipolar,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LfoTrigger {
+    Free,
+    // Restarts phase, delay and fade-in on note on
+    Retrigger,
+}
+
 pub struct LFO {
-    shape: WaveShape,
+    // Shape set last, used while shape parameter selects its index
+    shape: LfoShape,
+    shape_index: SharedParameter<i32>,
     frequency: SharedParameter<f32>,
+    // Start phase in cycles
+    phase: SharedParameter<f32>,
+    delay: SharedParameter<f32>,
+    fade_in: SharedParameter<f32>,
+    polarity: LfoPolarity,
+    trigger: LfoTrigger,
+    // Index of division in SYNC_DIVISIONS plus one, zero runs free at frequency
+    division: SharedParameter<i32>,
+    transport: Option<Shared<Transport>>,
+    seed: u32,
+    parameters_f32: Vec<NamedParameter<f32>>,
+    parameters_i32: Vec<NamedParameter<i32>>,
+    // Note on is applied at the next evaluation, as only modulation knows the time.
+    // Atomics let shared LFO be retriggered while modulation containers read it
+    retrigger: AtomicBool,
+    // Bits of f32 time
+    trigger_time: AtomicU32,
 }
 
 impl LFO {
-    pub fn new(shape: WaveShape, frequency: f32) -> Self {
+    pub fn new<S: Into<LfoShape>>(shape: S, frequency: f32) -> Self {
+        let shape = shape.into();
+        let shape_index = make_shared(ValueParameter::new(shape.index(), (0, 6)));
         let frequency = make_shared(ValueParameter::new(frequency, (0.001, 20.0)));
-        Self { shape, frequency }
+        let phase = make_shared(ValueParameter::new(0.0, (0.0, 1.0)));
+        let delay = make_shared(ValueParameter::new(0.0, (0.0, 10.0)));
+        let fade_in = make_shared(ValueParameter::new(0.0, (0.0, 10.0)));
+        let division = make_shared(ValueParameter::new(0, (0, SYNC_DIVISIONS.len() as i32)));
+        let parameters_f32 = vec![
+            NamedParameter::new(frequency.clone(), "Rate"),
+            NamedParameter::new(phase.clone(), "Phase"),
+            NamedParameter::new(delay.clone(), "Delay"),
+            NamedParameter::new(fade_in.clone(), "Fade In"),
+        ];
+        let parameters_i32 = vec![
+            NamedParameter::new(shape_index.clone(), "Shape"),
+            NamedParameter::new(division.clone(), "Sync"),
+        ];
+        Self {
+            shape,
+            shape_index,
+            frequency,
+            phase,
+            delay,
+            fade_in,
+            polarity: LfoPolarity::Unipolar,
+            trigger: LfoTrigger::Free,
+            division,
+            transport: None,
+            seed: DEFAULT_SEED,
+            parameters_f32,
+            parameters_i32,
+            retrigger: AtomicBool::new(false),
+            trigger_time: AtomicU32::new(0.0_f32.to_bits()),
+        }
+    }
+
+    // Values of LfoShape::index
+    pub fn shape(&self) -> SharedParameter<i32> {
+        self.shape_index.clone()
+    }
+
+    pub fn set_shape<S: Into<LfoShape>>(&mut self, shape: S) -> &mut Self {
+        self.shape = shape.into();
+        self.shape_index
+            .write()
+            .unwrap()
+            .set_value(self.shape.index());
+        self
+    }
+
+    // Fixed seed makes random shapes repeatable, LFOs with different seeds differ
+    pub fn set_seed(&mut self, seed: u32) -> &mut Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn frequency(&self) -> SharedParameter<f32> {
+        self.frequency.clone()
+    }
+
+    pub fn phase(&self) -> SharedParameter<f32> {
+        self.phase.clone()
+    }
+
+    pub fn delay(&self) -> SharedParameter<f32> {
+        self.delay.clone()
+    }
+
+    pub fn fade_in(&self) -> SharedParameter<f32> {
+        self.fade_in.clone()
+    }
+
+    pub fn polarity(&self) -> LfoPolarity {
+        self.polarity
+    }
+
+    pub fn set_polarity(&mut self, polarity: LfoPolarity) -> &mut Self {
+        self.polarity = polarity;
+        self
+    }
+
+    pub fn trigger(&self) -> LfoTrigger {
+        self.trigger
+    }
+
+    pub fn set_trigger(&mut self, trigger: LfoTrigger) -> &mut Self {
+        self.trigger = trigger;
+        self
+    }
+
+    pub fn set_transport(&mut self, transport: Shared<Transport>) -> &mut Self {
+        self.transport = Some(transport);
+        self
+    }
+
+    // Values of sync, see division field
+    pub fn division(&self) -> SharedParameter<i32> {
+        self.division.clone()
+    }
+
+    // Cycle length in beats of transport
+    pub fn sync(&self) -> Option<f32> {
+        let division = self.division.read().unwrap().get_value();
+        match division > 0 {
+            true => SYNC_DIVISIONS.get(division as usize - 1).copied(),
+            false => None,
+        }
+    }
+
+    // Synced LFO follows tempo of transport instead of its frequency
+    pub fn set_sync(&mut self, beats: Option<f32>) -> Result<&mut Self, Error> {
+        if beats.is_some() && self.transport.is_none() {
+            return Err("LFO cannot be synced without transport".into());
+        }
+        let division = match beats {
+            Some(beats) => {
+                SYNC_DIVISIONS
+                    .iter()
+                    .position(|&b| b == beats)
+                    .ok_or(format!(
+                        "Sync division should be one of {:?}",
+                        SYNC_DIVISIONS
+                    ))? as i32
+                    + 1
+            }
+            None => 0,
+        };
+        self.division.write().unwrap().set_value(division);
+        Ok(self)
+    }
+
+    // Restarts LFO modulating parameters shared by all voices
+    pub fn note_on(&self) {
+        if self.trigger == LfoTrigger::Retrigger {
+            self.retrigger.store(true, Ordering::Relaxed);
+        }
+    }
+
+    // Cycles per second
+    pub fn rate(&self) -> f32 {
+        match (self.sync(), &self.transport) {
+            (Some(beats), Some(transport)) => transport.read().unwrap().bpm() / 60.0 / beats,
+            _ => self.frequency.read().unwrap().get_value(),
+        }
+    }
+
+    // Value of single voice, retriggered LFO starts with every note
+    pub fn voice_value(&self, play_time: f32, time: f32) -> f32 {
+        match self.trigger {
+            LfoTrigger::Retrigger => self.value_at(play_time),
+            LfoTrigger::Free => self.value_at(time),
+        }
+    }
+
+    // Value at time since start or note on
+    pub fn value_at(&self, time: f32) -> f32 {
+        let elapsed = time - self.delay.read().unwrap().get_value();
+        if elapsed < 0.0 {
+            return 0.0;
+        }
+        let fade_in = self.fade_in.read().unwrap().get_value();
+        let fade = match fade_in > 0.0 {
+            true => (elapsed / fade_in).min(1.0),
+            false => 1.0,
+        };
+        let value = self.shape_at(self.cycles(elapsed)).clamp(-1.0, 1.0);
+        let value = match self.polarity {
+            LfoPolarity::Unipolar => (value + 1.0) * 0.5,
+            LfoPolarity::Bipolar => value,
+        };
+        value * fade
+    }
+
+    // Free running synced LFO is locked to position of playing transport
+    fn cycles(&self, elapsed: f32) -> f32 {
+        let phase = self.phase.read().unwrap().get_value();
+        match (self.trigger, self.sync(), &self.transport) {
+            (LfoTrigger::Free, Some(beats), Some(transport))
+                if transport.read().unwrap().is_playing() =>
+            {
+                phase + transport.read().unwrap().position() / beats
+            }
+            _ => phase + elapsed * self.rate(),
+        }
+    }
+
+    // Bipolar value of shape selected by parameter
+    fn shape_at(&self, cycles: f32) -> f32 {
+        let index = self.shape_index.read().unwrap().get_value();
+        match LfoShape::from_index(index) {
+            Some(shape) if index != self.shape.index() => self.value_of(&shape, cycles),
+            _ => self.value_of(&self.shape, cycles),
+        }
+    }
+
+    fn value_of(&self, shape: &LfoShape, cycles: f32) -> f32 {
+        let fraction = cycles.rem_euclid(1.0);
+        match shape {
+            LfoShape::Wave(shape) => shape.evaluate(PI_2M * fraction).unwrap_or(0.0),
+            LfoShape::SampleAndHold => self.random_at(cycles.floor()),
+            LfoShape::SmoothRandom => {
+                let from = self.random_at(cycles.floor());
+                let to = self.random_at(cycles.floor() + 1.0);
+                let step = fraction * fraction * (3.0 - 2.0 * fraction);
+                from + (to - from) * step
+            }
+            LfoShape::Table(table) => table
+                .read()
+                .unwrap()
+                .evaluate(PI_2M * fraction)
+                .unwrap_or(0.0),
+            LfoShape::Curve(curve) => {
+                let curve = curve.read().unwrap();
+                let x = curve.start().x + curve.difference().x * fraction;
+                curve.y_at(x) * 2.0 - 1.0
+            }
+        }
+    }
+
+    // Same cycle always gets the same random value
+    fn random_at(&self, cycle: f32) -> f32 {
+        let mut random = Random::new(self.seed ^ (cycle as i32 as u32).wrapping_mul(0x9E37_79B9));
+        random.next_u32();
+        random.next_bipolar()
+    }
+}
+
+impl NamedParametersContainer for LFO {
+    fn name(&self) -> Option<&'static str> {
+        Some("LFO")
+    }
+
+    fn parameters_f32(&self) -> Option<&[NamedParameter<f32>]> {
+        Some(&self.parameters_f32)
+    }
+
+    fn parameters_i32(&self) -> Option<&[NamedParameter<i32>]> {
+        Some(&self.parameters_i32)
     }
 }
 
 impl Evaluate<f32> for LFO {
-    fn evaluate(&self, t: f32) -> Result<f32, crate::error::Error> {
-        let freq = PI_2M * self.frequency.read().unwrap().get_value() * t;
-        Ok(0.5 + self.shape.evaluate(freq)? * 0.5)
+    fn evaluate(&self, t: f32) -> Result<f32, Error> {
+        if self.retrigger.swap(false, Ordering::Relaxed) {
+            self.trigger_time.store(t.to_bits(), Ordering::Relaxed);
+        }
+        let trigger_time = f32::from_bits(self.trigger_time.load(Ordering::Relaxed));
+        Ok(self.value_at(t - trigger_time))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_approx_eq::assert_approx_eq;
+    use cgmath::Vector2;
+
+    use crate::{
+        core::{
+            parameter::parameters_paths_i32, transport::Transport, waveshape::WaveShape,
+            wavetable::WaveTableBuilder,
+        },
+        utils::{
+            cubic_bezier::CubicBezierCurve,
+            evaluate::{Evaluate, ModulationContainer},
+            interpolation::InterpolateMethod,
+            make_shared,
+        },
+    };
+
+    use super::{LfoPolarity, LfoShape, LfoTrigger, LFO};
+
+    #[test]
+    fn test_lfo_phase_polarity() {
+        let mut lfo = LFO::new(WaveShape::Sin, 1.0);
+        assert_approx_eq!(lfo.value_at(0.0), 0.5);
+        assert_approx_eq!(lfo.value_at(0.25), 1.0);
+        lfo.phase().write().unwrap().set_value(0.25);
+        assert_approx_eq!(lfo.value_at(0.0), 1.0);
+        lfo.set_polarity(LfoPolarity::Bipolar);
+        assert_approx_eq!(lfo.value_at(0.5), -1.0);
+    }
+
+    #[test]
+    fn test_lfo_delay_fade_in() {
+        let mut lfo = LFO::new(WaveShape::Sin, 1.0);
+        lfo.set_polarity(LfoPolarity::Bipolar);
+        lfo.delay().write().unwrap().set_value(0.5);
+        lfo.fade_in().write().unwrap().set_value(1.0);
+        assert_approx_eq!(lfo.value_at(0.25), 0.0);
+        assert_approx_eq!(lfo.value_at(0.75), 0.25);
+        assert_approx_eq!(lfo.value_at(3.25), -1.0);
+    }
+
+    #[test]
+    fn test_lfo_retrigger() {
+        let mut lfo = LFO::new(WaveShape::Sin, 1.0);
+        lfo.note_on();
+        assert_approx_eq!(lfo.evaluate(10.25).unwrap(), 1.0);
+        assert_approx_eq!(lfo.voice_value(0.0, 10.25), 1.0);
+        lfo.set_trigger(LfoTrigger::Retrigger);
+        assert_approx_eq!(lfo.evaluate(10.5).unwrap(), 0.5);
+        assert_approx_eq!(lfo.voice_value(0.25, 10.5), 1.0);
+        lfo.note_on();
+        // Phase restarts at the first evaluation after note on
+        assert_approx_eq!(lfo.evaluate(10.6).unwrap(), 0.5);
+        assert_approx_eq!(lfo.evaluate(10.85).unwrap(), 1.0);
+    }
+
+    #[test]
+    fn test_lfo_modulation() {
+        let mut lfo = LFO::new(WaveShape::Sin, 1.0);
+        lfo.set_trigger(LfoTrigger::Retrigger);
+        let lfo = make_shared(lfo);
+        let mut container = ModulationContainer::new().range((0.0, 2.0));
+        container.modulators.push(lfo.clone());
+        assert_approx_eq!(container.next_value(0.25).unwrap(), 1.0);
+        assert_approx_eq!(container.next_value(0.25).unwrap(), 2.0);
+        lfo.read().unwrap().note_on();
+        assert_approx_eq!(container.next_value(0.25).unwrap(), 1.0);
+    }
+
+    #[test]
+    fn test_lfo_sync() {
+        let transport = make_shared(Transport::new(120.0));
+        let mut lfo = LFO::new(WaveShape::Sin, 1.0);
+        assert!(lfo.set_sync(Some(1.0)).is_err());
+        lfo.set_transport(transport.clone());
+        assert!(lfo.set_sync(Some(0.0)).is_err());
+        lfo.set_sync(Some(1.0)).unwrap();
+        assert_approx_eq!(lfo.rate(), 2.0);
+        assert_approx_eq!(lfo.value_at(0.125), 1.0);
+        // Free running LFO follows position of playing transport
+        transport.write().unwrap().start();
+        transport.write().unwrap().set_position(2.75);
+        assert_approx_eq!(lfo.value_at(0.125), 0.0);
+        // Sync parameter selects division, zero returns to frequency
+        lfo.division().write().unwrap().set_value(5);
+        assert_eq!(lfo.sync(), Some(2.0));
+        lfo.division().write().unwrap().set_value(0);
+        assert_eq!(lfo.sync(), None);
+        assert_approx_eq!(lfo.rate(), 1.0);
+        assert!(lfo.set_sync(Some(3.0)).is_err());
+    }
+
+    #[test]
+    fn test_lfo_random() {
+        let mut lfo = LFO::new(LfoShape::SampleAndHold, 1.0);
+        lfo.set_polarity(LfoPolarity::Bipolar);
+        let held = lfo.value_at(2.1);
+        assert_approx_eq!(lfo.value_at(2.9), held);
+        let values = (0..16).map(|i| lfo.value_at(i as f32)).collect::<Vec<_>>();
+        assert!(values.iter().all(|v| (-1.0..=1.0).contains(v)));
+        assert!(values.windows(2).any(|w| w[0] != w[1]));
+
+        lfo.set_shape(LfoShape::SmoothRandom);
+        assert_approx_eq!(lfo.value_at(3.0), values[3]);
+        assert_approx_eq!(lfo.value_at(3.5), (values[3] + values[4]) * 0.5);
+
+        // Random values are repeated by LFOs with the same seed
+        let mut other = LFO::new(LfoShape::SampleAndHold, 1.0);
+        other.set_polarity(LfoPolarity::Bipolar);
+        assert_approx_eq!(other.value_at(5.0), values[5]);
+        other.set_seed(7);
+        assert!((0..16).any(|i| other.value_at(i as f32) != values[i]));
+    }
+
+    #[test]
+    fn test_lfo_shape_parameter() {
+        let ramp = CubicBezierCurve::new_linear(Vector2::new(0.0, 0.0), Vector2::new(1.0, 1.0));
+        let mut lfo = LFO::new(LfoShape::Curve(make_shared(ramp)), 1.0);
+        lfo.set_polarity(LfoPolarity::Bipolar);
+        let shape = lfo.shape();
+        assert_eq!(shape.read().unwrap().get_value(), 6);
+        shape.write().unwrap().set_value(0);
+        assert_approx_eq!(lfo.value_at(0.25), 1.0);
+        // Custom shape is kept for the last value
+        shape.write().unwrap().set_value(6);
+        assert_approx_eq!(lfo.value_at(0.25), -0.5, 1e-4);
+        lfo.set_shape(WaveShape::Square);
+        assert_eq!(shape.read().unwrap().get_value(), 3);
+        let paths = parameters_paths_i32(&lfo, 0)
+            .into_iter()
+            .map(|p| p.path)
+            .collect::<Vec<_>>();
+        assert_eq!(paths, ["LFO1/Shape", "LFO1/Sync"]);
+    }
+
+    #[test]
+    fn test_lfo_custom_shapes() {
+        let table = WaveTableBuilder::new()
+            .from_shape(WaveShape::Sin, 256)
+            .set_interpolation(InterpolateMethod::Linear)
+            .build()
+            .unwrap();
+        let mut lfo = LFO::new(LfoShape::Table(make_shared(table)), 1.0);
+        lfo.set_polarity(LfoPolarity::Bipolar);
+        assert_approx_eq!(lfo.value_at(0.25), 1.0, 0.01);
+
+        let ramp = CubicBezierCurve::new_linear(Vector2::new(0.0, 0.0), Vector2::new(1.0, 1.0));
+        lfo.set_shape(LfoShape::Curve(make_shared(ramp)));
+        assert_approx_eq!(lfo.value_at(0.25), -0.5, 1e-4);
+        assert_approx_eq!(lfo.value_at(1.75), 0.5, 1e-4);
     }
 }
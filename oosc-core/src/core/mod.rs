@@ -4,4 +4,16 @@ pub mod note;
 pub mod oscillator;
 pub mod synthesizer;
 pub mod parameter;
+pub mod part;
+pub mod preset;
+pub mod transport;
+pub mod tuning;
 pub mod lfo;
+pub mod cross_modulation;
+pub mod additive;
+pub mod noise;
+pub mod sub_oscillator;
+pub mod sampler;
+pub mod velocity;
+pub mod modulation_config;
+pub(crate) mod voices;
@@ -0,0 +1,287 @@
+use std::{fs, path::Path};
+
+use crate::error::Error;
+
+use super::{
+    cross_modulation::CrossModulationKind, oscillator::ExpressionTarget, velocity::VelocityCurve,
+};
+
+// Controllers received by MIDI mediator
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ModulationController {
+    ModWheel,
+    // Channel pressure
+    Aftertouch,
+    // Highest pressure of held keys
+    PolyPressure,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ControllerTarget {
+    Pan,
+    Cents,
+}
+
+// Line of modulations file, part and oscillator numbers are 1-based there
+#[derive(Clone)]
+pub enum ModulationConfig {
+    // Per-voice key tracking, depth in decibels or cents per octave from middle C
+    KeyTracking {
+        part: usize,
+        oscillator: usize,
+        target: ExpressionTarget,
+        depth: f32,
+    },
+    // Key of the last note of part moves parameter at path like "Noise3/Low Cut",
+    // depth in units of the parameter per octave from middle C
+    ParameterKeyTracking {
+        part: usize,
+        path: String,
+        depth: f32,
+    },
+    Velocity {
+        part: usize,
+        oscillator: usize,
+        curve: VelocityCurve,
+    },
+    // Source oscillator modulates target oscillator of the same part
+    CrossModulation {
+        part: usize,
+        source: usize,
+        target: usize,
+        kind: CrossModulationKind,
+        index: f32,
+    },
+    // Controller value from 0 to 1 moves target parameter from min to max,
+    // connected by MIDI mediator which owns controllers
+    Controller {
+        part: usize,
+        oscillator: usize,
+        controller: ModulationController,
+        target: ControllerTarget,
+        range: (f32, f32),
+    },
+    // LFO of part moves target parameter the same way as controller
+    Lfo {
+        part: usize,
+        lfo: usize,
+        oscillator: usize,
+        target: ControllerTarget,
+        range: (f32, f32),
+    },
+}
+
+impl ModulationConfig {
+    // "key <part> <osc> gain|cents <depth>", "key <part> <parameter path> <depth>"
+    // "velocity <part> <osc> <curve>"
+    // "xmod <part> <source osc> <target osc> fm|pm|ring|am|sync <index>"
+    // "ctl <part> <osc> modwheel|aftertouch|polypressure pan|cents <min> <max>"
+    // or "lfo <part> <lfo> <osc> pan|cents <min> <max>"
+    pub fn parse(line: &str) -> Result<Self, Error> {
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        match fields[..] {
+            ["key", part, oscillator, target, depth] if !oscillator.contains('/') => {
+                Ok(ModulationConfig::KeyTracking {
+                    part: parse_index(part)?,
+                    oscillator: parse_index(oscillator)?,
+                    target: parse_target(target)?,
+                    depth: parse_depth(depth)?,
+                })
+            }
+            // Parameter names may contain spaces, path takes all fields before depth
+            ["key", part, ref path @ .., depth]
+                if path.first().is_some_and(|p| p.contains('/')) =>
+            {
+                Ok(ModulationConfig::ParameterKeyTracking {
+                    part: parse_index(part)?,
+                    path: path.join(" "),
+                    depth: parse_depth(depth)?,
+                })
+            }
+            ["velocity", part, oscillator, curve] => Ok(ModulationConfig::Velocity {
+                part: parse_index(part)?,
+                oscillator: parse_index(oscillator)?,
+                curve: VelocityCurve::parse(curve)?,
+            }),
+            ["xmod", part, source, target, kind, index] => Ok(ModulationConfig::CrossModulation {
+                part: parse_index(part)?,
+                source: parse_index(source)?,
+                target: parse_index(target)?,
+                kind: parse_kind(kind)?,
+                index: index
+                    .parse::<f32>()
+                    .map_err(|e| format!("Invalid index \"{}\": {}", index, e))?,
+            }),
+            ["ctl", part, oscillator, controller, target, min, max] => {
+                Ok(ModulationConfig::Controller {
+                    part: parse_index(part)?,
+                    oscillator: parse_index(oscillator)?,
+                    controller: parse_controller(controller)?,
+                    target: parse_controller_target(target)?,
+                    range: (parse_value(min)?, parse_value(max)?),
+                })
+            }
+            ["lfo", part, lfo, oscillator, target, min, max] => Ok(ModulationConfig::Lfo {
+                part: parse_index(part)?,
+                lfo: parse_index(lfo)?,
+                oscillator: parse_index(oscillator)?,
+                target: parse_controller_target(target)?,
+                range: (parse_value(min)?, parse_value(max)?),
+            }),
+            _ => Err(format!("Invalid modulation \"{}\"", line).into()),
+        }
+    }
+
+    // One modulation per line, empty lines and lines starting with # are skipped
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<Self>, Error> {
+        fs::read_to_string(path)?
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(Self::parse)
+            .collect()
+    }
+
+    pub fn part(&self) -> usize {
+        match self {
+            ModulationConfig::KeyTracking { part, .. }
+            | ModulationConfig::ParameterKeyTracking { part, .. }
+            | ModulationConfig::Velocity { part, .. }
+            | ModulationConfig::CrossModulation { part, .. }
+            | ModulationConfig::Controller { part, .. }
+            | ModulationConfig::Lfo { part, .. } => *part,
+        }
+    }
+}
+
+fn parse_index(value: &str) -> Result<usize, Error> {
+    match value.parse::<usize>() {
+        Ok(number @ 1..) => Ok(number - 1),
+        _ => Err(format!("Invalid number \"{}\"", value).into()),
+    }
+}
+
+fn parse_target(target: &str) -> Result<ExpressionTarget, Error> {
+    match target {
+        "gain" => Ok(ExpressionTarget::Gain),
+        "cents" => Ok(ExpressionTarget::Cents),
+        _ => Err(format!("Unknown modulation target \"{}\"", target).into()),
+    }
+}
+
+fn parse_depth(depth: &str) -> Result<f32, Error> {
+    depth
+        .parse::<f32>()
+        .map_err(|e| format!("Invalid depth \"{}\": {}", depth, e).into())
+}
+
+fn parse_value(value: &str) -> Result<f32, Error> {
+    value
+        .parse::<f32>()
+        .map_err(|e| format!("Invalid value \"{}\": {}", value, e).into())
+}
+
+fn parse_controller(controller: &str) -> Result<ModulationController, Error> {
+    match controller {
+        "modwheel" => Ok(ModulationController::ModWheel),
+        "aftertouch" => Ok(ModulationController::Aftertouch),
+        "polypressure" => Ok(ModulationController::PolyPressure),
+        _ => Err(format!("Unknown controller \"{}\"", controller).into()),
+    }
+}
+
+fn parse_controller_target(target: &str) -> Result<ControllerTarget, Error> {
+    match target {
+        "pan" => Ok(ControllerTarget::Pan),
+        "cents" => Ok(ControllerTarget::Cents),
+        _ => Err(format!("Unknown controller target \"{}\"", target).into()),
+    }
+}
+
+fn parse_kind(kind: &str) -> Result<CrossModulationKind, Error> {
+    match kind {
+        "fm" => Ok(CrossModulationKind::Frequency),
+        "pm" => Ok(CrossModulationKind::Phase),
+        "ring" => Ok(CrossModulationKind::Ring),
+        "am" => Ok(CrossModulationKind::Amplitude),
+        "sync" => Ok(CrossModulationKind::Sync),
+        _ => Err(format!("Unknown cross modulation \"{}\"", kind).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::{
+        cross_modulation::CrossModulationKind, oscillator::ExpressionTarget,
+        velocity::VelocityCurve,
+    };
+
+    use super::{ControllerTarget, ModulationConfig, ModulationController};
+
+    #[test]
+    fn test_modulation_config_parse() {
+        assert!(matches!(
+            ModulationConfig::parse("key 1 2 cents -600"),
+            Ok(ModulationConfig::KeyTracking {
+                part: 0,
+                oscillator: 1,
+                target: ExpressionTarget::Cents,
+                depth,
+            }) if depth == -600.0
+        ));
+        assert!(matches!(
+            ModulationConfig::parse("velocity 2 1 exponential:3"),
+            Ok(ModulationConfig::Velocity {
+                part: 1,
+                oscillator: 0,
+                curve: VelocityCurve::Exponential(e),
+            }) if e == 3.0
+        ));
+        assert!(matches!(
+            ModulationConfig::parse("xmod 1 2 1 pm 0.5"),
+            Ok(ModulationConfig::CrossModulation {
+                part: 0,
+                source: 1,
+                target: 0,
+                kind: CrossModulationKind::Phase,
+                index,
+            }) if index == 0.5
+        ));
+        assert!(ModulationConfig::parse("xmod 1 2 1 xm 0.5").is_err());
+        assert!(ModulationConfig::parse("key 0 1 gain 6").is_err());
+        assert!(matches!(
+            ModulationConfig::parse("key 1 Noise3/Low Cut 2000"),
+            Ok(ModulationConfig::ParameterKeyTracking {
+                part: 0,
+                path,
+                depth,
+            }) if path == "Noise3/Low Cut" && depth == 2000.0
+        ));
+        assert!(ModulationConfig::parse("key 1 1 cutoff 6").is_err());
+        assert!(ModulationConfig::parse("key 1 Noise3/Low Cut").is_err());
+        assert!(ModulationConfig::parse("velocity 1 1").is_err());
+        assert!(matches!(
+            ModulationConfig::parse("ctl 1 2 aftertouch pan -1 1"),
+            Ok(ModulationConfig::Controller {
+                part: 0,
+                oscillator: 1,
+                controller: ModulationController::Aftertouch,
+                target: ControllerTarget::Pan,
+                range,
+            }) if range == (-1.0, 1.0)
+        ));
+        assert!(ModulationConfig::parse("ctl 1 1 breath pan 0 1").is_err());
+        assert!(ModulationConfig::parse("ctl 1 1 modwheel gain 0 1").is_err());
+        assert!(matches!(
+            ModulationConfig::parse("lfo 1 2 1 cents -50 50"),
+            Ok(ModulationConfig::Lfo {
+                part: 0,
+                lfo: 1,
+                oscillator: 0,
+                target: ControllerTarget::Cents,
+                range,
+            }) if range == (-50.0, 50.0)
+        ));
+        assert!(ModulationConfig::parse("lfo 1 0 1 pan 0 1").is_err());
+    }
+}
@@ -0,0 +1,410 @@
+use std::any::Any;
+
+use crate::{
+    error::Error,
+    iir::coefficients::{build_filter, Coefficients, FilterType},
+    utils::{
+        adsr_envelope::ADSREnvelope, evaluate::Modulation, make_shared, random::Random,
+        sample_buffer::SampleBuffer, Shared,
+    },
+};
+
+use super::{
+    note::{Note, NoteEventReceiver},
+    oscillator::Oscillator,
+    parameter::{
+        NamedParameter, NamedParametersContainer, PanParameter, SharedParameter, ValueParameter,
+        VolumeParameter,
+    },
+    velocity::VelocityResponse,
+    voices::{envelope_value_at, Voices},
+};
+
+const MIN_CUTOFF: f32 = 20.0;
+const MAX_CUTOFF: f32 = 20000.0;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum NoiseColor {
+    White,
+    // -3 dB per octave
+    Pink,
+    // -6 dB per octave
+    Brown,
+}
+
+impl From<i32> for NoiseColor {
+    fn from(value: i32) -> Self {
+        match value {
+            1 => NoiseColor::Pink,
+            2 => NoiseColor::Brown,
+            _ => NoiseColor::White,
+        }
+    }
+}
+
+// First order low and high cut filters, rebuilt when cutoff or sample rate changes
+#[derive(Default)]
+struct NoiseFilter {
+    settings: (f32, f32, f32),
+    low_cut: Option<(Coefficients, [f32; 3])>,
+    high_cut: Option<(Coefficients, [f32; 3])>,
+}
+
+impl NoiseFilter {
+    fn update(&mut self, low: f32, high: f32, sample_rate: f32) {
+        if self.settings == (low, high, sample_rate) {
+            return;
+        }
+        self.settings = (low, high, sample_rate);
+        // Cutoff near the range bound means filter is off
+        let nyquist = sample_rate * 0.5;
+        self.low_cut = (low > MIN_CUTOFF).then(|| {
+            let filter = FilterType::HPF1(low.min(nyquist * 0.9));
+            (build_filter(&filter, sample_rate), [0.0; 3])
+        });
+        self.high_cut = (high < MAX_CUTOFF && high < nyquist * 0.9).then(|| {
+            let filter = FilterType::LPF1(high);
+            (build_filter(&filter, sample_rate), [0.0; 3])
+        });
+    }
+
+    fn process(&mut self, samples: &mut [f32]) {
+        self.low_cut
+            .iter_mut()
+            .chain(self.high_cut.iter_mut())
+            .for_each(|(coefficients, state)| coefficients.process(samples, state));
+    }
+}
+
+pub struct NoiseOscillator {
+    buffer: SampleBuffer,
+    envelope: Shared<ADSREnvelope>,
+    voices: Voices,
+    color: SharedParameter<i32>,
+    low_cut: SharedParameter<f32>,
+    high_cut: SharedParameter<f32>,
+    pan: Shared<PanParameter>,
+    gain: Shared<VolumeParameter>,
+    parameters_f32: Vec<NamedParameter<f32>>,
+    parameters_i32: Vec<NamedParameter<i32>>,
+    velocity: VelocityResponse,
+    random: Random,
+    pink: [f32; 7],
+    brown: f32,
+    filter: NoiseFilter,
+    samples: Vec<f32>,
+}
+
+impl NoiseOscillator {
+    pub fn envelope(&self) -> Shared<ADSREnvelope> {
+        self.envelope.clone()
+    }
+
+    // Values of NoiseColor
+    pub fn color(&self) -> SharedParameter<i32> {
+        self.color.clone()
+    }
+
+    pub fn set_color(&mut self, color: NoiseColor) -> &mut Self {
+        self.color.write().unwrap().set_value(color as i32);
+        self
+    }
+
+    // High-pass cutoff in Hz, lowest value disables filter
+    pub fn low_cut(&self) -> SharedParameter<f32> {
+        self.low_cut.clone()
+    }
+
+    // Low-pass cutoff in Hz, highest value disables filter
+    pub fn high_cut(&self) -> SharedParameter<f32> {
+        self.high_cut.clone()
+    }
+
+    pub fn pan(&self) -> Shared<PanParameter> {
+        self.pan.clone()
+    }
+
+    pub fn gain(&self) -> SharedParameter<f32> {
+        self.gain.clone()
+    }
+
+    pub fn velocity(&self) -> &VelocityResponse {
+        &self.velocity
+    }
+
+    pub fn velocity_mut(&mut self) -> &mut VelocityResponse {
+        &mut self.velocity
+    }
+
+    pub fn is_playing(&self, note: u32) -> bool {
+        self.voices.is_playing(note)
+    }
+
+    fn next_noise(&mut self, color: NoiseColor) -> f32 {
+        let white = self.random.next_bipolar();
+        match color {
+            NoiseColor::White => white,
+            // Paul Kellet's refined pink noise filter
+            NoiseColor::Pink => {
+                let b = &mut self.pink;
+                b[0] = 0.99886 * b[0] + white * 0.0555179;
+                b[1] = 0.99332 * b[1] + white * 0.0750759;
+                b[2] = 0.96900 * b[2] + white * 0.153852;
+                b[3] = 0.86650 * b[3] + white * 0.3104856;
+                b[4] = 0.55000 * b[4] + white * 0.5329522;
+                b[5] = -0.7616 * b[5] - white * 0.0168980;
+                let pink = b[..6].iter().sum::<f32>() + b[6] + white * 0.5362;
+                b[6] = white * 0.115926;
+                pink * 0.11
+            }
+            // Leaky integrator keeps brown noise from drifting away
+            NoiseColor::Brown => {
+                self.brown = (self.brown + 0.02 * white) / 1.02;
+                self.brown * 3.5
+            }
+        }
+    }
+}
+
+impl Oscillator for NoiseOscillator {
+    fn evaluate(&mut self, size: usize, delta_time: f32) -> Result<(), Error> {
+        let envelope = self.envelope.clone();
+        let envelope = envelope.read().unwrap();
+        self.voices.remove_released(&envelope);
+        let color = NoiseColor::from(self.color.read().unwrap().get_value());
+        let low = self.low_cut.read().unwrap().get_value();
+        let high = self.high_cut.read().unwrap().get_value();
+        self.filter.update(low, high, 1.0 / delta_time);
+
+        let mut samples = std::mem::take(&mut self.samples);
+        samples.clear();
+        (0..size).for_each(|_| {
+            // All notes share one noise source, so only their envelopes are summed
+            let level = self
+                .voices
+                .iter_mut()
+                .map(|note| {
+                    let value = envelope_value_at(note.play_time, note, &envelope);
+                    note.play_time += delta_time;
+                    value * self.velocity.gain(note.velocity)
+                })
+                .sum::<f32>();
+            samples.push(self.next_noise(color) * level);
+        });
+        self.filter.process(&mut samples);
+
+        let mut pan = self.pan.write().unwrap();
+        let gain = self.gain.read().unwrap().linear;
+        samples
+            .iter()
+            .enumerate()
+            .try_for_each(|(i, sample)| -> Result<(), Error> {
+                pan.next_value(delta_time)?;
+                let (left, right) = pan.polar;
+                self.buffer.set_at(0, i, sample * gain * left)?;
+                self.buffer.set_at(1, i, sample * gain * right)
+            })?;
+        self.samples = samples;
+        Ok(())
+    }
+
+    fn get_buffer_mut(&mut self) -> &mut SampleBuffer {
+        &mut self.buffer
+    }
+
+    fn get_buffer(&self) -> &SampleBuffer {
+        &self.buffer
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn parameters(&mut self) -> Option<&mut dyn NamedParametersContainer> {
+        Some(self)
+    }
+
+    fn velocity_response(&mut self) -> Option<&mut VelocityResponse> {
+        Some(&mut self.velocity)
+    }
+
+    fn shared_envelope(&self) -> Option<Shared<ADSREnvelope>> {
+        Some(self.envelope.clone())
+    }
+}
+
+impl NamedParametersContainer for NoiseOscillator {
+    fn name(&self) -> Option<&'static str> {
+        Some("Noise")
+    }
+
+    fn parameters_f32(&self) -> Option<&[NamedParameter<f32>]> {
+        Some(&self.parameters_f32)
+    }
+
+    fn parameters_i32(&self) -> Option<&[NamedParameter<i32>]> {
+        Some(&self.parameters_i32)
+    }
+}
+
+impl NoteEventReceiver for NoiseOscillator {
+    fn note_on(&mut self, note: Note) -> Result<(), Error> {
+        self.voices.note_on(note);
+        Ok(())
+    }
+
+    fn note_off(&mut self, note: u32) -> Result<(), Error> {
+        self.voices.note_off(note);
+        Ok(())
+    }
+
+    fn member_note_off(&mut self, channel: u8, note: u32) -> Result<(), Error> {
+        self.voices.member_note_off(channel, note);
+        Ok(())
+    }
+
+    fn release_all(&mut self) {
+        self.voices.release_all();
+    }
+
+    fn sustain(&mut self, hold: bool) -> Result<(), Error> {
+        self.voices.sustain(hold);
+        Ok(())
+    }
+
+    fn sostenuto(&mut self, hold: bool) -> Result<(), Error> {
+        self.voices.sostenuto(hold);
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct NoiseOscillatorBuilder {
+    buffer: Option<SampleBuffer>,
+    envelope: Option<ADSREnvelope>,
+    seed: Option<u32>,
+}
+
+impl NoiseOscillatorBuilder {
+    pub fn new() -> Self {
+        Self {
+            buffer: None,
+            envelope: None,
+            seed: None,
+        }
+    }
+
+    pub fn set_buffer(&mut self, buffer: SampleBuffer) -> &mut Self {
+        self.buffer = Some(buffer);
+        self
+    }
+
+    pub fn set_envelope(&mut self, envelope: ADSREnvelope) -> &mut Self {
+        self.envelope = Some(envelope);
+        self
+    }
+
+    // Fixed seed makes output repeatable
+    pub fn set_seed(&mut self, seed: u32) -> &mut Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn build(&mut self) -> Result<NoiseOscillator, Error> {
+        let buffer = self.buffer.take().ok_or(Error::Specify("samples buffer"))?;
+        let envelope = make_shared(self.envelope.take().ok_or(Error::Specify("envelope"))?);
+        let color = make_shared(ValueParameter::new(0, (0, 2)));
+        let low_cut = make_shared(ValueParameter::new(MIN_CUTOFF, (MIN_CUTOFF, MAX_CUTOFF)));
+        let high_cut = make_shared(ValueParameter::new(MAX_CUTOFF, (MIN_CUTOFF, MAX_CUTOFF)));
+        let pan = make_shared(PanParameter::default());
+        let gain = make_shared(VolumeParameter::default());
+        let velocity = VelocityResponse::default();
+        let parameters_f32 = vec![
+            NamedParameter::new(pan.clone(), "Pan"),
+            NamedParameter::new(gain.clone(), "Gain"),
+            NamedParameter::new(low_cut.clone(), "Low Cut"),
+            NamedParameter::new(high_cut.clone(), "High Cut"),
+            NamedParameter::new(velocity.sensitivity(), "Velocity"),
+        ];
+        let parameters_i32 = vec![NamedParameter::new(color.clone(), "Color")];
+        let random = match self.seed {
+            Some(seed) => Random::new(seed),
+            None => Random::default(),
+        };
+        Ok(NoiseOscillator {
+            samples: Vec::with_capacity(buffer.len()),
+            buffer,
+            envelope,
+            voices: Voices::default(),
+            color,
+            low_cut,
+            high_cut,
+            pan,
+            gain,
+            parameters_f32,
+            parameters_i32,
+            velocity,
+            random,
+            pink: [0.0; 7],
+            brown: 0.0,
+            filter: NoiseFilter::default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        core::{
+            note::{Note, NoteEventReceiver},
+            oscillator::Oscillator,
+        },
+        utils::{adsr_envelope::ADSREnvelope, sample_buffer::SampleBufferBuilder},
+    };
+
+    use super::{NoiseColor, NoiseOscillator, NoiseOscillatorBuilder};
+
+    fn build_noise() -> NoiseOscillator {
+        let buffer = SampleBufferBuilder::new()
+            .set_channels(2)
+            .set_samples(1000)
+            .build()
+            .unwrap();
+        NoiseOscillatorBuilder::new()
+            .set_buffer(buffer)
+            .set_envelope(ADSREnvelope::default())
+            .set_seed(1)
+            .build()
+            .unwrap()
+    }
+
+    fn energy(noise: &mut NoiseOscillator) -> f32 {
+        noise.evaluate(1000, 1.0 / 44100.0).unwrap();
+        let buffer = noise.get_buffer();
+        (0..1000).map(|i| buffer.at(0, i).unwrap().powi(2)).sum()
+    }
+
+    #[test]
+    fn test_noise() {
+        let mut noise = build_noise();
+        assert_eq!(energy(&mut noise), 0.0);
+        noise.note_on(Note::from(60)).unwrap();
+        assert!(noise.is_playing(60));
+        let white = energy(&mut noise);
+        assert!(white > 0.0);
+        (0..1000).for_each(|i| assert!(noise.get_buffer().at(0, i).unwrap().abs() <= 1.0));
+
+        noise.set_color(NoiseColor::Brown);
+        assert!(energy(&mut noise) > 0.0);
+        noise.set_color(NoiseColor::White);
+        noise.high_cut().write().unwrap().set_value(200.0);
+        assert!(energy(&mut noise) < white);
+
+        noise.note_off(60).unwrap();
+        assert!(!noise.is_playing(60));
+    }
+}
@@ -6,14 +6,47 @@ use crate::{
     },
 };
 
+pub const MAX_UNISON_VOICES: usize = 16;
+
+// Per-note expression from MPE member channel, pitch bend is in semitones
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Expression {
+    pub pitch_bend: f32,
+    pub pressure: f32,
+    pub timbre: f32,
+}
+
+impl Default for Expression {
+    fn default() -> Self {
+        Self {
+            pitch_bend: 0.0,
+            pressure: 0.0,
+            timbre: 0.5,
+        }
+    }
+}
+
 #[derive(Copy, Clone)]
 pub struct Note {
     pub note: u32,
     pub frequency: f32,
+    // Linear in 0..1, oscillators apply their velocity curves
     pub velocity: f32,
     pub play_time: f32,
-    pub hold_on: State,
+    pub phase: f32,
+    // Phases of unison voices except the first one, which uses phase
+    pub unison_phases: [f32; MAX_UNISON_VOICES - 1],
+    // Portamento offset from note pitch in semitones and its speed in semitones per second
+    pub glide: f32,
+    pub glide_rate: f32,
     pub state: State,
+    pub expression: Expression,
+    // Play time at which note was released
+    pub release_time: Option<f32>,
+    // Level attack starts from, non zero when single voice is retriggered
+    pub start_level: f32,
+    // MPE member channel, notes of the same key on different members are separate voices
+    pub channel: Option<u8>,
 }
 
 impl Note {
@@ -23,12 +56,43 @@ impl Note {
             frequency: note_to_freq(note),
             velocity: velocity_to_float(velocity),
             play_time: 0.0,
-            hold_on: State::Sustain,
+            phase: 0.0,
+            unison_phases: [0.0; MAX_UNISON_VOICES - 1],
+            glide: 0.0,
+            glide_rate: 0.0,
             state: State::Attack,
+            expression: Expression::default(),
+            release_time: None,
+            start_level: 0.0,
+            channel: None,
         }
     }
 }
 
+impl Note {
+    // Fractional note number of tuned frequency, exact for 12-TET notes
+    pub fn pitch(&self) -> f32 {
+        self.note as f32 + 12.0 * (self.frequency / note_to_freq(self.note)).log2()
+    }
+
+    // Envelope continues with release from the level reached so far
+    pub fn release(&mut self) {
+        self.release_time.get_or_insert(self.play_time);
+    }
+
+    pub fn is_released(&self) -> bool {
+        self.release_time.is_some()
+    }
+
+    pub fn is_voice(&self, channel: Option<u8>, note: u32) -> bool {
+        self.channel == channel && self.note == note
+    }
+
+    pub fn key(&self) -> VoiceKey {
+        (self.channel, self.note)
+    }
+}
+
 impl From<u32> for Note {
     fn from(value: u32) -> Self {
         Note::new(value, 127)
@@ -39,11 +103,97 @@ pub trait NoteEventReceiver {
     fn note_on(&mut self, note: Note) -> Result<(), Error>;
     fn note_off(&mut self, note: u32) -> Result<(), Error>;
     fn release_all(&mut self);
+    fn pitch_bend(&mut self, _value: f32) {}
+    // Release of note from MPE member channel, other voices of the key keep playing
+    fn member_note_off(&mut self, _channel: u8, note: u32) -> Result<(), Error> {
+        self.note_off(note)
+    }
+    fn note_expression(&mut self, _channel: u8, _note: u32, _expression: Expression) {}
+    fn sustain(&mut self, _hold: bool) -> Result<(), Error> {
+        Ok(())
+    }
+    fn sostenuto(&mut self, _hold: bool) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+// MPE member channel and key of voice, notes from other channels have no channel
+pub type VoiceKey = (Option<u8>, u32);
+
+#[derive(Default)]
+pub struct Pedals {
+    sustain: bool,
+    sostenuto: Vec<VoiceKey>,
+    deferred: Vec<VoiceKey>,
+}
+
+impl Pedals {
+    pub fn is_sustained(&self) -> bool {
+        self.sustain
+    }
+
+    pub fn is_deferred(&self, key: VoiceKey) -> bool {
+        self.deferred.contains(&key)
+    }
+
+    pub fn note_on(&mut self, key: VoiceKey) {
+        self.deferred.retain(|k| *k != key);
+    }
+
+    // Returns true if voice should be released right now
+    pub fn note_off(&mut self, key: VoiceKey) -> bool {
+        if self.sustain || self.sostenuto.contains(&key) {
+            if !self.is_deferred(key) {
+                self.deferred.push(key);
+            }
+            false
+        } else {
+            true
+        }
+    }
+
+    // Returns deferred voices that should be released after pedal up
+    pub fn set_sustain(&mut self, hold: bool) -> Vec<VoiceKey> {
+        self.sustain = hold;
+        self.take_released()
+    }
+
+    pub fn set_sostenuto<I>(&mut self, hold: bool, held_keys: I) -> Vec<VoiceKey>
+    where
+        I: Iterator<Item = VoiceKey>,
+    {
+        if hold {
+            if self.sostenuto.is_empty() {
+                self.sostenuto = held_keys.filter(|k| !self.is_deferred(*k)).collect();
+            }
+            vec![]
+        } else {
+            self.sostenuto.clear();
+            self.take_released()
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.deferred.clear();
+        self.sostenuto.clear();
+    }
+
+    fn take_released(&mut self) -> Vec<VoiceKey> {
+        if self.sustain {
+            return vec![];
+        }
+        let (held, released) = self
+            .deferred
+            .iter()
+            .partition(|k| self.sostenuto.contains(k));
+        self.deferred = held;
+        released
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::core::note::Note;
+    use crate::core::note::{Note, Pedals};
     use assert_approx_eq::assert_approx_eq;
 
     #[test]
@@ -55,4 +205,26 @@ mod tests {
         let note = Note::new(47, 127);
         assert_approx_eq!(note.frequency, 123.47, 0.05);
     }
+
+    #[test]
+    fn test_pedals() {
+        let mut pedals = Pedals::default();
+        assert!(pedals.note_off((None, 60)));
+        assert!(pedals.set_sustain(true).is_empty());
+        assert!(!pedals.note_off((None, 60)));
+        assert!(!pedals.note_off((None, 62)));
+        pedals.note_on((None, 62));
+        assert_eq!(pedals.set_sustain(false), vec![(None, 60)]);
+
+        let held = [(None, 64), (Some(2), 67)];
+        assert!(pedals.set_sostenuto(true, held.into_iter()).is_empty());
+        assert!(pedals.note_off((None, 60)));
+        assert!(!pedals.note_off((None, 64)));
+        assert!(pedals.note_off((Some(3), 67)));
+        assert!(!pedals.note_off((Some(2), 67)));
+        assert_eq!(
+            pedals.set_sostenuto(false, [].into_iter()),
+            vec![(None, 64), (Some(2), 67)]
+        );
+    }
 }
@@ -1,21 +1,27 @@
 use std::any::Any;
 
-use crate::core::note::Note;
+use crate::core::note::{Expression, Note, MAX_UNISON_VOICES};
 use crate::error::Error;
-use crate::utils::convert::note_to_freq;
+use crate::utils::convert::{semitones_to_freq_coefficient, split_bipolar_pan};
 use crate::utils::evaluate::Modulation;
+use crate::utils::random::Random;
 use crate::utils::{
-    adsr_envelope::{ADSREnvelope, State},
-    consts::PI_2M,
-    evaluate::Evaluate,
-    sample_buffer::SampleBuffer,
+    adsr_envelope::ADSREnvelope, consts::PI_2M, evaluate::Evaluate, sample_buffer::SampleBuffer,
 };
 use crate::utils::{make_shared, Shared};
 
+use super::cross_modulation::{CrossModulationKind, Modulator, VoiceSignal, VoiceSignals};
+use super::lfo::{LfoPolarity, LfoTrigger, LFO};
 use super::note::NoteEventReceiver;
-use super::parameter::{CallbackParameter, CentsParameter, SharedParameter, VolumeParameter};
+use super::parameter::{
+    CallbackParameter, CentsParameter, NamedParameter, NamedParametersContainer, Parameter,
+    SharedParameter, VolumeParameter,
+};
+use super::velocity::VelocityResponse;
+use super::voices::{envelope_value_at, Voices};
 use super::{
     parameter::{OctaveParameter, PanParameter, ValueParameter},
+    waveshape::WaveShape,
     wavetable::WaveTable,
 };
 
@@ -25,6 +31,125 @@ pub trait Oscillator: Send + Sync + NoteEventReceiver {
     fn get_buffer(&self) -> &SampleBuffer;
     fn as_any(&self) -> &dyn Any;
     fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn parameters(&mut self) -> Option<&mut dyn NamedParametersContainer> {
+        None
+    }
+    // Cross modulation by other oscillators, applied during next evaluate
+    fn set_modulators(&mut self, _modulators: Vec<Modulator>) {}
+    // Keep per-note output of evaluate for oscillators modulated by this one
+    fn record_voices(&mut self, _record: bool) {}
+    fn voice_signals(&self) -> Option<&VoiceSignals> {
+        None
+    }
+    fn velocity_response(&mut self) -> Option<&mut VelocityResponse> {
+        None
+    }
+    // Shapes stored in presets besides parameters
+    fn shared_envelope(&self) -> Option<Shared<ADSREnvelope>> {
+        None
+    }
+    fn shared_wavetable(&self) -> Option<Shared<WaveTable>> {
+        None
+    }
+}
+
+// Key with zero key tracking, routes of ExpressionSource::Key are relative to it
+pub const KEY_TRACKING_CENTER: f32 = 60.0;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ExpressionSource {
+    Pressure,
+    // Bipolar, centered at default CC74 value
+    Timbre,
+    // Octaves from middle C
+    Key,
+}
+
+impl ExpressionSource {
+    pub fn value(&self, note: &Note) -> f32 {
+        match self {
+            ExpressionSource::Pressure => note.expression.pressure,
+            ExpressionSource::Timbre => note.expression.timbre * 2.0 - 1.0,
+            ExpressionSource::Key => (note.pitch() - KEY_TRACKING_CENTER) / 12.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ExpressionTarget {
+    // Depth in decibels
+    Gain,
+    // Depth in cents
+    Cents,
+}
+
+// Per-voice modulation by note expression
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ExpressionRoute {
+    pub source: ExpressionSource,
+    pub target: ExpressionTarget,
+    pub depth: f32,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum VoiceMode {
+    Poly,
+    // Single voice, envelope is retriggered by every note
+    Mono,
+    // Single voice, envelope is not retriggered while any key is held
+    Legato,
+}
+
+impl From<i32> for VoiceMode {
+    fn from(value: i32) -> Self {
+        match value {
+            1 => VoiceMode::Mono,
+            2 => VoiceMode::Legato,
+            _ => VoiceMode::Poly,
+        }
+    }
+}
+
+// Which of held keys sounds in mono and legato modes
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum NotePriority {
+    Last,
+    Low,
+    High,
+}
+
+impl From<i32> for NotePriority {
+    fn from(value: i32) -> Self {
+        match value {
+            1 => NotePriority::Low,
+            2 => NotePriority::High,
+            _ => NotePriority::Last,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum GlideMode {
+    // Glide takes the same time for any interval
+    Time,
+    // Glide time is set per octave
+    Rate,
+}
+
+impl From<i32> for GlideMode {
+    fn from(value: i32) -> Self {
+        match value {
+            1 => GlideMode::Rate,
+            _ => GlideMode::Time,
+        }
+    }
+}
+
+// Pitch coefficient, pan offset and gain of single unison voice
+struct UnisonVoice {
+    coefficient: f32,
+    pan: f32,
+    gain: f32,
 }
 
 struct Parametrs {
@@ -33,15 +158,37 @@ struct Parametrs {
     pan: Shared<PanParameter>,
     wt_pos: SharedParameter<i32>,
     gain: Shared<VolumeParameter>,
+    pitch_bend_range: SharedParameter<i32>,
+    unison_voices: SharedParameter<i32>,
+    unison_detune: SharedParameter<f32>,
+    unison_curve: SharedParameter<f32>,
+    unison_spread: SharedParameter<f32>,
+    unison_blend: SharedParameter<f32>,
+    unison_phase: SharedParameter<f32>,
+    voice_mode: SharedParameter<i32>,
+    note_priority: SharedParameter<i32>,
+    glide_mode: SharedParameter<i32>,
+    glide_time: SharedParameter<f32>,
+    vibrato_depth: SharedParameter<f32>,
+    parameters_f32: Vec<NamedParameter<f32>>,
+    parameters_i32: Vec<NamedParameter<i32>>,
 }
 
 pub struct WavetableOscillator {
     buffer: SampleBuffer,
     envelope: Shared<ADSREnvelope>,
     wavetable: Shared<WaveTable>,
-    notes: Vec<Note>,
-    release_notes: Vec<Note>,
+    voices: Voices,
     parametrs: Parametrs,
+    pitch_bend: f32,
+    expression_routes: Vec<ExpressionRoute>,
+    velocity: VelocityResponse,
+    random: Random,
+    last_pitch: Option<f32>,
+    modulators: Vec<Modulator>,
+    vibrato: Shared<LFO>,
+    record_voices: bool,
+    voice_signals: VoiceSignals,
     time: f32,
 }
 
@@ -74,70 +221,439 @@ impl WavetableOscillator {
         self.parametrs.gain.clone()
     }
 
+    pub fn pitch_bend_range(&self) -> SharedParameter<i32> {
+        self.parametrs.pitch_bend_range.clone()
+    }
+
+    pub fn unison_voices(&self) -> SharedParameter<i32> {
+        self.parametrs.unison_voices.clone()
+    }
+
+    // Detune of outermost voices in cents
+    pub fn unison_detune(&self) -> SharedParameter<f32> {
+        self.parametrs.unison_detune.clone()
+    }
+
+    // 0 spreads voices linearly, higher values gather them near the centre
+    pub fn unison_curve(&self) -> SharedParameter<f32> {
+        self.parametrs.unison_curve.clone()
+    }
+
+    pub fn unison_spread(&self) -> SharedParameter<f32> {
+        self.parametrs.unison_spread.clone()
+    }
+
+    // 0 plays centre voices only, 1 plays side voices only
+    pub fn unison_blend(&self) -> SharedParameter<f32> {
+        self.parametrs.unison_blend.clone()
+    }
+
+    // Amount of random start phase of voices
+    pub fn unison_phase(&self) -> SharedParameter<f32> {
+        self.parametrs.unison_phase.clone()
+    }
+
+    // Values of VoiceMode
+    pub fn voice_mode(&self) -> SharedParameter<i32> {
+        self.parametrs.voice_mode.clone()
+    }
+
+    // Values of NotePriority
+    pub fn note_priority(&self) -> SharedParameter<i32> {
+        self.parametrs.note_priority.clone()
+    }
+
+    // Values of GlideMode
+    pub fn glide_mode(&self) -> SharedParameter<i32> {
+        self.parametrs.glide_mode.clone()
+    }
+
+    // Portamento time in seconds, zero disables it
+    pub fn glide_time(&self) -> SharedParameter<f32> {
+        self.parametrs.glide_time.clone()
+    }
+
+    // Pitch LFO evaluated for every voice
+    pub fn vibrato(&self) -> Shared<LFO> {
+        self.vibrato.clone()
+    }
+
+    // Vibrato depth in cents
+    pub fn vibrato_depth(&self) -> SharedParameter<f32> {
+        self.parametrs.vibrato_depth.clone()
+    }
+
+    pub fn set_voice_mode(&mut self, mode: VoiceMode) -> &mut Self {
+        self.parametrs
+            .voice_mode
+            .write()
+            .unwrap()
+            .set_value(mode as i32);
+        self
+    }
+
+    pub fn set_note_priority(&mut self, priority: NotePriority) -> &mut Self {
+        self.parametrs
+            .note_priority
+            .write()
+            .unwrap()
+            .set_value(priority as i32);
+        self
+    }
+
+    pub fn set_glide_mode(&mut self, mode: GlideMode) -> &mut Self {
+        self.parametrs
+            .glide_mode
+            .write()
+            .unwrap()
+            .set_value(mode as i32);
+        self
+    }
+
+    fn mode(&self) -> VoiceMode {
+        VoiceMode::from(self.parametrs.voice_mode.read().unwrap().get_value())
+    }
+
+    pub fn velocity(&self) -> &VelocityResponse {
+        &self.velocity
+    }
+
+    pub fn velocity_mut(&mut self) -> &mut VelocityResponse {
+        &mut self.velocity
+    }
+
+    pub fn expression_routes(&self) -> &[ExpressionRoute] {
+        &self.expression_routes
+    }
+
+    pub fn add_expression_route(
+        &mut self,
+        source: ExpressionSource,
+        target: ExpressionTarget,
+        depth: f32,
+    ) -> &mut Self {
+        self.expression_routes.push(ExpressionRoute {
+            source,
+            target,
+            depth,
+        });
+        self
+    }
+
+    pub fn clear_expression_routes(&mut self) {
+        self.expression_routes.clear();
+    }
+
+    pub fn expression_of(&self, note: u32) -> Option<Expression> {
+        let index = self.get_note(note).ok()?;
+        Some(self.voices.notes[index].expression)
+    }
+
+    pub fn is_playing(&self, note: u32) -> bool {
+        self.voices.is_playing(note)
+    }
+
     fn get_note(&self, note: u32) -> Result<usize, Error> {
         Ok(self
+            .voices
             .notes
             .iter()
             .position(|x| x.note == note)
             .ok_or(format!("Note {} not playing", note))?)
     }
 
-    fn remove_note(&mut self, index: usize) -> Note {
-        self.notes.remove(index)
+    // Note glides from pitch of the previous one
+    fn glide_note(&mut self, mut note: Note) -> Note {
+        if let Some(pitch) = self.last_pitch {
+            self.glide_from(&mut note, pitch);
+        }
+        self.last_pitch = Some(note.pitch());
+        note
     }
 
-    fn remove_released_notes(&mut self) {
-        let envelope = self.envelope.read().unwrap();
-        self.release_notes.retain(|note| {
-            note.state != State::None && note.play_time < envelope.time_range_of(State::Release).1
-        });
+    fn glide_from(&self, note: &mut Note, pitch: f32) {
+        let time = self.parametrs.glide_time.read().unwrap().get_value();
+        let offset = pitch - note.pitch();
+        if time <= 0.0 || offset == 0.0 {
+            return;
+        }
+        note.glide = offset;
+        note.glide_rate =
+            match GlideMode::from(self.parametrs.glide_mode.read().unwrap().get_value()) {
+                GlideMode::Time => offset.abs() / time,
+                GlideMode::Rate => 12.0 / time,
+            };
     }
 
-    fn envelope_value_at(t: f32, note: &Note, adsr: Shared<ADSREnvelope>) -> f32 {
-        let envelope = adsr.read().unwrap();
-        match note.hold_on {
-            State::None => envelope.evaluate(t),
-            _ => {
-                if t > envelope.time_range_of(note.hold_on).1 {
-                    envelope.peak_at(note.hold_on)
-                } else {
-                    envelope.evaluate(t)
-                }
+    // Key pressed or released by player or by pedal, in mono and legato modes
+    // single voice follows held keys afterwards
+    fn key_event<F: FnOnce(&mut Voices)>(&mut self, event: F) {
+        let mono = self.mode() != VoiceMode::Poly;
+        self.voices.set_mono(mono);
+        event(&mut self.voices);
+        if mono {
+            self.update_mono();
+        }
+    }
+
+    // Makes single voice follow held key with highest priority
+    fn update_mono(&mut self) {
+        let priority = NotePriority::from(self.parametrs.note_priority.read().unwrap().get_value());
+        let target = match priority {
+            NotePriority::Last => self.voices.held.last(),
+            NotePriority::Low => self.voices.held.iter().min_by_key(|n| n.note),
+            NotePriority::High => self.voices.held.iter().max_by_key(|n| n.note),
+        }
+        .copied();
+        let Some(target) = target else {
+            let notes = self.voices.notes.iter().map(|n| n.note).collect::<Vec<_>>();
+            notes.into_iter().for_each(|note| self.voices.release(note));
+            return;
+        };
+        let legato = self.mode() == VoiceMode::Legato;
+        match self.voices.notes.first().copied() {
+            Some(current) if current.is_voice(target.channel, target.note) => (),
+            Some(current) if legato => {
+                let mut voice = current;
+                voice.note = target.note;
+                voice.channel = target.channel;
+                voice.frequency = target.frequency;
+                voice.expression = target.expression;
+                voice.glide = 0.0;
+                self.glide_from(&mut voice, current.pitch() + current.glide);
+                self.last_pitch = Some(target.pitch());
+                self.voices.notes[0] = voice;
+            }
+            // Single voice is retriggered, envelope restarts from the level it has reached
+            Some(current) => {
+                let mut voice = target;
+                voice.phase = current.phase;
+                voice.unison_phases = current.unison_phases;
+                voice.start_level =
+                    Self::envelope_value_at(current.play_time, &current, self.envelope.clone());
+                self.last_pitch = Some(current.pitch() + current.glide);
+                self.voices.notes.clear();
+                let voice = self.glide_note(voice);
+                self.voices.notes.push(voice);
             }
+            None => {
+                let voice = self.glide_note(target);
+                self.voices.notes.push(voice);
+            }
+        }
+    }
+
+    // Voices nearest to the centre go first, single voice is never detuned
+    fn unison(&self) -> Vec<UnisonVoice> {
+        let count = self.parametrs.unison_voices.read().unwrap().get_value();
+        let count = (count.max(1) as usize).min(MAX_UNISON_VOICES);
+        if count == 1 {
+            return vec![UnisonVoice {
+                coefficient: 1.0,
+                pan: 0.0,
+                gain: 1.0,
+            }];
         }
+        let detune = self.parametrs.unison_detune.read().unwrap().get_value();
+        let exponent = 1.0 + 3.0 * self.parametrs.unison_curve.read().unwrap().get_value();
+        let spread = self.parametrs.unison_spread.read().unwrap().get_value();
+        let blend = self.parametrs.unison_blend.read().unwrap().get_value();
+        let mut positions = (0..count)
+            .map(|i| -1.0 + 2.0 * i as f32 / (count - 1) as f32)
+            .collect::<Vec<_>>();
+        positions.sort_by(|a, b| a.abs().total_cmp(&b.abs()));
+        // Odd count has single centre voice, even count has two
+        let centre = 2 - count % 2;
+        let (centre_gain, side_gain) = match count > centre {
+            true => (
+                ((1.0 - blend) / centre as f32).sqrt(),
+                (blend / (count - centre) as f32).sqrt(),
+            ),
+            false => ((1.0 / centre as f32).sqrt(), 0.0),
+        };
+        positions
+            .into_iter()
+            .enumerate()
+            .map(|(i, position)| {
+                let cents = position.signum() * position.abs().powf(exponent) * detune;
+                UnisonVoice {
+                    coefficient: semitones_to_freq_coefficient(cents / 100.0),
+                    pan: position * spread,
+                    gain: if i < centre { centre_gain } else { side_gain },
+                }
+            })
+            .collect()
+    }
+
+    // Returns gain coefficient and pitch offset in semitones of single voice
+    fn voice_modulation(routes: &[ExpressionRoute], note: &Note) -> (f32, f32) {
+        routes.iter().fold(
+            (1.0, note.expression.pitch_bend),
+            |(gain, semitones), route| {
+                let value = route.source.value(note) * route.depth;
+                match route.target {
+                    ExpressionTarget::Gain => (gain * 10.0_f32.powf(value / 20.0), semitones),
+                    ExpressionTarget::Cents => (gain, semitones + value / 100.0),
+                }
+            },
+        )
+    }
+
+    // Returns frequency coefficient, phase offset and gain of note at sample index
+    fn cross_modulation(
+        modulators: &[Modulator],
+        links: &[Vec<Option<usize>>],
+        note_index: usize,
+        index: usize,
+        note: &mut Note,
+    ) -> (f32, f32, f32) {
+        let (mut fm, mut pm, mut am) = (1.0, 0.0, 1.0);
+        modulators.iter().zip(links).for_each(|(m, links)| {
+            let (value, wrapped) = match links[note_index] {
+                Some(voice) => m.signals.sample(voice, index),
+                None => (0.0, false),
+            };
+            let depth = m.index.min(1.0);
+            match m.kind {
+                CrossModulationKind::Frequency => fm += m.index * value,
+                CrossModulationKind::Phase => pm += m.index * value,
+                CrossModulationKind::Ring => am *= 1.0 - depth * (1.0 - value),
+                CrossModulationKind::Amplitude => am *= 1.0 - depth * 0.5 * (1.0 - value),
+                CrossModulationKind::Sync if wrapped => {
+                    note.phase = 0.0;
+                    note.unison_phases.iter_mut().for_each(|p| *p = 0.0);
+                }
+                CrossModulationKind::Sync => (),
+            }
+        });
+        (fm, pm, am)
+    }
+
+    fn envelope_value_at(t: f32, note: &Note, adsr: Shared<ADSREnvelope>) -> f32 {
+        envelope_value_at(t, note, &adsr.read().unwrap())
     }
 }
 
 impl Oscillator for WavetableOscillator {
     fn evaluate(&mut self, size: usize, delta_time: f32) -> Result<(), Error> {
-        self.remove_released_notes();
+        self.voices.remove_released(&self.envelope.read().unwrap());
+        let unison = self.unison();
+        // Pans of unison voices follow smoothed pan, recalculated only when it changes
+        let mut voice_pans = vec![(0.0, 0.0); unison.len()];
+        let mut unison_pan = None;
         let buffer = &mut self.buffer;
         let mut pan = self.parametrs.pan.write().unwrap();
         let octave_offset = self.parametrs.octave_offset.read().unwrap().notes;
         let mut cents = self.parametrs.cents_offset.write().unwrap();
         let gain = self.parametrs.gain.read().unwrap().linear;
+        let pitch_bend_range = self.parametrs.pitch_bend_range.read().unwrap().get_value();
+        let bend = semitones_to_freq_coefficient(self.pitch_bend * pitch_bend_range as f32);
+        let vibrato = self.vibrato.read().unwrap();
+        let vibrato_depth = self.parametrs.vibrato_depth.read().unwrap().get_value() / 100.0;
+        // Voices of modulators are matched by channel and key once per buffer
+        let links = self
+            .modulators
+            .iter()
+            .map(|m| {
+                self.voices
+                    .notes
+                    .iter()
+                    .chain(self.voices.release_notes.iter())
+                    .map(|note| m.signals.position(note.channel, note.note))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        self.voice_signals.clear();
+        if self.record_voices {
+            self.voice_signals.voices = self
+                .voices
+                .notes
+                .iter()
+                .chain(self.voices.release_notes.iter())
+                .map(|note| VoiceSignal {
+                    channel: note.channel,
+                    note: note.note,
+                    samples: Vec::with_capacity(size),
+                    wraps: Vec::with_capacity(size),
+                })
+                .collect();
+        }
 
         (0..size).try_for_each(|i| -> Result<(), Error> {
             let mut iteration_buffer = [0.0; 2];
             pan.next_value(delta_time)?;
             let polar_pan = pan.polar;
+            let pan_value = pan.get_value();
+            if unison.len() > 1 && unison_pan != Some(pan_value) {
+                unison_pan = Some(pan_value);
+                voice_pans
+                    .iter_mut()
+                    .zip(unison.iter())
+                    .for_each(|(polar, voice)| {
+                        *polar = split_bipolar_pan((pan_value + voice.pan).clamp(-1.0, 1.0));
+                    });
+            }
             cents.next_value(delta_time)?;
             let cents = cents.freq;
-            self.notes
+            let time = self.time;
+            self.voices
                 .iter_mut()
-                .chain(self.release_notes.iter_mut())
-                .try_for_each(|note| -> Result<(), Error> {
+                .enumerate()
+                .try_for_each(|(k, note)| -> Result<(), Error> {
                     let t = note.play_time;
                     let envelope = Self::envelope_value_at(t, note, self.envelope.clone());
-                    let freq =
-                        PI_2M * note_to_freq((note.note as i32 + octave_offset) as u32) * t * cents;
-                    let sample = {
-                        let wavetable = self.wavetable.write().unwrap();
-                        wavetable.evaluate(freq)?
+                    let (voice_gain, voice_semitones) =
+                        Self::voice_modulation(&self.expression_routes, note);
+                    let voice_semitones = match vibrato_depth > 0.0 {
+                        true => voice_semitones + vibrato.voice_value(t, time) * vibrato_depth,
+                        false => voice_semitones,
                     };
-
-                    iteration_buffer[0] += sample * envelope * polar_pan.0 * note.velocity * gain;
-                    iteration_buffer[1] += sample * envelope * polar_pan.1 * note.velocity * gain;
+                    let freq = note.frequency
+                        * semitones_to_freq_coefficient(octave_offset as f32)
+                        * cents
+                        * bend
+                        * semitones_to_freq_coefficient(voice_semitones + note.glide);
+                    let glide_step = note.glide_rate * delta_time;
+                    note.glide = match note.glide.abs() > glide_step {
+                        true => note.glide - glide_step * note.glide.signum(),
+                        false => 0.0,
+                    };
+                    let (fm, pm, am) = Self::cross_modulation(&self.modulators, &links, k, i, note);
+                    let freq = freq * fm;
+                    let velocity = self.velocity.gain(note.velocity);
+                    let gain = gain * voice_gain * envelope * velocity * am;
+                    let wavetable = self.wavetable.read().unwrap();
+                    let mut voice_sample = 0.0;
+                    let mut wrapped = false;
+                    unison
+                        .iter()
+                        .enumerate()
+                        .try_for_each(|(v, voice)| -> Result<(), Error> {
+                            let phase = match v {
+                                0 => &mut note.phase,
+                                _ => &mut note.unison_phases[v - 1],
+                            };
+                            let position = (*phase + pm).rem_euclid(1.0);
+                            let sample = wavetable.evaluate(PI_2M * position)? * voice.gain * gain;
+                            voice_sample += sample;
+                            let polar_pan = match unison.len() {
+                                1 => polar_pan,
+                                _ => voice_pans[v],
+                            };
+                            iteration_buffer[0] += sample * polar_pan.0;
+                            iteration_buffer[1] += sample * polar_pan.1;
+                            let next =
+                                (*phase + freq * voice.coefficient * delta_time).rem_euclid(1.0);
+                            if v == 0 {
+                                wrapped = next < *phase;
+                            }
+                            *phase = next;
+                            Ok(())
+                        })?;
+                    if let Some(signal) = self.voice_signals.voices.get_mut(k) {
+                        signal.samples.push(voice_sample);
+                        signal.wraps.push(wrapped);
+                    }
 
                     note.play_time += delta_time;
                     Ok(())
@@ -170,34 +686,100 @@ impl Oscillator for WavetableOscillator {
     fn as_any_mut(&mut self) -> &mut dyn Any {
         self
     }
+
+    fn parameters(&mut self) -> Option<&mut dyn NamedParametersContainer> {
+        Some(self)
+    }
+
+    fn set_modulators(&mut self, modulators: Vec<Modulator>) {
+        self.modulators = modulators;
+    }
+
+    fn record_voices(&mut self, record: bool) {
+        self.record_voices = record;
+    }
+
+    fn voice_signals(&self) -> Option<&VoiceSignals> {
+        Some(&self.voice_signals)
+    }
+
+    fn velocity_response(&mut self) -> Option<&mut VelocityResponse> {
+        Some(&mut self.velocity)
+    }
+
+    fn shared_envelope(&self) -> Option<Shared<ADSREnvelope>> {
+        Some(self.envelope.clone())
+    }
+
+    fn shared_wavetable(&self) -> Option<Shared<WaveTable>> {
+        Some(self.wavetable.clone())
+    }
+}
+
+impl NamedParametersContainer for WavetableOscillator {
+    fn name(&self) -> Option<&'static str> {
+        Some("Oscillator")
+    }
+
+    fn parameters_f32(&self) -> Option<&[NamedParameter<f32>]> {
+        Some(&self.parametrs.parameters_f32)
+    }
+
+    fn parameters_i32(&self) -> Option<&[NamedParameter<i32>]> {
+        Some(&self.parametrs.parameters_i32)
+    }
 }
 
 impl NoteEventReceiver for WavetableOscillator {
     fn note_on(&mut self, note: Note) -> std::result::Result<(), Error> {
-        let index = self.get_note(note.note);
-        if index.is_ok() {
-            self.note_off(note.note)?;
+        let mut note = note;
+        if self.parametrs.unison_voices.read().unwrap().get_value() > 1 {
+            let amount = self.parametrs.unison_phase.read().unwrap().get_value();
+            note.phase = self.random.next_f32() * amount;
+            note.unison_phases
+                .iter_mut()
+                .for_each(|phase| *phase = self.random.next_f32() * amount);
+        }
+        match self.mode() {
+            VoiceMode::Poly => {
+                let note = self.glide_note(note);
+                self.key_event(|voices| voices.note_on(note));
+            }
+            _ => self.key_event(|voices| voices.hold(note)),
         }
-        self.notes.push(note);
         Ok(())
     }
 
     fn note_off(&mut self, note: u32) -> std::result::Result<(), Error> {
-        let index = self.get_note(note);
-        let index = match index {
-            Ok(i) => i,
-            Err(_) => return Ok(()),
-        };
-        let mut note = self.remove_note(index);
-        note.hold_on = State::None;
-        self.release_notes.push(note);
+        self.key_event(|voices| voices.note_off(note));
+        Ok(())
+    }
+
+    fn member_note_off(&mut self, channel: u8, note: u32) -> std::result::Result<(), Error> {
+        self.key_event(|voices| voices.member_note_off(channel, note));
         Ok(())
     }
 
     fn release_all(&mut self) {
-        while let Some(note) = self.notes.pop() {
-            self.release_notes.push(note);
-        }
+        self.voices.release_all();
+    }
+
+    fn sustain(&mut self, hold: bool) -> std::result::Result<(), Error> {
+        self.key_event(|voices| voices.sustain(hold));
+        Ok(())
+    }
+
+    fn sostenuto(&mut self, hold: bool) -> std::result::Result<(), Error> {
+        self.key_event(|voices| voices.sostenuto(hold));
+        Ok(())
+    }
+
+    fn pitch_bend(&mut self, value: f32) {
+        self.pitch_bend = value;
+    }
+
+    fn note_expression(&mut self, channel: u8, note: u32, expression: Expression) {
+        self.voices.note_expression(channel, note, expression);
     }
 }
 
@@ -237,9 +819,13 @@ impl OscillatorBuilder {
         let envelope = make_shared(self.envelope.take().ok_or(Error::Specify("envelope"))?);
         let wavetable = make_shared(self.wavetable.take().ok_or(Error::Specify("wavetable"))?);
         let octave_offset = make_shared(OctaveParameter::new(ValueParameter::new(0, (-2, 2))));
-        let cents_offset = make_shared(CentsParameter::new(ValueParameter::new(0.0, (-100.0, 100.0))));
+        let cents_offset = make_shared(CentsParameter::new(ValueParameter::new(
+            0.0,
+            (-100.0, 100.0),
+        )));
         let pan = make_shared(PanParameter::default());
         let gain = make_shared(VolumeParameter::default());
+        let pitch_bend_range = make_shared(ValueParameter::new(2, (0, 24)));
 
         let wt_clone = wavetable.clone();
         let wt_clone2 = wavetable.clone();
@@ -254,21 +840,87 @@ impl OscillatorBuilder {
                 (wt_range.0 as i32, wt_range.1 as i32)
             },
         });
+        let unison_voices = make_shared(ValueParameter::new(1, (1, MAX_UNISON_VOICES as i32)));
+        let unison_detune = make_shared(ValueParameter::new(20.0, (0.0, 100.0)));
+        let unison_curve = make_shared(ValueParameter::new(0.0, (0.0, 1.0)));
+        let unison_spread = make_shared(ValueParameter::new(0.5, (0.0, 1.0)));
+        let unison_blend = make_shared(ValueParameter::new(0.5, (0.0, 1.0)));
+        let unison_phase = make_shared(ValueParameter::new(1.0, (0.0, 1.0)));
+        let voice_mode = make_shared(ValueParameter::new(0, (0, 2)));
+        let note_priority = make_shared(ValueParameter::new(0, (0, 2)));
+        let glide_mode = make_shared(ValueParameter::new(0, (0, 1)));
+        let glide_time = make_shared(ValueParameter::new(0.0, (0.0, 5.0)));
+        let vibrato_depth = make_shared(ValueParameter::new(0.0, (0.0, 100.0)));
+        let mut vibrato = LFO::new(WaveShape::Sin, 5.0);
+        vibrato
+            .set_polarity(LfoPolarity::Bipolar)
+            .set_trigger(LfoTrigger::Retrigger);
+        let velocity = VelocityResponse::default();
+        let parameters_f32 = vec![
+            NamedParameter::new(pan.clone(), "Pan"),
+            NamedParameter::new(cents_offset.clone(), "Cents"),
+            NamedParameter::new(gain.clone(), "Gain"),
+            NamedParameter::new(unison_detune.clone(), "Detune"),
+            NamedParameter::new(unison_curve.clone(), "Detune Curve"),
+            NamedParameter::new(unison_spread.clone(), "Spread"),
+            NamedParameter::new(unison_blend.clone(), "Blend"),
+            NamedParameter::new(unison_phase.clone(), "Random Phase"),
+            NamedParameter::new(glide_time.clone(), "Glide"),
+            NamedParameter::new(velocity.sensitivity(), "Velocity"),
+            NamedParameter::new(vibrato.frequency(), "Vibrato Rate"),
+            NamedParameter::new(vibrato_depth.clone(), "Vibrato Depth"),
+            NamedParameter::new(vibrato.phase(), "Vibrato Phase"),
+            NamedParameter::new(vibrato.delay(), "Vibrato Delay"),
+            NamedParameter::new(vibrato.fade_in(), "Vibrato Fade In"),
+        ];
+        let parameters_i32 = vec![
+            NamedParameter::new(octave_offset.clone(), "Octave"),
+            NamedParameter::new(wt_pos.clone(), "Wt Pos"),
+            NamedParameter::new(pitch_bend_range.clone(), "Bend"),
+            NamedParameter::new(unison_voices.clone(), "Voices"),
+            NamedParameter::new(voice_mode.clone(), "Mode"),
+            NamedParameter::new(note_priority.clone(), "Priority"),
+            NamedParameter::new(glide_mode.clone(), "Glide Mode"),
+            NamedParameter::new(vibrato.shape(), "Vibrato Shape"),
+            NamedParameter::new(vibrato.division(), "Vibrato Sync"),
+        ];
         let parametrs = Parametrs {
             octave_offset,
             cents_offset,
             pan,
             wt_pos,
             gain,
+            pitch_bend_range,
+            unison_voices,
+            unison_detune,
+            unison_curve,
+            unison_spread,
+            unison_blend,
+            unison_phase,
+            voice_mode,
+            note_priority,
+            glide_mode,
+            glide_time,
+            vibrato_depth,
+            parameters_f32,
+            parameters_i32,
         };
 
         Ok(WavetableOscillator {
             buffer,
             envelope,
             wavetable,
-            notes: vec![],
-            release_notes: vec![],
+            voices: Voices::default(),
             parametrs,
+            pitch_bend: 0.0,
+            expression_routes: vec![],
+            velocity,
+            random: Random::default(),
+            last_pitch: None,
+            modulators: vec![],
+            vibrato: make_shared(vibrato),
+            record_voices: false,
+            voice_signals: VoiceSignals::default(),
             time: 0.0,
         })
     }
@@ -278,19 +930,24 @@ impl OscillatorBuilder {
 mod tests {
     use crate::{
         core::{
-            note::{Note, NoteEventReceiver},
-            oscillator::OscillatorBuilder,
+            cross_modulation::{CrossModulationKind, Modulator, VoiceSignal, VoiceSignals},
+            note::{Expression, Note, NoteEventReceiver},
+            oscillator::{
+                ExpressionSource, ExpressionTarget, GlideMode, NotePriority, Oscillator,
+                OscillatorBuilder, VoiceMode, WavetableOscillator,
+            },
             waveshape::WaveShape,
             wavetable::WaveTableBuilder,
         },
+        midi::controller::ControllerSource,
         utils::{
-            adsr_envelope::ADSREnvelope, interpolation::InterpolateMethod,
-            sample_buffer::SampleBufferBuilder,
+            adsr_envelope::ADSREnvelope, evaluate::Modulation, interpolation::InterpolateMethod,
+            make_shared, sample_buffer::SampleBufferBuilder,
         },
     };
+    use assert_approx_eq::assert_approx_eq;
 
-    #[test]
-    fn test_osc_notes() {
+    fn build_osc() -> WavetableOscillator {
         let adsr = ADSREnvelope::default();
         let buffer = SampleBufferBuilder::new()
             .set_channels(2)
@@ -302,16 +959,392 @@ mod tests {
             .set_interpolation(InterpolateMethod::Linear)
             .build()
             .unwrap();
-        let mut osc = OscillatorBuilder::new()
+        OscillatorBuilder::new()
             .set_buffer(buffer)
             .set_envelope(adsr)
             .set_wavetable(table)
             .build()
-            .unwrap();
+            .unwrap()
+    }
+
+    #[test]
+    fn test_osc_notes() {
+        let mut osc = build_osc();
         osc.note_on(Note::from(60)).unwrap();
         let get = osc.get_note(60);
         assert!(get.is_ok());
         let get = osc.get_note(61);
         assert!(get.is_err());
     }
+
+    #[test]
+    fn test_osc_pitch_bend() {
+        let delta_time = 1.0 / 44100.0;
+        let mut osc = build_osc();
+        osc.note_on(Note::from(69)).unwrap();
+        osc.evaluate(1, delta_time).unwrap();
+        let phase = osc.voices.notes[0].phase;
+        assert_approx_eq!(phase, 440.0 * delta_time, 1e-5);
+
+        let mut osc = build_osc();
+        osc.pitch_bend_range().write().unwrap().set_value(12);
+        osc.pitch_bend(1.0);
+        osc.note_on(Note::from(69)).unwrap();
+        osc.evaluate(1, delta_time).unwrap();
+        let phase = osc.voices.notes[0].phase;
+        assert_approx_eq!(phase, 880.0 * delta_time, 1e-5);
+    }
+
+    #[test]
+    fn test_osc_expression() {
+        let delta_time = 1.0 / 44100.0;
+        let mut osc = build_osc();
+        osc.add_expression_route(ExpressionSource::Timbre, ExpressionTarget::Cents, 1200.0);
+        let mut note = Note::from(69);
+        note.expression.pitch_bend = 12.0;
+        osc.note_on(note).unwrap();
+        // The same key on two member channels gives two voices
+        let member = |channel| Note {
+            channel: Some(channel),
+            ..Note::from(57)
+        };
+        osc.note_on(member(2)).unwrap();
+        osc.note_on(member(3)).unwrap();
+        osc.evaluate(1, delta_time).unwrap();
+        assert_approx_eq!(osc.voices.notes[0].phase, 880.0 * delta_time, 1e-5);
+        assert_approx_eq!(osc.voices.notes[1].phase, 220.0 * delta_time, 1e-5);
+
+        let expression = Expression {
+            timbre: 1.0,
+            ..Expression::default()
+        };
+        osc.note_expression(2, 57, expression);
+        osc.evaluate(1, delta_time).unwrap();
+        assert_approx_eq!(osc.voices.notes[1].phase, 660.0 * delta_time, 1e-5);
+        assert_approx_eq!(osc.voices.notes[2].phase, 440.0 * delta_time, 1e-5);
+        assert_eq!(osc.expression_of(57), Some(expression));
+
+        osc.member_note_off(2, 57).unwrap();
+        assert!(osc.is_playing(57));
+        assert_eq!(osc.voices.notes[1].channel, Some(3));
+        osc.note_off(57).unwrap();
+        assert!(!osc.is_playing(57));
+    }
+
+    #[test]
+    fn test_osc_key_tracking() {
+        let delta_time = 1.0 / 44100.0;
+        let mut osc = build_osc();
+        // Half of pitch tracking, octave above middle C sounds half octave above it
+        osc.add_expression_route(ExpressionSource::Key, ExpressionTarget::Cents, -600.0);
+        osc.note_on(Note::from(72)).unwrap();
+        osc.evaluate(1, delta_time).unwrap();
+        assert_approx_eq!(osc.voices.notes[0].phase, 369.99 * delta_time, 1e-6);
+    }
+
+    #[test]
+    fn test_osc_vibrato() {
+        let delta_time = 1.0 / 44100.0;
+        let mut osc = build_osc();
+        osc.vibrato_depth().write().unwrap().set_value(100.0);
+        // Vibrato starts at its peak
+        osc.vibrato()
+            .read()
+            .unwrap()
+            .phase()
+            .write()
+            .unwrap()
+            .set_value(0.25);
+        osc.note_on(Note::from(69)).unwrap();
+        osc.evaluate(1, 0.5).unwrap();
+        osc.note_on(Note::from(57)).unwrap();
+        let phase = osc.voices.notes[0].phase;
+        osc.evaluate(1, delta_time).unwrap();
+        // Each voice has own vibrato phase, the first one is in the trough
+        assert_approx_eq!(osc.voices.notes[0].phase - phase, 415.30 * delta_time, 1e-5);
+        assert_approx_eq!(osc.voices.notes[1].phase, 233.08 * delta_time, 1e-5);
+    }
+
+    #[test]
+    fn test_osc_unison() {
+        let delta_time = 1.0 / 44100.0;
+        let mut osc = build_osc();
+        osc.unison_voices().write().unwrap().set_value(3);
+        osc.unison_detune().write().unwrap().set_value(100.0);
+        osc.unison_phase().write().unwrap().set_value(0.0);
+        osc.note_on(Note::from(69)).unwrap();
+        osc.evaluate(1, delta_time).unwrap();
+        let note = osc.voices.notes[0];
+        assert_approx_eq!(note.phase, 440.0 * delta_time, 1e-5);
+        let mut sides = [note.unison_phases[0], note.unison_phases[1]];
+        sides.sort_by(f32::total_cmp);
+        assert_approx_eq!(sides[0], 415.3 * delta_time, 1e-5);
+        assert_approx_eq!(sides[1], 466.16 * delta_time, 1e-5);
+
+        let voices = osc.unison();
+        assert_approx_eq!(voices[0].gain, 0.5_f32.sqrt());
+        assert_approx_eq!(voices[1].gain, 0.5);
+        assert_approx_eq!(voices[1].pan.abs(), 0.5);
+        osc.unison_blend().write().unwrap().set_value(1.0);
+        osc.unison_voices().write().unwrap().set_value(2);
+        let voices = osc.unison();
+        assert!(voices
+            .iter()
+            .all(|v| (v.gain - 0.5_f32.sqrt()).abs() < 1e-6));
+        osc.unison_voices().write().unwrap().set_value(4);
+        let voices = osc.unison();
+        assert_approx_eq!(voices[0].gain, 0.0);
+        assert_approx_eq!(voices[3].gain, 0.5_f32.sqrt());
+    }
+
+    #[test]
+    fn test_osc_unison_pan() {
+        let mut osc = build_osc();
+        osc.unison_voices().write().unwrap().set_value(2);
+        osc.unison_spread().write().unwrap().set_value(0.0);
+        {
+            let pan = osc.pan();
+            let mut pan = pan.write().unwrap();
+            let container = pan.container_mut();
+            container
+                .modulators
+                .push(make_shared(ControllerSource::new(1.0)));
+            container.modulation_range = (-1.0, 1.0);
+        }
+        osc.note_on(Note::from(69)).unwrap();
+        osc.evaluate(10, 1.0 / 44100.0).unwrap();
+        // Modulated pan moves both unison voices hard right from the first sample
+        let buffer = osc.get_buffer();
+        assert!(buffer.iter(0).unwrap().all(|s| s.abs() < 1e-6));
+        assert!(buffer.iter(1).unwrap().any(|s| s != 0.0));
+    }
+
+    #[test]
+    fn test_osc_voice_modes() {
+        let mut osc = build_osc();
+        osc.set_voice_mode(VoiceMode::Mono);
+        osc.note_on(Note::from(60)).unwrap();
+        osc.note_on(Note::from(64)).unwrap();
+        assert!(!osc.is_playing(60));
+        assert!(osc.is_playing(64));
+        osc.note_off(64).unwrap();
+        assert!(osc.is_playing(60));
+        osc.note_off(60).unwrap();
+        assert!(osc.voices.notes.is_empty());
+
+        let mut osc = build_osc();
+        osc.set_voice_mode(VoiceMode::Legato);
+        osc.note_on(Note::from(60)).unwrap();
+        osc.evaluate(1, 0.01).unwrap();
+        osc.note_on(Note::from(64)).unwrap();
+        assert!(osc.is_playing(64));
+        assert_eq!(osc.voices.notes.len(), 1);
+        assert!(osc.voices.notes[0].play_time > 0.0);
+
+        let mut osc = build_osc();
+        osc.set_voice_mode(VoiceMode::Mono)
+            .set_note_priority(NotePriority::Low);
+        osc.note_on(Note::from(60)).unwrap();
+        osc.note_on(Note::from(64)).unwrap();
+        assert!(osc.is_playing(60));
+        osc.note_on(Note::from(55)).unwrap();
+        assert!(osc.is_playing(55));
+        osc.note_off(55).unwrap();
+        assert!(osc.is_playing(60));
+    }
+
+    #[test]
+    fn test_osc_glide() {
+        let mut osc = build_osc();
+        osc.set_voice_mode(VoiceMode::Mono);
+        osc.glide_time().write().unwrap().set_value(0.1);
+        osc.note_on(Note::from(60)).unwrap();
+        osc.note_on(Note::from(72)).unwrap();
+        assert_approx_eq!(osc.voices.notes[0].glide, -12.0);
+        osc.evaluate(1, 0.05).unwrap();
+        assert_approx_eq!(osc.voices.notes[0].glide, -6.0, 1e-4);
+        osc.evaluate(1, 0.1).unwrap();
+        assert_approx_eq!(osc.voices.notes[0].glide, 0.0);
+
+        osc.set_glide_mode(GlideMode::Rate);
+        osc.note_on(Note::from(66)).unwrap();
+        assert_approx_eq!(osc.voices.notes[0].glide_rate, 120.0);
+    }
+
+    fn modulator(kind: CrossModulationKind, value: f32, wrap: bool) -> Modulator {
+        let voice = VoiceSignal {
+            channel: None,
+            note: 69,
+            samples: vec![value; 10],
+            wraps: vec![wrap; 10],
+        };
+        Modulator {
+            kind,
+            index: 1.0,
+            signals: VoiceSignals {
+                voices: vec![voice],
+            },
+        }
+    }
+
+    #[test]
+    fn test_osc_cross_modulation() {
+        let delta_time = 1.0 / 44100.0;
+        let mut osc = build_osc();
+        osc.record_voices(true);
+        osc.set_modulators(vec![modulator(CrossModulationKind::Frequency, 1.0, false)]);
+        osc.note_on(Note::from(69)).unwrap();
+        osc.evaluate(1, delta_time).unwrap();
+        assert_approx_eq!(osc.voices.notes[0].phase, 880.0 * delta_time, 1e-5);
+        assert_eq!(osc.voice_signals().unwrap().voices[0].samples.len(), 1);
+
+        osc.set_modulators(vec![modulator(CrossModulationKind::Sync, 0.0, true)]);
+        osc.evaluate(1, delta_time).unwrap();
+        assert_approx_eq!(osc.voices.notes[0].phase, 440.0 * delta_time, 1e-5);
+
+        osc.set_modulators(vec![modulator(CrossModulationKind::Ring, 0.0, false)]);
+        osc.evaluate(10, delta_time).unwrap();
+        assert!(osc.voice_signals().unwrap().voices[0]
+            .samples
+            .iter()
+            .all(|s| *s == 0.0));
+
+        // Voice of modulator modulates only the voice of its member channel
+        let mut osc = build_osc();
+        let mut modulator = modulator(CrossModulationKind::Frequency, 1.0, false);
+        modulator.signals.voices[0].channel = Some(2);
+        osc.set_modulators(vec![modulator]);
+        let member = |channel| Note {
+            channel: Some(channel),
+            ..Note::from(69)
+        };
+        osc.note_on(member(2)).unwrap();
+        osc.note_on(member(3)).unwrap();
+        osc.evaluate(1, delta_time).unwrap();
+        assert_approx_eq!(osc.voices.notes[0].phase, 880.0 * delta_time, 1e-5);
+        assert_approx_eq!(osc.voices.notes[1].phase, 440.0 * delta_time, 1e-5);
+    }
+
+    #[test]
+    fn test_osc_sustain() {
+        let mut osc = build_osc();
+        osc.note_on(Note::from(60)).unwrap();
+        osc.sustain(true).unwrap();
+        osc.note_off(60).unwrap();
+        osc.note_on(Note::from(64)).unwrap();
+        osc.note_off(64).unwrap();
+        assert!(osc.is_playing(60));
+        assert!(osc.is_playing(64));
+        osc.note_on(Note::from(64)).unwrap();
+        osc.sustain(false).unwrap();
+        assert!(!osc.is_playing(60));
+        assert!(osc.is_playing(64));
+        osc.note_off(64).unwrap();
+        assert!(!osc.is_playing(64));
+    }
+
+    #[test]
+    fn test_osc_member_sustain() {
+        let mut osc = build_osc();
+        let member = |channel| Note {
+            channel: Some(channel),
+            ..Note::from(60)
+        };
+        osc.note_on(member(3)).unwrap();
+        osc.note_on(member(5)).unwrap();
+        osc.sustain(true).unwrap();
+        osc.member_note_off(3, 60).unwrap();
+        osc.sustain(false).unwrap();
+        // Pedal up releases the key only on the channel it was released on
+        assert_eq!(osc.voices.notes.len(), 1);
+        assert_eq!(osc.voices.notes[0].channel, Some(5));
+
+        osc.sostenuto(true).unwrap();
+        osc.note_on(member(3)).unwrap();
+        osc.member_note_off(3, 60).unwrap();
+        osc.member_note_off(5, 60).unwrap();
+        assert_eq!(osc.voices.notes.len(), 1);
+        osc.sostenuto(false).unwrap();
+        assert!(!osc.is_playing(60));
+    }
+
+    #[test]
+    fn test_osc_sostenuto() {
+        let mut osc = build_osc();
+        osc.note_on(Note::from(60)).unwrap();
+        osc.sostenuto(true).unwrap();
+        osc.note_on(Note::from(64)).unwrap();
+        osc.note_off(60).unwrap();
+        osc.note_off(64).unwrap();
+        assert!(osc.is_playing(60));
+        assert!(!osc.is_playing(64));
+        osc.sustain(true).unwrap();
+        osc.sostenuto(false).unwrap();
+        assert!(osc.is_playing(60));
+        osc.sustain(false).unwrap();
+        assert!(!osc.is_playing(60));
+
+        // Every held key is caught, not only the one mono voice sounds
+        osc.set_voice_mode(VoiceMode::Mono);
+        osc.note_on(Note::from(60)).unwrap();
+        osc.note_on(Note::from(64)).unwrap();
+        osc.sostenuto(true).unwrap();
+        osc.note_off(60).unwrap();
+        osc.note_off(64).unwrap();
+        assert_eq!(osc.voices.held.len(), 2);
+        assert!(osc.is_playing(64));
+        osc.sostenuto(false).unwrap();
+        assert!(osc.voices.notes.is_empty());
+    }
+
+    fn level(osc: &WavetableOscillator, note: &Note, t: f32) -> f32 {
+        WavetableOscillator::envelope_value_at(t, note, osc.envelope.clone())
+    }
+
+    #[test]
+    fn test_osc_early_release() {
+        let mut osc = build_osc();
+        osc.note_on(Note::from(60)).unwrap();
+        osc.evaluate(1, 0.05).unwrap();
+        osc.note_off(60).unwrap();
+        // Released halfway through attack, release falls from there instead of jumping to peak
+        let note = osc.voices.release_notes[0];
+        assert_approx_eq!(level(&osc, &note, 0.05), 0.5, 1e-4);
+        assert_approx_eq!(level(&osc, &note, 0.1), 0.25, 1e-4);
+        assert_approx_eq!(level(&osc, &note, 0.15), 0.0);
+        osc.evaluate(1, 0.05).unwrap();
+        assert_eq!(osc.voices.release_notes.len(), 1);
+        osc.evaluate(1, 0.05).unwrap();
+        osc.evaluate(1, 0.0).unwrap();
+        assert!(osc.voices.release_notes.is_empty());
+
+        // Note held long after sustain stage is released from sustain level
+        osc.note_on(Note::from(60)).unwrap();
+        osc.evaluate(1, 2.0).unwrap();
+        osc.note_off(60).unwrap();
+        let note = osc.voices.release_notes[0];
+        assert_approx_eq!(level(&osc, &note, 2.0), 0.5, 1e-4);
+        assert_approx_eq!(level(&osc, &note, 2.05), 0.25, 1e-4);
+        osc.evaluate(1, 0.05).unwrap();
+        assert_eq!(osc.voices.release_notes.len(), 1);
+    }
+
+    #[test]
+    fn test_osc_retrigger_level() {
+        let mut osc = build_osc();
+        osc.set_voice_mode(VoiceMode::Mono);
+        osc.note_on(Note::from(60)).unwrap();
+        osc.evaluate(1, 0.05).unwrap();
+        let phase = osc.voices.notes[0].phase;
+        osc.note_on(Note::from(64)).unwrap();
+        // Retriggered voice keeps its phase and rises from the level it has reached
+        let note = osc.voices.notes[0];
+        assert!(osc.voices.release_notes.is_empty());
+        assert_approx_eq!(note.phase, phase);
+        assert_approx_eq!(note.start_level, 0.5, 1e-4);
+        assert_approx_eq!(level(&osc, &note, 0.0), 0.5, 1e-4);
+        assert_approx_eq!(level(&osc, &note, 0.05), 0.75, 1e-4);
+        assert_approx_eq!(level(&osc, &note, 0.1), 1.0, 1e-4);
+        assert_approx_eq!(level(&osc, &note, 0.2), 0.75, 1e-4);
+    }
 }
@@ -48,13 +48,54 @@ pub trait NamedParametersContainer {
     fn parameters_f32(&self) -> Option<&[NamedParameter<f32>]> {
         None
     }
-    fn parameters_i32(&self) -> Option<&[NamedParameter<f32>]> {
+    fn parameters_i32(&self) -> Option<&[NamedParameter<i32>]> {
         None
     }
 }
 
 pub type SharedParameter<T> = Shared<dyn Parameter<T>>;
 
+pub struct PathParameter<T>
+where
+    T: Clone,
+{
+    pub path: String,
+    pub parameter: SharedParameter<T>,
+}
+
+pub fn parameters_paths(
+    container: &dyn NamedParametersContainer,
+    index: usize,
+) -> Vec<PathParameter<f32>> {
+    paths(container, container.parameters_f32(), index)
+}
+
+pub fn parameters_paths_i32(
+    container: &dyn NamedParametersContainer,
+    index: usize,
+) -> Vec<PathParameter<i32>> {
+    paths(container, container.parameters_i32(), index)
+}
+
+fn paths<T: Clone>(
+    container: &dyn NamedParametersContainer,
+    parameters: Option<&[NamedParameter<T>]>,
+    index: usize,
+) -> Vec<PathParameter<T>> {
+    let name = container.name().unwrap_or("Unnamed");
+    parameters
+        .map(|parameters| {
+            parameters
+                .iter()
+                .map(|p| PathParameter {
+                    path: format!("{}{}/{}", name, index + 1, p.name),
+                    parameter: p.parameter.clone(),
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
 pub struct ValueParameter<T>
 where
     T: Clone,
@@ -0,0 +1,1161 @@
+use std::{fs, path::Path};
+
+use rayon::prelude::*;
+
+use crate::{
+    effects::{amplifier::Amplifier, Effect, State},
+    error::Error,
+    utils::{
+        adsr_envelope::ADSREnvelope,
+        evaluate::{Evaluate, Modulation},
+        sample_buffer::{SampleBuffer, SampleBufferBuilder, SampleBufferMono},
+        Shared,
+    },
+};
+
+use super::{
+    cross_modulation::{evaluation_order, CrossModulation, CrossModulationKind, Modulator},
+    lfo::LFO,
+    modulation_config::{ControllerTarget, ModulationConfig},
+    note::{Expression, Note},
+    oscillator::{ExpressionSource, Oscillator, WavetableOscillator},
+    parameter::{
+        parameters_paths, parameters_paths_i32, NamedParameter, NamedParametersContainer,
+        PanParameter, PathParameter, SharedParameter, ValueParameter, VolumeParameter,
+    },
+    preset::{PreparedPreset, ENVELOPE_SUFFIX, WAVETABLE_SUFFIX},
+    synthesizer::{LockedEffect, LockedOscillator},
+    tuning::Tuning,
+    wavetable::WaveTable,
+};
+
+// Source of midi event, track is known only for events from SMF playback
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Route {
+    pub channel: u8,
+    pub track: Option<usize>,
+}
+
+impl Route {
+    pub fn channel(channel: u8) -> Self {
+        Self {
+            channel,
+            track: None,
+        }
+    }
+
+    pub fn track(channel: u8, track: usize) -> Self {
+        Self {
+            channel,
+            track: Some(track),
+        }
+    }
+}
+
+// Oscillators added to part besides its wavetable oscillators
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PartSource {
+    Noise,
+    Sub,
+    Additive,
+    Sampler,
+}
+
+impl PartSource {
+    pub fn parse(name: &str) -> Result<Self, Error> {
+        match name {
+            "noise" => Ok(PartSource::Noise),
+            "sub" => Ok(PartSource::Sub),
+            "additive" => Ok(PartSource::Additive),
+            "sampler" => Ok(PartSource::Sampler),
+            _ => Err(format!("Invalid part source \"{}\"", name).into()),
+        }
+    }
+}
+
+// Channels and tracks of part in form shown by UI, e.g. "ch:1,2 tr:0" or "omni",
+// channels are counted from 1. Sources are listed as "src:noise,sub"
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct PartAssignment {
+    pub channels: Vec<u8>,
+    pub tracks: Vec<usize>,
+    pub sources: Vec<PartSource>,
+}
+
+impl PartAssignment {
+    pub fn parse(line: &str) -> Result<Self, Error> {
+        let mut assignment = Self::default();
+        for field in line.split_whitespace() {
+            match field.split_once(':') {
+                Some(("ch", values)) => {
+                    for value in values.split(',') {
+                        match value.parse::<u8>() {
+                            Ok(channel @ 1..=16) => assignment.channels.push(channel - 1),
+                            _ => return Err(format!("Invalid channel \"{}\"", value).into()),
+                        }
+                    }
+                }
+                Some(("tr", values)) => {
+                    for value in values.split(',') {
+                        let track = value
+                            .parse::<usize>()
+                            .map_err(|e| format!("Invalid track \"{}\": {}", value, e))?;
+                        assignment.tracks.push(track);
+                    }
+                }
+                Some(("src", values)) => {
+                    for value in values.split(',') {
+                        assignment.sources.push(PartSource::parse(value)?);
+                    }
+                }
+                None if field == "omni" => (),
+                _ => return Err(format!("Invalid part field \"{}\"", field).into()),
+            }
+        }
+        Ok(assignment)
+    }
+
+    // One part per line, empty lines and lines starting with # are skipped
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<Self>, Error> {
+        fs::read_to_string(path)?
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(Self::parse)
+            .collect()
+    }
+}
+
+// Parameter moved by key of every note, only the offset of the last note is replaced,
+// so edits of the parameter between notes are kept
+struct KeyTracking {
+    parameter: SharedParameter<f32>,
+    depth: f32,
+    offset: f32,
+}
+
+impl KeyTracking {
+    fn note_on(&mut self, note: &Note) {
+        let offset = ExpressionSource::Key.value(note) * self.depth;
+        let mut parameter = self.parameter.write().unwrap();
+        let value = parameter.get_value() - self.offset + offset;
+        parameter.set_value(value);
+        self.offset = offset;
+    }
+}
+
+// Smoothly moves parameters to new values, used for switching patches without clicks
+struct ParameterMorph {
+    targets: Vec<(SharedParameter<f32>, f32, f32)>,
+    time: f32,
+    duration: f32,
+}
+
+impl ParameterMorph {
+    // Returns true when all parameters reached their targets
+    fn next(&mut self, delta_time: f32) -> bool {
+        self.time += delta_time;
+        let t = match self.duration > 0.0 {
+            true => (self.time / self.duration).min(1.0),
+            false => 1.0,
+        };
+        self.targets.iter().for_each(|(parameter, from, to)| {
+            parameter.write().unwrap().set_value(from + (to - from) * t);
+        });
+        t >= 1.0
+    }
+}
+
+// Shapes and integer values of preset, exchanged with those of part at once.
+// Replaced data stays here, so it is freed when the next preset is applied
+struct PresetSwap {
+    wavetables: Vec<(Shared<WaveTable>, SampleBufferMono, usize)>,
+    envelopes: Vec<(Shared<ADSREnvelope>, ADSREnvelope)>,
+    integers: Vec<(SharedParameter<i32>, i32)>,
+}
+
+impl PresetSwap {
+    fn swap(&mut self) -> Result<(), Error> {
+        self.wavetables
+            .iter_mut()
+            .for_each(|(wavetable, buffer, chunk_size)| {
+                wavetable.write().unwrap().swap_samples(buffer, chunk_size);
+            });
+        self.envelopes
+            .iter_mut()
+            .try_for_each(|(envelope, prepared)| envelope.write().unwrap().swap_shape(prepared))?;
+        self.integers.iter().for_each(|(parameter, value)| {
+            parameter.write().unwrap().set_value(*value);
+        });
+        Ok(())
+    }
+}
+
+// Fades part output out, swaps preset in while it is silent and fades it back in
+struct PresetSwitch {
+    swap: PresetSwap,
+    fade: f32,
+    time: f32,
+    swapped: bool,
+}
+
+impl PresetSwitch {
+    fn level(&self, time: f32) -> f32 {
+        let t = (time / self.fade).min(1.0);
+        match self.swapped {
+            true => t,
+            false => 1.0 - t,
+        }
+    }
+
+    fn is_silent(&self) -> bool {
+        !self.swapped && self.time >= self.fade
+    }
+
+    fn is_done(&self) -> bool {
+        self.swapped && self.time >= self.fade
+    }
+
+    fn process(&mut self, size: usize, delta_time: f32, buffer: &mut SampleBuffer) {
+        buffer.iter_buffers().for_each(|buffer| {
+            buffer
+                .iter_mut()
+                .take(size)
+                .enumerate()
+                .for_each(|(i, s)| *s *= self.level(self.time + i as f32 * delta_time));
+        });
+        self.time += size as f32 * delta_time;
+    }
+}
+
+pub(crate) fn sound_parameters<'a>(
+    oscillators: impl Iterator<Item = LockedOscillator>,
+    effects: impl Iterator<Item = LockedEffect>,
+    modulations: impl Iterator<Item = &'a CrossModulation>,
+) -> Vec<PathParameter<f32>> {
+    let oscillators = oscillators.enumerate().flat_map(|(i, osc)| {
+        let mut osc = osc.write().unwrap();
+        osc.parameters()
+            .map(|p| parameters_paths(p, i))
+            .unwrap_or_default()
+    });
+    let effects = effects.enumerate().flat_map(|(i, effect)| {
+        let mut effect = effect.write().unwrap();
+        effect
+            .parameters()
+            .map(|p| parameters_paths(p, i))
+            .unwrap_or_default()
+    });
+    let modulations = modulations
+        .enumerate()
+        .flat_map(|(i, modulation)| parameters_paths(modulation, i));
+    oscillators.chain(effects).chain(modulations).collect()
+}
+
+// Integer parameters like octave or voice mode, only oscillators have them
+pub(crate) fn sound_parameters_i32(
+    oscillators: impl Iterator<Item = LockedOscillator>,
+) -> Vec<PathParameter<i32>> {
+    oscillators
+        .enumerate()
+        .flat_map(|(i, osc)| {
+            let mut osc = osc.write().unwrap();
+            osc.parameters()
+                .map(|p| parameters_paths_i32(p, i))
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+pub struct Part {
+    buffer: SampleBuffer,
+    oscillators: Vec<LockedOscillator>,
+    effects: Vec<LockedEffect>,
+    mixer: Amplifier,
+    channels: Vec<u8>,
+    tracks: Vec<usize>,
+    parameters_f32: Vec<NamedParameter<f32>>,
+    morph: Option<ParameterMorph>,
+    modulations: Vec<CrossModulation>,
+    // Sources are evaluated before their targets
+    order: Vec<usize>,
+    // Oscillators with own tuning instead of synthesizer one
+    tunings: Vec<(usize, Shared<Tuning>)>,
+    // LFOs modulating shared parameters, retriggered by notes of this part
+    lfos: Vec<Shared<LFO>>,
+    key_tracking: Vec<KeyTracking>,
+    switch: Option<PresetSwitch>,
+    // Data replaced by the last preset, freed outside of audio thread
+    retired: Option<PresetSwap>,
+}
+
+impl Part {
+    pub fn output(&mut self, size: usize, delta_time: f32) -> Result<&SampleBuffer, Error> {
+        if let Some(morph) = self.morph.as_mut() {
+            if morph.next(size as f32 * delta_time) {
+                self.morph = None;
+            }
+        }
+        if let Some(switch) = self.switch.as_mut().filter(|s| s.is_silent()) {
+            switch.swap.swap()?;
+            switch.swapped = true;
+            switch.time = 0.0;
+        }
+        match self.modulations.is_empty() {
+            true => self
+                .oscillators
+                .par_iter_mut()
+                .try_for_each(|osc| -> Result<(), Error> {
+                    osc.write().unwrap().evaluate(size, delta_time)
+                })?,
+            false => self.evaluate_modulated(size, delta_time)?,
+        }
+        let buffer = &mut self.buffer;
+        buffer.fill(0.);
+        self.oscillators
+            .iter()
+            .try_for_each(|osc| -> Result<(), Error> {
+                buffer.combine(osc.write().unwrap().get_buffer())
+            })?;
+        self.effects
+            .iter_mut()
+            .try_for_each(|effect| -> Result<(), Error> {
+                effect.write().unwrap().process(size, buffer)
+            })?;
+        self.mixer.process(size, buffer)?;
+        if let Some(switch) = self.switch.as_mut() {
+            switch.process(size, delta_time, buffer);
+            if switch.is_done() {
+                self.retired = self.switch.take().map(|s| s.swap);
+            }
+        }
+        Ok(&self.buffer)
+    }
+
+    fn evaluate_modulated(&self, size: usize, delta_time: f32) -> Result<(), Error> {
+        self.order
+            .iter()
+            .try_for_each(|&target| -> Result<(), Error> {
+                let modulators = self
+                    .modulations
+                    .iter()
+                    .filter(|m| m.target == target)
+                    .map(|m| Modulator {
+                        kind: m.kind,
+                        index: m.index().read().unwrap().get_value(),
+                        signals: self.oscillators[m.source]
+                            .read()
+                            .unwrap()
+                            .voice_signals()
+                            .cloned()
+                            .unwrap_or_default(),
+                    })
+                    .collect();
+                let mut osc = self.oscillators[target].write().unwrap();
+                osc.set_modulators(modulators);
+                osc.evaluate(size, delta_time)
+            })
+    }
+
+    pub fn modulations(&self) -> &[CrossModulation] {
+        &self.modulations
+    }
+
+    // Source modulates target, both are indices of oscillators in this part
+    pub fn add_cross_modulation(
+        &mut self,
+        source: usize,
+        target: usize,
+        kind: CrossModulationKind,
+        index: f32,
+    ) -> Result<&mut Self, Error> {
+        let count = self.oscillators.len();
+        if source >= count || target >= count {
+            return Err(format!("Oscillator {} or {} not found", source, target).into());
+        }
+        let mut modulations = std::mem::take(&mut self.modulations);
+        modulations.push(CrossModulation::new(source, target, kind, index));
+        match evaluation_order(count, &modulations) {
+            Ok(order) => self.order = order,
+            Err(e) => {
+                modulations.pop();
+                self.modulations = modulations;
+                return Err(e);
+            }
+        }
+        self.modulations = modulations;
+        self.oscillators[source]
+            .write()
+            .unwrap()
+            .record_voices(true);
+        Ok(self)
+    }
+
+    pub fn oscillator_tuning(&self, index: usize) -> Option<Shared<Tuning>> {
+        self.tunings
+            .iter()
+            .find(|(i, _)| *i == index)
+            .map(|(_, tuning)| tuning.clone())
+    }
+
+    pub fn lfos(&self) -> &[Shared<LFO>] {
+        &self.lfos
+    }
+
+    pub fn add_lfo(&mut self, lfo: Shared<LFO>) -> &mut Self {
+        self.lfos.push(lfo);
+        self
+    }
+
+    pub fn apply_modulation(&mut self, config: &ModulationConfig) -> Result<(), Error> {
+        let oscillator = |index: usize| {
+            self.oscillators
+                .get(index)
+                .ok_or(format!("Oscillator {} not found", index + 1))
+        };
+        match config {
+            ModulationConfig::KeyTracking {
+                oscillator: index,
+                target,
+                depth,
+                ..
+            } => {
+                oscillator(*index)?
+                    .write()
+                    .unwrap()
+                    .as_any_mut()
+                    .downcast_mut::<WavetableOscillator>()
+                    .ok_or("Key tracking is supported by wavetable oscillators only")?
+                    .add_expression_route(ExpressionSource::Key, *target, *depth);
+            }
+            ModulationConfig::ParameterKeyTracking { path, depth, .. } => {
+                let parameter = self
+                    .get_named_parameters()
+                    .into_iter()
+                    .find(|p| p.path == *path)
+                    .ok_or(format!("Parameter \"{}\" not found", path))?
+                    .parameter;
+                self.key_tracking.push(KeyTracking {
+                    parameter,
+                    depth: *depth,
+                    offset: 0.0,
+                });
+            }
+            ModulationConfig::Velocity {
+                oscillator: index,
+                curve,
+                ..
+            } => {
+                oscillator(*index)?
+                    .write()
+                    .unwrap()
+                    .velocity_response()
+                    .ok_or("Oscillator has no velocity response")?
+                    .set_curve(curve.clone());
+            }
+            ModulationConfig::CrossModulation {
+                source,
+                target,
+                kind,
+                index,
+                ..
+            } => {
+                self.add_cross_modulation(*source, *target, *kind, *index)?;
+            }
+            ModulationConfig::Controller { .. } => {
+                return Err("Controller modulation is connected by MIDI mediator".into())
+            }
+            ModulationConfig::Lfo {
+                lfo,
+                oscillator,
+                target,
+                range,
+                ..
+            } => {
+                let lfo = self
+                    .lfos
+                    .get(*lfo)
+                    .ok_or(format!("LFO {} not found", lfo + 1))?
+                    .clone();
+                self.add_controller_modulation(*oscillator, *target, lfo, *range)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Source replaces value of target parameter while connected
+    pub fn add_controller_modulation(
+        &mut self,
+        index: usize,
+        target: ControllerTarget,
+        source: Shared<dyn Evaluate<f32>>,
+        range: (f32, f32),
+    ) -> Result<(), Error> {
+        let mut osc = self
+            .oscillators
+            .get(index)
+            .ok_or(format!("Oscillator {} not found", index + 1))?
+            .write()
+            .unwrap();
+        let osc = osc
+            .as_any_mut()
+            .downcast_mut::<WavetableOscillator>()
+            .ok_or("Controller modulation is supported by wavetable oscillators only")?;
+        let connect = |parameter: &mut dyn Modulation| {
+            let container = parameter.container_mut();
+            container.modulators.push(source);
+            container.modulation_range = range;
+        };
+        match target {
+            ControllerTarget::Pan => connect(&mut *osc.pan().write().unwrap()),
+            ControllerTarget::Cents => connect(&mut *osc.cents_offset().write().unwrap()),
+        }
+        Ok(())
+    }
+
+    // Notes are retuned for oscillator, None returns it to synthesizer tuning
+    pub fn set_oscillator_tuning(
+        &mut self,
+        index: usize,
+        tuning: Option<Shared<Tuning>>,
+    ) -> Result<&mut Self, Error> {
+        if index >= self.oscillators.len() {
+            return Err(format!("Oscillator {} not found", index).into());
+        }
+        self.tunings.retain(|(i, _)| *i != index);
+        if let Some(tuning) = tuning {
+            self.tunings.push((index, tuning));
+        }
+        Ok(self)
+    }
+
+    pub fn clear_cross_modulations(&mut self) {
+        self.modulations.clear();
+        self.oscillators.iter().for_each(|osc| {
+            let mut osc = osc.write().unwrap();
+            osc.record_voices(false);
+            osc.set_modulators(vec![]);
+        });
+    }
+
+    // Part without channels listens to every channel (omni)
+    pub fn accepts(&self, route: Route) -> bool {
+        match route.track {
+            Some(track) => self.tracks.contains(&track),
+            None => self.channels.is_empty() || self.channels.contains(&route.channel),
+        }
+    }
+
+    pub fn channels(&self) -> &[u8] {
+        &self.channels
+    }
+
+    pub fn set_channels(&mut self, channels: Vec<u8>) -> &mut Self {
+        self.channels = channels;
+        self
+    }
+
+    pub fn tracks(&self) -> &[usize] {
+        &self.tracks
+    }
+
+    pub fn set_tracks(&mut self, tracks: Vec<usize>) -> &mut Self {
+        self.tracks = tracks;
+        self
+    }
+
+    pub fn volume(&self) -> Shared<VolumeParameter> {
+        self.mixer.volume()
+    }
+
+    pub fn pan(&self) -> Shared<PanParameter> {
+        self.mixer.pan()
+    }
+
+    pub fn oscillators(&self) -> impl Iterator<Item = LockedOscillator> + '_ {
+        self.oscillators.iter().cloned()
+    }
+
+    pub fn effects(&self) -> impl Iterator<Item = LockedEffect> + '_ {
+        self.effects.iter().cloned()
+    }
+
+    // Parameters of oscillators, effects and LFOs with paths local to this part, mixer is excluded
+    pub fn get_named_parameters(&self) -> Vec<PathParameter<f32>> {
+        let lfos = self.lfo_parameters(parameters_paths);
+        sound_parameters(self.oscillators(), self.effects(), self.modulations.iter())
+            .into_iter()
+            .chain(lfos)
+            .collect()
+    }
+
+    pub fn get_named_parameters_i32(&self) -> Vec<PathParameter<i32>> {
+        let lfos = self.lfo_parameters(parameters_paths_i32);
+        sound_parameters_i32(self.oscillators())
+            .into_iter()
+            .chain(lfos)
+            .collect()
+    }
+
+    fn lfo_parameters<T: Clone>(
+        &self,
+        paths: fn(&dyn NamedParametersContainer, usize) -> Vec<PathParameter<T>>,
+    ) -> Vec<PathParameter<T>> {
+        self.lfos
+            .iter()
+            .enumerate()
+            .flat_map(|(i, lfo)| paths(&*lfo.read().unwrap(), i))
+            .collect()
+    }
+
+    // Paths like "Oscillator1/Envelope", numbered the same way as parameters
+    pub fn get_named_envelopes(&self) -> Vec<(String, Shared<ADSREnvelope>)> {
+        self.oscillator_shapes(ENVELOPE_SUFFIX, |osc| osc.shared_envelope())
+    }
+
+    pub fn get_named_wavetables(&self) -> Vec<(String, Shared<WaveTable>)> {
+        self.oscillator_shapes(WAVETABLE_SUFFIX, |osc| osc.shared_wavetable())
+    }
+
+    fn oscillator_shapes<T: ?Sized>(
+        &self,
+        suffix: &str,
+        shape: impl Fn(&dyn Oscillator) -> Option<Shared<T>>,
+    ) -> Vec<(String, Shared<T>)> {
+        self.oscillators
+            .iter()
+            .enumerate()
+            .filter_map(|(i, osc)| {
+                let mut osc = osc.write().unwrap();
+                let shape = shape(&*osc)?;
+                let name = osc.parameters().and_then(|p| p.name()).unwrap_or("Unnamed");
+                Some((format!("{}{}{}", name, i + 1, suffix), shape))
+            })
+            .collect()
+    }
+
+    // Float parameters are crossfaded, shapes and integer parameters are swapped in
+    // while output faded out in the middle of crossfade
+    pub fn apply_preset(&mut self, preset: PreparedPreset, crossfade: f32) -> Result<(), Error> {
+        self.retired = None;
+        let PreparedPreset {
+            values,
+            envelopes,
+            wavetables,
+        } = preset;
+        let named_wavetables = self.get_named_wavetables();
+        let wavetables = wavetables
+            .into_iter()
+            .filter_map(|(path, buffer, chunk_size)| {
+                let (_, wavetable) = named_wavetables.iter().find(|(p, _)| *p == path)?;
+                Some((wavetable.clone(), buffer, chunk_size))
+            })
+            .collect();
+        let named_envelopes = self.get_named_envelopes();
+        let envelopes = envelopes
+            .into_iter()
+            .filter_map(|(path, prepared)| {
+                let (_, envelope) = named_envelopes.iter().find(|(p, _)| *p == path)?;
+                Some((envelope.clone(), prepared))
+            })
+            .collect();
+        let integers = self
+            .get_named_parameters_i32()
+            .into_iter()
+            .filter_map(|p| {
+                let (_, value) = values.iter().find(|(path, _)| *path == p.path)?;
+                Some((p.parameter, value.round() as i32))
+            })
+            .collect();
+        let mut swap = PresetSwap {
+            wavetables,
+            envelopes,
+            integers,
+        };
+        // Switch in progress continues fading out from its current level
+        let level = self
+            .switch
+            .as_ref()
+            .map_or(1.0, |switch| switch.level(switch.time));
+        let fade = crossfade * 0.5;
+        match fade > 0.0 {
+            true => {
+                self.switch = Some(PresetSwitch {
+                    swap,
+                    fade,
+                    time: (1.0 - level) * fade,
+                    swapped: false,
+                })
+            }
+            false => {
+                self.switch = None;
+                swap.swap()?;
+                self.retired = Some(swap);
+            }
+        }
+        self.morph_to(&values, crossfade);
+        Ok(())
+    }
+
+    pub fn morph_to(&mut self, values: &[(String, f32)], duration: f32) {
+        let targets = self
+            .get_named_parameters()
+            .into_iter()
+            .filter_map(|p| {
+                let (_, to) = values.iter().find(|(path, _)| *path == p.path)?;
+                let from = p.parameter.read().unwrap().get_value();
+                Some((p.parameter, from, *to))
+            })
+            .collect();
+        self.morph = Some(ParameterMorph {
+            targets,
+            time: 0.0,
+            duration,
+        });
+    }
+
+    pub fn release_all(&mut self) {
+        self.oscillators.par_iter_mut().for_each(|osc| {
+            osc.write().unwrap().release_all();
+        })
+    }
+
+    pub fn note_on(&mut self, note: Note) -> Result<(), Error> {
+        self.lfos
+            .iter()
+            .for_each(|lfo| lfo.read().unwrap().note_on());
+        self.key_tracking
+            .iter_mut()
+            .for_each(|tracking| tracking.note_on(&note));
+        let tunings = &self.tunings;
+        self.oscillators.par_iter_mut().enumerate().try_for_each(
+            |(index, osc)| -> Result<(), Error> {
+                let note = match tunings.iter().find(|(i, _)| *i == index) {
+                    Some((_, tuning)) => tuning.read().unwrap().tune(note),
+                    None => Some(note),
+                };
+                match note {
+                    Some(note) => osc.write().unwrap().note_on(note),
+                    None => Ok(()),
+                }
+            },
+        )
+    }
+
+    pub fn note_off(&mut self, note: u32) -> Result<(), Error> {
+        self.oscillators
+            .par_iter_mut()
+            .try_for_each(|osc| -> Result<(), Error> { osc.write().unwrap().note_off(note) })
+    }
+
+    pub fn pitch_bend(&mut self, value: f32) {
+        self.oscillators.par_iter_mut().for_each(|osc| {
+            osc.write().unwrap().pitch_bend(value);
+        })
+    }
+
+    pub fn member_note_off(&mut self, channel: u8, note: u32) -> Result<(), Error> {
+        self.oscillators
+            .par_iter_mut()
+            .try_for_each(|osc| -> Result<(), Error> {
+                osc.write().unwrap().member_note_off(channel, note)
+            })
+    }
+
+    pub fn note_expression(&mut self, channel: u8, note: u32, expression: Expression) {
+        self.oscillators.par_iter_mut().for_each(|osc| {
+            osc.write()
+                .unwrap()
+                .note_expression(channel, note, expression);
+        })
+    }
+
+    pub fn sustain(&mut self, hold: bool) -> Result<(), Error> {
+        self.oscillators
+            .par_iter_mut()
+            .try_for_each(|osc| -> Result<(), Error> { osc.write().unwrap().sustain(hold) })
+    }
+
+    pub fn sostenuto(&mut self, hold: bool) -> Result<(), Error> {
+        self.oscillators
+            .par_iter_mut()
+            .try_for_each(|osc| -> Result<(), Error> { osc.write().unwrap().sostenuto(hold) })
+    }
+}
+
+impl NamedParametersContainer for Part {
+    fn name(&self) -> Option<&'static str> {
+        Some("Part")
+    }
+
+    fn parameters_f32(&self) -> Option<&[NamedParameter<f32>]> {
+        Some(&self.parameters_f32)
+    }
+}
+
+#[derive(Default)]
+pub struct PartBuilder {
+    buffer: Option<SampleBuffer>,
+    oscillators: Vec<LockedOscillator>,
+    effects: Vec<LockedEffect>,
+    channels: Vec<u8>,
+    tracks: Vec<usize>,
+}
+
+impl PartBuilder {
+    pub fn new() -> Self {
+        Self {
+            buffer: None,
+            oscillators: vec![],
+            effects: vec![],
+            channels: vec![],
+            tracks: vec![],
+        }
+    }
+
+    pub fn set_buffer(&mut self, buffer_size: usize) -> Result<&mut Self, Error> {
+        self.buffer = Some(
+            SampleBufferBuilder::new()
+                .set_channels(2)
+                .set_samples(buffer_size)
+                .build()?,
+        );
+        Ok(self)
+    }
+
+    pub fn add_osc(&mut self, osc: LockedOscillator) -> &mut Self {
+        self.oscillators.push(osc);
+        self
+    }
+
+    pub fn add_effect(&mut self, effect: LockedEffect) -> &mut Self {
+        self.effects.push(effect);
+        self
+    }
+
+    pub fn add_channel(&mut self, channel: u8) -> &mut Self {
+        self.channels.push(channel);
+        self
+    }
+
+    pub fn add_track(&mut self, track: usize) -> &mut Self {
+        self.tracks.push(track);
+        self
+    }
+
+    pub fn assign(&mut self, assignment: &PartAssignment) -> &mut Self {
+        self.channels.extend(&assignment.channels);
+        self.tracks.extend(&assignment.tracks);
+        self
+    }
+
+    pub fn build(&mut self) -> Result<Part, Error> {
+        let buffer = self.buffer.take().ok_or(Error::Specify("buffer size"))?;
+        let volume = VolumeParameter::from(ValueParameter::new(0.0, (-96.0, 6.0)));
+        let pan = PanParameter::from(ValueParameter::new(0.0, (-1.0, 1.0)));
+        let mixer = Amplifier::new(volume, pan, State::Enabled);
+        let parameters_f32 = vec![
+            NamedParameter::new(mixer.volume(), "Volume"),
+            NamedParameter::new(mixer.pan(), "Pan"),
+        ];
+        Ok(Part {
+            buffer,
+            oscillators: std::mem::take(&mut self.oscillators),
+            effects: std::mem::take(&mut self.effects),
+            mixer,
+            channels: std::mem::take(&mut self.channels),
+            tracks: std::mem::take(&mut self.tracks),
+            parameters_f32,
+            morph: None,
+            modulations: vec![],
+            order: vec![],
+            tunings: vec![],
+            lfos: vec![],
+            key_tracking: vec![],
+            switch: None,
+            retired: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        core::{
+            cross_modulation::CrossModulationKind,
+            lfo::{LfoTrigger, LFO},
+            modulation_config::ModulationConfig,
+            note::Note,
+            oscillator::{ExpressionSource, OscillatorBuilder, WavetableOscillator},
+            preset::{PreparedPreset, Preset},
+            synthesizer::LockedOscillator,
+            tuning::Tuning,
+            velocity::VelocityCurve,
+            waveshape::WaveShape,
+            wavetable::WaveTableBuilder,
+        },
+        utils::{
+            adsr_envelope::ADSREnvelope, evaluate::Evaluate, interpolation::InterpolateMethod,
+            make_shared, sample_buffer::SampleBufferBuilder,
+        },
+    };
+    use assert_approx_eq::assert_approx_eq;
+
+    use super::{PartAssignment, PartBuilder, PartSource, Route};
+
+    fn build_osc() -> LockedOscillator {
+        let buffer = SampleBufferBuilder::new()
+            .set_channels(2)
+            .set_samples(10)
+            .build()
+            .unwrap();
+        let table = WaveTableBuilder::new()
+            .from_shape(WaveShape::Sin, 64)
+            .set_interpolation(InterpolateMethod::Linear)
+            .build()
+            .unwrap();
+        make_shared(
+            OscillatorBuilder::new()
+                .set_buffer(buffer)
+                .set_envelope(ADSREnvelope::default())
+                .set_wavetable(table)
+                .build()
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_part_routing() {
+        let mut part = PartBuilder::new().set_buffer(10).unwrap().build().unwrap();
+        assert!(part.accepts(Route::channel(5)));
+        part.set_channels(vec![1, 2]);
+        assert!(part.accepts(Route::channel(1)));
+        assert!(!part.accepts(Route::channel(5)));
+        assert!(!part.accepts(Route::track(1, 7)));
+        part.set_tracks(vec![3]);
+        assert!(part.accepts(Route::track(9, 3)));
+        assert!(part.accepts(Route::channel(1)));
+
+        let assignment = PartAssignment::parse("ch:2 src:noise,sampler").unwrap();
+        assert_eq!(assignment.channels, vec![1]);
+        assert_eq!(
+            assignment.sources,
+            vec![PartSource::Noise, PartSource::Sampler]
+        );
+        assert!(PartAssignment::parse("src:fm").is_err());
+    }
+
+    #[test]
+    fn test_part_preset() {
+        let mut part = PartBuilder::new()
+            .set_buffer(10)
+            .unwrap()
+            .add_osc(build_osc())
+            .build()
+            .unwrap();
+        let preset = Preset::capture("Init", &part);
+        assert!(preset
+            .values
+            .iter()
+            .any(|(path, _)| path == "Oscillator1/Octave"));
+        assert_eq!(preset.envelopes[0].0, "Oscillator1/Envelope");
+        assert_eq!(preset.wavetables[0].0, "Oscillator1/Wavetable");
+        assert_eq!(preset.wavetables[0].1.samples.len(), 64);
+
+        let (_, wavetable) = part.get_named_wavetables().remove(0);
+        wavetable.write().unwrap().load([0.0; 8].into_iter(), 4);
+        let (_, envelope) = part.get_named_envelopes().remove(0);
+        let mut shape = envelope.read().unwrap().shape();
+        shape.segments.pop();
+        envelope.write().unwrap().set_shape(&shape).unwrap();
+        let integers = part.get_named_parameters_i32();
+        let octave = &integers.iter().find(|p| p.path == "Oscillator1/Octave");
+        let octave = octave.unwrap().parameter.clone();
+        octave.write().unwrap().set_value(2);
+        let pan = part.get_named_parameters()[0].parameter.clone();
+        pan.write().unwrap().set_value(0.5);
+
+        part.apply_preset(PreparedPreset::new(&preset).unwrap(), 0.0)
+            .unwrap();
+        // Integers and shapes are restored at once, floats after crossfade
+        assert_eq!(octave.read().unwrap().get_value(), 0);
+        assert_eq!(wavetable.read().unwrap().samples().len(), 64);
+        assert_eq!(envelope.read().unwrap().segments().len(), 4);
+        assert_approx_eq!(pan.read().unwrap().get_value(), 0.5);
+        part.output(10, 0.01).unwrap();
+        assert_approx_eq!(pan.read().unwrap().get_value(), 0.0);
+
+        // With crossfade they are swapped once output faded out
+        octave.write().unwrap().set_value(2);
+        wavetable.write().unwrap().load([0.0; 8].into_iter(), 4);
+        part.apply_preset(PreparedPreset::new(&preset).unwrap(), 0.1)
+            .unwrap();
+        part.output(4, 0.01).unwrap();
+        assert_eq!(octave.read().unwrap().get_value(), 2);
+        part.output(4, 0.01).unwrap();
+        assert_eq!(octave.read().unwrap().get_value(), 2);
+        part.output(4, 0.01).unwrap();
+        assert_eq!(octave.read().unwrap().get_value(), 0);
+        assert_eq!(wavetable.read().unwrap().samples().len(), 64);
+    }
+
+    #[test]
+    fn test_part_cross_modulation() {
+        let (carrier, modulator) = (build_osc(), build_osc());
+        let mut part = PartBuilder::new()
+            .set_buffer(10)
+            .unwrap()
+            .add_osc(carrier.clone())
+            .add_osc(modulator.clone())
+            .build()
+            .unwrap();
+        assert!(part
+            .add_cross_modulation(0, 2, CrossModulationKind::Ring, 1.0)
+            .is_err());
+        part.add_cross_modulation(1, 0, CrossModulationKind::Ring, 1.0)
+            .unwrap();
+        assert!(part
+            .add_cross_modulation(0, 1, CrossModulationKind::Sync, 1.0)
+            .is_err());
+        assert_eq!(part.modulations().len(), 1);
+        // Cycle of modulations from config is rejected too
+        let config = ModulationConfig::parse("xmod 1 1 2 fm 1").unwrap();
+        assert!(part.apply_modulation(&config).is_err());
+        assert_eq!(part.get_named_parameters()[0].path, "Oscillator1/Pan");
+        assert!(part
+            .get_named_parameters()
+            .iter()
+            .any(|p| p.path == "Modulation1/Index"));
+
+        part.note_on(Note::from(69)).unwrap();
+        part.output(10, 0.01).unwrap();
+        let modulator = modulator.read().unwrap();
+        let signal = &modulator.voice_signals().unwrap().voices[0];
+        assert_eq!(signal.samples.len(), 10);
+        assert!(signal.samples.iter().any(|s| *s != 0.0));
+        let carrier = carrier.read().unwrap();
+        let ringed = &carrier.voice_signals().unwrap().voices;
+        assert!(ringed.is_empty());
+        (0..10).for_each(|i| {
+            let c = carrier.get_buffer().at(0, i).unwrap();
+            let m = modulator.get_buffer().at(0, i).unwrap();
+            assert!(c.abs() <= m.abs() + 1e-6);
+        });
+    }
+
+    #[test]
+    fn test_part_tuning() {
+        let (first, second) = (build_osc(), build_osc());
+        let mut part = PartBuilder::new()
+            .set_buffer(10)
+            .unwrap()
+            .add_osc(first.clone())
+            .add_osc(second.clone())
+            .build()
+            .unwrap();
+        let mut tuning = Tuning::default();
+        tuning.set_frequency(69, None);
+        part.set_oscillator_tuning(1, Some(make_shared(tuning)))
+            .unwrap();
+        assert!(part.set_oscillator_tuning(2, None).is_err());
+        assert!(part.oscillator_tuning(1).is_some());
+        part.note_on(Note::from(69)).unwrap();
+        let is_playing = |osc: &LockedOscillator| {
+            let osc = osc.read().unwrap();
+            let osc = osc.as_any().downcast_ref::<WavetableOscillator>().unwrap();
+            osc.is_playing(69)
+        };
+        assert!(is_playing(&first));
+        assert!(!is_playing(&second));
+    }
+
+    #[test]
+    fn test_part_modulation_config() {
+        let osc = build_osc();
+        let mut part = PartBuilder::new()
+            .set_buffer(10)
+            .unwrap()
+            .add_osc(osc.clone())
+            .build()
+            .unwrap();
+        part.apply_modulation(&ModulationConfig::parse("key 1 1 gain -6").unwrap())
+            .unwrap();
+        part.apply_modulation(&ModulationConfig::parse("velocity 1 1 linear").unwrap())
+            .unwrap();
+        assert!(part
+            .apply_modulation(&ModulationConfig::parse("velocity 1 2 linear").unwrap())
+            .is_err());
+
+        // Key of the last note moves parameter, edits between notes are kept
+        let config = ModulationConfig::parse("key 1 Oscillator1/Vibrato Depth 10").unwrap();
+        part.apply_modulation(&config).unwrap();
+        let config = ModulationConfig::parse("key 1 Oscillator1/Cutoff 10").unwrap();
+        assert!(part.apply_modulation(&config).is_err());
+        let depth = osc
+            .read()
+            .unwrap()
+            .as_any()
+            .downcast_ref::<WavetableOscillator>()
+            .unwrap()
+            .vibrato_depth();
+        part.note_on(Note::from(84)).unwrap();
+        assert_approx_eq!(depth.read().unwrap().get_value(), 20.0);
+        depth.write().unwrap().set_value(50.0);
+        part.note_on(Note::from(72)).unwrap();
+        assert_approx_eq!(depth.read().unwrap().get_value(), 40.0);
+
+        let osc = osc.read().unwrap();
+        let osc = osc.as_any().downcast_ref::<WavetableOscillator>().unwrap();
+        assert_eq!(osc.expression_routes().len(), 1);
+        assert_eq!(osc.expression_routes()[0].source, ExpressionSource::Key);
+        assert!(matches!(osc.velocity().curve(), VelocityCurve::Linear));
+    }
+
+    #[test]
+    fn test_part_lfo() {
+        let mut lfo = LFO::new(WaveShape::Sin, 1.0);
+        lfo.set_trigger(LfoTrigger::Retrigger);
+        let lfo = make_shared(lfo);
+        let mut part = PartBuilder::new()
+            .set_buffer(10)
+            .unwrap()
+            .add_osc(build_osc())
+            .build()
+            .unwrap();
+        part.add_lfo(lfo.clone());
+        assert_approx_eq!(lfo.read().unwrap().evaluate(5.25).unwrap(), 1.0);
+        // Note restarts LFO at the next evaluation
+        part.note_on(Note::from(60)).unwrap();
+        assert_approx_eq!(lfo.read().unwrap().evaluate(6.0).unwrap(), 0.5);
+
+        // LFO parameters are reachable by paths and LFO modulates oscillator
+        let lfo_paths = |paths: Vec<String>| {
+            paths
+                .into_iter()
+                .filter(|path| path.starts_with("LFO"))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            lfo_paths(
+                part.get_named_parameters()
+                    .into_iter()
+                    .map(|p| p.path)
+                    .collect()
+            ),
+            ["LFO1/Rate", "LFO1/Phase", "LFO1/Delay", "LFO1/Fade In"]
+        );
+        assert_eq!(
+            lfo_paths(
+                part.get_named_parameters_i32()
+                    .into_iter()
+                    .map(|p| p.path)
+                    .collect()
+            ),
+            ["LFO1/Shape", "LFO1/Sync"]
+        );
+        let config = ModulationConfig::parse("lfo 1 1 1 pan -1 1").unwrap();
+        assert!(part.apply_modulation(&config).is_ok());
+        let config = ModulationConfig::parse("lfo 1 2 1 pan -1 1").unwrap();
+        assert!(part.apply_modulation(&config).is_err());
+    }
+}
@@ -0,0 +1,313 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use crate::{
+    error::Error,
+    utils::{
+        adsr_envelope::{ADSREnvelope, EnvelopeShape},
+        sample_buffer::SampleBufferMono,
+    },
+};
+
+use super::part::Part;
+
+pub const PRESET_EXTENSION: &str = "preset";
+pub const PRESET_CROSSFADE_TIME: f32 = 0.05;
+
+pub(crate) const ENVELOPE_SUFFIX: &str = "/Envelope";
+pub(crate) const WAVETABLE_SUFFIX: &str = "/Wavetable";
+
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct WavetableData {
+    pub chunk_size: usize,
+    pub samples: Vec<f32>,
+}
+
+impl WavetableData {
+    // "<chunk size>:<sample>,<sample>,..."
+    pub fn to_text(&self) -> String {
+        let samples = self
+            .samples
+            .iter()
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        format!("{}:{}", self.chunk_size, samples)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let (chunk_size, samples) = text
+            .split_once(':')
+            .ok_or(format!("Invalid wavetable \"{}\"", text))?;
+        let chunk_size = chunk_size
+            .parse::<usize>()
+            .map_err(|e| format!("Invalid wavetable chunk size: {}", e))?;
+        let samples = samples
+            .split(',')
+            .map(|s| s.parse::<f32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Invalid wavetable sample: {}", e))?;
+        if chunk_size == 0 || samples.len() % chunk_size != 0 {
+            return Err(format!(
+                "Wavetable of {} samples cannot be split into chunks of {}",
+                samples.len(),
+                chunk_size
+            )
+            .into());
+        }
+        Ok(Self {
+            chunk_size,
+            samples,
+        })
+    }
+}
+
+// Snapshot of part sound, stored as "<path> <value>" lines. Values hold both float and
+// integer parameters, envelopes and wavetables use paths ending with /Envelope and /Wavetable
+#[derive(Clone, Default)]
+pub struct Preset {
+    pub name: String,
+    pub values: Vec<(String, f32)>,
+    pub envelopes: Vec<(String, EnvelopeShape)>,
+    pub wavetables: Vec<(String, WavetableData)>,
+}
+
+impl Preset {
+    pub fn capture(name: &str, part: &Part) -> Self {
+        let values_f32 = part.get_named_parameters().into_iter().map(|p| {
+            let value = p.parameter.read().unwrap().get_value();
+            (p.path, value)
+        });
+        let values_i32 = part.get_named_parameters_i32().into_iter().map(|p| {
+            let value = p.parameter.read().unwrap().get_value();
+            (p.path, value as f32)
+        });
+        let envelopes = part
+            .get_named_envelopes()
+            .into_iter()
+            .map(|(path, envelope)| (path, envelope.read().unwrap().shape()))
+            .collect();
+        let wavetables = part
+            .get_named_wavetables()
+            .into_iter()
+            .map(|(path, wavetable)| {
+                let wavetable = wavetable.read().unwrap();
+                let data = WavetableData {
+                    chunk_size: wavetable.chunk_len(),
+                    samples: wavetable.samples().to_vec(),
+                };
+                (path, data)
+            })
+            .collect();
+        Self {
+            name: name.to_owned(),
+            values: values_f32.chain(values_i32).collect(),
+            envelopes,
+            wavetables,
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let values = self
+            .values
+            .iter()
+            .map(|(path, value)| format!("{} {}\n", path, value));
+        let envelopes = self
+            .envelopes
+            .iter()
+            .map(|(path, shape)| format!("{} {}\n", path, shape.to_text()));
+        let wavetables = self
+            .wavetables
+            .iter()
+            .map(|(path, data)| format!("{} {}\n", path, data.to_text()));
+        let data = values
+            .chain(envelopes)
+            .chain(wavetables)
+            .collect::<String>();
+        fs::write(path, data)?;
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(name: &str, path: P) -> Result<Self, Error> {
+        let mut preset = Self {
+            name: name.to_owned(),
+            ..Default::default()
+        };
+        for line in fs::read_to_string(path)?.lines() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (path, value) = line
+                .trim()
+                .rsplit_once(' ')
+                .ok_or(format!("Invalid preset value \"{}\"", line))?;
+            let path = path.to_owned();
+            match path {
+                _ if path.ends_with(ENVELOPE_SUFFIX) => {
+                    preset.envelopes.push((path, EnvelopeShape::parse(value)?))
+                }
+                _ if path.ends_with(WAVETABLE_SUFFIX) => {
+                    preset.wavetables.push((path, WavetableData::parse(value)?))
+                }
+                _ => {
+                    let value = value
+                        .parse::<f32>()
+                        .map_err(|e| format!("Invalid preset value: {}", e))?;
+                    preset.values.push((path, value));
+                }
+            }
+        }
+        Ok(preset)
+    }
+}
+
+// Preset with envelopes and wavetable buffers built ahead, so part applying it under
+// synthesizer lock only swaps them in
+pub struct PreparedPreset {
+    pub values: Vec<(String, f32)>,
+    pub envelopes: Vec<(String, ADSREnvelope)>,
+    pub wavetables: Vec<(String, SampleBufferMono, usize)>,
+}
+
+impl PreparedPreset {
+    pub fn new(preset: &Preset) -> Result<Self, Error> {
+        let envelopes = preset
+            .envelopes
+            .iter()
+            .map(|(path, shape)| {
+                let mut envelope = ADSREnvelope::default();
+                envelope.set_shape(shape)?;
+                Ok((path.clone(), envelope))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let wavetables = preset
+            .wavetables
+            .iter()
+            .map(|(path, data)| {
+                let buffer = SampleBufferMono::from(data.samples.clone());
+                (path.clone(), buffer, data.chunk_size)
+            })
+            .collect();
+        Ok(Self {
+            values: preset.values.clone(),
+            envelopes,
+            wavetables,
+        })
+    }
+}
+
+// Presets addressed by bank (CC0 * 128 + CC32) and program number
+#[derive(Default)]
+pub struct PresetBank {
+    presets: BTreeMap<(u16, u8), Preset>,
+}
+
+impl PresetBank {
+    pub fn new() -> Self {
+        Self {
+            presets: BTreeMap::new(),
+        }
+    }
+
+    pub fn insert(&mut self, bank: u16, program: u8, preset: Preset) -> &mut Self {
+        self.presets.insert((bank, program), preset);
+        self
+    }
+
+    pub fn get(&self, bank: u16, program: u8) -> Option<&Preset> {
+        self.presets.get(&(bank, program))
+    }
+
+    // Lowest program of bank without preset
+    pub fn free_program(&self, bank: u16) -> Option<u8> {
+        (0..128).find(|program| !self.presets.contains_key(&(bank, *program)))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u16, u8, &Preset)> {
+        self.presets
+            .iter()
+            .map(|((bank, program), preset)| (*bank, *program, preset))
+    }
+
+    // Files are named "<bank>_<program>_<name>.preset"
+    pub fn load_dir<P: AsRef<Path>>(dir: P) -> Result<Self, Error> {
+        let mut bank = Self::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some(PRESET_EXTENSION) {
+                continue;
+            }
+            let stem = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .unwrap_or_default();
+            let mut fields = stem.splitn(3, '_');
+            let number = fields.next().and_then(|v| v.parse::<u16>().ok());
+            let program = fields.next().and_then(|v| v.parse::<u8>().ok());
+            let name = fields.next().unwrap_or_default();
+            if let (Some(number), Some(program)) = (number, program.filter(|p| *p < 128)) {
+                bank.insert(number, program, Preset::load(name, &path)?);
+            }
+        }
+        Ok(bank)
+    }
+
+    pub fn save_dir<P: AsRef<Path>>(&self, dir: P) -> Result<(), Error> {
+        fs::create_dir_all(&dir)?;
+        self.iter().try_for_each(|(bank, program, preset)| {
+            let file = format!("{}_{}_{}.{}", bank, program, preset.name, PRESET_EXTENSION);
+            preset.save(dir.as_ref().join(file))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::adsr_envelope::ADSREnvelope;
+
+    use super::{Preset, PresetBank, WavetableData};
+
+    #[test]
+    fn test_preset_bank_persist() {
+        let dir = std::env::temp_dir().join("oosc_test_presets");
+        let mut bank = PresetBank::new();
+        bank.insert(
+            129,
+            5,
+            Preset {
+                name: "Soft pad".to_owned(),
+                values: vec![
+                    ("Oscillator1/Gain".to_owned(), -6.0),
+                    ("Chorus1/Depth".to_owned(), 0.25),
+                ],
+                envelopes: vec![(
+                    "Oscillator1/Envelope".to_owned(),
+                    ADSREnvelope::default().shape(),
+                )],
+                wavetables: vec![(
+                    "Oscillator1/Wavetable".to_owned(),
+                    WavetableData {
+                        chunk_size: 2,
+                        samples: vec![0.0, 0.5, -0.5, 1.0],
+                    },
+                )],
+            },
+        );
+        assert_eq!(bank.free_program(129), Some(0));
+        assert_eq!(bank.free_program(0), Some(0));
+        bank.save_dir(&dir).unwrap();
+        let loaded = PresetBank::load_dir(&dir).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+        assert!(loaded.get(129, 4).is_none());
+        let preset = loaded.get(129, 5).unwrap();
+        assert_eq!(preset.name, "Soft pad");
+        let saved = bank.get(129, 5).unwrap();
+        assert_eq!(preset.values, saved.values);
+        assert_eq!(preset.wavetables, saved.wavetables);
+        assert_eq!(preset.envelopes.len(), 1);
+        assert_eq!(
+            preset.envelopes[0].1.to_text(),
+            saved.envelopes[0].1.to_text()
+        );
+        assert!(WavetableData::parse("3:0,1").is_err());
+    }
+}
@@ -0,0 +1,742 @@
+use std::{any::Any, path::Path, sync::Arc};
+
+use crate::{
+    error::Error,
+    utils::{
+        adsr_envelope::ADSREnvelope, convert::semitones_to_freq_coefficient, evaluate::Modulation,
+        interpolation::interpolate_linear, make_shared, sample_buffer::SampleBuffer, Shared,
+    },
+};
+
+use super::{
+    note::{Expression, Note, NoteEventReceiver},
+    oscillator::Oscillator,
+    parameter::{
+        NamedParameter, NamedParametersContainer, PanParameter, SharedParameter, ValueParameter,
+        VolumeParameter,
+    },
+    velocity::VelocityResponse,
+    voices::{envelope_value_at, Voice, Voices},
+};
+
+pub const ZONES_FILE: &str = "zones.txt";
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LoopMode {
+    // Plays from start to the end of recording
+    OneShot,
+    // Repeats loop region until the note is released and faded out
+    Loop,
+}
+
+// Mono recording with playback settings, positions are in frames
+#[derive(Clone)]
+pub struct Sample {
+    data: Vec<f32>,
+    sample_rate: f32,
+    root_key: u32,
+    start: usize,
+    loop_start: usize,
+    loop_end: usize,
+    crossfade: usize,
+    mode: LoopMode,
+}
+
+impl Sample {
+    pub fn new(data: Vec<f32>, sample_rate: f32) -> Self {
+        let len = data.len();
+        Self {
+            data,
+            sample_rate,
+            root_key: 60,
+            start: 0,
+            loop_start: 0,
+            loop_end: len,
+            crossfade: 0,
+            mode: LoopMode::OneShot,
+        }
+    }
+
+    // Channels of WAV file are mixed down to mono
+    pub fn load<P: AsRef<std::path::Path>>(path: P) -> Result<Self, Error> {
+        let mut reader = hound::WavReader::open(path).map_err(|e| e.to_string())?;
+        let spec = reader.spec();
+        let samples: Vec<f32> = match spec.sample_format {
+            hound::SampleFormat::Float => reader.samples::<f32>().filter_map(|s| s.ok()).collect(),
+            hound::SampleFormat::Int => {
+                let scale = 1.0 / (1_i64 << (spec.bits_per_sample - 1)) as f32;
+                reader
+                    .samples::<i32>()
+                    .filter_map(|s| s.ok())
+                    .map(|s| s as f32 * scale)
+                    .collect()
+            }
+        };
+        let channels = spec.channels.max(1) as usize;
+        let data = samples
+            .chunks(channels)
+            .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+            .collect();
+        Ok(Self::new(data, spec.sample_rate as f32))
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn root_key(&self) -> u32 {
+        self.root_key
+    }
+
+    pub fn set_root_key(mut self, root_key: u32) -> Self {
+        self.root_key = root_key;
+        self
+    }
+
+    pub fn set_start(mut self, start: usize) -> Self {
+        self.start = start.min(self.len().saturating_sub(1));
+        self
+    }
+
+    // Enables looping between given frames
+    pub fn set_loop(mut self, start: usize, end: usize) -> Result<Self, Error> {
+        if start >= end || end > self.len() {
+            return Err(format!(
+                "Invalid loop {}..{} of sample with {} frames",
+                start,
+                end,
+                self.len()
+            )
+            .into());
+        }
+        self.loop_start = start;
+        self.loop_end = end;
+        self.mode = LoopMode::Loop;
+        Ok(self)
+    }
+
+    // Length of crossfade before loop end, limited by loop length and frames before loop
+    pub fn set_crossfade(mut self, crossfade: usize) -> Self {
+        self.crossfade = crossfade;
+        self
+    }
+
+    pub fn set_mode(mut self, mode: LoopMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn mode(&self) -> LoopMode {
+        self.mode
+    }
+
+    fn crossfade_len(&self) -> usize {
+        self.crossfade
+            .min(self.loop_start)
+            .min(self.loop_end - self.loop_start)
+    }
+
+    fn frame(&self, position: f64) -> f32 {
+        let index = position.floor() as usize;
+        let fraction = (position - index as f64) as f32;
+        let next = match self.mode {
+            LoopMode::Loop if index + 1 >= self.loop_end => self.loop_start,
+            _ => index + 1,
+        };
+        let value = |i: usize| self.data.get(i).copied().unwrap_or(0.0);
+        interpolate_linear(value(index), value(next), fraction)
+    }
+
+    // Value at playback position, None after the end of one-shot sample
+    pub fn value_at(&self, position: f64) -> Option<f32> {
+        match self.mode {
+            LoopMode::OneShot if position >= self.len() as f64 => None,
+            LoopMode::OneShot => Some(self.frame(position)),
+            LoopMode::Loop => {
+                let crossfade = self.crossfade_len() as f64;
+                let fade_start = self.loop_end as f64 - crossfade;
+                let value = self.frame(position);
+                if crossfade > 0.0 && position >= fade_start {
+                    // Tail of loop fades into frames before loop start
+                    let length = (self.loop_end - self.loop_start) as f64;
+                    let t = ((position - fade_start) / crossfade) as f32;
+                    Some(interpolate_linear(value, self.frame(position - length), t))
+                } else {
+                    Some(value)
+                }
+            }
+        }
+    }
+
+    // Moves playback position, wrapping it inside loop
+    pub fn advance(&self, position: f64, step: f64) -> f64 {
+        let position = position + step;
+        match self.mode {
+            LoopMode::Loop if position >= self.loop_end as f64 => {
+                let length = (self.loop_end - self.loop_start) as f64;
+                self.loop_start as f64 + (position - self.loop_start as f64) % length
+            }
+            _ => position,
+        }
+    }
+}
+
+// Sample played for range of keys and velocities, ranges are inclusive
+#[derive(Clone)]
+pub struct SampleZone {
+    pub keys: (u32, u32),
+    pub velocities: (u32, u32),
+    pub sample: Arc<Sample>,
+}
+
+impl SampleZone {
+    pub fn new(sample: Sample) -> Self {
+        Self {
+            keys: (0, 127),
+            velocities: (0, 127),
+            sample: Arc::new(sample),
+        }
+    }
+
+    pub fn keys(self, low: u32, high: u32) -> Self {
+        Self {
+            keys: (low, high),
+            ..self
+        }
+    }
+
+    pub fn velocities(self, low: u32, high: u32) -> Self {
+        Self {
+            velocities: (low, high),
+            ..self
+        }
+    }
+
+    pub fn contains(&self, key: u32, velocity: u32) -> bool {
+        (self.keys.0..=self.keys.1).contains(&key)
+            && (self.velocities.0..=self.velocities.1).contains(&velocity)
+    }
+}
+
+// Line of zones file, settings which are not given keep defaults of sample and zone:
+// "<file> root:60 keys:0-59 vel:0-127 start:0 loop:100-900 xfade:64 mode:oneshot|loop"
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct ZoneSettings {
+    pub file: String,
+    pub root_key: Option<u32>,
+    pub keys: Option<(u32, u32)>,
+    pub velocities: Option<(u32, u32)>,
+    pub start: Option<usize>,
+    pub loop_points: Option<(usize, usize)>,
+    pub crossfade: Option<usize>,
+    pub mode: Option<LoopMode>,
+}
+
+impl ZoneSettings {
+    pub fn parse(line: &str) -> Result<Self, Error> {
+        let mut fields = line.split_whitespace();
+        let mut settings = Self {
+            file: fields
+                .next()
+                .ok_or(format!("Invalid zone \"{}\"", line))?
+                .to_owned(),
+            ..Default::default()
+        };
+        for field in fields {
+            match field.split_once(':') {
+                Some(("root", value)) => settings.root_key = Some(parse_number(value)?),
+                Some(("keys", value)) => settings.keys = Some(parse_range(value)?),
+                Some(("vel", value)) => settings.velocities = Some(parse_range(value)?),
+                Some(("start", value)) => settings.start = Some(parse_number(value)?),
+                Some(("loop", value)) => settings.loop_points = Some(parse_range(value)?),
+                Some(("xfade", value)) => settings.crossfade = Some(parse_number(value)?),
+                Some(("mode", "oneshot")) => settings.mode = Some(LoopMode::OneShot),
+                Some(("mode", "loop")) => settings.mode = Some(LoopMode::Loop),
+                _ => return Err(format!("Invalid zone field \"{}\"", field).into()),
+            }
+        }
+        Ok(settings)
+    }
+
+    // Loop mode without loop points repeats the whole sample
+    pub fn zone(&self, mut sample: Sample) -> Result<SampleZone, Error> {
+        if let Some(root_key) = self.root_key {
+            sample = sample.set_root_key(root_key);
+        }
+        if let Some(start) = self.start {
+            sample = sample.set_start(start);
+        }
+        if let Some((start, end)) = self.loop_points {
+            sample = sample.set_loop(start, end)?;
+        }
+        if let Some(crossfade) = self.crossfade {
+            sample = sample.set_crossfade(crossfade);
+        }
+        if let Some(mode) = self.mode {
+            sample = sample.set_mode(mode);
+        }
+        let mut zone = SampleZone::new(sample);
+        if let Some((low, high)) = self.keys {
+            zone = zone.keys(low, high);
+        }
+        if let Some((low, high)) = self.velocities {
+            zone = zone.velocities(low, high);
+        }
+        Ok(zone)
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str) -> Result<T, Error>
+where
+    T::Err: std::fmt::Display,
+{
+    value
+        .parse::<T>()
+        .map_err(|e| format!("Invalid zone value \"{}\": {}", value, e).into())
+}
+
+fn parse_range<T: std::str::FromStr>(value: &str) -> Result<(T, T), Error>
+where
+    T::Err: std::fmt::Display,
+{
+    let (low, high) = value
+        .split_once('-')
+        .ok_or(format!("Invalid zone range \"{}\"", value))?;
+    Ok((parse_number(low)?, parse_number(high)?))
+}
+
+// Multisampled instrument from zones file of directory, one zone per line with
+// empty lines and lines starting with # skipped. Without zones file WAV files are
+// named by their root key, e.g. 60.wav, and each covers keys up to halfway to the next root key
+pub fn load_zones<P: AsRef<Path>>(dir: P) -> Result<Vec<SampleZone>, Error> {
+    let zones_file = dir.as_ref().join(ZONES_FILE);
+    if zones_file.exists() {
+        return std::fs::read_to_string(zones_file)?
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                let settings = ZoneSettings::parse(line)?;
+                let sample = Sample::load(dir.as_ref().join(&settings.file))?;
+                settings.zone(sample)
+            })
+            .collect();
+    }
+    let mut files = std::fs::read_dir(dir)
+        .map_err(|e| e.to_string())?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|e| e == "wav"))
+        .filter_map(|path| {
+            let key = path.file_stem()?.to_str()?.parse::<u32>().ok()?;
+            Some((key, path))
+        })
+        .collect::<Vec<_>>();
+    files.sort_by_key(|(key, _)| *key);
+    let mut low = 0;
+    files
+        .iter()
+        .enumerate()
+        .map(|(i, (key, path))| {
+            let high = files.get(i + 1).map_or(127, |(next, _)| (key + next) / 2);
+            let zone = SampleZone::new(Sample::load(path)?.set_root_key(*key)).keys(low, high);
+            low = high + 1;
+            Ok(zone)
+        })
+        .collect()
+}
+
+struct SamplerVoice {
+    note: Note,
+    sample: Arc<Sample>,
+    position: f64,
+}
+
+impl Voice for SamplerVoice {
+    fn note(&self) -> &Note {
+        &self.note
+    }
+
+    fn note_mut(&mut self) -> &mut Note {
+        &mut self.note
+    }
+}
+
+pub struct SamplerOscillator {
+    buffer: SampleBuffer,
+    envelope: Shared<ADSREnvelope>,
+    zones: Vec<SampleZone>,
+    voices: Voices<SamplerVoice>,
+    pitch_bend: f32,
+    pitch_bend_range: SharedParameter<i32>,
+    pan: Shared<PanParameter>,
+    gain: Shared<VolumeParameter>,
+    parameters_f32: Vec<NamedParameter<f32>>,
+    parameters_i32: Vec<NamedParameter<i32>>,
+    velocity: VelocityResponse,
+}
+
+impl SamplerOscillator {
+    pub fn envelope(&self) -> Shared<ADSREnvelope> {
+        self.envelope.clone()
+    }
+
+    pub fn pitch_bend_range(&self) -> SharedParameter<i32> {
+        self.pitch_bend_range.clone()
+    }
+
+    pub fn pan(&self) -> Shared<PanParameter> {
+        self.pan.clone()
+    }
+
+    pub fn gain(&self) -> SharedParameter<f32> {
+        self.gain.clone()
+    }
+
+    pub fn velocity(&self) -> &VelocityResponse {
+        &self.velocity
+    }
+
+    pub fn velocity_mut(&mut self) -> &mut VelocityResponse {
+        &mut self.velocity
+    }
+
+    pub fn zones(&self) -> &[SampleZone] {
+        &self.zones
+    }
+
+    // First zone containing key and velocity is played
+    pub fn add_zone(&mut self, zone: SampleZone) -> &mut Self {
+        self.zones.push(zone);
+        self
+    }
+
+    pub fn clear_zones(&mut self) {
+        self.zones.clear();
+    }
+
+    pub fn is_playing(&self, note: u32) -> bool {
+        self.voices.is_playing(note)
+    }
+
+    fn zone_of(&self, note: &Note) -> Option<&SampleZone> {
+        let velocity = (note.velocity * 127.0).round() as u32;
+        self.zones.iter().find(|z| z.contains(note.note, velocity))
+    }
+}
+
+impl Oscillator for SamplerOscillator {
+    fn evaluate(&mut self, size: usize, delta_time: f32) -> Result<(), Error> {
+        let envelope = self.envelope.clone();
+        let envelope = envelope.read().unwrap();
+        self.voices.remove_released(&envelope);
+        let bend_range = self.pitch_bend_range.read().unwrap().get_value();
+        let bend = semitones_to_freq_coefficient(self.pitch_bend * bend_range as f32);
+        let mut pan = self.pan.write().unwrap();
+        let gain = self.gain.read().unwrap().linear;
+
+        (0..size).try_for_each(|i| -> Result<(), Error> {
+            pan.next_value(delta_time)?;
+            let (left, right) = pan.polar;
+            let mut sample = 0.0;
+            self.voices.iter_mut().for_each(|voice| {
+                let note = &mut voice.note;
+                let Some(value) = voice.sample.value_at(voice.position) else {
+                    return;
+                };
+                let level = envelope_value_at(note.play_time, note, &envelope);
+                sample += value * level * self.velocity.gain(note.velocity);
+                // Tuned pitch relative to 12-TET pitch of root key
+                let semitones =
+                    note.pitch() - voice.sample.root_key as f32 + note.expression.pitch_bend;
+                let rate = voice.sample.sample_rate
+                    * delta_time
+                    * bend
+                    * semitones_to_freq_coefficient(semitones);
+                voice.position = voice.sample.advance(voice.position, rate as f64);
+                note.play_time += delta_time;
+            });
+            self.buffer.set_at(0, i, sample * gain * left)?;
+            self.buffer.set_at(1, i, sample * gain * right)
+        })?;
+        // Finished one-shot voices are dropped even if the key is still held
+        self.voices
+            .retain(|v| v.sample.value_at(v.position).is_some());
+        Ok(())
+    }
+
+    fn get_buffer_mut(&mut self) -> &mut SampleBuffer {
+        &mut self.buffer
+    }
+
+    fn get_buffer(&self) -> &SampleBuffer {
+        &self.buffer
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn parameters(&mut self) -> Option<&mut dyn NamedParametersContainer> {
+        Some(self)
+    }
+
+    fn velocity_response(&mut self) -> Option<&mut VelocityResponse> {
+        Some(&mut self.velocity)
+    }
+
+    fn shared_envelope(&self) -> Option<Shared<ADSREnvelope>> {
+        Some(self.envelope.clone())
+    }
+}
+
+impl NamedParametersContainer for SamplerOscillator {
+    fn name(&self) -> Option<&'static str> {
+        Some("Sampler")
+    }
+
+    fn parameters_f32(&self) -> Option<&[NamedParameter<f32>]> {
+        Some(&self.parameters_f32)
+    }
+
+    fn parameters_i32(&self) -> Option<&[NamedParameter<i32>]> {
+        Some(&self.parameters_i32)
+    }
+}
+
+impl NoteEventReceiver for SamplerOscillator {
+    fn note_on(&mut self, note: Note) -> Result<(), Error> {
+        // Keys outside of all zones are silent
+        if let Some(zone) = self.zone_of(&note) {
+            let sample = zone.sample.clone();
+            self.voices.note_on(SamplerVoice {
+                note,
+                position: sample.start as f64,
+                sample,
+            });
+        }
+        Ok(())
+    }
+
+    fn note_off(&mut self, note: u32) -> Result<(), Error> {
+        self.voices.note_off(note);
+        Ok(())
+    }
+
+    fn member_note_off(&mut self, channel: u8, note: u32) -> Result<(), Error> {
+        self.voices.member_note_off(channel, note);
+        Ok(())
+    }
+
+    fn release_all(&mut self) {
+        self.voices.release_all();
+    }
+
+    fn pitch_bend(&mut self, value: f32) {
+        self.pitch_bend = value;
+    }
+
+    fn note_expression(&mut self, channel: u8, note: u32, expression: Expression) {
+        self.voices.note_expression(channel, note, expression);
+    }
+
+    fn sustain(&mut self, hold: bool) -> Result<(), Error> {
+        self.voices.sustain(hold);
+        Ok(())
+    }
+
+    fn sostenuto(&mut self, hold: bool) -> Result<(), Error> {
+        self.voices.sostenuto(hold);
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct SamplerOscillatorBuilder {
+    buffer: Option<SampleBuffer>,
+    envelope: Option<ADSREnvelope>,
+    zones: Vec<SampleZone>,
+}
+
+impl SamplerOscillatorBuilder {
+    pub fn new() -> Self {
+        Self {
+            buffer: None,
+            envelope: None,
+            zones: vec![],
+        }
+    }
+
+    pub fn set_buffer(&mut self, buffer: SampleBuffer) -> &mut Self {
+        self.buffer = Some(buffer);
+        self
+    }
+
+    pub fn set_envelope(&mut self, envelope: ADSREnvelope) -> &mut Self {
+        self.envelope = Some(envelope);
+        self
+    }
+
+    pub fn add_zone(&mut self, zone: SampleZone) -> &mut Self {
+        self.zones.push(zone);
+        self
+    }
+
+    pub fn build(&mut self) -> Result<SamplerOscillator, Error> {
+        let buffer = self.buffer.take().ok_or(Error::Specify("samples buffer"))?;
+        let envelope = make_shared(self.envelope.take().ok_or(Error::Specify("envelope"))?);
+        let pan = make_shared(PanParameter::default());
+        let gain = make_shared(VolumeParameter::default());
+        let velocity = VelocityResponse::default();
+        let parameters_f32 = vec![
+            NamedParameter::new(pan.clone(), "Pan"),
+            NamedParameter::new(gain.clone(), "Gain"),
+            NamedParameter::new(velocity.sensitivity(), "Velocity"),
+        ];
+        let pitch_bend_range: SharedParameter<i32> = make_shared(ValueParameter::new(2, (0, 24)));
+        let parameters_i32 = vec![NamedParameter::new(pitch_bend_range.clone(), "Bend")];
+        Ok(SamplerOscillator {
+            buffer,
+            envelope,
+            zones: std::mem::take(&mut self.zones),
+            voices: Voices::default(),
+            pitch_bend: 0.0,
+            pitch_bend_range,
+            pan,
+            gain,
+            parameters_f32,
+            parameters_i32,
+            velocity,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_approx_eq::assert_approx_eq;
+
+    use crate::{
+        core::{
+            note::{Note, NoteEventReceiver},
+            oscillator::Oscillator,
+        },
+        utils::{adsr_envelope::ADSREnvelope, sample_buffer::SampleBufferBuilder},
+    };
+
+    use super::{
+        LoopMode, Sample, SampleZone, SamplerOscillator, SamplerOscillatorBuilder, ZoneSettings,
+    };
+
+    fn ramp(len: usize) -> Sample {
+        Sample::new((0..len).map(|i| i as f32).collect(), 100.0)
+    }
+
+    fn build_sampler(zones: Vec<SampleZone>) -> SamplerOscillator {
+        let buffer = SampleBufferBuilder::new()
+            .set_channels(2)
+            .set_samples(10)
+            .build()
+            .unwrap();
+        let mut builder = SamplerOscillatorBuilder::new();
+        builder
+            .set_buffer(buffer)
+            .set_envelope(ADSREnvelope::default());
+        zones.into_iter().for_each(|zone| {
+            builder.add_zone(zone);
+        });
+        builder.build().unwrap()
+    }
+
+    #[test]
+    fn test_sample_loop() {
+        let sample = ramp(10).set_loop(4, 8).unwrap();
+        assert_eq!(sample.mode(), LoopMode::Loop);
+        assert_approx_eq!(sample.advance(7.0, 2.0) as f32, 5.0);
+        assert_approx_eq!(sample.value_at(7.5).unwrap(), 5.5);
+        assert!(ramp(10).set_loop(8, 4).is_err());
+
+        let sample = sample.set_crossfade(2);
+        assert_approx_eq!(sample.value_at(5.0).unwrap(), 5.0);
+        // Halfway through crossfade, frame 7 is blended with frame 3
+        assert_approx_eq!(sample.value_at(7.0).unwrap(), 5.0);
+
+        let one_shot = ramp(10).set_start(2);
+        assert_approx_eq!(one_shot.value_at(9.0).unwrap(), 9.0);
+        assert!(one_shot.value_at(10.0).is_none());
+    }
+
+    #[test]
+    fn test_sampler_pitch() {
+        let delta_time = 1.0 / 100.0;
+        let mut sampler = build_sampler(vec![SampleZone::new(ramp(100).set_root_key(60))]);
+        sampler.note_on(Note::from(72)).unwrap();
+        sampler.evaluate(3, delta_time).unwrap();
+        assert_approx_eq!(sampler.voices.notes[0].position as f32, 6.0, 1e-4);
+
+        let mut sampler = build_sampler(vec![SampleZone::new(ramp(5))]);
+        sampler.note_on(Note::from(60)).unwrap();
+        sampler.evaluate(10, delta_time).unwrap();
+        assert!(!sampler.is_playing(60));
+    }
+
+    #[test]
+    fn test_sampler_zones() {
+        let zones = vec![
+            SampleZone::new(ramp(10).set_root_key(48)).keys(0, 59),
+            SampleZone::new(ramp(10).set_root_key(72))
+                .keys(60, 127)
+                .velocities(0, 63),
+            SampleZone::new(ramp(10).set_root_key(84))
+                .keys(60, 127)
+                .velocities(64, 127),
+        ];
+        let mut sampler = build_sampler(zones);
+        sampler.note_on(Note::new(50, 100)).unwrap();
+        sampler.note_on(Note::new(70, 30)).unwrap();
+        sampler.note_on(Note::new(71, 100)).unwrap();
+        let roots = sampler
+            .voices
+            .notes
+            .iter()
+            .map(|v| v.sample.root_key())
+            .collect::<Vec<_>>();
+        assert_eq!(roots, vec![48, 72, 84]);
+
+        sampler.clear_zones();
+        sampler.note_on(Note::from(60)).unwrap();
+        assert!(!sampler.is_playing(60));
+    }
+
+    #[test]
+    fn test_zone_settings() {
+        let line = "pad.wav root:48 keys:40-59 vel:64-127 loop:4-8 xfade:2";
+        let settings = ZoneSettings::parse(line).unwrap();
+        assert_eq!(settings.file, "pad.wav");
+        assert_eq!(settings.loop_points, Some((4, 8)));
+        let zone = settings.zone(ramp(10)).unwrap();
+        assert_eq!(zone.keys, (40, 59));
+        assert!(zone.contains(50, 100));
+        assert!(!zone.contains(50, 30));
+        assert_eq!(zone.sample.root_key(), 48);
+        assert_eq!(zone.sample.mode(), LoopMode::Loop);
+        assert_approx_eq!(zone.sample.value_at(7.0).unwrap(), 5.0);
+
+        let one_shot = ZoneSettings::parse("hit.wav loop:4-8 mode:oneshot").unwrap();
+        let zone = one_shot.zone(ramp(10)).unwrap();
+        assert_eq!(zone.sample.mode(), LoopMode::OneShot);
+        assert!(ZoneSettings::parse("").is_err());
+        assert!(ZoneSettings::parse("a.wav keys:60").is_err());
+        assert!(ZoneSettings::parse("a.wav mode:pingpong").is_err());
+        assert!(ZoneSettings::parse("a.wav loop:8-4")
+            .unwrap()
+            .zone(ramp(10))
+            .is_err());
+    }
+}
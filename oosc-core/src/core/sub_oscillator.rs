@@ -0,0 +1,343 @@
+use std::any::Any;
+
+use crate::{
+    error::Error,
+    utils::{
+        adsr_envelope::ADSREnvelope,
+        consts::PI_2M,
+        convert::semitones_to_freq_coefficient,
+        evaluate::{Evaluate, Modulation},
+        make_shared,
+        sample_buffer::SampleBuffer,
+        Shared,
+    },
+};
+
+use super::{
+    note::{Expression, Note, NoteEventReceiver},
+    oscillator::Oscillator,
+    parameter::{
+        NamedParameter, NamedParametersContainer, PanParameter, SharedParameter, ValueParameter,
+        VolumeParameter,
+    },
+    velocity::VelocityResponse,
+    voices::{envelope_value_at, Voices},
+    waveshape::WaveShape,
+};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SubShape {
+    Sin,
+    Square,
+    Triangle,
+    Saw,
+}
+
+impl From<i32> for SubShape {
+    fn from(value: i32) -> Self {
+        match value {
+            1 => SubShape::Square,
+            2 => SubShape::Triangle,
+            3 => SubShape::Saw,
+            _ => SubShape::Sin,
+        }
+    }
+}
+
+impl From<SubShape> for WaveShape {
+    fn from(value: SubShape) -> Self {
+        match value {
+            SubShape::Sin => WaveShape::Sin,
+            SubShape::Square => WaveShape::Square,
+            SubShape::Triangle => WaveShape::Triangle,
+            SubShape::Saw => WaveShape::Saw,
+        }
+    }
+}
+
+// Simple waveform following played note one or two octaves down
+pub struct SubOscillator {
+    buffer: SampleBuffer,
+    envelope: Shared<ADSREnvelope>,
+    voices: Voices,
+    shape: SharedParameter<i32>,
+    octave: SharedParameter<i32>,
+    pitch_bend_range: SharedParameter<i32>,
+    pan: Shared<PanParameter>,
+    gain: Shared<VolumeParameter>,
+    parameters_f32: Vec<NamedParameter<f32>>,
+    parameters_i32: Vec<NamedParameter<i32>>,
+    velocity: VelocityResponse,
+    pitch_bend: f32,
+}
+
+impl SubOscillator {
+    pub fn envelope(&self) -> Shared<ADSREnvelope> {
+        self.envelope.clone()
+    }
+
+    // Values of SubShape
+    pub fn shape(&self) -> SharedParameter<i32> {
+        self.shape.clone()
+    }
+
+    pub fn set_shape(&mut self, shape: SubShape) -> &mut Self {
+        self.shape.write().unwrap().set_value(shape as i32);
+        self
+    }
+
+    // Octaves below played note, 1 or 2
+    pub fn octave(&self) -> SharedParameter<i32> {
+        self.octave.clone()
+    }
+
+    pub fn pitch_bend_range(&self) -> SharedParameter<i32> {
+        self.pitch_bend_range.clone()
+    }
+
+    pub fn pan(&self) -> Shared<PanParameter> {
+        self.pan.clone()
+    }
+
+    pub fn gain(&self) -> SharedParameter<f32> {
+        self.gain.clone()
+    }
+
+    pub fn velocity(&self) -> &VelocityResponse {
+        &self.velocity
+    }
+
+    pub fn velocity_mut(&mut self) -> &mut VelocityResponse {
+        &mut self.velocity
+    }
+
+    pub fn is_playing(&self, note: u32) -> bool {
+        self.voices.is_playing(note)
+    }
+}
+
+impl Oscillator for SubOscillator {
+    fn evaluate(&mut self, size: usize, delta_time: f32) -> Result<(), Error> {
+        let envelope = self.envelope.clone();
+        let envelope = envelope.read().unwrap();
+        self.voices.remove_released(&envelope);
+        let shape = WaveShape::from(SubShape::from(self.shape.read().unwrap().get_value()));
+        let octave = self.octave.read().unwrap().get_value();
+        let divider = (1 << octave) as f32;
+        let bend_range = self.pitch_bend_range.read().unwrap().get_value();
+        let bend = semitones_to_freq_coefficient(self.pitch_bend * bend_range as f32);
+        let mut pan = self.pan.write().unwrap();
+        let gain = self.gain.read().unwrap().linear;
+
+        (0..size).try_for_each(|i| -> Result<(), Error> {
+            pan.next_value(delta_time)?;
+            let (left, right) = pan.polar;
+            let sample =
+                self.voices
+                    .iter_mut()
+                    .try_fold(0.0, |sum, note| -> Result<f32, Error> {
+                        let level = envelope_value_at(note.play_time, note, &envelope);
+                        let velocity = self.velocity.gain(note.velocity);
+                        let sample = shape.evaluate(PI_2M * note.phase)? * level * velocity;
+                        let freq = note.frequency / divider
+                            * bend
+                            * semitones_to_freq_coefficient(note.expression.pitch_bend);
+                        note.phase = (note.phase + freq * delta_time).fract();
+                        note.play_time += delta_time;
+                        Ok(sum + sample)
+                    })?;
+            self.buffer.set_at(0, i, sample * gain * left)?;
+            self.buffer.set_at(1, i, sample * gain * right)
+        })
+    }
+
+    fn get_buffer_mut(&mut self) -> &mut SampleBuffer {
+        &mut self.buffer
+    }
+
+    fn get_buffer(&self) -> &SampleBuffer {
+        &self.buffer
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn parameters(&mut self) -> Option<&mut dyn NamedParametersContainer> {
+        Some(self)
+    }
+
+    fn velocity_response(&mut self) -> Option<&mut VelocityResponse> {
+        Some(&mut self.velocity)
+    }
+
+    fn shared_envelope(&self) -> Option<Shared<ADSREnvelope>> {
+        Some(self.envelope.clone())
+    }
+}
+
+impl NamedParametersContainer for SubOscillator {
+    fn name(&self) -> Option<&'static str> {
+        Some("Sub")
+    }
+
+    fn parameters_f32(&self) -> Option<&[NamedParameter<f32>]> {
+        Some(&self.parameters_f32)
+    }
+
+    fn parameters_i32(&self) -> Option<&[NamedParameter<i32>]> {
+        Some(&self.parameters_i32)
+    }
+}
+
+impl NoteEventReceiver for SubOscillator {
+    fn note_on(&mut self, note: Note) -> Result<(), Error> {
+        self.voices.note_on(note);
+        Ok(())
+    }
+
+    fn note_off(&mut self, note: u32) -> Result<(), Error> {
+        self.voices.note_off(note);
+        Ok(())
+    }
+
+    fn member_note_off(&mut self, channel: u8, note: u32) -> Result<(), Error> {
+        self.voices.member_note_off(channel, note);
+        Ok(())
+    }
+
+    fn release_all(&mut self) {
+        self.voices.release_all();
+    }
+
+    fn pitch_bend(&mut self, value: f32) {
+        self.pitch_bend = value;
+    }
+
+    fn note_expression(&mut self, channel: u8, note: u32, expression: Expression) {
+        self.voices.note_expression(channel, note, expression);
+    }
+
+    fn sustain(&mut self, hold: bool) -> Result<(), Error> {
+        self.voices.sustain(hold);
+        Ok(())
+    }
+
+    fn sostenuto(&mut self, hold: bool) -> Result<(), Error> {
+        self.voices.sostenuto(hold);
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct SubOscillatorBuilder {
+    buffer: Option<SampleBuffer>,
+    envelope: Option<ADSREnvelope>,
+}
+
+impl SubOscillatorBuilder {
+    pub fn new() -> Self {
+        Self {
+            buffer: None,
+            envelope: None,
+        }
+    }
+
+    pub fn set_buffer(&mut self, buffer: SampleBuffer) -> &mut Self {
+        self.buffer = Some(buffer);
+        self
+    }
+
+    pub fn set_envelope(&mut self, envelope: ADSREnvelope) -> &mut Self {
+        self.envelope = Some(envelope);
+        self
+    }
+
+    pub fn build(&mut self) -> Result<SubOscillator, Error> {
+        let buffer = self.buffer.take().ok_or(Error::Specify("samples buffer"))?;
+        let envelope = make_shared(self.envelope.take().ok_or(Error::Specify("envelope"))?);
+        let pan = make_shared(PanParameter::default());
+        let gain = make_shared(VolumeParameter::default());
+        let velocity = VelocityResponse::default();
+        let parameters_f32 = vec![
+            NamedParameter::new(pan.clone(), "Pan"),
+            NamedParameter::new(gain.clone(), "Gain"),
+            NamedParameter::new(velocity.sensitivity(), "Velocity"),
+        ];
+        let shape: SharedParameter<i32> = make_shared(ValueParameter::new(0, (0, 3)));
+        let octave: SharedParameter<i32> = make_shared(ValueParameter::new(1, (1, 2)));
+        let pitch_bend_range: SharedParameter<i32> = make_shared(ValueParameter::new(2, (0, 24)));
+        let parameters_i32 = vec![
+            NamedParameter::new(shape.clone(), "Shape"),
+            NamedParameter::new(octave.clone(), "Octave"),
+            NamedParameter::new(pitch_bend_range.clone(), "Bend"),
+        ];
+        Ok(SubOscillator {
+            buffer,
+            envelope,
+            voices: Voices::default(),
+            shape,
+            octave,
+            pitch_bend_range,
+            pan,
+            gain,
+            parameters_f32,
+            parameters_i32,
+            velocity,
+            pitch_bend: 0.0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_approx_eq::assert_approx_eq;
+
+    use crate::{
+        core::{
+            note::{Note, NoteEventReceiver},
+            oscillator::Oscillator,
+        },
+        utils::{adsr_envelope::ADSREnvelope, sample_buffer::SampleBufferBuilder},
+    };
+
+    use super::{SubOscillator, SubOscillatorBuilder};
+
+    fn build_sub() -> SubOscillator {
+        let buffer = SampleBufferBuilder::new()
+            .set_channels(2)
+            .set_samples(10)
+            .build()
+            .unwrap();
+        SubOscillatorBuilder::new()
+            .set_buffer(buffer)
+            .set_envelope(ADSREnvelope::default())
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_sub_octave() {
+        let delta_time = 1.0 / 44100.0;
+        let mut sub = build_sub();
+        sub.note_on(Note::from(69)).unwrap();
+        sub.evaluate(1, delta_time).unwrap();
+        assert_approx_eq!(sub.voices.notes[0].phase, 220.0 * delta_time, 1e-5);
+
+        sub.octave().write().unwrap().set_value(2);
+        sub.note_on(Note::from(69)).unwrap();
+        sub.evaluate(1, delta_time).unwrap();
+        assert_approx_eq!(sub.voices.notes[0].phase, 110.0 * delta_time, 1e-5);
+        assert_eq!(sub.voices.release_notes.len(), 1);
+
+        sub.sustain(true).unwrap();
+        sub.note_off(69).unwrap();
+        assert!(sub.is_playing(69));
+        sub.sustain(false).unwrap();
+        assert!(!sub.is_playing(69));
+    }
+}
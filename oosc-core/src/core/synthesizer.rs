@@ -1,7 +1,17 @@
-use crate::{utils::{Shared, SharedMutex}, effects::Effect};
-use rayon::prelude::*;
+use crate::{
+    effects::Effect,
+    utils::{make_shared, Shared, SharedMutex},
+};
 
-use super::{note::Note, oscillator::Oscillator};
+use super::{
+    modulation_config::ModulationConfig,
+    note::{Expression, Note, NoteEventReceiver},
+    oscillator::Oscillator,
+    parameter::{parameters_paths, PathParameter},
+    part::{Part, PartBuilder, Route},
+    preset::PreparedPreset,
+    tuning::Tuning,
+};
 use crate::{
     error::Error,
     utils::sample_buffer::{SampleBuffer, SampleBufferBuilder},
@@ -14,9 +24,9 @@ pub type SyncSynthesizer = SharedMutex<Synthesizer>;
 
 pub struct Synthesizer {
     buffer: SampleBuffer,
-    oscillators: Vec<LockedOscillator>,
-    effects: Vec<LockedEffect>,
+    parts: Vec<Part>,
     sample_rate: u32,
+    tuning: Shared<Tuning>,
 }
 
 impl Synthesizer {
@@ -24,20 +34,10 @@ impl Synthesizer {
         let buffer = &mut self.buffer;
         let delta_time = 1.0 / self.sample_rate as f32;
         buffer.fill(0.);
-        self.oscillators
-            .par_iter_mut()
-            .try_for_each(|osc| -> Result<(), Error> {
-                osc.write().unwrap().evaluate(size, delta_time)
-            })?;
-        self.oscillators
-            .iter()
-            .try_for_each(|osc| -> Result<(), Error> {
-                buffer.combine(osc.write().unwrap().get_buffer())
-            })?;
-        self.effects
+        self.parts
             .iter_mut()
-            .try_for_each(|effect| -> Result<(), Error> {
-                effect.write().unwrap().process(size, buffer)
+            .try_for_each(|part| -> Result<(), Error> {
+                buffer.combine(part.output(size, delta_time)?)
             })?;
         Ok(&self.buffer)
     }
@@ -47,35 +47,183 @@ impl Synthesizer {
     }
 
     pub fn release_all(&mut self) {
-        self.oscillators.par_iter_mut().for_each(|osc| {
-            osc.write().unwrap().release_all();
-        })
+        self.parts.iter_mut().for_each(|part| part.release_all())
+    }
+
+    // Unmapped keys of tuning are not played
+    pub fn note_on(&mut self, route: Route, note: Note) -> Result<(), Error> {
+        let Some(note) = self.tuning.read().unwrap().tune(note) else {
+            return Ok(());
+        };
+        self.routed(route).try_for_each(|part| part.note_on(note))
     }
 
-    pub fn note_on(&mut self, note: Note) -> Result<(), Error> {
-        self.oscillators
-            .par_iter_mut()
-            .try_for_each(|osc| -> Result<(), Error> { osc.write().unwrap().note_on(note) })
+    pub fn tuning(&self) -> Shared<Tuning> {
+        self.tuning.clone()
     }
 
-    pub fn note_off(&mut self, note: u32) -> Result<(), Error> {
-        self.oscillators
-            .par_iter_mut()
-            .try_for_each(|osc| -> Result<(), Error> { osc.write().unwrap().note_off(note) })
+    pub fn apply_modulation(&mut self, config: &ModulationConfig) -> Result<(), Error> {
+        self.parts
+            .get_mut(config.part())
+            .ok_or(format!("Part {} not found", config.part() + 1))?
+            .apply_modulation(config)
+    }
+
+    pub fn note_off(&mut self, route: Route, note: u32) -> Result<(), Error> {
+        self.routed(route).try_for_each(|part| part.note_off(note))
+    }
+
+    pub fn pitch_bend(&mut self, route: Route, value: f32) {
+        self.routed(route).for_each(|part| part.pitch_bend(value))
+    }
+
+    pub fn member_note_off(&mut self, route: Route, channel: u8, note: u32) -> Result<(), Error> {
+        self.routed(route)
+            .try_for_each(|part| part.member_note_off(channel, note))
+    }
+
+    pub fn note_expression(
+        &mut self,
+        route: Route,
+        channel: u8,
+        note: u32,
+        expression: Expression,
+    ) {
+        self.routed(route)
+            .for_each(|part| part.note_expression(channel, note, expression))
+    }
+
+    pub fn sustain(&mut self, route: Route, hold: bool) -> Result<(), Error> {
+        self.routed(route).try_for_each(|part| part.sustain(hold))
+    }
+
+    pub fn sostenuto(&mut self, route: Route, hold: bool) -> Result<(), Error> {
+        self.routed(route).try_for_each(|part| part.sostenuto(hold))
+    }
+
+    pub fn get_parts(&self) -> &[Part] {
+        &self.parts
+    }
+
+    pub fn get_parts_mut(&mut self) -> &mut [Part] {
+        &mut self.parts
     }
 
     pub fn get_oscillators<T>(&self) -> impl Iterator<Item = LockedOscillator> + '_
     where
         T: Oscillator + 'static,
     {
-        self.oscillators.iter().filter_map(|osc| {
-            let osc_lock = osc.write().unwrap();
-            osc_lock.as_any().downcast_ref::<T>().map(|_| osc.clone())
-        })
+        self.parts
+            .iter()
+            .flat_map(|part| part.oscillators())
+            .filter(|osc| {
+                let osc_lock = osc.write().unwrap();
+                osc_lock.as_any().downcast_ref::<T>().is_some()
+            })
     }
 
     pub fn get_named_effects(&self) -> impl Iterator<Item = LockedEffect> + '_ {
-        self.effects.iter().cloned()
+        self.parts.iter().flat_map(|part| part.effects())
+    }
+
+    // Paths of part parameters prefixed by part, e.g. "Part1/Oscillator1/Gain"
+    pub fn get_named_parameters(&self) -> Vec<PathParameter<f32>> {
+        self.parts
+            .iter()
+            .enumerate()
+            .flat_map(|(i, part)| {
+                part_paths(i, part.get_named_parameters()).chain(parameters_paths(part, i))
+            })
+            .collect()
+    }
+
+    pub fn get_named_parameters_i32(&self) -> Vec<PathParameter<i32>> {
+        self.parts
+            .iter()
+            .enumerate()
+            .flat_map(|(i, part)| part_paths(i, part.get_named_parameters_i32()))
+            .collect()
+    }
+
+    // Number of parts receiving events of route, each needs its own prepared preset
+    pub fn routed_count(&self, route: Route) -> usize {
+        let route = self.resolve(route);
+        self.parts.iter().filter(|part| part.accepts(route)).count()
+    }
+
+    // Presets are handed to routed parts in order, parts without one are skipped
+    pub fn apply_preset(
+        &mut self,
+        route: Route,
+        presets: Vec<PreparedPreset>,
+        crossfade: f32,
+    ) -> Result<(), Error> {
+        self.routed(route)
+            .zip(presets)
+            .try_for_each(|(part, preset)| part.apply_preset(preset, crossfade))
+    }
+
+    // Tracks claimed by some part are routed by track, other events by channel
+    fn resolve(&self, route: Route) -> Route {
+        let claimed = route
+            .track
+            .is_some_and(|track| self.parts.iter().any(|part| part.tracks().contains(&track)));
+        match claimed {
+            true => route,
+            false => Route::channel(route.channel),
+        }
+    }
+
+    fn routed(&mut self, route: Route) -> impl Iterator<Item = &mut Part> {
+        let route = self.resolve(route);
+        self.parts
+            .iter_mut()
+            .filter(move |part| part.accepts(route))
+    }
+}
+
+fn part_paths<T: Clone>(
+    index: usize,
+    parameters: Vec<PathParameter<T>>,
+) -> impl Iterator<Item = PathParameter<T>> {
+    parameters.into_iter().map(move |p| PathParameter {
+        path: format!("Part{}/{}", index + 1, p.path),
+        parameter: p.parameter,
+    })
+}
+
+// Notes without MIDI channel, e.g. from computer keyboard, are played by parts of first channel
+impl NoteEventReceiver for Synthesizer {
+    fn note_on(&mut self, note: Note) -> Result<(), Error> {
+        Synthesizer::note_on(self, Route::channel(0), note)
+    }
+
+    fn note_off(&mut self, note: u32) -> Result<(), Error> {
+        Synthesizer::note_off(self, Route::channel(0), note)
+    }
+
+    fn release_all(&mut self) {
+        Synthesizer::release_all(self)
+    }
+
+    fn pitch_bend(&mut self, value: f32) {
+        Synthesizer::pitch_bend(self, Route::channel(0), value)
+    }
+
+    fn member_note_off(&mut self, channel: u8, note: u32) -> Result<(), Error> {
+        Synthesizer::member_note_off(self, Route::channel(0), channel, note)
+    }
+
+    fn note_expression(&mut self, channel: u8, note: u32, expression: Expression) {
+        Synthesizer::note_expression(self, Route::channel(0), channel, note, expression)
+    }
+
+    fn sustain(&mut self, hold: bool) -> Result<(), Error> {
+        Synthesizer::sustain(self, Route::channel(0), hold)
+    }
+
+    fn sostenuto(&mut self, hold: bool) -> Result<(), Error> {
+        Synthesizer::sostenuto(self, Route::channel(0), hold)
     }
 }
 
@@ -84,7 +232,9 @@ pub struct SynthesizerBuilder {
     buffer: Option<SampleBuffer>,
     oscillators: Option<Vec<LockedOscillator>>,
     effects: Option<Vec<LockedEffect>>,
+    parts: Vec<Part>,
     sample_rate: Option<u32>,
+    tuning: Option<Tuning>,
 }
 
 impl SynthesizerBuilder {
@@ -93,7 +243,9 @@ impl SynthesizerBuilder {
             buffer: None,
             oscillators: None,
             effects: None,
+            parts: vec![],
             sample_rate: None,
+            tuning: None,
         }
     }
 
@@ -130,25 +282,51 @@ impl SynthesizerBuilder {
         self
     }
 
+    pub fn add_part(&mut self, part: Part) -> &mut Self {
+        self.parts.push(part);
+        self
+    }
+
     pub fn set_sample_rate(&mut self, sample_rate: u32) -> &mut Self {
         self.sample_rate = Some(sample_rate);
         self
     }
 
+    pub fn set_tuning(&mut self, tuning: Tuning) -> &mut Self {
+        self.tuning = Some(tuning);
+        self
+    }
+
     pub fn build(&mut self) -> Result<Synthesizer, Error> {
         let buffer = self.buffer.take().ok_or(Error::Specify("buffer size"))?;
-        let oscillators = self
-            .oscillators
-            .take()
-            .ok_or(Error::Specify("oscillators"))?;
         let sample_rate = self.sample_rate.ok_or(Error::Specify("sample_rate"))?;
-        let effects = self.effects.take().unwrap_or(vec![]);
+        let mut parts = vec![];
+        // Oscillators and effects added directly form default part, which listens all channels
+        if let Some(oscillators) = self.oscillators.take() {
+            let mut builder = PartBuilder::new();
+            builder.set_buffer(buffer.len())?;
+            oscillators.into_iter().for_each(|osc| {
+                builder.add_osc(osc);
+            });
+            self.effects
+                .take()
+                .unwrap_or_default()
+                .into_iter()
+                .for_each(|effect| {
+                    builder.add_effect(effect);
+                });
+            parts.push(builder.build()?);
+        }
+        parts.append(&mut self.parts);
+        if parts.is_empty() {
+            return Err(Error::Specify("oscillators"));
+        }
 
         Ok(Synthesizer {
             buffer,
-            oscillators,
-            effects,
+            parts,
             sample_rate,
+            tuning: make_shared(self.tuning.take().unwrap_or_default()),
         })
     }
 }
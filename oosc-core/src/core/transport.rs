@@ -0,0 +1,181 @@
+use std::{collections::VecDeque, time::Instant};
+
+pub const CLOCKS_PER_BEAT: usize = 24;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TransportState {
+    Stopped,
+    Playing,
+}
+
+// Estimates tempo from intervals between MIDI clock messages
+#[derive(Default)]
+pub struct MidiClock {
+    last: Option<f32>,
+    intervals: VecDeque<f32>,
+}
+
+impl MidiClock {
+    pub fn tick(&mut self, time: f32) -> Option<f32> {
+        let last = self.last.replace(time)?;
+        let interval = time - last;
+        // Too long pause means clock was stopped, start estimation again
+        if interval <= 0.0 || interval > 1.0 {
+            self.intervals.clear();
+            return None;
+        }
+        if self.intervals.len() == CLOCKS_PER_BEAT {
+            self.intervals.pop_front();
+        }
+        self.intervals.push_back(interval);
+        let average = self.intervals.iter().sum::<f32>() / self.intervals.len() as f32;
+        Some(60.0 / (average * CLOCKS_PER_BEAT as f32))
+    }
+
+    pub fn reset(&mut self) {
+        self.last = None;
+        self.intervals.clear();
+    }
+}
+
+pub struct Transport {
+    state: TransportState,
+    bpm: f32,
+    position: f32,
+    restart: bool,
+    clock: MidiClock,
+    external_clock: bool,
+    epoch: Instant,
+}
+
+impl Transport {
+    pub fn new(bpm: f32) -> Self {
+        Self {
+            state: TransportState::Stopped,
+            bpm,
+            position: 0.0,
+            restart: false,
+            clock: MidiClock::default(),
+            external_clock: false,
+            epoch: Instant::now(),
+        }
+    }
+
+    pub fn bpm(&self) -> f32 {
+        self.bpm
+    }
+
+    pub fn set_bpm(&mut self, bpm: f32) {
+        self.bpm = bpm;
+    }
+
+    pub fn state(&self) -> TransportState {
+        self.state
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.state == TransportState::Playing
+    }
+
+    // Position in beats from start
+    pub fn position(&self) -> f32 {
+        self.position
+    }
+
+    pub fn set_position(&mut self, beats: f32) {
+        self.position = beats;
+    }
+
+    // True if tempo is estimated from incoming MIDI clock
+    pub fn is_external_clock(&self) -> bool {
+        self.external_clock
+    }
+
+    pub fn start(&mut self) {
+        self.position = 0.0;
+        self.restart = true;
+        self.state = TransportState::Playing;
+    }
+
+    pub fn resume(&mut self) {
+        self.state = TransportState::Playing;
+    }
+
+    pub fn stop(&mut self) {
+        self.state = TransportState::Stopped;
+    }
+
+    // Returns true once after every start from beginning
+    pub fn take_restart(&mut self) -> bool {
+        std::mem::take(&mut self.restart)
+    }
+
+    pub fn advance(&mut self, delta_time: f32) {
+        if self.is_playing() {
+            self.position += delta_time * self.bpm / 60.0;
+        }
+    }
+
+    pub fn clock(&mut self) {
+        let time = self.epoch.elapsed().as_secs_f32();
+        self.clock_at(time);
+    }
+
+    pub fn clock_at(&mut self, time: f32) {
+        match self.clock.tick(time) {
+            Some(bpm) => {
+                self.bpm = bpm;
+                self.external_clock = true;
+            }
+            None => self.external_clock = false,
+        }
+    }
+
+    pub fn reset_clock(&mut self) {
+        self.clock.reset();
+        self.external_clock = false;
+    }
+}
+
+impl Default for Transport {
+    fn default() -> Self {
+        Self::new(120.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_approx_eq::assert_approx_eq;
+
+    use super::{Transport, TransportState, CLOCKS_PER_BEAT};
+
+    #[test]
+    fn test_clock_tempo() {
+        let mut transport = Transport::default();
+        let interval = 60.0 / (90.0 * CLOCKS_PER_BEAT as f32);
+        (0..48).for_each(|i| transport.clock_at(i as f32 * interval));
+        assert!(transport.is_external_clock());
+        assert_approx_eq!(transport.bpm(), 90.0, 0.01);
+        transport.clock_at(100.0);
+        assert!(!transport.is_external_clock());
+        assert_approx_eq!(transport.bpm(), 90.0, 0.01);
+    }
+
+    #[test]
+    fn test_transport_state() {
+        let mut transport = Transport::new(120.0);
+        transport.advance(1.0);
+        assert_eq!(transport.position(), 0.0);
+        transport.start();
+        assert!(transport.take_restart());
+        assert!(!transport.take_restart());
+        transport.advance(1.0);
+        assert_approx_eq!(transport.position(), 2.0);
+        transport.stop();
+        assert_eq!(transport.state(), TransportState::Stopped);
+        transport.advance(1.0);
+        transport.resume();
+        transport.advance(0.5);
+        assert_approx_eq!(transport.position(), 3.0);
+    }
+}
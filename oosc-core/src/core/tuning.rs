@@ -0,0 +1,411 @@
+use crate::{
+    error::Error,
+    utils::convert::{note_to_freq, pitch_to_freq},
+};
+
+use super::note::Note;
+
+// Value of MTS frequency data meaning that note is not changed
+const MTS_NO_CHANGE: (u8, u8, u8) = (0x7F, 0x7F, 0x7F);
+const MTS_SUB_ID: u8 = 0x08;
+
+fn parse_error(kind: &str, message: &str) -> Error {
+    format!("Invalid {} file: {}", kind, message).into()
+}
+
+// Non-comment lines of Scala files, comments start with '!'
+fn data_lines(text: &str) -> impl Iterator<Item = &str> {
+    text.lines()
+        .map(|line| line.trim_end_matches('\r'))
+        .filter(|line| !line.starts_with('!'))
+}
+
+// Scale from Scala .scl file, degrees are in cents and the last one is the period
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scale {
+    pub description: String,
+    pub degrees: Vec<f32>,
+}
+
+impl Scale {
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let mut lines = data_lines(text);
+        let description = lines
+            .next()
+            .ok_or_else(|| parse_error("scale", "no description"))?
+            .trim()
+            .to_owned();
+        let count = lines
+            .next()
+            .and_then(|line| line.split_whitespace().next()?.parse::<usize>().ok())
+            .ok_or_else(|| parse_error("scale", "no number of notes"))?;
+        let degrees = lines
+            .filter(|line| !line.trim().is_empty())
+            .take(count)
+            .map(Self::parse_pitch)
+            .collect::<Result<Vec<_>, _>>()?;
+        if degrees.len() != count || count == 0 {
+            return Err(parse_error("scale", "wrong number of notes"));
+        }
+        Ok(Self {
+            description,
+            degrees,
+        })
+    }
+
+    pub fn load<P: AsRef<std::path::Path>>(path: P) -> Result<Self, Error> {
+        Self::parse(&std::fs::read_to_string(path).map_err(|e| e.to_string())?)
+    }
+
+    // Values with period are cents, others are ratios like 3/2 or 2
+    fn parse_pitch(line: &str) -> Result<f32, Error> {
+        let value = line
+            .split_whitespace()
+            .next()
+            .ok_or_else(|| parse_error("scale", "empty pitch"))?;
+        let invalid = || parse_error("scale", &format!("bad pitch {}", value));
+        if value.contains('.') {
+            return value.parse::<f32>().map_err(|_| invalid());
+        }
+        let (numerator, denominator) = value.split_once('/').unwrap_or((value, "1"));
+        let numerator = numerator.parse::<f32>().map_err(|_| invalid())?;
+        let denominator = denominator.parse::<f32>().map_err(|_| invalid())?;
+        if numerator <= 0.0 || denominator <= 0.0 {
+            return Err(invalid());
+        }
+        Ok(1200.0 * (numerator / denominator).log2())
+    }
+
+    // Cents of any scale degree, degree 0 is the tonic
+    pub fn cents(&self, degree: i32) -> f32 {
+        let size = self.degrees.len() as i32;
+        let period = self.degrees[self.degrees.len() - 1];
+        let step = match degree.rem_euclid(size) {
+            0 => 0.0,
+            i => self.degrees[i as usize - 1],
+        };
+        degree.div_euclid(size) as f32 * period + step
+    }
+}
+
+// Keyboard mapping from Scala .kbm file
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyboardMapping {
+    pub first: u32,
+    pub last: u32,
+    // Key of scale tonic
+    pub middle: u32,
+    pub reference_key: u32,
+    pub reference_frequency: f32,
+    // Scale degree repeated each mapping size keys
+    pub octave_degree: i32,
+    // Scale degree of each key from middle key, None for unmapped keys. Empty is linear mapping
+    pub mapping: Vec<Option<i32>>,
+}
+
+impl KeyboardMapping {
+    // Consecutive keys play consecutive degrees, tonic is on middle key
+    pub fn linear(middle: u32, reference_key: u32, reference_frequency: f32) -> Self {
+        Self {
+            first: 0,
+            last: 127,
+            middle,
+            reference_key,
+            reference_frequency,
+            octave_degree: 0,
+            mapping: vec![],
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let mut lines = data_lines(text).filter(|line| !line.trim().is_empty());
+        let mut next = |name: &str| {
+            lines
+                .next()
+                .and_then(|line| line.split_whitespace().next())
+                .ok_or_else(|| parse_error("keyboard mapping", &format!("no {}", name)))
+        };
+        fn number<T: std::str::FromStr>(value: &str) -> Result<T, Error> {
+            value
+                .parse::<T>()
+                .map_err(|_| parse_error("keyboard mapping", &format!("bad value {}", value)))
+        }
+        let size = number::<usize>(next("map size")?)?;
+        let first = number(next("first note")?)?;
+        let last = number(next("last note")?)?;
+        let middle = number(next("middle note")?)?;
+        let reference_key = number(next("reference note")?)?;
+        let reference_frequency = number(next("reference frequency")?)?;
+        let octave_degree = number(next("octave degree")?)?;
+        let mapping = (0..size)
+            .map(|_| match next("mapping") {
+                Ok("x") | Err(_) => Ok(None),
+                Ok(value) => number(value).map(Some),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            first,
+            last,
+            middle,
+            reference_key,
+            reference_frequency,
+            octave_degree,
+            mapping,
+        })
+    }
+
+    pub fn load<P: AsRef<std::path::Path>>(path: P) -> Result<Self, Error> {
+        Self::parse(&std::fs::read_to_string(path).map_err(|e| e.to_string())?)
+    }
+
+    // Scale degree played by key
+    fn degree(&self, key: u32, scale: &Scale) -> Option<i32> {
+        let offset = key as i32 - self.middle as i32;
+        if self.mapping.is_empty() {
+            return Some(offset);
+        }
+        let size = self.mapping.len() as i32;
+        let octave_degree = match self.octave_degree {
+            0 => scale.degrees.len() as i32,
+            degree => degree,
+        };
+        let degree = self.mapping[offset.rem_euclid(size) as usize]?;
+        Some(offset.div_euclid(size) * octave_degree + degree)
+    }
+}
+
+impl Default for KeyboardMapping {
+    fn default() -> Self {
+        Self::linear(60, 69, 440.0)
+    }
+}
+
+// Frequencies of all MIDI keys, None for unmapped keys which are not played
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tuning {
+    frequencies: [Option<f32>; 128],
+}
+
+impl Tuning {
+    // 12-TET with frequency of A4
+    pub fn equal(reference: f32) -> Self {
+        let mut frequencies = [None; 128];
+        frequencies.iter_mut().enumerate().for_each(|(key, f)| {
+            *f = Some(note_to_freq(key as u32) * reference / 440.0);
+        });
+        Self { frequencies }
+    }
+
+    // Keys outside of mapping range keep 12-TET pitch
+    pub fn from_scale(scale: &Scale, mapping: &KeyboardMapping) -> Result<Self, Error> {
+        let reference = mapping
+            .degree(mapping.reference_key, scale)
+            .ok_or("Reference key of keyboard mapping is unmapped")?;
+        let reference_cents = scale.cents(reference);
+        let mut tuning = Self::equal(440.0);
+        (mapping.first..=mapping.last.min(127)).for_each(|key| {
+            tuning.frequencies[key as usize] = mapping.degree(key, scale).map(|degree| {
+                let cents = scale.cents(degree) - reference_cents;
+                mapping.reference_frequency * 2.0_f32.powf(cents / 1200.0)
+            });
+        });
+        Ok(tuning)
+    }
+
+    pub fn load<P: AsRef<std::path::Path>>(scale: P, mapping: Option<P>) -> Result<Self, Error> {
+        let mapping = match mapping {
+            Some(path) => KeyboardMapping::load(path)?,
+            None => KeyboardMapping::default(),
+        };
+        Self::from_scale(&Scale::load(scale)?, &mapping)
+    }
+
+    pub fn frequency(&self, key: u32) -> Option<f32> {
+        *self.frequencies.get(key as usize)?
+    }
+
+    pub fn set_frequency(&mut self, key: u32, frequency: Option<f32>) -> &mut Self {
+        if let Some(f) = self.frequencies.get_mut(key as usize) {
+            *f = frequency;
+        }
+        self
+    }
+
+    // Note with tuned frequency, None if key is unmapped
+    pub fn tune(&self, note: Note) -> Option<Note> {
+        let frequency = self.frequency(note.note)?;
+        Some(Note { frequency, ..note })
+    }
+
+    // Applies MIDI Tuning Standard message, data may include F0 and F7 bytes.
+    // Returns false for other SysEx messages. Changes apply to the next played notes,
+    // tuning program and bank numbers and channel masks are ignored
+    pub fn receive_sysex(&mut self, data: &[u8]) -> Result<bool, Error> {
+        let data = data.strip_prefix(&[0xF0]).unwrap_or(data);
+        let data = data.strip_suffix(&[0xF7]).unwrap_or(data);
+        let [universal @ (0x7E | 0x7F), _device, MTS_SUB_ID, format, body @ ..] = data else {
+            return Ok(false);
+        };
+        // Truncated messages are ignored like unknown ones, broken dump should not stop playback
+        let applied = match (universal, format) {
+            // Bulk dump, with bank for 0x04: program, 16 bytes of name, 128 frequencies
+            (0x7E, 0x01 | 0x04) => {
+                let skip = if *format == 0x04 { 18 } else { 17 };
+                body.get(skip..skip + 128 * 3).map(|frequencies| {
+                    frequencies
+                        .chunks(3)
+                        .enumerate()
+                        .for_each(|(key, f)| self.set_mts_frequency(key as u32, f))
+                })
+            }
+            // Single note tuning change, with bank for 0x07
+            (0x7F, 0x02) | (0x7E | 0x7F, 0x07) => {
+                let skip = if *format == 0x07 { 2 } else { 1 };
+                body.get(skip)
+                    .and_then(|count| body.get(skip + 1..skip + 1 + *count as usize * 4))
+                    .map(|changes| {
+                        changes.chunks(4).for_each(|change| {
+                            self.set_mts_frequency(change[0] as u32, &change[1..])
+                        })
+                    })
+            }
+            // Scale/octave tuning, offsets in cents of 12 notes after 3 bytes of channel mask
+            (_, 0x08) => body
+                .get(3..15)
+                .map(|offsets| self.set_octave_offsets(|i| offsets[i] as f32 - 64.0)),
+            (_, 0x09) => body.get(3..27).map(|offsets| {
+                self.set_octave_offsets(|i| {
+                    let value = ((offsets[2 * i] as u16) << 7) | offsets[2 * i + 1] as u16;
+                    (value as f32 - 8192.0) * 100.0 / 8192.0
+                })
+            }),
+            _ => None,
+        };
+        Ok(applied.is_some())
+    }
+
+    // Semitone and 14-bit fraction of semitone above it
+    fn set_mts_frequency(&mut self, key: u32, data: &[u8]) {
+        let &[semitone, msb, lsb] = data else {
+            return;
+        };
+        if (semitone, msb, lsb) == MTS_NO_CHANGE {
+            return;
+        }
+        let fraction = (((msb as u16) << 7) | lsb as u16) as f32 / 16384.0;
+        self.set_frequency(key, Some(pitch_to_freq(semitone as f32 + fraction)));
+    }
+
+    fn set_octave_offsets<F: Fn(usize) -> f32>(&mut self, cents: F) {
+        (0..128).for_each(|key: u32| {
+            let offset = cents(key as usize % 12);
+            let frequency = note_to_freq(key) * 2.0_f32.powf(offset / 1200.0);
+            self.set_frequency(key, Some(frequency));
+        });
+    }
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Self::equal(440.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_approx_eq::assert_approx_eq;
+
+    use crate::core::note::Note;
+
+    use super::{KeyboardMapping, Scale, Tuning};
+
+    const SCALE: &str = "! meantone.scl
+!
+Test scale with ratios and cents
+ 5
+!
+ 9/8
+ 300.0 cents
+ 3/2
+ 700.0
+ 2
+";
+
+    #[test]
+    fn test_scale() {
+        let scale = Scale::parse(SCALE).unwrap();
+        assert_eq!(scale.description, "Test scale with ratios and cents");
+        assert_approx_eq!(scale.degrees[0], 203.91, 0.01);
+        assert_approx_eq!(scale.cents(5), 1200.0);
+        assert_approx_eq!(scale.cents(-2), -498.04, 0.01);
+        assert!(Scale::parse("bad\n2\n100.0\n").is_err());
+
+        // Tonic on C4, A4 is 9 keys above it and reference frequency is 440 Hz
+        let tuning = Tuning::from_scale(&scale, &KeyboardMapping::default()).unwrap();
+        assert_approx_eq!(tuning.frequency(69).unwrap(), 440.0, 0.01);
+        let c4 = 440.0 / 2.0_f32.powf(scale.cents(9) / 1200.0);
+        assert_approx_eq!(tuning.frequency(60).unwrap(), c4, 0.01);
+        assert_approx_eq!(tuning.frequency(65).unwrap(), c4 * 2.0, 0.01);
+        assert_approx_eq!(tuning.frequency(62).unwrap(), c4 * 1.189207, 0.01);
+    }
+
+    #[test]
+    fn test_keyboard_mapping() {
+        let text = "! 12 keys mapped to pentatonic scale
+12
+0
+127
+60
+60
+261.0
+5
+! mapping
+0
+x
+1
+x
+2
+3
+x
+4
+x
+x
+x
+x
+";
+        let mapping = KeyboardMapping::parse(text).unwrap();
+        assert_eq!(mapping.mapping.len(), 12);
+        let tuning = Tuning::from_scale(&Scale::parse(SCALE).unwrap(), &mapping).unwrap();
+        assert_approx_eq!(tuning.frequency(60).unwrap(), 261.0);
+        assert_approx_eq!(tuning.frequency(72).unwrap(), 522.0, 0.01);
+        assert_approx_eq!(tuning.frequency(65).unwrap(), 261.0 * 1.5, 0.01);
+        assert!(tuning.frequency(61).is_none());
+        assert!(tuning.tune(Note::from(61)).is_none());
+    }
+
+    #[test]
+    fn test_mts() {
+        let mut tuning = Tuning::default();
+        // Real-time single note change: A4 tuned to A#4 + 50 cents, C4 unchanged
+        let message = [
+            0xF0, 0x7F, 0x7F, 0x08, 0x02, 0x00, 0x02, 69, 70, 0x40, 0x00, 60, 0x7F, 0x7F, 0x7F,
+            0xF7,
+        ];
+        assert!(tuning.receive_sysex(&message).unwrap());
+        assert_approx_eq!(tuning.frequency(69).unwrap(), 466.16 * 1.0293, 0.05);
+        assert_approx_eq!(tuning.frequency(60).unwrap(), 261.63, 0.01);
+
+        // Scale/octave 1-byte form: every D is 20 cents lower
+        let mut message = vec![0xF0, 0x7E, 0x7F, 0x08, 0x08, 0x03, 0x7F, 0x7F];
+        message.extend((0..12).map(|i| if i == 2 { 44 } else { 64 }));
+        message.push(0xF7);
+        assert!(tuning.receive_sysex(&message).unwrap());
+        assert_approx_eq!(tuning.frequency(62).unwrap(), 293.66 * 0.98851, 0.01);
+        assert_approx_eq!(tuning.frequency(69).unwrap(), 440.0, 0.01);
+
+        assert!(!tuning.receive_sysex(&[0x7E, 0x7F, 0x06, 0x01]).unwrap());
+        assert!(!tuning
+            .receive_sysex(&[0x7F, 0x7F, 0x08, 0x02, 0x00])
+            .unwrap());
+    }
+}
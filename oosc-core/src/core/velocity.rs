@@ -0,0 +1,171 @@
+use cgmath::Vector2;
+
+use crate::{
+    error::Error,
+    utils::{cubic_bezier::CubicBezierCurve, make_shared},
+};
+
+use super::parameter::{SharedParameter, ValueParameter};
+
+const CURVE_TABLE_SIZE: usize = 128;
+
+#[derive(Clone)]
+pub enum VelocityCurve {
+    Linear,
+    // Velocity raised to exponent, 2 is the classic square curve
+    Exponential(f32),
+    // Curve from (0, a) to (1, d), x of control points should be increasing
+    Bezier(CubicBezierCurve),
+}
+
+impl VelocityCurve {
+    pub fn evaluate(&self, velocity: f32) -> f32 {
+        let velocity = velocity.clamp(0.0, 1.0);
+        match self {
+            VelocityCurve::Linear => velocity,
+            VelocityCurve::Exponential(exponent) => velocity.powf(*exponent),
+            VelocityCurve::Bezier(curve) => curve.y_at(velocity).clamp(0.0, 1.0),
+        }
+    }
+
+    pub fn bezier(b: (f32, f32), c: (f32, f32)) -> Self {
+        VelocityCurve::Bezier(CubicBezierCurve::new(
+            Vector2::new(0.0, 0.0),
+            Vector2::new(b.0, b.1),
+            Vector2::new(c.0, c.1),
+            Vector2::new(1.0, 1.0),
+        ))
+    }
+
+    // "linear", "exponential:<exponent>" or "bezier:<bx>,<by>,<cx>,<cy>"
+    pub fn parse(curve: &str) -> Result<Self, Error> {
+        let number = |value: &str| {
+            value
+                .parse::<f32>()
+                .map_err(|e| format!("Invalid curve value \"{}\": {}", value, e))
+        };
+        match curve.split_once(':') {
+            None if curve == "linear" => Ok(VelocityCurve::Linear),
+            Some(("exponential", exponent)) => Ok(VelocityCurve::Exponential(number(exponent)?)),
+            Some(("bezier", points)) => {
+                let points = points
+                    .split(',')
+                    .map(number)
+                    .collect::<Result<Vec<_>, _>>()?;
+                match points[..] {
+                    [bx, by, cx, cy] => Ok(Self::bezier((bx, by), (cx, cy))),
+                    _ => Err("Bezier curve needs two control points".into()),
+                }
+            }
+            _ => Err(format!("Unknown velocity curve \"{}\"", curve).into()),
+        }
+    }
+}
+
+// Maps note velocity to gain of oscillator
+pub struct VelocityResponse {
+    curve: VelocityCurve,
+    // Curve evaluated at evenly spaced velocities, so rendering does not solve bezier
+    table: Vec<f32>,
+    sensitivity: SharedParameter<f32>,
+}
+
+impl VelocityResponse {
+    pub fn new(curve: VelocityCurve) -> Self {
+        let sensitivity = make_shared(ValueParameter::new(1.0, (0.0, 1.0)));
+        let mut response = Self {
+            curve: VelocityCurve::Linear,
+            table: vec![],
+            sensitivity,
+        };
+        response.set_curve(curve);
+        response
+    }
+
+    pub fn curve(&self) -> &VelocityCurve {
+        &self.curve
+    }
+
+    pub fn set_curve(&mut self, curve: VelocityCurve) -> &mut Self {
+        self.table = (0..=CURVE_TABLE_SIZE)
+            .map(|i| curve.evaluate(i as f32 / CURVE_TABLE_SIZE as f32))
+            .collect();
+        self.curve = curve;
+        self
+    }
+
+    // Zero sensitivity plays every note at full level
+    pub fn sensitivity(&self) -> SharedParameter<f32> {
+        self.sensitivity.clone()
+    }
+
+    pub fn gain(&self, velocity: f32) -> f32 {
+        let position = velocity.clamp(0.0, 1.0) * CURVE_TABLE_SIZE as f32;
+        let index = (position as usize).min(CURVE_TABLE_SIZE - 1);
+        let fraction = position - index as f32;
+        let value = self.table[index] + (self.table[index + 1] - self.table[index]) * fraction;
+        let sensitivity = self.sensitivity.read().unwrap().get_value();
+        1.0 - sensitivity + sensitivity * value
+    }
+}
+
+// Square curve keeps the response oscillators had before velocity curves
+impl Default for VelocityResponse {
+    fn default() -> Self {
+        Self::new(VelocityCurve::Exponential(2.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_approx_eq::assert_approx_eq;
+
+    use crate::utils::convert::velocity_to_float;
+
+    use super::{VelocityCurve, VelocityResponse};
+
+    #[test]
+    fn test_velocity_curves() {
+        assert_approx_eq!(VelocityCurve::Linear.evaluate(0.3), 0.3);
+        assert_approx_eq!(VelocityCurve::Exponential(2.0).evaluate(0.5), 0.25);
+        assert_approx_eq!(VelocityCurve::Exponential(2.0).evaluate(1.5), 1.0);
+        let linear = VelocityCurve::bezier((1.0 / 3.0, 1.0 / 3.0), (2.0 / 3.0, 2.0 / 3.0));
+        assert_approx_eq!(linear.evaluate(0.7), 0.7, 0.001);
+        // Control points pulled up make soft notes louder
+        let soft = VelocityCurve::bezier((0.0, 0.8), (0.2, 1.0));
+        assert!(soft.evaluate(0.3) > 0.8);
+        assert_approx_eq!(soft.evaluate(1.0), 1.0, 0.001);
+    }
+
+    #[test]
+    fn test_velocity_curve_parse() {
+        assert!(matches!(
+            VelocityCurve::parse("linear"),
+            Ok(VelocityCurve::Linear)
+        ));
+        assert!(matches!(
+            VelocityCurve::parse("exponential:0.5"),
+            Ok(VelocityCurve::Exponential(e)) if e == 0.5
+        ));
+        let soft = VelocityCurve::parse("bezier:0,0.8,0.2,1").unwrap();
+        assert!(soft.evaluate(0.3) > 0.8);
+        assert!(VelocityCurve::parse("bezier:0,0.8").is_err());
+        assert!(VelocityCurve::parse("exponential:x").is_err());
+        assert!(VelocityCurve::parse("square").is_err());
+    }
+
+    #[test]
+    fn test_velocity_sensitivity() {
+        let mut response = VelocityResponse::default();
+        assert_approx_eq!(response.gain(0.5), 0.25, 0.001);
+        let velocity = velocity_to_float(64);
+        assert_approx_eq!(response.gain(velocity), 64.0 * 64.0 / (127.0 * 127.0), 0.001);
+        assert_approx_eq!(response.gain(1.0), 1.0);
+        response.sensitivity().write().unwrap().set_value(0.5);
+        assert_approx_eq!(response.gain(0.0), 0.5);
+        assert_approx_eq!(response.gain(0.5), 0.625, 0.001);
+        response.set_curve(VelocityCurve::Linear);
+        response.sensitivity().write().unwrap().set_value(0.0);
+        assert_approx_eq!(response.gain(0.1), 1.0);
+    }
+}
@@ -0,0 +1,199 @@
+use crate::utils::adsr_envelope::{ADSREnvelope, State};
+
+use super::note::{Expression, Note, Pedals, VoiceKey};
+
+// Envelope level of note, sustained while the key is down,
+// then release is scaled from the level reached at release
+pub(crate) fn envelope_value_at(t: f32, note: &Note, envelope: &ADSREnvelope) -> f32 {
+    match note.release_time {
+        Some(release) if envelope.is_gated() => {
+            let level = held_value_at(release, note, envelope);
+            envelope.release_from(level, (t - release).max(0.0))
+        }
+        _ => held_value_at(t, note, envelope),
+    }
+}
+
+fn held_value_at(t: f32, note: &Note, envelope: &ADSREnvelope) -> f32 {
+    let value = envelope.held(t);
+    // Retriggered voice rises from its previous level instead of the start of envelope
+    let end = envelope.time_range_of(State::Attack).1;
+    let from = envelope.level_at_point(0);
+    let peak = envelope.peak_at(State::Attack);
+    if note.start_level == 0.0 || t >= end || peak == from {
+        return value;
+    }
+    note.start_level + (value - from) * (peak - note.start_level) / (peak - from)
+}
+
+// Played note together with state an oscillator keeps for it
+pub(crate) trait Voice {
+    fn note(&self) -> &Note;
+    fn note_mut(&mut self) -> &mut Note;
+}
+
+impl Voice for Note {
+    fn note(&self) -> &Note {
+        self
+    }
+
+    fn note_mut(&mut self) -> &mut Note {
+        self
+    }
+}
+
+// Notes with sustain and sostenuto pedals, shared by all oscillators.
+// In mono mode pedals act on held keys and oscillator picks the voice from them
+pub(crate) struct Voices<V: Voice = Note> {
+    pub notes: Vec<V>,
+    pub release_notes: Vec<V>,
+    // Keys held in mono and legato modes, in order of pressing
+    pub held: Vec<Note>,
+    mono: bool,
+    pedals: Pedals,
+}
+
+impl<V: Voice> Default for Voices<V> {
+    fn default() -> Self {
+        Self {
+            notes: vec![],
+            release_notes: vec![],
+            held: vec![],
+            mono: false,
+            pedals: Pedals::default(),
+        }
+    }
+}
+
+impl<V: Voice> Voices<V> {
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut V> {
+        self.notes.iter_mut().chain(self.release_notes.iter_mut())
+    }
+
+    pub fn is_playing(&self, note: u32) -> bool {
+        self.notes.iter().any(|v| v.note().note == note)
+    }
+
+    pub fn set_mono(&mut self, mono: bool) {
+        self.mono = mono;
+    }
+
+    pub fn note_on(&mut self, voice: V) {
+        let (channel, note) = (voice.note().channel, voice.note().note);
+        self.release_voice(channel, note);
+        self.pedals.note_on((channel, note));
+        self.notes.push(voice);
+    }
+
+    // Key pressed in mono mode, sounding voice is chosen by oscillator
+    pub fn hold(&mut self, note: Note) {
+        self.pedals.note_on((note.channel, note.note));
+        self.held.retain(|n| !n.is_voice(note.channel, note.note));
+        self.held.push(note);
+    }
+
+    // Releases the key on every channel it is held on
+    pub fn note_off(&mut self, note: u32) {
+        self.held_keys()
+            .into_iter()
+            .filter(|(_, n)| *n == note)
+            .for_each(|key| self.key_off(key));
+    }
+
+    pub fn member_note_off(&mut self, channel: u8, note: u32) {
+        self.key_off((Some(channel), note));
+    }
+
+    pub fn note_expression(&mut self, channel: u8, note: u32, expression: Expression) {
+        self.notes
+            .iter_mut()
+            .filter(|v| v.note().is_voice(Some(channel), note))
+            .for_each(|v| v.note_mut().expression = expression);
+    }
+
+    pub fn release_all(&mut self) {
+        self.pedals.reset();
+        self.held.clear();
+        while !self.notes.is_empty() {
+            self.release_at(self.notes.len() - 1);
+        }
+    }
+
+    pub fn sustain(&mut self, hold: bool) {
+        self.pedals
+            .set_sustain(hold)
+            .into_iter()
+            .for_each(|key| self.release_key(key));
+    }
+
+    pub fn sostenuto(&mut self, hold: bool) {
+        let held_keys = self.held_keys();
+        self.pedals
+            .set_sostenuto(hold, held_keys.into_iter())
+            .into_iter()
+            .for_each(|key| self.release_key(key));
+    }
+
+    pub fn remove_released(&mut self, envelope: &ADSREnvelope) {
+        self.remove_released_after(|release| envelope.end_after(release));
+    }
+
+    // End gives time envelopes of note released at given time are finished
+    pub fn remove_released_after<F: Fn(f32) -> f32>(&mut self, end: F) {
+        self.release_notes.retain(|v| {
+            let note = v.note();
+            note.state != State::None
+                && note.play_time < end(note.release_time.unwrap_or(note.play_time))
+        });
+    }
+
+    pub fn retain<F: FnMut(&V) -> bool>(&mut self, mut f: F) {
+        self.notes.retain(&mut f);
+        self.release_notes.retain(f);
+    }
+
+    // Every voice of the key, whichever channel played it
+    pub fn release(&mut self, note: u32) {
+        while let Some(index) = self.notes.iter().position(|v| v.note().note == note) {
+            self.release_at(index);
+        }
+    }
+
+    pub fn release_voice(&mut self, channel: Option<u8>, note: u32) {
+        if let Some(index) = self
+            .notes
+            .iter()
+            .position(|v| v.note().is_voice(channel, note))
+        {
+            self.release_at(index);
+        }
+    }
+
+    // Mono voice sounds only one of held keys, so pedals catch all of them
+    fn held_keys(&self) -> Vec<VoiceKey> {
+        match self.mono {
+            false => self.notes.iter().map(|v| v.note().key()).collect(),
+            true => self.held.iter().map(Note::key).collect(),
+        }
+    }
+
+    fn key_off(&mut self, key: VoiceKey) {
+        if self.pedals.note_off(key) {
+            self.release_key(key);
+        }
+    }
+
+    // Key released by player or by pedal, only voice of its channel is released
+    fn release_key(&mut self, (channel, note): VoiceKey) {
+        match self.mono {
+            false => self.release_voice(channel, note),
+            true => self.held.retain(|n| !n.is_voice(channel, note)),
+        }
+    }
+
+    fn release_at(&mut self, index: usize) {
+        let mut voice = self.notes.remove(index);
+        voice.note_mut().release();
+        self.release_notes.push(voice);
+    }
+}
@@ -161,6 +161,11 @@ impl WaveTable {
         self.buffer.len() / self.chunk_size
     }
 
+    // All chunks of wavetable, not only the current position
+    pub fn samples(&self) -> &[f32] {
+        self.buffer.get_slice()
+    }
+
     pub fn get_slice(&self) -> Result<&[f32], Error> {
         Ok(self
             .buffer
@@ -194,6 +199,13 @@ impl WaveTable {
         self.position = 0;
     }
 
+    // Exchanges samples with prepared buffer, so replaced ones are freed by the caller
+    pub fn swap_samples(&mut self, buffer: &mut SampleBufferMono, chunk_size: &mut usize) {
+        std::mem::swap(&mut self.buffer, buffer);
+        std::mem::swap(&mut self.chunk_size, chunk_size);
+        self.position = 0;
+    }
+
     pub fn load_from<P: AsRef<std::path::Path>>(
         &mut self,
         path: P,
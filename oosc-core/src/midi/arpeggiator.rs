@@ -0,0 +1,474 @@
+use midly::MidiMessage;
+
+use crate::{
+    core::transport::Transport,
+    error::Error,
+    utils::{random::Random, Shared, SharedMutex},
+};
+
+use super::{
+    mediator::MidiEventReceiver,
+    note_sender::NoteSender,
+    smf_extensions::{OwnedTrackEvent, OwnedTrackEventKind},
+};
+
+pub const MAX_ARP_OCTAVES: u8 = 4;
+// Steps per beat: quarter, eighth, eighth triplet, sixteenth, sixteenth triplet, thirty-second
+pub const ARP_RATES: [u32; 6] = [1, 2, 3, 4, 6, 8];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ArpMode {
+    Up,
+    Down,
+    UpDown,
+    Random,
+    AsPlayed,
+}
+
+impl ArpMode {
+    pub fn next(self) -> Self {
+        match self {
+            ArpMode::Up => ArpMode::Down,
+            ArpMode::Down => ArpMode::UpDown,
+            ArpMode::UpDown => ArpMode::Random,
+            ArpMode::Random => ArpMode::AsPlayed,
+            ArpMode::AsPlayed => ArpMode::Up,
+        }
+    }
+}
+
+impl std::fmt::Display for ArpMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ArpMode::Up => "up",
+            ArpMode::Down => "down",
+            ArpMode::UpDown => "up-down",
+            ArpMode::Random => "random",
+            ArpMode::AsPlayed => "played",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Turns held chords into note patterns, sits in front of the mediator so both
+// midi-in and SMF playback are arpeggiated. Steps are produced by advance.
+pub struct Arpeggiator {
+    receiver: SharedMutex<dyn MidiEventReceiver>,
+    transport: Shared<Transport>,
+    enabled: bool,
+    mode: ArpMode,
+    octaves: u8,
+    rate: u32,
+    gate: f32,
+    swing: f32,
+    latch: bool,
+    // Physically held keys and arpeggiated keys with velocities, in played order
+    held: Vec<(u8, u8)>,
+    notes: Vec<(u8, u8)>,
+    // Time in beats since first key of the chord, steps are counted from there
+    position: f32,
+    step: u32,
+    // Chord is played on channel and track of its first key
+    sender: NoteSender,
+    // Channel, track and key of notes of other routes passed by while chord is held
+    passed: Vec<(u8, Option<usize>, u8)>,
+    random: Random,
+}
+
+impl Arpeggiator {
+    pub fn new(receiver: SharedMutex<dyn MidiEventReceiver>, transport: Shared<Transport>) -> Self {
+        Self {
+            sender: NoteSender::new(receiver.clone()),
+            receiver,
+            transport,
+            enabled: false,
+            mode: ArpMode::Up,
+            octaves: 1,
+            rate: 4,
+            gate: 0.5,
+            swing: 0.0,
+            latch: false,
+            held: vec![],
+            notes: vec![],
+            position: 0.0,
+            step: 0,
+            passed: vec![],
+            random: Random::from_time(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    // Disabling releases sounding note and forgets latched chord
+    pub fn set_enabled(&mut self, enabled: bool) -> Result<&mut Self, Error> {
+        if !enabled {
+            self.sender.release()?;
+            self.held.clear();
+            self.notes.clear();
+        }
+        self.enabled = enabled;
+        Ok(self)
+    }
+
+    pub fn mode(&self) -> ArpMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: ArpMode) -> &mut Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn octaves(&self) -> u8 {
+        self.octaves
+    }
+
+    pub fn set_octaves(&mut self, octaves: u8) -> &mut Self {
+        self.octaves = octaves.clamp(1, MAX_ARP_OCTAVES);
+        self
+    }
+
+    // Steps per beat
+    pub fn rate(&self) -> u32 {
+        self.rate
+    }
+
+    pub fn set_rate(&mut self, rate: u32) -> &mut Self {
+        self.rate = rate.max(1);
+        self
+    }
+
+    // Part of step the note sounds
+    pub fn gate(&self) -> f32 {
+        self.gate
+    }
+
+    pub fn set_gate(&mut self, gate: f32) -> &mut Self {
+        self.gate = gate.clamp(0.05, 1.0);
+        self
+    }
+
+    // Part of step every second step is delayed by
+    pub fn swing(&self) -> f32 {
+        self.swing
+    }
+
+    pub fn set_swing(&mut self, swing: f32) -> &mut Self {
+        self.swing = swing.clamp(0.0, 0.5);
+        self
+    }
+
+    pub fn is_latch(&self) -> bool {
+        self.latch
+    }
+
+    // Latched chord keeps playing after keys are released, until next chord
+    pub fn set_latch(&mut self, latch: bool) -> Result<&mut Self, Error> {
+        self.latch = latch;
+        if !latch {
+            self.notes = self.held.clone();
+            if self.notes.is_empty() {
+                self.sender.release()?;
+            }
+        }
+        Ok(self)
+    }
+
+    pub fn advance(&mut self, delta_time: f32) -> Result<(), Error> {
+        if self.notes.is_empty() && self.sender.playing().is_none() {
+            return Ok(());
+        }
+        let bpm = self.transport.read().unwrap().bpm();
+        self.position += delta_time * bpm / 60.0;
+        self.sender.release_due(self.position)?;
+        while !self.notes.is_empty() && self.step_time(self.step) <= self.position {
+            self.play_step()?;
+        }
+        Ok(())
+    }
+
+    fn step_time(&self, step: u32) -> f32 {
+        let swing = if step % 2 == 1 { self.swing } else { 0.0 };
+        (step as f32 + swing) / self.rate as f32
+    }
+
+    fn pattern(&self) -> Vec<(u8, u8)> {
+        let mut notes = self.notes.clone();
+        if self.mode != ArpMode::AsPlayed {
+            notes.sort_by_key(|(key, _)| *key);
+        }
+        let mut pattern = (0..self.octaves)
+            .flat_map(|octave| {
+                notes
+                    .iter()
+                    .map(move |(key, vel)| (*key as u32 + octave as u32 * 12, *vel))
+            })
+            .filter(|(key, _)| *key < 128)
+            .map(|(key, vel)| (key as u8, vel))
+            .collect::<Vec<_>>();
+        match self.mode {
+            ArpMode::Down => pattern.reverse(),
+            // Top and bottom notes are not repeated on turns
+            ArpMode::UpDown if pattern.len() > 2 => {
+                let down = pattern[1..pattern.len() - 1].iter().rev().copied();
+                pattern.extend(down.collect::<Vec<_>>());
+            }
+            _ => (),
+        }
+        pattern
+    }
+
+    fn play_step(&mut self) -> Result<(), Error> {
+        self.sender.release()?;
+        let pattern = self.pattern();
+        let index = match self.mode {
+            ArpMode::Random => self.random.next_u32() as usize,
+            _ => self.step as usize,
+        } % pattern.len();
+        let (key, vel) = pattern[index];
+        let start = self.step_time(self.step);
+        let end = start + self.gate * (self.step_time(self.step + 1) - start);
+        self.step += 1;
+        self.sender.note_on(key, vel, end)
+    }
+
+    fn key_down(&mut self, key: u8, vel: u8) {
+        // Latched chord is replaced when new one starts
+        if self.latch && self.held.is_empty() {
+            self.notes.clear();
+        }
+        if self.notes.is_empty() {
+            self.position = 0.0;
+            self.step = 0;
+        }
+        self.held.retain(|(k, _)| *k != key);
+        self.held.push((key, vel));
+        self.notes.retain(|(k, _)| *k != key);
+        self.notes.push((key, vel));
+    }
+
+    fn key_up(&mut self, key: u8) -> Result<(), Error> {
+        self.held.retain(|(k, _)| *k != key);
+        if !self.latch {
+            self.notes.retain(|(k, _)| *k != key);
+            if self.notes.is_empty() {
+                self.sender.release()?;
+            }
+        }
+        Ok(())
+    }
+
+    // Returns false if event is not consumed by arpeggiator. Only chord of single
+    // channel and track is arpeggiated, notes of other routes go to their parts
+    fn receive_note(
+        &mut self,
+        track: Option<usize>,
+        event: &OwnedTrackEvent,
+    ) -> Result<bool, Error> {
+        if !self.enabled {
+            return Ok(false);
+        }
+        match event.kind {
+            OwnedTrackEventKind::Midi {
+                channel,
+                message: MidiMessage::NoteOn { key, vel },
+            } if vel > 0 => {
+                let routed = self.sender.is_route(channel, track);
+                if !self.held.is_empty() && !routed {
+                    self.passed.push((channel, track, key.as_int()));
+                    return Ok(false);
+                }
+                // New chord of other route replaces latched one
+                if self.held.is_empty() && !routed {
+                    self.sender.release()?;
+                    self.notes.clear();
+                    self.sender.set_route(channel, track);
+                }
+                self.key_down(key.as_int(), vel.as_int());
+                Ok(true)
+            }
+            OwnedTrackEventKind::Midi {
+                channel,
+                message: MidiMessage::NoteOn { key, .. } | MidiMessage::NoteOff { key, .. },
+            } => {
+                let passed = (channel, track, key.as_int());
+                if let Some(index) = self.passed.iter().position(|p| *p == passed) {
+                    self.passed.remove(index);
+                    return Ok(false);
+                }
+                if !self.sender.is_route(channel, track) {
+                    return Ok(false);
+                }
+                self.key_up(key.as_int())?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+}
+
+impl MidiEventReceiver for Arpeggiator {
+    fn receive_event(&mut self, event: &OwnedTrackEvent) -> Result<(), Error> {
+        if self.receive_note(None, event)? {
+            return Ok(());
+        }
+        self.receiver.lock().unwrap().receive_event(event)
+    }
+
+    fn receive_track_event(&mut self, track: usize, event: &OwnedTrackEvent) -> Result<(), Error> {
+        if self.receive_note(Some(track), event)? {
+            return Ok(());
+        }
+        self.receiver
+            .lock()
+            .unwrap()
+            .receive_track_event(track, event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use midly::{num::u7, MidiMessage};
+
+    use crate::{
+        core::transport::Transport,
+        midi::{
+            mediator::MidiEventReceiver,
+            note_sender::tests::{take, Received},
+            smf_extensions::{OwnedTrackEvent, OwnedTrackEventKind},
+        },
+        utils::{make_shared, make_shared_mutex, SharedMutex},
+    };
+
+    use super::{ArpMode, Arpeggiator};
+
+    fn message(channel: u8, message: MidiMessage) -> OwnedTrackEvent {
+        OwnedTrackEvent {
+            delta: 0,
+            kind: OwnedTrackEventKind::Midi { channel, message },
+        }
+    }
+
+    fn note(key: u8, vel: u8) -> OwnedTrackEvent {
+        message(
+            0,
+            MidiMessage::NoteOn {
+                key: u7::new(key),
+                vel: u7::new(vel),
+            },
+        )
+    }
+
+    fn build() -> (Arpeggiator, SharedMutex<Received>) {
+        let received = make_shared_mutex(Received::default());
+        // 60 bpm, so one beat lasts one second
+        let transport = make_shared(Transport::new(60.0));
+        let mut arp = Arpeggiator::new(received.clone(), transport);
+        arp.set_enabled(true).unwrap().set_rate(4).set_gate(0.5);
+        (arp, received)
+    }
+
+    #[test]
+    fn test_arp_up_octaves() {
+        let (mut arp, received) = build();
+        arp.set_octaves(2);
+        arp.receive_event(&note(64, 100)).unwrap();
+        arp.receive_event(&note(60, 100)).unwrap();
+        assert!(take(&received).is_empty());
+        (0..8).for_each(|_| arp.advance(0.125).unwrap());
+        assert_eq!(
+            take(&received),
+            vec![60, -60, 64, -64, 72, -72, 76, -76, 60]
+        );
+        arp.receive_event(&note(60, 0)).unwrap();
+        arp.receive_event(&note(64, 0)).unwrap();
+        assert_eq!(take(&received), vec![-60]);
+        arp.advance(1.0).unwrap();
+        assert!(take(&received).is_empty());
+    }
+
+    #[test]
+    fn test_arp_modes() {
+        let (mut arp, received) = build();
+        arp.set_gate(1.0).set_mode(ArpMode::UpDown);
+        [67, 60, 64]
+            .iter()
+            .for_each(|key| arp.receive_event(&note(*key, 100)).unwrap());
+        arp.advance(0.0).unwrap();
+        (0..4).for_each(|_| arp.advance(0.25).unwrap());
+        let ons = |keys: Vec<i32>| keys.into_iter().filter(|k| *k > 0).collect::<Vec<_>>();
+        assert_eq!(ons(take(&received)), vec![60, 64, 67, 64, 60]);
+        arp.set_mode(ArpMode::AsPlayed);
+        (0..3).for_each(|_| arp.advance(0.25).unwrap());
+        assert_eq!(ons(take(&received)), vec![64, 67, 60]);
+        arp.set_mode(ArpMode::Down);
+        (0..2).for_each(|_| arp.advance(0.25).unwrap());
+        assert_eq!(ons(take(&received)), vec![60, 67]);
+    }
+
+    #[test]
+    fn test_arp_latch_swing() {
+        let (mut arp, received) = build();
+        arp.set_latch(true).unwrap().set_swing(0.5);
+        arp.receive_event(&note(60, 100)).unwrap();
+        arp.receive_event(&note(60, 0)).unwrap();
+        arp.advance(0.0).unwrap();
+        assert_eq!(take(&received), vec![60]);
+        // Second step is delayed by half of step
+        arp.advance(0.3).unwrap();
+        assert_eq!(take(&received), vec![-60]);
+        arp.advance(0.1).unwrap();
+        assert_eq!(take(&received), vec![60]);
+        // New chord replaces latched one
+        arp.receive_event(&note(62, 100)).unwrap();
+        arp.advance(0.0).unwrap();
+        assert_eq!(take(&received), vec![-60, 62]);
+        arp.set_latch(false).unwrap();
+        arp.receive_event(&note(62, 0)).unwrap();
+        assert_eq!(take(&received), vec![-62]);
+        arp.set_enabled(false).unwrap();
+        arp.receive_event(&note(48, 100)).unwrap();
+        assert_eq!(take(&received), vec![48]);
+    }
+
+    #[test]
+    fn test_arp_routes() {
+        let (mut arp, received) = build();
+        let on = |channel, key| {
+            message(
+                channel,
+                MidiMessage::NoteOn {
+                    key: u7::new(key),
+                    vel: u7::new(100),
+                },
+            )
+        };
+        let off = |channel, key| {
+            message(
+                channel,
+                MidiMessage::NoteOff {
+                    key: u7::new(key),
+                    vel: u7::new(0),
+                },
+            )
+        };
+        arp.receive_event(&on(0, 60)).unwrap();
+        // Key of other channel is played by its own part while the chord is held
+        arp.receive_event(&on(1, 72)).unwrap();
+        assert_eq!(take(&received), vec![72]);
+        arp.advance(0.0).unwrap();
+        assert_eq!(take(&received), vec![60]);
+        arp.receive_event(&off(0, 60)).unwrap();
+        assert_eq!(take(&received), vec![-60]);
+
+        // Next chord is arpeggiated on its channel, passed key is still released
+        arp.receive_event(&on(1, 64)).unwrap();
+        arp.receive_event(&off(1, 72)).unwrap();
+        assert_eq!(take(&received), vec![-72]);
+        arp.advance(0.0).unwrap();
+        assert_eq!(take(&received), vec![64]);
+        assert_eq!(arp.sender.channel(), 1);
+    }
+}
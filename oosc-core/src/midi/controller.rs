@@ -0,0 +1,66 @@
+use crate::{error::Error, utils::evaluate::Evaluate};
+
+pub const BANK_SELECT_MSB: u8 = 0;
+pub const MOD_WHEEL: u8 = 1;
+pub const DATA_ENTRY_MSB: u8 = 6;
+pub const BANK_SELECT_LSB: u8 = 32;
+pub const DATA_ENTRY_LSB: u8 = 38;
+pub const NRPN_LSB: u8 = 98;
+pub const NRPN_MSB: u8 = 99;
+pub const RPN_LSB: u8 = 100;
+pub const RPN_MSB: u8 = 101;
+pub const SUSTAIN_PEDAL: u8 = 64;
+pub const SOSTENUTO_PEDAL: u8 = 66;
+pub const TIMBRE: u8 = 74;
+pub const ALL_NOTES_OFF: u8 = 123;
+
+// Controllers handled by mediator, they cannot be bound to parameters by MIDI learn
+pub fn is_reserved(controller: u8) -> bool {
+    matches!(
+        controller,
+        BANK_SELECT_MSB
+            | BANK_SELECT_LSB
+            | DATA_ENTRY_MSB
+            | DATA_ENTRY_LSB
+            | SUSTAIN_PEDAL
+            | SOSTENUTO_PEDAL
+            | NRPN_LSB..=RPN_MSB
+            // Channel mode messages
+            | 120..=127
+    )
+}
+
+#[inline]
+pub fn is_pedal_down(value: u8) -> bool {
+    value >= 64
+}
+
+pub struct ControllerSource {
+    value: f32,
+}
+
+impl ControllerSource {
+    pub fn new(value: f32) -> Self {
+        Self { value }
+    }
+
+    pub fn value(&self) -> f32 {
+        self.value
+    }
+
+    pub fn set_value(&mut self, value: f32) {
+        self.value = value;
+    }
+}
+
+impl Default for ControllerSource {
+    fn default() -> Self {
+        Self::new(0.0)
+    }
+}
+
+impl Evaluate<f32> for ControllerSource {
+    fn evaluate(&self, _t: f32) -> Result<f32, Error> {
+        Ok(self.value)
+    }
+}
@@ -0,0 +1,371 @@
+use std::{fs, path::Path, sync::Arc};
+
+use crate::{
+    core::parameter::{PathParameter, SharedParameter},
+    error::Error,
+    utils::interpolation::{interpolate_range, InterpolateMethod},
+};
+
+use super::controller::{
+    is_reserved, DATA_ENTRY_LSB, DATA_ENTRY_MSB, NRPN_LSB, NRPN_MSB, RPN_LSB, RPN_MSB,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Controller {
+    Cc(u8),
+    Nrpn(u16),
+}
+
+pub struct ControllerBinding {
+    pub controller: Controller,
+    pub path: String,
+    pub curve: InterpolateMethod,
+}
+
+#[derive(Default)]
+struct NrpnState {
+    parameter: Option<(u8, u8)>,
+    value_msb: u8,
+    value_lsb: u8,
+}
+
+impl NrpnState {
+    fn number(&self) -> Option<u16> {
+        self.parameter
+            .map(|(msb, lsb)| ((msb as u16) << 7) | lsb as u16)
+    }
+
+    fn value(&self) -> f32 {
+        (((self.value_msb as u16) << 7) | self.value_lsb as u16) as f32 / 16383.0
+    }
+}
+
+// Parameter value types which can be bound to controllers
+pub trait Learnable: Clone + Sized {
+    fn parameters(learn: &MidiLearn) -> &[PathParameter<Self>];
+    fn apply(parameter: &SharedParameter<Self>, value: f32, curve: InterpolateMethod);
+}
+
+impl Learnable for f32 {
+    fn parameters(learn: &MidiLearn) -> &[PathParameter<Self>] {
+        &learn.parameters
+    }
+
+    fn apply(parameter: &SharedParameter<Self>, value: f32, curve: InterpolateMethod) {
+        let mut parameter = parameter.write().unwrap();
+        let value = interpolate_range(parameter.range(), value, curve);
+        parameter.set_value(value);
+    }
+}
+
+impl Learnable for i32 {
+    fn parameters(learn: &MidiLearn) -> &[PathParameter<Self>] {
+        &learn.parameters_i32
+    }
+
+    fn apply(parameter: &SharedParameter<Self>, value: f32, curve: InterpolateMethod) {
+        let mut parameter = parameter.write().unwrap();
+        let (min, max) = parameter.range();
+        let value = interpolate_range((min as f32, max as f32), value, curve);
+        parameter.set_value(value.round() as i32);
+    }
+}
+
+pub struct MidiLearn {
+    parameters: Vec<PathParameter<f32>>,
+    parameters_i32: Vec<PathParameter<i32>>,
+    bindings: Vec<ControllerBinding>,
+    armed: Option<(String, InterpolateMethod)>,
+    nrpn: NrpnState,
+}
+
+impl MidiLearn {
+    pub fn new(parameters: Vec<PathParameter<f32>>) -> Self {
+        Self {
+            parameters,
+            parameters_i32: vec![],
+            bindings: vec![],
+            armed: None,
+            nrpn: NrpnState::default(),
+        }
+    }
+
+    pub fn set_parameters_i32(&mut self, parameters: Vec<PathParameter<i32>>) -> &mut Self {
+        self.parameters_i32 = parameters;
+        self
+    }
+
+    pub fn bindings(&self) -> &[ControllerBinding] {
+        &self.bindings
+    }
+
+    pub fn arm<T: Learnable>(
+        &mut self,
+        parameter: &SharedParameter<T>,
+        curve: InterpolateMethod,
+    ) -> Result<(), Error> {
+        let path = self
+            .path_of(parameter)
+            .ok_or("Parameter cannot be learned")?;
+        self.armed = Some((path, curve));
+        Ok(())
+    }
+
+    pub fn disarm(&mut self) {
+        self.armed = None;
+    }
+
+    pub fn is_armed<T: Learnable>(&self, parameter: &SharedParameter<T>) -> bool {
+        match &self.armed {
+            Some((path, _)) => Some(path) == self.path_of(parameter).as_ref(),
+            None => false,
+        }
+    }
+
+    pub fn binding_of<T: Learnable>(
+        &self,
+        parameter: &SharedParameter<T>,
+    ) -> Option<&ControllerBinding> {
+        let path = self.path_of(parameter)?;
+        self.bindings.iter().find(|b| b.path == path)
+    }
+
+    pub fn bind(&mut self, controller: Controller, path: String, curve: InterpolateMethod) {
+        self.bindings
+            .retain(|b| b.controller != controller && b.path != path);
+        self.bindings.push(ControllerBinding {
+            controller,
+            path,
+            curve,
+        });
+    }
+
+    pub fn unbind<T: Learnable>(&mut self, parameter: &SharedParameter<T>) {
+        if let Some(path) = self.path_of(parameter) {
+            self.bindings.retain(|b| b.path != path);
+        }
+    }
+
+    // Returns true if controller was consumed by learn or some binding,
+    // reserved controllers and NRPN numbers are always passed to mediator
+    pub fn receive_controller(&mut self, controller: u8, value: u8) -> Result<bool, Error> {
+        match controller {
+            NRPN_MSB => {
+                let lsb = self.nrpn.parameter.map(|p| p.1).unwrap_or(0);
+                self.nrpn.parameter = Some((value, lsb));
+                Ok(false)
+            }
+            NRPN_LSB => {
+                let msb = self.nrpn.parameter.map(|p| p.0).unwrap_or(0);
+                self.nrpn.parameter = Some((msb, value));
+                Ok(false)
+            }
+            RPN_MSB | RPN_LSB => {
+                self.nrpn.parameter = None;
+                Ok(false)
+            }
+            DATA_ENTRY_MSB | DATA_ENTRY_LSB => match self.nrpn.number() {
+                Some(number) => {
+                    if controller == DATA_ENTRY_MSB {
+                        self.nrpn.value_msb = value;
+                    } else {
+                        self.nrpn.value_lsb = value;
+                    }
+                    self.receive(Controller::Nrpn(number), self.nrpn.value())
+                }
+                None => Ok(false),
+            },
+            _ if is_reserved(controller) => Ok(false),
+            _ => self.receive(Controller::Cc(controller), value as f32 / 127.0),
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let data = self
+            .bindings
+            .iter()
+            .map(|b| {
+                let (kind, number) = match b.controller {
+                    Controller::Cc(n) => ("cc", n as u16),
+                    Controller::Nrpn(n) => ("nrpn", n),
+                };
+                format!("{} {} {} {}\n", kind, number, format_curve(b.curve), b.path)
+            })
+            .collect::<String>();
+        fs::write(path, data)?;
+        Ok(())
+    }
+
+    // Path is the rest of line, words of parameter names are joined by single space
+    pub fn load<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Error> {
+        let data = fs::read_to_string(path)?;
+        data.lines()
+            .filter(|line| !line.trim().is_empty())
+            .try_for_each(|line| -> Result<(), Error> {
+                let mut fields = line.split_whitespace();
+                let mut next = || fields.next().ok_or(format!("Invalid mapping \"{}\"", line));
+                let kind = next()?;
+                let number = next()?
+                    .parse::<u16>()
+                    .map_err(|e| format!("Invalid controller number: {}", e))?;
+                let curve = parse_curve(next()?)?;
+                let path = fields.collect::<Vec<_>>().join(" ");
+                if path.is_empty() {
+                    return Err(format!("Invalid mapping \"{}\"", line).into());
+                }
+                let controller = match kind {
+                    "cc" if number < 128 && !is_reserved(number as u8) => {
+                        Controller::Cc(number as u8)
+                    }
+                    "nrpn" if number < 16384 => Controller::Nrpn(number),
+                    _ => return Err(format!("Invalid controller \"{} {}\"", kind, number).into()),
+                };
+                self.bind(controller, path, curve);
+                Ok(())
+            })
+    }
+
+    fn receive(&mut self, controller: Controller, value: f32) -> Result<bool, Error> {
+        if let Some((path, curve)) = self.armed.take() {
+            self.bind(controller, path, curve);
+        }
+        let binding = match self.bindings.iter().find(|b| b.controller == controller) {
+            Some(binding) => binding,
+            None => return Ok(false),
+        };
+        if let Some(p) = self.parameters.iter().find(|p| p.path == binding.path) {
+            f32::apply(&p.parameter, value, binding.curve);
+        } else if let Some(p) = self.parameters_i32.iter().find(|p| p.path == binding.path) {
+            i32::apply(&p.parameter, value, binding.curve);
+        }
+        Ok(true)
+    }
+
+    fn path_of<T: Learnable>(&self, parameter: &SharedParameter<T>) -> Option<String> {
+        T::parameters(self)
+            .iter()
+            .find(|p| Arc::ptr_eq(&p.parameter, parameter))
+            .map(|p| p.path.clone())
+    }
+}
+
+fn format_curve(curve: InterpolateMethod) -> String {
+    match curve {
+        InterpolateMethod::Exponential(c) => format!("exponential:{}", c),
+        _ => "linear".to_owned(),
+    }
+}
+
+fn parse_curve(curve: &str) -> Result<InterpolateMethod, Error> {
+    match curve.split_once(':') {
+        Some(("exponential", c)) => Ok(InterpolateMethod::Exponential(
+            c.parse::<f32>()
+                .map_err(|e| format!("Invalid curve coefficient: {}", e))?,
+        )),
+        None if curve == "linear" => Ok(InterpolateMethod::Linear),
+        _ => Err(format!("Unknown curve \"{}\"", curve).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        core::parameter::{PathParameter, SharedParameter, ValueParameter},
+        utils::{interpolation::InterpolateMethod, make_shared},
+    };
+    use assert_approx_eq::assert_approx_eq;
+
+    use super::{Controller, MidiLearn};
+
+    fn build_learn() -> (MidiLearn, SharedParameter<f32>) {
+        let parameter: SharedParameter<f32> = make_shared(ValueParameter::new(0.0, (-1.0, 1.0)));
+        let learn = MidiLearn::new(vec![PathParameter {
+            path: "Oscillator1/Pan".to_owned(),
+            parameter: parameter.clone(),
+        }]);
+        (learn, parameter)
+    }
+
+    #[test]
+    fn test_learn_cc() {
+        let (mut learn, parameter) = build_learn();
+        assert!(!learn.receive_controller(74, 127).unwrap());
+        learn.arm(&parameter, InterpolateMethod::Linear).unwrap();
+        assert!(learn.is_armed(&parameter));
+        assert!(learn.receive_controller(74, 127).unwrap());
+        assert!(!learn.is_armed(&parameter));
+        assert_approx_eq!(parameter.read().unwrap().get_value(), 1.0);
+        learn.receive_controller(74, 0).unwrap();
+        assert_approx_eq!(parameter.read().unwrap().get_value(), -1.0);
+        assert_eq!(
+            learn.binding_of(&parameter).unwrap().controller,
+            Controller::Cc(74)
+        );
+    }
+
+    #[test]
+    fn test_learn_i32() {
+        let (mut learn, _) = build_learn();
+        let parameter: SharedParameter<i32> = make_shared(ValueParameter::new(0, (-2, 2)));
+        learn.set_parameters_i32(vec![PathParameter {
+            path: "Oscillator1/Octave".to_owned(),
+            parameter: parameter.clone(),
+        }]);
+        learn.arm(&parameter, InterpolateMethod::Linear).unwrap();
+        learn.receive_controller(20, 127).unwrap();
+        assert_eq!(parameter.read().unwrap().get_value(), 2);
+        learn.receive_controller(20, 32).unwrap();
+        assert_eq!(parameter.read().unwrap().get_value(), -1);
+        assert_eq!(
+            learn.binding_of(&parameter).unwrap().controller,
+            Controller::Cc(20)
+        );
+    }
+
+    #[test]
+    fn test_learn_nrpn() {
+        let (mut learn, parameter) = build_learn();
+        learn.arm(&parameter, InterpolateMethod::Linear).unwrap();
+        // Reserved controllers are not learned and reach mediator
+        assert!(!learn.receive_controller(64, 127).unwrap());
+        assert!(learn.is_armed(&parameter));
+        assert!(!learn.receive_controller(99, 1).unwrap());
+        assert!(!learn.receive_controller(98, 2).unwrap());
+        learn.receive_controller(6, 127).unwrap();
+        learn.receive_controller(38, 127).unwrap();
+        assert_eq!(
+            learn.binding_of(&parameter).unwrap().controller,
+            Controller::Nrpn(130)
+        );
+        assert_approx_eq!(parameter.read().unwrap().get_value(), 1.0);
+    }
+
+    #[test]
+    fn test_learn_persist() {
+        let (mut learn, parameter) = build_learn();
+        learn.bind(
+            Controller::Nrpn(130),
+            "Oscillator1/Pan".to_owned(),
+            InterpolateMethod::Exponential(10.0),
+        );
+        let path =
+            std::env::temp_dir().join(format!("oosc_test_mappings_{}.txt", std::process::id()));
+        learn.save(&path).unwrap();
+        let (mut loaded, _) = build_learn();
+        loaded.load(&path).unwrap();
+        std::fs::write(&path, "cc\t74  linear   Part1/Oscillator1/Vibrato  Rate\n").unwrap();
+        loaded.load(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let binding = loaded.bindings().first().unwrap();
+        assert_eq!(binding.controller, Controller::Nrpn(130));
+        assert_eq!(binding.path, "Oscillator1/Pan");
+        assert!(matches!(
+            binding.curve,
+            InterpolateMethod::Exponential(c) if c == 10.0
+        ));
+        assert_eq!(loaded.bindings()[1].controller, Controller::Cc(74));
+        assert_eq!(loaded.bindings()[1].path, "Part1/Oscillator1/Vibrato Rate");
+        learn.unbind(&parameter);
+        assert!(learn.bindings().is_empty());
+    }
+}
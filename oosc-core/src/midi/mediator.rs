@@ -1,44 +1,876 @@
+use midly::live::SystemRealtime;
+
 use crate::{
-    core::{note::Note, synthesizer::SyncSynthesizer},
+    core::{
+        modulation_config::{ModulationConfig, ModulationController},
+        note::{Expression, Note},
+        part::Route,
+        preset::{PreparedPreset, PresetBank, PRESET_CROSSFADE_TIME},
+        synthesizer::{SyncSynthesizer, Synthesizer},
+        transport::Transport,
+    },
     error::Error,
+    utils::{convert::controller_to_float, make_shared, Shared},
 };
 
-use super::smf_extensions::{OwnedTrackEvent, OwnedTrackEventKind};
+use super::{
+    controller::{
+        is_pedal_down, ControllerSource, BANK_SELECT_LSB, BANK_SELECT_MSB, DATA_ENTRY_MSB,
+        MOD_WHEEL, RPN_LSB, RPN_MSB, SOSTENUTO_PEDAL, SUSTAIN_PEDAL, TIMBRE,
+    },
+    learn::MidiLearn,
+    mpe::{MpeZone, MPE_CONFIGURATION_RPN},
+    smf_extensions::{OwnedSystemCommon, OwnedTrackEvent, OwnedTrackEventKind},
+};
 
 pub trait MidiEventReceiver: Send + Sync {
     fn receive_event(&mut self, event: &OwnedTrackEvent) -> Result<(), Error>;
+    fn receive_track_event(&mut self, _track: usize, event: &OwnedTrackEvent) -> Result<(), Error> {
+        self.receive_event(event)
+    }
+}
+
+// Controllers modulating parameters of one part, set by channels the part is routed from
+#[derive(Default)]
+struct PartControllers {
+    mod_wheel: Shared<ControllerSource>,
+    aftertouch: Shared<ControllerSource>,
+    poly_pressure: Shared<ControllerSource>,
+}
+
+impl PartControllers {
+    fn get(&self, controller: ModulationController) -> &Shared<ControllerSource> {
+        match controller {
+            ModulationController::ModWheel => &self.mod_wheel,
+            ModulationController::Aftertouch => &self.aftertouch,
+            ModulationController::PolyPressure => &self.poly_pressure,
+        }
+    }
 }
 
 pub struct MidiSynthesizerMediator {
     synthesizer: SyncSynthesizer,
+    controllers: Vec<PartControllers>,
+    // Polyphonic pressure of every key per channel
+    key_pressure: [[f32; 128]; 16],
+    learn: Option<Shared<MidiLearn>>,
+    transport: Shared<Transport>,
+    presets: Option<Shared<PresetBank>>,
+    // Bank select MSB and LSB per channel
+    banks: [(u8, u8); 16],
+    // Registered parameter number MSB and LSB per channel
+    rpn: [(u8, u8); 16],
+    mpe_zones: Vec<MpeZone>,
+    // Expression and playing note of every member channel
+    expressions: [Expression; 16],
+    member_notes: [Option<u32>; 16],
 }
 
 impl MidiSynthesizerMediator {
     pub fn new(synthesizer: SyncSynthesizer) -> Self {
-        Self { synthesizer }
+        let parts = synthesizer.lock().unwrap().get_parts().len();
+        Self {
+            synthesizer,
+            controllers: (0..parts).map(|_| PartControllers::default()).collect(),
+            key_pressure: [[0.0; 128]; 16],
+            learn: None,
+            transport: make_shared(Transport::default()),
+            presets: None,
+            banks: [(0, 0); 16],
+            rpn: [(127, 127); 16],
+            mpe_zones: vec![],
+            expressions: [Expression::default(); 16],
+            member_notes: [None; 16],
+        }
     }
-}
 
-impl MidiEventReceiver for MidiSynthesizerMediator {
-    fn receive_event(&mut self, event: &OwnedTrackEvent) -> Result<(), Error> {
+    pub fn mpe_zones(&self) -> &[MpeZone] {
+        &self.mpe_zones
+    }
+
+    // Zone with zero members is removed, overlapped channels are taken from other zone
+    pub fn set_mpe_zone(&mut self, zone: MpeZone) -> &mut Self {
+        self.mpe_zones.retain(|z| z.manager() != zone.manager());
+        let free = 14 - zone.members().min(14);
+        self.mpe_zones.iter_mut().for_each(|other| {
+            if other.members() > free {
+                let range = other.pitch_bend_range();
+                *other = MpeZone::new(other.manager(), free);
+                other.set_pitch_bend_range(range);
+            }
+        });
+        self.mpe_zones.retain(|z| z.members() > 0);
+        if zone.members() > 0 {
+            self.mpe_zones.push(zone);
+        }
+        self
+    }
+
+    pub fn set_presets(&mut self, presets: Shared<PresetBank>) -> &mut Self {
+        self.presets = Some(presets);
+        self
+    }
+
+    pub fn set_transport(&mut self, transport: Shared<Transport>) -> &mut Self {
+        self.transport = transport;
+        self
+    }
+
+    pub fn transport(&self) -> Shared<Transport> {
+        self.transport.clone()
+    }
+
+    pub fn set_learn(&mut self, learn: Shared<MidiLearn>) -> &mut Self {
+        self.learn = Some(learn);
+        self
+    }
+
+    // Value of controller received on channels of part
+    pub fn controller(
+        &self,
+        part: usize,
+        controller: ModulationController,
+    ) -> Option<Shared<ControllerSource>> {
+        self.controllers
+            .get(part)
+            .map(|c| c.get(controller).clone())
+    }
+
+    // Controller modulations are connected here, other ones are passed to synthesizer
+    pub fn apply_modulation(&mut self, config: &ModulationConfig) -> Result<(), Error> {
         let mut syn = self.synthesizer.lock().unwrap();
+        let ModulationConfig::Controller {
+            part,
+            oscillator,
+            controller,
+            target,
+            range,
+        } = config
+        else {
+            return syn.apply_modulation(config);
+        };
+        let source = self
+            .controller(*part, *controller)
+            .ok_or(format!("Part {} not found", part + 1))?;
+        syn.get_parts_mut()
+            .get_mut(*part)
+            .ok_or(format!("Part {} not found", part + 1))?
+            .add_controller_modulation(*oscillator, *target, source, *range)
+    }
+
+    fn receive_controller(
+        &mut self,
+        syn: &mut Synthesizer,
+        route: Route,
+        controller: u8,
+        value: u8,
+    ) -> Result<(), Error> {
+        if let Some(learn) = &self.learn {
+            if learn
+                .write()
+                .unwrap()
+                .receive_controller(controller, value)?
+            {
+                return Ok(());
+            }
+        }
+        match controller {
+            MOD_WHEEL => {
+                let value = controller_to_float(value.into());
+                self.set_controller(syn, route, ModulationController::ModWheel, value);
+            }
+            BANK_SELECT_MSB => self.banks[route.channel as usize].0 = value,
+            BANK_SELECT_LSB => self.banks[route.channel as usize].1 = value,
+            SUSTAIN_PEDAL => syn.sustain(route, is_pedal_down(value))?,
+            SOSTENUTO_PEDAL => syn.sostenuto(route, is_pedal_down(value))?,
+            RPN_MSB => self.rpn[route.channel as usize].0 = value,
+            RPN_LSB => self.rpn[route.channel as usize].1 = value,
+            DATA_ENTRY_MSB => {
+                let (msb, lsb) = self.rpn[route.channel as usize];
+                let number = ((msb as u16) << 7) | lsb as u16;
+                // MPE configuration message is valid only on first and last channels
+                if number == MPE_CONFIGURATION_RPN && matches!(route.channel, 0 | 15) {
+                    self.set_mpe_zone(MpeZone::new(route.channel, value));
+                }
+            }
+            _ => (),
+        };
+        Ok(())
+    }
+
+    fn set_controller(
+        &self,
+        syn: &Synthesizer,
+        route: Route,
+        controller: ModulationController,
+        value: f32,
+    ) {
+        syn.get_parts()
+            .iter()
+            .zip(self.controllers.iter())
+            .filter(|(part, _)| part.accepts(route))
+            .for_each(|(_, controllers)| {
+                controllers
+                    .get(controller)
+                    .write()
+                    .unwrap()
+                    .set_value(value);
+            });
+    }
+
+    // Parts of channel follow the highest pressure of its keys
+    fn set_key_pressure(&mut self, syn: &Synthesizer, route: Route, key: usize, pressure: f32) {
+        let keys = &mut self.key_pressure[route.channel as usize];
+        keys[key] = pressure;
+        let max = keys.iter().copied().fold(0.0, f32::max);
+        self.set_controller(syn, route, ModulationController::PolyPressure, max);
+    }
+
+    // Preset is prepared for every routed part before synthesizer is locked to swap it in,
+    // so audio thread is not blocked while wavetables and envelopes are built
+    fn receive_program(&mut self, route: Route, program: u8) -> Result<(), Error> {
+        let (msb, lsb) = self.banks[route.channel as usize];
+        let bank = ((msb as u16) << 7) | lsb as u16;
+        let Some(presets) = &self.presets else {
+            return Ok(());
+        };
+        let presets = presets.read().unwrap();
+        let Some(preset) = presets.get(bank, program) else {
+            return Ok(());
+        };
+        let parts = self.synthesizer.lock().unwrap().routed_count(route);
+        let prepared = (0..parts)
+            .map(|_| PreparedPreset::new(preset))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.synthesizer
+            .lock()
+            .unwrap()
+            .apply_preset(route, prepared, PRESET_CROSSFADE_TIME)
+    }
+
+    fn receive_routed(
+        &mut self,
+        track: Option<usize>,
+        event: &OwnedTrackEvent,
+    ) -> Result<(), Error> {
+        if let OwnedTrackEventKind::Midi {
+            channel,
+            message: midly::MidiMessage::ProgramChange { program },
+        } = event.kind
+        {
+            let channel = self
+                .mpe_zones
+                .iter()
+                .find(|z| z.is_member(channel))
+                .map_or(channel, |zone| zone.manager());
+            return self.receive_program(Route { channel, track }, program.as_int());
+        }
+        let synthesizer = self.synthesizer.clone();
+        let mut syn = synthesizer.lock().unwrap();
         match event.kind {
-            OwnedTrackEventKind::Midi { message, .. } => match message {
-                midly::MidiMessage::NoteOn { key, vel } => {
-                    let key = key.as_int();
-                    let vel = vel.as_int();
-                    syn.note_on(Note::new(key.into(), vel.into()))?;
+            OwnedTrackEventKind::Midi { channel, message } => {
+                let zone = self
+                    .mpe_zones
+                    .iter()
+                    .find(|z| z.is_member(channel))
+                    .copied();
+                match zone {
+                    Some(zone) => {
+                        let route = Route {
+                            channel: zone.manager(),
+                            track,
+                        };
+                        self.receive_member(&mut syn, zone, channel, route, message)
+                    }
+                    None => {
+                        let route = Route { channel, track };
+                        self.receive_message(&mut syn, route, message)
+                    }
                 }
-                midly::MidiMessage::NoteOff { key, .. } => {
-                    let key = key.as_int();
-                    syn.note_off(key.into())?;
+            }
+            // SysEx of SMF track or live stream, only tuning messages are handled
+            OwnedTrackEventKind::SysEx(ref data)
+            | OwnedTrackEventKind::Common(OwnedSystemCommon::SysEx(ref data)) => {
+                syn.tuning().write().unwrap().receive_sysex(data)?;
+                Ok(())
+            }
+            OwnedTrackEventKind::Escape(_) => Ok(()),
+            OwnedTrackEventKind::Meta(_) => Ok(()),
+            OwnedTrackEventKind::Common(OwnedSystemCommon::SongPosition(sixteenths)) => {
+                let beats = sixteenths as f32 / 4.0;
+                self.transport.write().unwrap().set_position(beats);
+                Ok(())
+            }
+            OwnedTrackEventKind::Common(_) => Ok(()),
+            OwnedTrackEventKind::Realtime(message) => {
+                let mut transport = self.transport.write().unwrap();
+                match message {
+                    SystemRealtime::TimingClock => transport.clock(),
+                    SystemRealtime::Start => transport.start(),
+                    SystemRealtime::Continue => transport.resume(),
+                    SystemRealtime::Stop => transport.stop(),
+                    SystemRealtime::Reset => {
+                        transport.stop();
+                        transport.reset_clock();
+                        syn.release_all();
+                    }
+                    SystemRealtime::ActiveSensing | SystemRealtime::Undefined(_) => (),
+                };
+                Ok(())
+            }
+        }
+    }
+
+    // Messages from MPE member channel are sent to parts of zone manager channel
+    fn receive_member(
+        &mut self,
+        syn: &mut Synthesizer,
+        zone: MpeZone,
+        channel: u8,
+        route: Route,
+        message: midly::MidiMessage,
+    ) -> Result<(), Error> {
+        let index = channel as usize;
+        match message {
+            midly::MidiMessage::NoteOn { key, vel } if vel > 0 => {
+                let key = key.as_int().into();
+                let mut note = Note::new(key, vel.as_int().into());
+                note.expression = self.expressions[index];
+                note.channel = Some(channel);
+                self.member_notes[index] = Some(key);
+                syn.note_on(route, note)?;
+            }
+            midly::MidiMessage::NoteOn { key, .. } | midly::MidiMessage::NoteOff { key, .. } => {
+                let key = key.as_int().into();
+                if self.member_notes[index] == Some(key) {
+                    self.member_notes[index] = None;
                 }
-                _ => (),
-            },
-            OwnedTrackEventKind::SysEx(_) => (),
-            OwnedTrackEventKind::Escape(_) => (),
-            OwnedTrackEventKind::Meta(_) => (),
+                syn.member_note_off(route, channel, key)?;
+            }
+            midly::MidiMessage::PitchBend { bend } => {
+                self.expressions[index].pitch_bend = bend.as_f32() * zone.pitch_bend_range();
+                self.update_expression(syn, route, index);
+            }
+            midly::MidiMessage::ChannelAftertouch { vel } => {
+                self.expressions[index].pressure = controller_to_float(vel.as_int().into());
+                self.update_expression(syn, route, index);
+            }
+            midly::MidiMessage::Controller { controller, value } if controller == TIMBRE => {
+                self.expressions[index].timbre = controller_to_float(value.as_int().into());
+                self.update_expression(syn, route, index);
+            }
+            _ => self.receive_message(syn, route, message)?,
+        };
+        Ok(())
+    }
+
+    fn update_expression(&mut self, syn: &mut Synthesizer, route: Route, index: usize) {
+        if let Some(note) = self.member_notes[index] {
+            syn.note_expression(route, index as u8, note, self.expressions[index]);
+        }
+    }
+
+    fn receive_message(
+        &mut self,
+        syn: &mut Synthesizer,
+        route: Route,
+        message: midly::MidiMessage,
+    ) -> Result<(), Error> {
+        match message {
+            midly::MidiMessage::NoteOn { key, vel } if vel == 0 => {
+                let key = key.as_int();
+                self.set_key_pressure(syn, route, key.into(), 0.0);
+                syn.note_off(route, key.into())?;
+            }
+            midly::MidiMessage::NoteOn { key, vel } => {
+                let key = key.as_int();
+                let vel = vel.as_int();
+                syn.note_on(route, Note::new(key.into(), vel.into()))?;
+            }
+            midly::MidiMessage::NoteOff { key, .. } => {
+                let key = key.as_int();
+                self.set_key_pressure(syn, route, key.into(), 0.0);
+                syn.note_off(route, key.into())?;
+            }
+            midly::MidiMessage::PitchBend { bend } => {
+                syn.pitch_bend(route, bend.as_f32());
+            }
+            midly::MidiMessage::Controller { controller, value } => {
+                self.receive_controller(syn, route, controller.as_int(), value.as_int())?;
+            }
+            // Handled by receive_program before synthesizer is locked
+            midly::MidiMessage::ProgramChange { .. } => {}
+            midly::MidiMessage::ChannelAftertouch { vel } => {
+                let value = controller_to_float(vel.as_int().into());
+                self.set_controller(syn, route, ModulationController::Aftertouch, value);
+            }
+            midly::MidiMessage::Aftertouch { key, vel } => {
+                let value = controller_to_float(vel.as_int().into());
+                self.set_key_pressure(syn, route, key.as_int().into(), value);
+            }
         };
         Ok(())
     }
 }
+
+impl MidiEventReceiver for MidiSynthesizerMediator {
+    fn receive_event(&mut self, event: &OwnedTrackEvent) -> Result<(), Error> {
+        self.receive_routed(None, event)
+    }
+
+    fn receive_track_event(&mut self, track: usize, event: &OwnedTrackEvent) -> Result<(), Error> {
+        self.receive_routed(Some(track), event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use midly::{num::u7, MidiMessage, PitchBend};
+
+    use crate::{
+        core::{
+            modulation_config::{ModulationConfig, ModulationController},
+            oscillator::{OscillatorBuilder, WavetableOscillator},
+            part::{PartAssignment, PartBuilder},
+            preset::{Preset, PresetBank},
+            synthesizer::{SyncSynthesizer, SynthesizerBuilder},
+            waveshape::WaveShape,
+            wavetable::WaveTableBuilder,
+        },
+        midi::smf_extensions::{OwnedTrackEvent, OwnedTrackEventKind},
+        utils::{
+            adsr_envelope::ADSREnvelope, make_shared, make_shared_mutex,
+            sample_buffer::SampleBufferBuilder, Shared,
+        },
+    };
+    use assert_approx_eq::assert_approx_eq;
+
+    use super::{MidiEventReceiver, MidiSynthesizerMediator, MpeZone};
+
+    fn build_osc() -> Shared<WavetableOscillator> {
+        let buffer = SampleBufferBuilder::new()
+            .set_channels(2)
+            .set_samples(10)
+            .build()
+            .unwrap();
+        let table = WaveTableBuilder::new()
+            .from_shape(WaveShape::Sin, 10)
+            .build()
+            .unwrap();
+        let osc = OscillatorBuilder::new()
+            .set_buffer(buffer)
+            .set_envelope(ADSREnvelope::default())
+            .set_wavetable(table)
+            .build()
+            .unwrap();
+        make_shared(osc)
+    }
+
+    fn build_synthesizer() -> SyncSynthesizer {
+        make_shared_mutex(
+            SynthesizerBuilder::new()
+                .set_buffer(10)
+                .unwrap()
+                .add_osc(build_osc())
+                .set_sample_rate(44100)
+                .build()
+                .unwrap(),
+        )
+    }
+
+    fn event(message: MidiMessage) -> OwnedTrackEvent {
+        channel_event(0, message)
+    }
+
+    fn channel_event(channel: u8, message: MidiMessage) -> OwnedTrackEvent {
+        OwnedTrackEvent {
+            delta: 0,
+            kind: OwnedTrackEventKind::Midi { channel, message },
+        }
+    }
+
+    fn is_playing(synthesizer: &SyncSynthesizer, note: u32) -> bool {
+        let syn = synthesizer.lock().unwrap();
+        let osc = syn.get_oscillators::<WavetableOscillator>().next().unwrap();
+        let osc = osc.read().unwrap();
+        let osc = osc.as_any().downcast_ref::<WavetableOscillator>().unwrap();
+        osc.is_playing(note)
+    }
+
+    #[test]
+    fn test_zero_velocity_note_off() {
+        let synthesizer = build_synthesizer();
+        let mut mediator = MidiSynthesizerMediator::new(synthesizer.clone());
+        let key = u7::new(60);
+        mediator
+            .receive_event(&event(MidiMessage::NoteOn {
+                key,
+                vel: u7::new(100),
+            }))
+            .unwrap();
+        assert!(is_playing(&synthesizer, 60));
+        mediator
+            .receive_event(&event(MidiMessage::NoteOn {
+                key,
+                vel: u7::new(0),
+            }))
+            .unwrap();
+        assert!(!is_playing(&synthesizer, 60));
+    }
+
+    #[test]
+    fn test_controllers() {
+        let synthesizer = build_synthesizer();
+        let mut mediator = MidiSynthesizerMediator::new(synthesizer.clone());
+        let config = ModulationConfig::parse("ctl 1 1 modwheel pan -1 1").unwrap();
+        mediator.apply_modulation(&config).unwrap();
+        mediator
+            .receive_event(&event(MidiMessage::ChannelAftertouch { vel: u7::new(127) }))
+            .unwrap();
+        mediator
+            .receive_event(&event(MidiMessage::Controller {
+                controller: u7::new(1),
+                value: u7::new(127),
+            }))
+            .unwrap();
+        assert_approx_eq!(
+            controller_value(&mediator, 0, ModulationController::ModWheel),
+            1.0
+        );
+        let mut syn = synthesizer.lock().unwrap();
+        syn.output(10).unwrap();
+        let pan = &syn.get_named_parameters()[0];
+        assert_eq!(pan.path, "Part1/Oscillator1/Pan");
+        assert_approx_eq!(pan.parameter.read().unwrap().get_value(), 1.0);
+        drop(syn);
+        mediator
+            .receive_event(&event(MidiMessage::Aftertouch {
+                key: u7::new(60),
+                vel: u7::new(127),
+            }))
+            .unwrap();
+        assert_approx_eq!(
+            controller_value(&mediator, 0, ModulationController::PolyPressure),
+            1.0
+        );
+        mediator
+            .receive_event(&event(MidiMessage::NoteOff {
+                key: u7::new(60),
+                vel: u7::new(0),
+            }))
+            .unwrap();
+        assert_approx_eq!(
+            controller_value(&mediator, 0, ModulationController::PolyPressure),
+            0.0
+        );
+        // Released key does not reset channel pressure
+        assert_approx_eq!(
+            controller_value(&mediator, 0, ModulationController::Aftertouch),
+            1.0
+        );
+    }
+
+    #[test]
+    fn test_channel_controllers() {
+        let (_, _, synthesizer) = build_routed_parts();
+        let mut mediator = MidiSynthesizerMediator::new(synthesizer);
+        let pressure = |channel, key| {
+            channel_event(
+                channel,
+                MidiMessage::Aftertouch {
+                    key: u7::new(key),
+                    vel: u7::new(127),
+                },
+            )
+        };
+        let note_off = |channel, key| {
+            channel_event(
+                channel,
+                MidiMessage::NoteOff {
+                    key: u7::new(key),
+                    vel: u7::new(0),
+                },
+            )
+        };
+        mediator
+            .receive_event(&channel_event(
+                1,
+                MidiMessage::Controller {
+                    controller: u7::new(1),
+                    value: u7::new(127),
+                },
+            ))
+            .unwrap();
+        assert_approx_eq!(
+            controller_value(&mediator, 0, ModulationController::ModWheel),
+            0.0
+        );
+        assert_approx_eq!(
+            controller_value(&mediator, 1, ModulationController::ModWheel),
+            1.0
+        );
+        mediator.receive_event(&pressure(0, 60)).unwrap();
+        mediator.receive_event(&pressure(1, 60)).unwrap();
+        // Released key of other channel keeps pressure of first part
+        mediator.receive_event(&note_off(1, 60)).unwrap();
+        assert_approx_eq!(
+            controller_value(&mediator, 0, ModulationController::PolyPressure),
+            1.0
+        );
+        assert_approx_eq!(
+            controller_value(&mediator, 1, ModulationController::PolyPressure),
+            0.0
+        );
+        mediator.receive_event(&note_off(0, 60)).unwrap();
+        assert_approx_eq!(
+            controller_value(&mediator, 0, ModulationController::PolyPressure),
+            0.0
+        );
+        assert!(mediator
+            .controller(2, ModulationController::ModWheel)
+            .is_none());
+    }
+
+    #[test]
+    fn test_sustain_pedal() {
+        let synthesizer = build_synthesizer();
+        let mut mediator = MidiSynthesizerMediator::new(synthesizer.clone());
+        let key = u7::new(60);
+        let pedal = |value| {
+            event(MidiMessage::Controller {
+                controller: u7::new(64),
+                value: u7::new(value),
+            })
+        };
+        mediator.receive_event(&pedal(127)).unwrap();
+        mediator
+            .receive_event(&event(MidiMessage::NoteOn {
+                key,
+                vel: u7::new(100),
+            }))
+            .unwrap();
+        mediator
+            .receive_event(&event(MidiMessage::NoteOff {
+                key,
+                vel: u7::new(0),
+            }))
+            .unwrap();
+        assert!(is_playing(&synthesizer, 60));
+        mediator.receive_event(&pedal(0)).unwrap();
+        assert!(!is_playing(&synthesizer, 60));
+    }
+
+    fn build_routed_parts() -> (
+        Shared<WavetableOscillator>,
+        Shared<WavetableOscillator>,
+        SyncSynthesizer,
+    ) {
+        let first = build_osc();
+        let second = build_osc();
+        let synthesizer = make_shared_mutex(
+            SynthesizerBuilder::new()
+                .set_buffer(10)
+                .unwrap()
+                .add_part(
+                    PartBuilder::new()
+                        .set_buffer(10)
+                        .unwrap()
+                        .add_osc(first.clone())
+                        .assign(&PartAssignment::parse("ch:1").unwrap())
+                        .build()
+                        .unwrap(),
+                )
+                .add_part(
+                    PartBuilder::new()
+                        .set_buffer(10)
+                        .unwrap()
+                        .add_osc(second.clone())
+                        .assign(&PartAssignment::parse("ch:2 tr:2").unwrap())
+                        .build()
+                        .unwrap(),
+                )
+                .set_sample_rate(44100)
+                .build()
+                .unwrap(),
+        );
+        (first, second, synthesizer)
+    }
+
+    fn controller_value(
+        mediator: &MidiSynthesizerMediator,
+        part: usize,
+        controller: ModulationController,
+    ) -> f32 {
+        mediator
+            .controller(part, controller)
+            .unwrap()
+            .read()
+            .unwrap()
+            .value()
+    }
+
+    #[test]
+    fn test_channel_routing() {
+        let (first, second, synthesizer) = build_routed_parts();
+        let mut mediator = MidiSynthesizerMediator::new(synthesizer);
+        let note_on = |key| MidiMessage::NoteOn {
+            key: u7::new(key),
+            vel: u7::new(100),
+        };
+        mediator
+            .receive_event(&channel_event(1, note_on(60)))
+            .unwrap();
+        assert!(!first.read().unwrap().is_playing(60));
+        assert!(second.read().unwrap().is_playing(60));
+        mediator
+            .receive_event(&channel_event(0, note_on(62)))
+            .unwrap();
+        assert!(first.read().unwrap().is_playing(62));
+        assert!(!second.read().unwrap().is_playing(62));
+        mediator
+            .receive_track_event(2, &channel_event(0, note_on(64)))
+            .unwrap();
+        assert!(!first.read().unwrap().is_playing(64));
+        assert!(second.read().unwrap().is_playing(64));
+        assert!(PartAssignment::parse("ch:17").is_err());
+        assert!(PartAssignment::parse("ch:1 bus:2").is_err());
+    }
+
+    #[test]
+    fn test_program_change() {
+        let synthesizer = build_synthesizer();
+        let mut bank = PresetBank::new();
+        bank.insert(
+            (2 << 7) | 1,
+            3,
+            Preset {
+                name: "Quiet".to_owned(),
+                values: vec![("Oscillator1/Pan".to_owned(), -0.5)],
+                ..Default::default()
+            },
+        );
+        let mut mediator = MidiSynthesizerMediator::new(synthesizer.clone());
+        mediator.set_presets(make_shared(bank));
+        let pan = || {
+            let syn = synthesizer.lock().unwrap();
+            let parameters = syn.get_named_parameters();
+            let pan = parameters
+                .iter()
+                .find(|p| p.path == "Part1/Oscillator1/Pan");
+            let value = pan.unwrap().parameter.read().unwrap().get_value();
+            value
+        };
+        let controller = |controller, value| {
+            event(MidiMessage::Controller {
+                controller: u7::new(controller),
+                value: u7::new(value),
+            })
+        };
+        let program_change = event(MidiMessage::ProgramChange {
+            program: u7::new(3),
+        });
+        // Program from other bank is missing, nothing is changed
+        mediator.receive_event(&program_change).unwrap();
+        mediator.receive_event(&controller(0, 2)).unwrap();
+        mediator.receive_event(&controller(32, 1)).unwrap();
+        mediator.receive_event(&program_change).unwrap();
+        assert_approx_eq!(pan(), 0.0);
+        // Values are crossfaded during output to avoid clicks
+        let mut syn = synthesizer.lock().unwrap();
+        syn.output(10).unwrap();
+        drop(syn);
+        let value = pan();
+        assert!(value < 0.0 && value > -0.5);
+        let mut syn = synthesizer.lock().unwrap();
+        (0..300).for_each(|_| {
+            syn.output(10).unwrap();
+        });
+        drop(syn);
+        assert_approx_eq!(pan(), -0.5);
+    }
+
+    #[test]
+    fn test_mpe() {
+        let synthesizer = build_synthesizer();
+        let mut mediator = MidiSynthesizerMediator::new(synthesizer.clone());
+        let controller = |channel, controller, value| {
+            channel_event(
+                channel,
+                MidiMessage::Controller {
+                    controller: u7::new(controller),
+                    value: u7::new(value),
+                },
+            )
+        };
+        // MPE configuration message for lower zone with 3 member channels
+        [(101, 0), (100, 6), (6, 3)]
+            .into_iter()
+            .for_each(|(cc, value)| mediator.receive_event(&controller(0, cc, value)).unwrap());
+        assert_eq!(mediator.mpe_zones(), &[MpeZone::lower(3)]);
+
+        let expression = |note| {
+            let syn = synthesizer.lock().unwrap();
+            let osc = syn.get_oscillators::<WavetableOscillator>().next().unwrap();
+            let osc = osc.read().unwrap();
+            let osc = osc.as_any().downcast_ref::<WavetableOscillator>().unwrap();
+            osc.expression_of(note)
+        };
+        mediator
+            .receive_event(&channel_event(
+                2,
+                MidiMessage::PitchBend {
+                    bend: PitchBend::from_f32(0.5),
+                },
+            ))
+            .unwrap();
+        mediator
+            .receive_event(&channel_event(
+                2,
+                MidiMessage::NoteOn {
+                    key: u7::new(60),
+                    vel: u7::new(100),
+                },
+            ))
+            .unwrap();
+        mediator
+            .receive_event(&channel_event(
+                3,
+                MidiMessage::NoteOn {
+                    key: u7::new(64),
+                    vel: u7::new(100),
+                },
+            ))
+            .unwrap();
+        assert_approx_eq!(expression(60).unwrap().pitch_bend, 24.0, 0.01);
+        mediator
+            .receive_event(&channel_event(
+                3,
+                MidiMessage::ChannelAftertouch { vel: u7::new(127) },
+            ))
+            .unwrap();
+        mediator.receive_event(&controller(3, 74, 0)).unwrap();
+        let other = expression(64).unwrap();
+        assert_approx_eq!(other.pitch_bend, 0.0);
+        assert_approx_eq!(other.pressure, 1.0);
+        assert_approx_eq!(other.timbre, 0.0);
+        assert_approx_eq!(expression(60).unwrap().pressure, 0.0);
+        // Channel aftertouch of member channel is not global
+        assert_approx_eq!(
+            controller_value(&mediator, 0, ModulationController::Aftertouch),
+            0.0
+        );
+
+        // The same key on other member keeps playing when first one is released
+        let key = |channel, message: fn(u7, u7) -> MidiMessage| {
+            channel_event(channel, message(u7::new(60), u7::new(100)))
+        };
+        let note_on = |key, vel| MidiMessage::NoteOn { key, vel };
+        let note_off = |key, vel| MidiMessage::NoteOff { key, vel };
+        mediator.receive_event(&key(1, note_on)).unwrap();
+        mediator.receive_event(&key(2, note_off)).unwrap();
+        assert!(is_playing(&synthesizer, 60));
+        assert_approx_eq!(expression(60).unwrap().pitch_bend, 0.0);
+        mediator.receive_event(&key(1, note_off)).unwrap();
+        assert!(!is_playing(&synthesizer, 60));
+    }
+}
@@ -1,3 +1,11 @@
+pub mod arpeggiator;
+pub mod controller;
+pub mod learn;
 pub mod mediator;
+pub mod mpe;
+pub mod note_sender;
+pub mod output;
 pub mod playback;
+pub mod recorder;
+pub mod sequencer;
 pub mod smf_extensions;
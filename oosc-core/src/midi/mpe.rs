@@ -0,0 +1,137 @@
+use std::{fs, ops::RangeInclusive, path::Path};
+
+use crate::error::Error;
+
+pub const MPE_CONFIGURATION_RPN: u16 = 6;
+pub const DEFAULT_MEMBER_BEND_RANGE: f32 = 48.0;
+const LOWER_MANAGER: u8 = 0;
+const UPPER_MANAGER: u8 = 15;
+
+// Manager channel carries messages common for the zone, every member channel
+// carries single note with its own pitch bend, pressure and timbre
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct MpeZone {
+    manager: u8,
+    members: u8,
+    pitch_bend_range: f32,
+}
+
+impl MpeZone {
+    pub fn lower(members: u8) -> Self {
+        Self::new(LOWER_MANAGER, members)
+    }
+
+    pub fn upper(members: u8) -> Self {
+        Self::new(UPPER_MANAGER, members)
+    }
+
+    // Only first and last channels can be managers, other values give lower zone
+    pub fn new(manager: u8, members: u8) -> Self {
+        let manager = match manager {
+            UPPER_MANAGER => UPPER_MANAGER,
+            _ => LOWER_MANAGER,
+        };
+        Self {
+            manager,
+            members: members.min(15),
+            pitch_bend_range: DEFAULT_MEMBER_BEND_RANGE,
+        }
+    }
+
+    pub fn manager(&self) -> u8 {
+        self.manager
+    }
+
+    pub fn members(&self) -> u8 {
+        self.members
+    }
+
+    pub fn member_channels(&self) -> RangeInclusive<u8> {
+        match self.manager {
+            LOWER_MANAGER => 1..=self.members,
+            _ => (UPPER_MANAGER - self.members)..=(UPPER_MANAGER - 1),
+        }
+    }
+
+    pub fn is_member(&self, channel: u8) -> bool {
+        self.member_channels().contains(&channel)
+    }
+
+    pub fn contains(&self, channel: u8) -> bool {
+        channel == self.manager || self.is_member(channel)
+    }
+
+    // Pitch bend range of member channels in semitones
+    pub fn pitch_bend_range(&self) -> f32 {
+        self.pitch_bend_range
+    }
+
+    pub fn set_pitch_bend_range(&mut self, semitones: f32) -> &mut Self {
+        self.pitch_bend_range = semitones;
+        self
+    }
+
+    // "lower|upper <members>" with optional member pitch bend range in semitones
+    pub fn parse(line: &str) -> Result<Self, Error> {
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        let (manager, members, range) = match fields[..] {
+            [manager, members] => (manager, members, None),
+            [manager, members, range] => (manager, members, Some(range)),
+            _ => return Err(format!("Invalid MPE zone \"{}\"", line).into()),
+        };
+        let members = members
+            .parse::<u8>()
+            .map_err(|e| format!("Invalid member channels \"{}\": {}", members, e))?;
+        let mut zone = match manager {
+            "lower" => Self::lower(members),
+            "upper" => Self::upper(members),
+            _ => return Err(format!("Unknown MPE zone \"{}\"", manager).into()),
+        };
+        if let Some(range) = range {
+            let range = range
+                .parse::<f32>()
+                .map_err(|e| format!("Invalid pitch bend range \"{}\": {}", range, e))?;
+            zone.set_pitch_bend_range(range);
+        }
+        Ok(zone)
+    }
+
+    // One zone per line, empty lines and lines starting with # are skipped
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<Self>, Error> {
+        fs::read_to_string(path)?
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(Self::parse)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MpeZone;
+
+    #[test]
+    fn test_mpe_zones() {
+        let lower = MpeZone::lower(7);
+        assert_eq!(lower.member_channels(), 1..=7);
+        assert!(lower.contains(0));
+        assert!(!lower.is_member(0));
+        assert!(!lower.contains(8));
+        let upper = MpeZone::upper(20);
+        assert_eq!(upper.members(), 15);
+        assert_eq!(upper.member_channels(), 0..=14);
+        assert_eq!(MpeZone::new(3, 2).manager(), 0);
+    }
+
+    #[test]
+    fn test_mpe_zone_parse() {
+        assert_eq!(MpeZone::parse("lower 7").unwrap(), MpeZone::lower(7));
+        let upper = MpeZone::parse("upper 3 24").unwrap();
+        assert_eq!(upper.member_channels(), 12..=14);
+        assert_eq!(upper.pitch_bend_range(), 24.0);
+        assert!(MpeZone::parse("middle 3").is_err());
+        assert!(MpeZone::parse("lower").is_err());
+        assert!(MpeZone::parse("lower 3 wide").is_err());
+    }
+}
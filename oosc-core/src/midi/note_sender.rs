@@ -0,0 +1,157 @@
+use midly::{num::u7, MidiMessage};
+
+use crate::{error::Error, utils::SharedMutex};
+
+use super::{
+    mediator::MidiEventReceiver,
+    smf_extensions::{OwnedTrackEvent, OwnedTrackEventKind},
+};
+
+// Single sounding note of generated patterns, shared by arpeggiator and step sequencer
+pub struct NoteSender {
+    receiver: SharedMutex<dyn MidiEventReceiver>,
+    channel: u8,
+    track: Option<usize>,
+    // Sounding key and beat of its release
+    playing: Option<(u8, f32)>,
+}
+
+impl NoteSender {
+    pub fn new(receiver: SharedMutex<dyn MidiEventReceiver>) -> Self {
+        Self {
+            receiver,
+            channel: 0,
+            track: None,
+            playing: None,
+        }
+    }
+
+    pub fn channel(&self) -> u8 {
+        self.channel
+    }
+
+    pub fn is_route(&self, channel: u8, track: Option<usize>) -> bool {
+        self.channel == channel && self.track == track
+    }
+
+    // Notes are sent as track events if track is set
+    pub fn set_route(&mut self, channel: u8, track: Option<usize>) -> &mut Self {
+        self.channel = channel.min(15);
+        self.track = track;
+        self
+    }
+
+    pub fn playing(&self) -> Option<(u8, f32)> {
+        self.playing
+    }
+
+    // Forgets sounding note without release, caller sends note off later
+    pub fn take(&mut self) -> Option<(u8, f32)> {
+        self.playing.take()
+    }
+
+    pub fn note_on(&mut self, key: u8, vel: u8, end: f32) -> Result<(), Error> {
+        self.send(MidiMessage::NoteOn {
+            key: u7::new(key.min(127)),
+            vel: u7::new(vel.clamp(1, 127)),
+        })?;
+        self.playing = Some((key, end));
+        Ok(())
+    }
+
+    // Sounding note is kept without retrigger until new end
+    pub fn hold(&mut self, key: u8, end: f32) {
+        self.playing = Some((key, end));
+    }
+
+    pub fn note_off(&self, key: u8) -> Result<(), Error> {
+        self.send(MidiMessage::NoteOff {
+            key: u7::new(key.min(127)),
+            vel: u7::new(0),
+        })
+    }
+
+    pub fn release(&mut self) -> Result<(), Error> {
+        match self.playing.take() {
+            Some((key, _)) => self.note_off(key),
+            None => Ok(()),
+        }
+    }
+
+    // Releases sounding note if its end is reached
+    pub fn release_due(&mut self, position: f32) -> Result<(), Error> {
+        match self.playing {
+            Some((_, end)) if end <= position => self.release(),
+            _ => Ok(()),
+        }
+    }
+
+    fn send(&self, message: MidiMessage) -> Result<(), Error> {
+        let event = OwnedTrackEvent {
+            delta: 0,
+            kind: OwnedTrackEventKind::Midi {
+                channel: self.channel,
+                message,
+            },
+        };
+        let mut receiver = self.receiver.lock().unwrap();
+        match self.track {
+            Some(track) => receiver.receive_track_event(track, &event),
+            None => receiver.receive_event(&event),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use midly::MidiMessage;
+
+    use crate::{
+        error::Error,
+        midi::{
+            mediator::MidiEventReceiver,
+            smf_extensions::{OwnedTrackEvent, OwnedTrackEventKind},
+        },
+        utils::{make_shared_mutex, SharedMutex},
+    };
+
+    use super::NoteSender;
+
+    // Keys of note on events, note offs are negative
+    #[derive(Default)]
+    pub(crate) struct Received(pub Vec<i32>);
+
+    impl MidiEventReceiver for Received {
+        fn receive_event(&mut self, event: &OwnedTrackEvent) -> Result<(), Error> {
+            if let OwnedTrackEventKind::Midi { message, .. } = event.kind {
+                match message {
+                    MidiMessage::NoteOn { key, .. } => self.0.push(key.as_int() as i32),
+                    MidiMessage::NoteOff { key, .. } => self.0.push(-(key.as_int() as i32)),
+                    _ => (),
+                }
+            }
+            Ok(())
+        }
+    }
+
+    pub(crate) fn take(received: &SharedMutex<Received>) -> Vec<i32> {
+        std::mem::take(&mut received.lock().unwrap().0)
+    }
+
+    #[test]
+    fn test_note_sender() {
+        let received = make_shared_mutex(Received::default());
+        let mut sender = NoteSender::new(received.clone());
+        sender.note_on(60, 0, 1.0).unwrap();
+        sender.release_due(0.5).unwrap();
+        assert_eq!(take(&received), vec![60]);
+        sender.release_due(1.0).unwrap();
+        assert_eq!(take(&received), vec![-60]);
+        sender.note_on(62, 100, 2.0).unwrap();
+        let previous = sender.take();
+        sender.hold(62, 3.0);
+        sender.release().unwrap();
+        assert_eq!(previous, Some((62, 2.0)));
+        assert_eq!(take(&received), vec![62, -62]);
+    }
+}
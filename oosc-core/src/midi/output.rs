@@ -0,0 +1,339 @@
+use midly::{
+    live::{LiveEvent, SystemCommon, SystemRealtime},
+    num::{u14, u4, u7},
+    MidiMessage, TrackEventKind,
+};
+
+use crate::{
+    core::transport::{Transport, TransportState, CLOCKS_PER_BEAT},
+    error::Error,
+    utils::SharedMutex,
+};
+
+use super::{
+    controller::ALL_NOTES_OFF, mediator::MidiEventReceiver, smf_extensions::OwnedTrackEvent,
+};
+
+// Connections of MIDI backends are not Sync, so only Send is required
+pub trait MidiSender: Send {
+    fn send(&mut self, message: &[u8]) -> Result<(), Error>;
+    // Called on failed send, broken connection should be dropped and the error kept for user
+    fn fail(&mut self, error: Error);
+}
+
+// Failures are not returned, so a broken port cannot stop the audio thread
+fn send_event(output: &SharedMutex<dyn MidiSender>, event: LiveEvent) {
+    let mut message = vec![];
+    let mut output = output.lock().unwrap();
+    if let Err(e) = event.write_std(&mut message) {
+        output.fail(format!("Cannot write MIDI message: {}", e).into());
+        return;
+    }
+    if let Err(e) = output.send(&message) {
+        output.fail(e);
+    }
+}
+
+// Echoes played notes and SMF playback to MIDI output, then passes events on
+pub struct MidiThru {
+    receiver: SharedMutex<dyn MidiEventReceiver>,
+    output: SharedMutex<dyn MidiSender>,
+    thru: bool,
+    playback: bool,
+}
+
+impl MidiThru {
+    pub fn new(
+        receiver: SharedMutex<dyn MidiEventReceiver>,
+        output: SharedMutex<dyn MidiSender>,
+    ) -> Self {
+        Self {
+            receiver,
+            output,
+            thru: false,
+            playback: false,
+        }
+    }
+
+    pub fn is_thru(&self) -> bool {
+        self.thru
+    }
+
+    // Echo events from midi-in ports
+    pub fn set_thru(&mut self, thru: bool) -> &mut Self {
+        self.thru = thru;
+        self
+    }
+
+    pub fn is_playback(&self) -> bool {
+        self.playback
+    }
+
+    // Send events from SMF playback
+    pub fn set_playback(&mut self, playback: bool) -> &mut Self {
+        self.playback = playback;
+        self
+    }
+
+    // Releases notes left on external gear after thru or playback is switched off
+    pub fn all_notes_off(&self) {
+        (0..16).for_each(|channel| {
+            let message = MidiMessage::Controller {
+                controller: u7::new(ALL_NOTES_OFF),
+                value: u7::new(0),
+            };
+            send_event(
+                &self.output,
+                LiveEvent::Midi {
+                    channel: u4::new(channel),
+                    message,
+                },
+            )
+        })
+    }
+
+    // Only channel and sysex messages are sent, meta and system messages have no sense on output
+    fn forward(&self, event: &OwnedTrackEvent) {
+        if let Some(live) = TrackEventKind::try_from(&event.kind)
+            .ok()
+            .and_then(|kind| kind.as_live_event())
+        {
+            send_event(&self.output, live);
+        }
+    }
+}
+
+impl MidiEventReceiver for MidiThru {
+    fn receive_event(&mut self, event: &OwnedTrackEvent) -> Result<(), Error> {
+        if self.thru {
+            self.forward(event);
+        }
+        self.receiver.lock().unwrap().receive_event(event)
+    }
+
+    fn receive_track_event(&mut self, track: usize, event: &OwnedTrackEvent) -> Result<(), Error> {
+        if self.playback {
+            self.forward(event);
+        }
+        self.receiver
+            .lock()
+            .unwrap()
+            .receive_track_event(track, event)
+    }
+}
+
+// Sends MIDI clock, Start, Continue and Stop following the transport
+pub struct MidiClockOutput {
+    output: SharedMutex<dyn MidiSender>,
+    master: bool,
+    last_state: TransportState,
+    clocks: u64,
+}
+
+impl MidiClockOutput {
+    pub fn new(output: SharedMutex<dyn MidiSender>) -> Self {
+        Self {
+            output,
+            master: false,
+            last_state: TransportState::Stopped,
+            clocks: 0,
+        }
+    }
+
+    pub fn is_master(&self) -> bool {
+        self.master
+    }
+
+    pub fn set_master(&mut self, master: bool) -> &mut Self {
+        self.master = master;
+        self
+    }
+
+    // Called after transport is advanced, restarted is true after start from beginning
+    pub fn update(&mut self, transport: &Transport, restarted: bool) {
+        let state = transport.state();
+        let changed = state != self.last_state;
+        self.last_state = state;
+        let due = (transport.position() * CLOCKS_PER_BEAT as f32).floor() as u64;
+        // Clock is not sent back while oosc is synced to external one
+        if !self.master || transport.is_external_clock() {
+            self.clocks = due;
+            return;
+        }
+        if restarted && state == TransportState::Playing {
+            self.clocks = 0;
+            self.send_realtime(SystemRealtime::Start);
+        } else if changed {
+            match state {
+                // Song position pointer counts sixteenths, 6 clocks each
+                TransportState::Playing => {
+                    let sixteenths = (self.clocks / 6).min(0x3fff);
+                    self.clocks = sixteenths * 6;
+                    let position = SystemCommon::SongPosition(u14::new(sixteenths as u16));
+                    send_event(&self.output, LiveEvent::Common(position));
+                    self.send_realtime(SystemRealtime::Continue);
+                }
+                TransportState::Stopped => self.send_realtime(SystemRealtime::Stop),
+            }
+        }
+        match state {
+            TransportState::Playing => {
+                while self.clocks < due {
+                    self.send_realtime(SystemRealtime::TimingClock);
+                    self.clocks += 1;
+                }
+            }
+            // Position may be moved while stopped, continue from there
+            TransportState::Stopped => self.clocks = due,
+        }
+    }
+
+    fn send_realtime(&self, message: SystemRealtime) {
+        send_event(&self.output, LiveEvent::Realtime(message))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use midly::{num::u7, MidiMessage};
+
+    use crate::{
+        core::transport::Transport,
+        error::Error,
+        midi::{
+            mediator::MidiEventReceiver,
+            smf_extensions::{OwnedTrackEvent, OwnedTrackEventKind},
+        },
+        utils::{make_shared_mutex, SharedMutex},
+    };
+
+    use super::{MidiClockOutput, MidiSender, MidiThru};
+
+    #[derive(Default)]
+    struct Sent(Vec<Vec<u8>>);
+
+    impl MidiSender for Sent {
+        fn send(&mut self, message: &[u8]) -> Result<(), Error> {
+            self.0.push(message.to_vec());
+            Ok(())
+        }
+
+        fn fail(&mut self, _error: Error) {}
+    }
+
+    // Fails until failure is reported once, like a port that gets disconnected
+    #[derive(Default)]
+    struct Broken {
+        connected: bool,
+        errors: usize,
+    }
+
+    impl MidiSender for Broken {
+        fn send(&mut self, _message: &[u8]) -> Result<(), Error> {
+            match self.connected {
+                true => Err("Port is gone".into()),
+                false => Ok(()),
+            }
+        }
+
+        fn fail(&mut self, _error: Error) {
+            self.connected = false;
+            self.errors += 1;
+        }
+    }
+
+    impl MidiEventReceiver for Sent {
+        fn receive_event(&mut self, _event: &OwnedTrackEvent) -> Result<(), Error> {
+            self.0.push(vec![]);
+            Ok(())
+        }
+    }
+
+    fn take(sent: &SharedMutex<Sent>) -> Vec<Vec<u8>> {
+        std::mem::take(&mut sent.lock().unwrap().0)
+    }
+
+    #[test]
+    fn test_midi_thru() {
+        let output = make_shared_mutex(Sent::default());
+        let receiver = make_shared_mutex(Sent::default());
+        let mut thru = MidiThru::new(receiver.clone(), output.clone());
+        let event = OwnedTrackEvent {
+            delta: 0,
+            kind: OwnedTrackEventKind::Midi {
+                channel: 1,
+                message: MidiMessage::NoteOn {
+                    key: u7::new(60),
+                    vel: u7::new(100),
+                },
+            },
+        };
+        thru.receive_event(&event).unwrap();
+        thru.set_thru(true).receive_event(&event).unwrap();
+        thru.receive_track_event(0, &event).unwrap();
+        assert_eq!(take(&output), vec![vec![0x91, 60, 100]]);
+        thru.set_thru(false).set_playback(true);
+        thru.receive_track_event(0, &event).unwrap();
+        assert_eq!(take(&output).len(), 1);
+        assert_eq!(take(&receiver).len(), 4);
+    }
+
+    #[test]
+    fn test_clock_output() {
+        let output = make_shared_mutex(Sent::default());
+        let mut clock = MidiClockOutput::new(output.clone());
+        let mut transport = Transport::new(120.0);
+        transport.start();
+        transport.advance(0.5);
+        clock.update(&transport, true);
+        assert!(take(&output).is_empty());
+
+        clock.set_master(true);
+        transport.start();
+        transport.advance(0.5);
+        clock.update(&transport, true);
+        let sent = take(&output);
+        assert_eq!(sent[0], vec![0xfa]);
+        assert_eq!(sent.len(), 25);
+        assert!(sent[1..].iter().all(|m| *m == vec![0xf8]));
+
+        transport.stop();
+        clock.update(&transport, false);
+        assert_eq!(take(&output), vec![vec![0xfc]]);
+        transport.resume();
+        transport.advance(0.25);
+        clock.update(&transport, false);
+        let sent = take(&output);
+        assert_eq!(sent[0], vec![0xf2, 4, 0]);
+        assert_eq!(sent[1], vec![0xfb]);
+        assert_eq!(sent.len(), 14);
+    }
+
+    #[test]
+    fn test_send_failure() {
+        let output = make_shared_mutex(Broken {
+            connected: true,
+            errors: 0,
+        });
+        let receiver = make_shared_mutex(Sent::default());
+        let mut thru = MidiThru::new(receiver.clone(), output.clone());
+        thru.set_thru(true);
+        let event = OwnedTrackEvent {
+            delta: 0,
+            kind: OwnedTrackEventKind::Midi {
+                channel: 0,
+                message: MidiMessage::NoteOff {
+                    key: u7::new(60),
+                    vel: u7::new(0),
+                },
+            },
+        };
+        // Events still reach the synthesizer, failed port is dropped once
+        thru.receive_event(&event).unwrap();
+        thru.receive_event(&event).unwrap();
+        assert_eq!(take(&receiver).len(), 2);
+        let output = output.lock().unwrap();
+        assert!(!output.connected);
+        assert_eq!(output.errors, 1);
+    }
+}
@@ -2,7 +2,10 @@ use midly::{Smf, Timing};
 
 use crate::{error::Error, utils::SharedMutex};
 
-use super::{mediator::MidiEventReceiver, smf_extensions::OwnedSmf};
+use super::{
+    mediator::MidiEventReceiver,
+    smf_extensions::{OwnedMetaMessage, OwnedSmf, OwnedTrackEventKind},
+};
 
 #[derive(Clone, Copy)]
 pub enum PlaybackState {
@@ -15,6 +18,8 @@ pub enum PlaybackState {
 pub trait MidiPlayback: Sync + Send {
     fn load(&mut self, data: Smf<'_>);
     fn play(&mut self);
+    fn pause(&mut self);
+    fn rewind(&mut self);
     fn set_bpm(&mut self, bpm: f32);
     fn get_state(&self) -> PlaybackState;
     fn process_events(
@@ -25,13 +30,20 @@ pub trait MidiPlayback: Sync + Send {
     fn reset(&mut self);
 }
 
+#[derive(Clone, Copy, Default)]
+struct TrackCursor {
+    position: usize,
+    ticks: u32,
+}
+
 pub struct SmfPlayback {
     time: f32,
-    midi_ticks: u32,
+    midi_ticks: f32,
     tps: f32,
     bpm: f32,
     ppq: u32,
     data: Option<OwnedSmf>,
+    cursors: Vec<TrackCursor>,
     state: PlaybackState,
 }
 
@@ -39,11 +51,12 @@ impl SmfPlayback {
     pub fn new() -> Self {
         Self {
             time: 0.0,
-            midi_ticks: 0,
+            midi_ticks: 0.0,
             tps: 0.0,
             bpm: 0.0,
             ppq: 0,
             data: None,
+            cursors: vec![],
             state: PlaybackState::None,
         }
     }
@@ -56,13 +69,15 @@ impl SmfPlayback {
         };
         let bpm = 120.0;
         let tps = Self::calculate_tps(bpm, ppq.into());
+        let cursors = vec![TrackCursor::default(); data.tracks.len()];
         Ok(Self {
             time: 0.0,
-            midi_ticks: 0,
+            midi_ticks: 0.0,
             tps,
             bpm,
             ppq: ppq.into(),
             data: Some(data),
+            cursors,
             state: PlaybackState::Stoped,
         })
     }
@@ -86,41 +101,35 @@ impl MidiPlayback for SmfPlayback {
     ) -> Result<(), Error> {
         let mut receiver = event_receiver.lock().unwrap();
         match self.state {
-            PlaybackState::Playing(mut t) => {
+            PlaybackState::Playing(_) => {
                 let data = self
                     .data
                     .as_ref()
                     .ok_or("Cannot get midi data for playback")?;
-                t = self.time + delta_time;
-                let playback_time_ticks: u32 = (t * self.tps) as u32;
-                let playback_midi_ticks: u32 = self.midi_ticks;
-                let delta_ticks = playback_time_ticks - playback_midi_ticks;
-                let mut last_event_ticks: u32 = playback_midi_ticks;
-                let mut current_ticks: u32 = 0;
-
-                for track in data.tracks.iter().skip(1) {
-                    for event in track.iter() {
-                        current_ticks += event.delta;
-                        if current_ticks > playback_midi_ticks + delta_ticks {
-                            self.midi_ticks = match last_event_ticks {
-                                0 => 1,
-                                v => v,
-                            };
-                            return Ok(());
+                self.time += delta_time;
+                // Ticks are accumulated, so tempo changes do not move playback position
+                self.midi_ticks += delta_time * self.tps;
+                let playback_ticks = self.midi_ticks as u32;
+                let mut tempo = None;
+                // Tracks are played simultaneously, every track has own position
+                for (index, track) in data.tracks.iter().enumerate() {
+                    let cursor = &mut self.cursors[index];
+                    while let Some(event) = track.get(cursor.position) {
+                        let ticks = cursor.ticks + event.delta;
+                        if ticks > playback_ticks {
+                            break;
                         }
-                        if current_ticks > playback_midi_ticks
-                            || (current_ticks == 0 && playback_midi_ticks == 0)
-                        {
-                            receiver.receive_event(event)?;
-                            last_event_ticks = current_ticks;
+                        if let OwnedTrackEventKind::Meta(OwnedMetaMessage::Tempo(us)) = event.kind {
+                            tempo = Some(60_000_000.0 / us as f32);
                         }
+                        receiver.receive_track_event(index, event)?;
+                        cursor.ticks = ticks;
+                        cursor.position += 1;
                     }
                 }
-
-                self.midi_ticks = match last_event_ticks {
-                    0 => 1,
-                    v => v,
-                };
+                if let Some(bpm) = tempo {
+                    self.set_bpm(bpm);
+                }
                 Ok(())
             }
             _ => Ok(()),
@@ -134,13 +143,16 @@ impl MidiPlayback for SmfPlayback {
 
     fn reset(&mut self) {
         self.data = None;
+        self.cursors.clear();
         self.time = 0.0;
-        self.midi_ticks = 0;
+        self.midi_ticks = 0.0;
         self.state = PlaybackState::None;
     }
 
     fn load(&mut self, data: Smf<'_>) {
-        self.data = Some(OwnedSmf::try_from(&data).unwrap());
+        let owned = OwnedSmf::try_from(&data).unwrap();
+        self.cursors = vec![TrackCursor::default(); owned.tracks.len()];
+        self.data = Some(owned);
         let ppq = match data.header.timing {
             Timing::Metrical(v) => v.into(),
             Timing::Timecode(_, _) => 192,
@@ -148,13 +160,28 @@ impl MidiPlayback for SmfPlayback {
         self.ppq = ppq as u32;
         self.bpm = 120.0;
         self.tps = Self::calculate_tps(self.bpm, self.ppq);
-        self.midi_ticks = 0;
+        self.midi_ticks = 0.0;
         self.time = 0.0;
         self.state = PlaybackState::Stoped;
     }
 
+    // Nothing to play until file is loaded
     fn play(&mut self) {
-        self.state = PlaybackState::Playing(self.time);
+        if self.data.is_some() {
+            self.state = PlaybackState::Playing(self.time);
+        }
+    }
+
+    fn pause(&mut self) {
+        if let PlaybackState::Playing(_) = self.state {
+            self.state = PlaybackState::Paused(self.time);
+        }
+    }
+
+    fn rewind(&mut self) {
+        self.cursors.fill(TrackCursor::default());
+        self.midi_ticks = 0.0;
+        self.time = 0.0;
     }
 
     fn get_state(&self) -> PlaybackState {
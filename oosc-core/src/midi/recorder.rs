@@ -0,0 +1,272 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use midly::{
+    num::{u15, u24, u28},
+    Format, Header, MetaMessage, Smf, Timing, Track, TrackEvent, TrackEventKind,
+};
+
+use crate::{error::Error, utils::SharedMutex};
+
+use super::{
+    mediator::MidiEventReceiver,
+    smf_extensions::{OwnedTrackEvent, OwnedTrackEventKind},
+};
+
+struct RecordedEvent {
+    time: f32,
+    kind: OwnedTrackEventKind,
+}
+
+pub struct MidiRecorder {
+    receiver: SharedMutex<dyn MidiEventReceiver>,
+    events: Vec<RecordedEvent>,
+    start: Option<Instant>,
+    path: Option<PathBuf>,
+    format: Format,
+    bpm: f32,
+    ppq: u16,
+}
+
+impl MidiRecorder {
+    pub fn new(receiver: SharedMutex<dyn MidiEventReceiver>) -> Self {
+        Self {
+            receiver,
+            events: vec![],
+            start: None,
+            path: None,
+            format: Format::SingleTrack,
+            bpm: 120.0,
+            ppq: 480,
+        }
+    }
+
+    pub fn to_file<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+        self.path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    // SingleTrack writes type 0 file, other formats write one track per channel (type 1)
+    pub fn set_format(&mut self, format: Format) -> &mut Self {
+        self.format = format;
+        self
+    }
+
+    pub fn set_bpm(&mut self, bpm: f32) -> &mut Self {
+        self.bpm = bpm;
+        self
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.start.is_some()
+    }
+
+    pub fn time(&self) -> f32 {
+        self.start
+            .map(|start| start.elapsed().as_secs_f32())
+            .unwrap_or(0.0)
+    }
+
+    pub fn start(&mut self) -> Result<(), Error> {
+        if self.path.is_none() {
+            return Err(Error::Specify("midi file"));
+        }
+        self.events.clear();
+        self.start = Some(Instant::now());
+        Ok(())
+    }
+
+    pub fn stop(&mut self) -> Result<(), Error> {
+        if self.start.take().is_none() {
+            return Ok(());
+        }
+        let path = self.path.take().ok_or("Cannot get midi file path")?;
+        self.to_smf().save(path)?;
+        self.events.clear();
+        Ok(())
+    }
+
+    pub fn to_smf(&self) -> Smf<'_> {
+        let mut smf = Smf::new(Header::new(
+            self.format,
+            Timing::Metrical(u15::new(self.ppq)),
+        ));
+        let tempo = (60_000_000.0 / self.bpm) as u32;
+        let mut conductor = vec![Self::event(
+            0,
+            TrackEventKind::Meta(MetaMessage::Tempo(u24::new(tempo))),
+        )];
+        match self.format {
+            Format::SingleTrack => {
+                conductor.extend(self.track_events(self.events.iter()));
+                smf.tracks.push(Self::end_track(conductor));
+            }
+            _ => {
+                let mut channels: BTreeMap<u8, Vec<&RecordedEvent>> = BTreeMap::new();
+                let mut common = vec![];
+                self.events.iter().for_each(|event| match event.kind {
+                    OwnedTrackEventKind::Midi { channel, .. } => {
+                        channels.entry(channel).or_default().push(event)
+                    }
+                    _ => common.push(event),
+                });
+                conductor.extend(self.track_events(common.into_iter()));
+                smf.tracks.push(Self::end_track(conductor));
+                channels.into_values().for_each(|events| {
+                    let track = self.track_events(events.into_iter()).collect();
+                    smf.tracks.push(Self::end_track(track));
+                });
+            }
+        }
+        smf
+    }
+
+    fn record(&mut self, event: &OwnedTrackEvent) {
+        // Tempo, end of track etc. from SMF playback have no sense in recorded file,
+        // clock and other system messages cannot be stored
+        if !matches!(
+            event.kind,
+            OwnedTrackEventKind::Midi { .. } | OwnedTrackEventKind::SysEx(_)
+        ) {
+            return;
+        }
+        let time = self.time();
+        self.events.push(RecordedEvent {
+            time,
+            kind: event.kind.clone(),
+        });
+    }
+
+    fn track_events<'a>(
+        &self,
+        events: impl Iterator<Item = &'a RecordedEvent> + 'a,
+    ) -> impl Iterator<Item = TrackEvent<'a>> + 'a {
+        let ticks_per_second = self.bpm / 60.0 * self.ppq as f32;
+        let mut last_ticks = 0;
+        events.filter_map(move |event| {
+            let kind = TrackEventKind::try_from(&event.kind).ok()?;
+            let ticks = (event.time * ticks_per_second).round() as u32;
+            let delta = ticks.saturating_sub(last_ticks);
+            last_ticks = last_ticks.max(ticks);
+            Some(Self::event(delta, kind))
+        })
+    }
+
+    fn end_track(mut track: Track<'_>) -> Track<'_> {
+        track.push(Self::event(
+            0,
+            TrackEventKind::Meta(MetaMessage::EndOfTrack),
+        ));
+        track
+    }
+
+    fn event(delta: u32, kind: TrackEventKind<'_>) -> TrackEvent<'_> {
+        TrackEvent {
+            delta: u28::new(delta),
+            kind,
+        }
+    }
+}
+
+impl MidiEventReceiver for MidiRecorder {
+    fn receive_event(&mut self, event: &OwnedTrackEvent) -> Result<(), Error> {
+        if self.is_recording() {
+            self.record(event);
+        }
+        self.receiver.lock().unwrap().receive_event(event)
+    }
+
+    fn receive_track_event(&mut self, track: usize, event: &OwnedTrackEvent) -> Result<(), Error> {
+        if self.is_recording() {
+            self.record(event);
+        }
+        self.receiver
+            .lock()
+            .unwrap()
+            .receive_track_event(track, event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use midly::{num::u7, Format, MetaMessage, MidiMessage, TrackEventKind};
+
+    use crate::{
+        error::Error,
+        midi::{
+            mediator::MidiEventReceiver,
+            smf_extensions::{OwnedTrackEvent, OwnedTrackEventKind},
+        },
+        utils::make_shared_mutex,
+    };
+
+    use super::{MidiRecorder, RecordedEvent};
+
+    struct NullReceiver;
+
+    impl MidiEventReceiver for NullReceiver {
+        fn receive_event(&mut self, _event: &OwnedTrackEvent) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    fn note(time: f32, channel: u8, key: u8) -> RecordedEvent {
+        RecordedEvent {
+            time,
+            kind: OwnedTrackEventKind::Midi {
+                channel,
+                message: MidiMessage::NoteOn {
+                    key: u7::new(key),
+                    vel: u7::new(100),
+                },
+            },
+        }
+    }
+
+    #[test]
+    fn test_recorder_format() {
+        let mut recorder = MidiRecorder::new(make_shared_mutex(NullReceiver));
+        recorder.events = vec![note(0.0, 0, 60), note(0.5, 1, 62), note(1.0, 0, 64)];
+
+        let smf = recorder.to_smf();
+        assert_eq!(smf.tracks.len(), 1);
+        let track = &smf.tracks[0];
+        assert!(matches!(
+            track[0].kind,
+            TrackEventKind::Meta(MetaMessage::Tempo(t)) if t.as_int() == 500_000
+        ));
+        let deltas: Vec<u32> = track.iter().map(|e| e.delta.as_int()).collect();
+        assert_eq!(deltas, vec![0, 0, 480, 480, 0]);
+
+        recorder.set_format(Format::Parallel);
+        let smf = recorder.to_smf();
+        assert_eq!(smf.tracks.len(), 3);
+        let deltas: Vec<u32> = smf.tracks[1].iter().map(|e| e.delta.as_int()).collect();
+        assert_eq!(deltas, vec![0, 960, 0]);
+        let deltas: Vec<u32> = smf.tracks[2].iter().map(|e| e.delta.as_int()).collect();
+        assert_eq!(deltas, vec![480, 0]);
+    }
+
+    #[test]
+    fn test_recorder_file() {
+        let path = std::env::temp_dir().join("oosc_test_record.mid");
+        let mut recorder = MidiRecorder::new(make_shared_mutex(NullReceiver));
+        assert!(recorder.start().is_err());
+        recorder.to_file(&path).start().unwrap();
+        recorder
+            .receive_event(&OwnedTrackEvent {
+                delta: 0,
+                kind: note(0.0, 0, 60).kind,
+            })
+            .unwrap();
+        recorder.stop().unwrap();
+        assert!(!recorder.is_recording());
+        let data = std::fs::read(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let smf = midly::Smf::parse(&data).unwrap();
+        assert_eq!(smf.tracks[0].len(), 3);
+    }
+}
@@ -0,0 +1,426 @@
+use std::path::Path;
+
+use midly::{
+    num::{u15, u24, u28, u4, u7},
+    Format, Header, MetaMessage, MidiMessage, Smf, Timing, TrackEvent, TrackEventKind,
+};
+
+use crate::{
+    core::{parameter::PathParameter, transport::Transport},
+    error::Error,
+    utils::{random::Random, Shared, SharedMutex},
+};
+
+use super::{mediator::MidiEventReceiver, note_sender::NoteSender};
+
+pub const MIN_STEPS: usize = 16;
+pub const MAX_STEPS: usize = 64;
+const SMF_PPQ: u16 = 480;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Step {
+    pub active: bool,
+    pub note: u8,
+    pub velocity: u8,
+    // Part of step the note sounds
+    pub gate: f32,
+    // Note is held into next step and is not retriggered if next step plays the same note
+    pub tie: bool,
+    pub probability: f32,
+    // Parameter paths and values set while step plays
+    pub locks: Vec<(String, f32)>,
+}
+
+impl Default for Step {
+    fn default() -> Self {
+        Self {
+            active: false,
+            note: 60,
+            velocity: 100,
+            gate: 0.5,
+            tie: false,
+            probability: 1.0,
+            locks: vec![],
+        }
+    }
+}
+
+// Plays steps following transport position and sends notes to receiver
+pub struct StepSequencer {
+    transport: Shared<Transport>,
+    parameters: Vec<PathParameter<f32>>,
+    steps: Vec<Step>,
+    length: usize,
+    // Steps per beat
+    rate: u32,
+    enabled: bool,
+    // Absolute number of last played step since transport start
+    last: Option<u64>,
+    sender: NoteSender,
+    tied: bool,
+    // Locked parameters with values to restore
+    locked: Vec<(usize, f32)>,
+    random: Random,
+}
+
+impl StepSequencer {
+    pub fn new(
+        receiver: SharedMutex<dyn MidiEventReceiver>,
+        transport: Shared<Transport>,
+        parameters: Vec<PathParameter<f32>>,
+    ) -> Self {
+        Self {
+            transport,
+            parameters,
+            steps: vec![Step::default(); MAX_STEPS],
+            length: MIN_STEPS,
+            rate: 4,
+            enabled: false,
+            last: None,
+            sender: NoteSender::new(receiver),
+            tied: false,
+            locked: vec![],
+            random: Random::from_time(),
+        }
+    }
+
+    pub fn steps(&self) -> &[Step] {
+        &self.steps[..self.length]
+    }
+
+    pub fn step_mut(&mut self, index: usize) -> Option<&mut Step> {
+        self.steps[..self.length].get_mut(index)
+    }
+
+    pub fn length(&self) -> usize {
+        self.length
+    }
+
+    // Steps after the length are kept, so pattern can be shortened and extended back
+    pub fn set_length(&mut self, length: usize) -> Result<&mut Self, Error> {
+        if !(MIN_STEPS..=MAX_STEPS).contains(&length) {
+            return Err(format!("Length should be {}..{} steps", MIN_STEPS, MAX_STEPS).into());
+        }
+        self.length = length;
+        Ok(self)
+    }
+
+    pub fn rate(&self) -> u32 {
+        self.rate
+    }
+
+    pub fn set_rate(&mut self, rate: u32) -> &mut Self {
+        self.rate = rate.max(1);
+        self
+    }
+
+    pub fn channel(&self) -> u8 {
+        self.sender.channel()
+    }
+
+    pub fn set_channel(&mut self, channel: u8) -> &mut Self {
+        self.sender.set_route(channel, None);
+        self
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) -> Result<&mut Self, Error> {
+        if !enabled {
+            self.stop()?;
+        }
+        self.enabled = enabled;
+        Ok(self)
+    }
+
+    // Parameters which can be locked by steps
+    pub fn parameters(&self) -> &[PathParameter<f32>] {
+        &self.parameters
+    }
+
+    // Index of playing step
+    pub fn current_step(&self) -> Option<usize> {
+        self.last.map(|step| (step % self.length as u64) as usize)
+    }
+
+    // Called after transport is advanced
+    pub fn advance(&mut self) -> Result<(), Error> {
+        let (playing, position) = {
+            let transport = self.transport.read().unwrap();
+            (transport.is_playing(), transport.position())
+        };
+        if !self.enabled || !playing {
+            return self.stop();
+        }
+        self.sender.release_due(position)?;
+        let current = (position * self.rate as f32).floor() as u64;
+        if self.last != Some(current) {
+            self.last = Some(current);
+            let index = (current % self.length as u64) as usize;
+            self.play_step(index, current as f32 / self.rate as f32)?;
+        }
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), Error> {
+        self.restore_locks();
+        self.last = None;
+        self.tied = false;
+        self.sender.release()
+    }
+
+    fn play_step(&mut self, index: usize, start: f32) -> Result<(), Error> {
+        self.restore_locks();
+        let step = self.steps[index].clone();
+        let fire = step.active && self.random.next_f32() < step.probability;
+        let tied = std::mem::take(&mut self.tied);
+        let previous = self.sender.take().map(|(key, _)| key);
+        if let Some(key) = previous.filter(|_| !tied) {
+            self.sender.note_off(key)?;
+        }
+        let mut retrigger = true;
+        if fire {
+            step.locks
+                .iter()
+                .for_each(|(path, value)| self.lock(path, *value));
+            retrigger = !tied || previous != Some(step.note);
+            let end = match step.tie {
+                true => f32::INFINITY,
+                false => start + step.gate / self.rate as f32,
+            };
+            match retrigger {
+                true => self.sender.note_on(step.note, step.velocity, end)?,
+                false => self.sender.hold(step.note, end),
+            }
+            self.tied = step.tie;
+        }
+        // Legato, tied note is released after the next one starts
+        if let Some(key) = previous.filter(|_| tied && retrigger) {
+            self.sender.note_off(key)?;
+        }
+        Ok(())
+    }
+
+    fn lock(&mut self, path: &str, value: f32) {
+        let Some(index) = self.parameters.iter().position(|p| p.path == path) else {
+            return;
+        };
+        let mut parameter = self.parameters[index].parameter.write().unwrap();
+        if !self.locked.iter().any(|(i, _)| *i == index) {
+            self.locked.push((index, parameter.get_value()));
+        }
+        parameter.set_value(value);
+    }
+
+    fn restore_locks(&mut self) {
+        self.locked.drain(..).for_each(|(index, value)| {
+            self.parameters[index]
+                .parameter
+                .write()
+                .unwrap()
+                .set_value(value)
+        });
+    }
+
+    // Notes of one pattern loop as start tick, end tick, key and velocity.
+    // Every active step is exported, probabilities and locks are not stored.
+    fn pattern_notes(&self) -> Vec<(u32, u32, u8, u8)> {
+        let step_ticks = (SMF_PPQ as u32 / self.rate) as f32;
+        let mut notes: Vec<(u32, u32, u8, u8)> = vec![];
+        let mut tied = false;
+        self.steps().iter().enumerate().for_each(|(i, step)| {
+            let start = (i as f32 * step_ticks) as u32;
+            let was_tied = std::mem::take(&mut tied);
+            if !step.active {
+                return;
+            }
+            let gate = if step.tie { 1.0 } else { step.gate };
+            let end = ((i as f32 + gate) * step_ticks) as u32;
+            tied = step.tie;
+            match notes.last_mut() {
+                Some(last) if was_tied && last.2 == step.note && last.1 == start => last.1 = end,
+                _ => notes.push((start, end, step.note.min(127), step.velocity.clamp(1, 127))),
+            }
+        });
+        notes
+    }
+
+    pub fn to_smf(&self, loops: usize) -> Smf<'static> {
+        let mut smf = Smf::new(Header::new(
+            Format::SingleTrack,
+            Timing::Metrical(u15::new(SMF_PPQ)),
+        ));
+        let bpm = self.transport.read().unwrap().bpm();
+        let pattern_ticks = self.length as u32 * (SMF_PPQ as u32 / self.rate);
+        let channel = u4::new(self.channel());
+        let notes = self.pattern_notes();
+        // Note offs go before note ons at the same tick
+        let mut events = (0..loops as u32)
+            .flat_map(|l| {
+                notes.iter().flat_map(move |(start, end, key, vel)| {
+                    let offset = l * pattern_ticks;
+                    let key = u7::new(*key);
+                    [
+                        (
+                            offset + end,
+                            0,
+                            MidiMessage::NoteOff {
+                                key,
+                                vel: u7::new(0),
+                            },
+                        ),
+                        (
+                            offset + start,
+                            1,
+                            MidiMessage::NoteOn {
+                                key,
+                                vel: u7::new(*vel),
+                            },
+                        ),
+                    ]
+                })
+            })
+            .collect::<Vec<_>>();
+        events.sort_by_key(|(tick, order, _)| (*tick, *order));
+        let tempo = (60_000_000.0 / bpm) as u32;
+        let mut track = vec![TrackEvent {
+            delta: u28::new(0),
+            kind: TrackEventKind::Meta(MetaMessage::Tempo(u24::new(tempo))),
+        }];
+        let mut last = 0;
+        events.into_iter().for_each(|(tick, _, message)| {
+            track.push(TrackEvent {
+                delta: u28::new(tick - last),
+                kind: TrackEventKind::Midi { channel, message },
+            });
+            last = tick;
+        });
+        track.push(TrackEvent {
+            delta: u28::new((loops as u32 * pattern_ticks).saturating_sub(last)),
+            kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+        });
+        smf.tracks.push(track);
+        smf
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P, loops: usize) -> Result<(), Error> {
+        self.to_smf(loops).save(path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use midly::{MidiMessage, TrackEventKind};
+
+    use crate::{
+        core::{
+            parameter::{PathParameter, SharedParameter, ValueParameter},
+            transport::Transport,
+        },
+        midi::note_sender::tests::{take, Received},
+        utils::{make_shared, make_shared_mutex, Shared, SharedMutex},
+    };
+
+    use super::StepSequencer;
+
+    fn build() -> (StepSequencer, SharedMutex<Received>, Shared<Transport>) {
+        let received = make_shared_mutex(Received::default());
+        let transport = make_shared(Transport::new(60.0));
+        let parameter: SharedParameter<f32> = make_shared(ValueParameter::new(0.5, (0.0, 1.0)));
+        let parameters = vec![PathParameter {
+            path: "Osc1/Gain".to_owned(),
+            parameter,
+        }];
+        let mut sequencer = StepSequencer::new(received.clone(), transport.clone(), parameters);
+        sequencer.set_enabled(true).unwrap();
+        let mut set = |index: usize, note: u8, tie: bool| {
+            let step = sequencer.step_mut(index).unwrap();
+            step.active = true;
+            step.note = note;
+            step.tie = tie;
+        };
+        set(0, 60, false);
+        set(1, 62, true);
+        set(2, 62, true);
+        set(3, 64, false);
+        (sequencer, received, transport)
+    }
+
+    fn play(sequencer: &mut StepSequencer, transport: &Shared<Transport>, time: f32) {
+        transport.write().unwrap().advance(time);
+        sequencer.advance().unwrap();
+    }
+
+    #[test]
+    fn test_sequencer_steps() {
+        let (mut sequencer, received, transport) = build();
+        sequencer.step_mut(5).unwrap().active = true;
+        sequencer.step_mut(5).unwrap().probability = 0.0;
+        sequencer.advance().unwrap();
+        assert!(take(&received).is_empty());
+        transport.write().unwrap().start();
+        sequencer.advance().unwrap();
+        assert_eq!(take(&received), vec![60]);
+        play(&mut sequencer, &transport, 0.125);
+        assert_eq!(take(&received), vec![-60]);
+        // Tied steps with same note are merged, different note is played legato
+        (0..4).for_each(|_| play(&mut sequencer, &transport, 0.125));
+        assert_eq!(take(&received), vec![62]);
+        (0..5).for_each(|_| play(&mut sequencer, &transport, 0.125));
+        assert_eq!(take(&received), vec![64, -62, -64]);
+        assert_eq!(sequencer.current_step(), Some(5));
+        transport.write().unwrap().stop();
+        sequencer.advance().unwrap();
+        assert_eq!(sequencer.current_step(), None);
+    }
+
+    #[test]
+    fn test_sequencer_locks() {
+        let (mut sequencer, _, transport) = build();
+        let parameter = sequencer.parameters()[0].parameter.clone();
+        sequencer
+            .step_mut(1)
+            .unwrap()
+            .locks
+            .push(("Osc1/Gain".to_owned(), 0.9));
+        transport.write().unwrap().start();
+        sequencer.advance().unwrap();
+        assert_eq!(parameter.read().unwrap().get_value(), 0.5);
+        play(&mut sequencer, &transport, 0.25);
+        assert_eq!(parameter.read().unwrap().get_value(), 0.9);
+        play(&mut sequencer, &transport, 0.25);
+        assert_eq!(parameter.read().unwrap().get_value(), 0.5);
+        play(&mut sequencer, &transport, 3.5);
+        play(&mut sequencer, &transport, 0.25);
+        assert_eq!(parameter.read().unwrap().get_value(), 0.9);
+        sequencer.set_enabled(false).unwrap();
+        assert_eq!(parameter.read().unwrap().get_value(), 0.5);
+    }
+
+    #[test]
+    fn test_sequencer_smf() {
+        let (mut sequencer, _, _) = build();
+        assert!(sequencer.set_length(8).is_err());
+        sequencer.set_length(32).unwrap();
+        let smf = sequencer.to_smf(2);
+        let track = &smf.tracks[0];
+        let events = track
+            .iter()
+            .filter_map(|e| match e.kind {
+                TrackEventKind::Midi { message, .. } => Some((e.delta.as_int(), message)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        // 60 for half of step, tied 62 for two steps, 64 legato after it
+        let deltas = events.iter().map(|(d, _)| *d).collect::<Vec<_>>();
+        assert_eq!(
+            deltas,
+            vec![0, 60, 60, 240, 0, 60, 3420, 60, 60, 240, 0, 60]
+        );
+        assert!(matches!(events[3].1, MidiMessage::NoteOff { key, .. } if key == 62));
+        assert_eq!(track.last().unwrap().delta.as_int(), 3420);
+    }
+}
@@ -1,5 +1,6 @@
-use midly::live::LiveEvent;
+use midly::live::{LiveEvent, MtcQuarterFrameMessage, SystemCommon, SystemRealtime};
 use midly::Header;
+use midly::num::u7;
 use midly::MidiMessage;
 use midly::Smf;
 use midly::Track;
@@ -7,6 +8,7 @@ use midly::TrackEvent;
 use midly::TrackEventKind;
 use midly::{MetaMessage, SmpteTime};
 
+#[derive(Clone)]
 pub enum OwnedMetaMessage {
     TrackNumber(Option<u16>),
     Text(Vec<u8>),
@@ -56,11 +58,67 @@ impl<'a> TryFrom<&MetaMessage<'a>> for OwnedMetaMessage {
     }
 }
 
+impl<'a> From<&'a OwnedMetaMessage> for MetaMessage<'a> {
+    fn from(value: &'a OwnedMetaMessage) -> Self {
+        match value {
+            OwnedMetaMessage::TrackNumber(v) => Self::TrackNumber(*v),
+            OwnedMetaMessage::Text(v) => Self::Text(v),
+            OwnedMetaMessage::Copyright(v) => Self::Copyright(v),
+            OwnedMetaMessage::TrackName(v) => Self::TrackName(v),
+            OwnedMetaMessage::InstrumentName(v) => Self::InstrumentName(v),
+            OwnedMetaMessage::Lyric(v) => Self::Lyric(v),
+            OwnedMetaMessage::Marker(v) => Self::Marker(v),
+            OwnedMetaMessage::CuePoint(v) => Self::CuePoint(v),
+            OwnedMetaMessage::ProgramName(v) => Self::ProgramName(v),
+            OwnedMetaMessage::DeviceName(v) => Self::DeviceName(v),
+            OwnedMetaMessage::MidiChannel(v) => Self::MidiChannel((*v).into()),
+            OwnedMetaMessage::MidiPort(v) => Self::MidiPort((*v).into()),
+            OwnedMetaMessage::EndOfTrack => Self::EndOfTrack,
+            OwnedMetaMessage::Tempo(v) => Self::Tempo((*v).into()),
+            OwnedMetaMessage::SmpteOffset(v) => Self::SmpteOffset(*v),
+            OwnedMetaMessage::TimeSignature(a, b, c, d) => Self::TimeSignature(*a, *b, *c, *d),
+            OwnedMetaMessage::KeySignature(a, b) => Self::KeySignature(*a, *b),
+            OwnedMetaMessage::SequencerSpecific(v) => Self::SequencerSpecific(v),
+            OwnedMetaMessage::Unknown(a, b) => Self::Unknown(*a, b),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub enum OwnedSystemCommon {
+    SysEx(Vec<u8>),
+    MidiTimeCodeQuarterFrame(MtcQuarterFrameMessage, u8),
+    SongPosition(u16),
+    SongSelect(u8),
+    TuneRequest,
+    Undefined(u8, Vec<u8>),
+}
+
+impl<'a> From<&SystemCommon<'a>> for OwnedSystemCommon {
+    fn from(value: &SystemCommon<'a>) -> Self {
+        let to_vec = |data: &[u7]| data.iter().map(|v| v.as_int()).collect();
+        match value {
+            SystemCommon::SysEx(v) => Self::SysEx(to_vec(v)),
+            SystemCommon::MidiTimeCodeQuarterFrame(m, v) => {
+                Self::MidiTimeCodeQuarterFrame(*m, v.as_int())
+            }
+            SystemCommon::SongPosition(v) => Self::SongPosition(v.as_int()),
+            SystemCommon::SongSelect(v) => Self::SongSelect(v.as_int()),
+            SystemCommon::TuneRequest => Self::TuneRequest,
+            SystemCommon::Undefined(status, v) => Self::Undefined(*status, to_vec(v)),
+        }
+    }
+}
+
+// Common and Realtime messages exist only in live streams and cannot be stored in SMF
+#[derive(Clone)]
 pub enum OwnedTrackEventKind {
     Midi { channel: u8, message: MidiMessage },
     SysEx(Vec<u8>),
     Escape(Vec<u8>),
     Meta(OwnedMetaMessage),
+    Common(OwnedSystemCommon),
+    Realtime(SystemRealtime),
 }
 
 impl<'a> TryFrom<&TrackEventKind<'a>> for OwnedTrackEventKind {
@@ -78,6 +136,25 @@ impl<'a> TryFrom<&TrackEventKind<'a>> for OwnedTrackEventKind {
     }
 }
 
+impl<'a> TryFrom<&'a OwnedTrackEventKind> for TrackEventKind<'a> {
+    type Error = crate::error::Error;
+
+    fn try_from(value: &'a OwnedTrackEventKind) -> Result<Self, Self::Error> {
+        Ok(match value {
+            OwnedTrackEventKind::Midi { channel, message } => Self::Midi {
+                channel: (*channel).into(),
+                message: *message,
+            },
+            OwnedTrackEventKind::SysEx(v) => Self::SysEx(v),
+            OwnedTrackEventKind::Escape(v) => Self::Escape(v),
+            OwnedTrackEventKind::Meta(v) => Self::Meta(v.into()),
+            OwnedTrackEventKind::Common(_) | OwnedTrackEventKind::Realtime(_) => {
+                return Err("System messages cannot be stored in track".into())
+            }
+        })
+    }
+}
+
 impl<'a> TryFrom<&LiveEvent<'a>> for OwnedTrackEventKind {
     type Error = crate::error::Error;
 
@@ -87,12 +164,13 @@ impl<'a> TryFrom<&LiveEvent<'a>> for OwnedTrackEventKind {
                 channel: (*channel).into(),
                 message: *message,
             },
-            LiveEvent::Common(_) => todo!(),
-            LiveEvent::Realtime(_) => todo!(),
+            LiveEvent::Common(v) => Self::Common(v.into()),
+            LiveEvent::Realtime(v) => Self::Realtime(*v),
         })
     }
 }
 
+#[derive(Clone)]
 pub struct OwnedTrackEvent {
     pub delta: u32,
     pub kind: OwnedTrackEventKind,
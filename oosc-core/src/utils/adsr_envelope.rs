@@ -1,16 +1,20 @@
+use std::mem::take;
+
 use cgmath::Vector2;
 
 use crate::{
-    core::parameter::{CallbackParameter, SharedParameter},
+    core::parameter::{CallbackParameter, SharedParameter, ValueParameter},
     error::Error,
 };
 
-use super::{cubic_bezier::CubicBezierCurve, make_shared, Shared};
+use super::{cubic_bezier::CubicBezierCurve, evaluate::Evaluate, make_shared, Shared};
 
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, PartialEq, Debug)]
 pub enum State {
     None,
+    Delay,
     Attack,
+    Hold,
     Decay,
     Sustain,
     Release,
@@ -24,119 +28,434 @@ pub struct SharedCurve {
     pub curve: Shared<CubicBezierCurve>,
 }
 
+impl State {
+    fn name(&self) -> &'static str {
+        match self {
+            State::None => "none",
+            State::Delay => "delay",
+            State::Attack => "attack",
+            State::Hold => "hold",
+            State::Decay => "decay",
+            State::Sustain => "sustain",
+            State::Release => "release",
+        }
+    }
+
+    fn parse(name: &str) -> Result<Self, Error> {
+        [
+            State::None,
+            State::Delay,
+            State::Attack,
+            State::Hold,
+            State::Decay,
+            State::Sustain,
+            State::Release,
+        ]
+        .into_iter()
+        .find(|state| state.name() == name)
+        .ok_or(format!("Unknown envelope stage \"{}\"", name).into())
+    }
+}
+
+pub struct Segment {
+    // Stage segment belongs to, several segments can make up one stage
+    pub kind: State,
+    pub curve: SharedCurve,
+}
+
+// Plain copy of envelope, stored in presets without sharing its curves
+#[derive(Clone)]
+pub struct EnvelopeShape {
+    pub segments: Vec<(State, CubicBezierCurve)>,
+    pub sustain_point: Option<usize>,
+    pub loop_points: (usize, usize),
+    pub sustain_loop: bool,
+}
+
+impl EnvelopeShape {
+    // Segments "<stage>:<ax>,<ay>,<bx>,<by>,<cx>,<cy>,<dx>,<dy>" followed by
+    // "sustain_point:<point>|none" and "loop:<start>,<end>,<0|1>", all joined by ';'
+    pub fn to_text(&self) -> String {
+        let segments = self.segments.iter().map(|(kind, c)| {
+            format!(
+                "{}:{},{},{},{},{},{},{},{}",
+                kind.name(),
+                c.a.x,
+                c.a.y,
+                c.b.x,
+                c.b.y,
+                c.c.x,
+                c.c.y,
+                c.d.x,
+                c.d.y
+            )
+        });
+        let sustain = match self.sustain_point {
+            Some(point) => format!("sustain_point:{}", point),
+            None => "sustain_point:none".to_owned(),
+        };
+        let (start, end) = self.loop_points;
+        let looped = format!("loop:{},{},{}", start, end, self.sustain_loop as u8);
+        segments
+            .chain([sustain, looped])
+            .collect::<Vec<_>>()
+            .join(";")
+    }
+
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let mut shape = Self {
+            segments: vec![],
+            sustain_point: None,
+            loop_points: (0, 0),
+            sustain_loop: false,
+        };
+        for field in text.split(';') {
+            let (name, values) = field
+                .split_once(':')
+                .ok_or(format!("Invalid envelope field \"{}\"", field))?;
+            let numbers = |values: &str| {
+                values
+                    .split(',')
+                    .map(|v| v.parse::<f32>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| format!("Invalid envelope value \"{}\": {}", field, e))
+            };
+            match (name, values) {
+                ("sustain_point", "none") => shape.sustain_point = None,
+                ("sustain_point", point) => {
+                    shape.sustain_point = Some(
+                        point
+                            .parse::<usize>()
+                            .map_err(|e| format!("Invalid sustain point: {}", e))?,
+                    )
+                }
+                ("loop", values) => match numbers(values)?[..] {
+                    [start, end, looped] => {
+                        shape.loop_points = (start as usize, end as usize);
+                        shape.sustain_loop = looped != 0.0;
+                    }
+                    _ => return Err(format!("Invalid envelope loop \"{}\"", field).into()),
+                },
+                (name, values) => match numbers(values)?[..] {
+                    [ax, ay, bx, by, cx, cy, dx, dy] => shape.segments.push((
+                        State::parse(name)?,
+                        CubicBezierCurve::new(
+                            Vector2::new(ax, ay),
+                            Vector2::new(bx, by),
+                            Vector2::new(cx, cy),
+                            Vector2::new(dx, dy),
+                        ),
+                    )),
+                    _ => return Err(format!("Invalid envelope segment \"{}\"", field).into()),
+                },
+            }
+        }
+        if shape.segments.is_empty() {
+            return Err("Envelope has no segments".into());
+        }
+        Ok(shape)
+    }
+}
+
+// Breakpoint envelope of bezier segments, ADSR is only its default shape.
+// While key is down segments up to sustain point are played and the level there is held,
+// or segments between loop points are repeated when looping is enabled.
+// Release plays segments after sustain point scaled from the level reached at release.
+// Envelope without sustain point is one-shot and ignores release.
 pub struct ADSREnvelope {
-    pub attack: SharedCurve,
-    pub decay: SharedCurve,
-    pub sustain: SharedCurve,
-    pub release: SharedCurve,
+    segments: Vec<Segment>,
+    sustain_point: Option<usize>,
+    loop_points: (usize, usize),
+    pub sustain_loop: SharedParameter<bool>,
 }
 
 impl ADSREnvelope {
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    pub fn shape(&self) -> EnvelopeShape {
+        EnvelopeShape {
+            segments: self
+                .segments
+                .iter()
+                .map(|s| (s.kind, s.curve.curve.read().unwrap().clone()))
+                .collect(),
+            sustain_point: self.sustain_point,
+            loop_points: self.loop_points,
+            sustain_loop: self.is_sustain_looped(),
+        }
+    }
+
+    // Curves are updated in place when segment count is the same, so editors keep them
+    pub fn set_shape(&mut self, shape: &EnvelopeShape) -> Result<(), Error> {
+        if shape.segments.is_empty() {
+            return Err("Envelope has no segments".into());
+        }
+        match shape.segments.len() == self.segments.len() {
+            true => self
+                .segments
+                .iter_mut()
+                .zip(shape.segments.iter())
+                .for_each(|(segment, (kind, curve))| {
+                    segment.kind = *kind;
+                    *segment.curve.curve.write().unwrap() = curve.clone();
+                }),
+            false => self.segments = ADSREnvelopeBuilder::link_segments(shape.segments.clone()),
+        }
+        self.set_sustain_point(shape.sustain_point)?;
+        self.set_loop_points(shape.loop_points.0, shape.loop_points.1)?;
+        self.sustain_loop
+            .write()
+            .unwrap()
+            .set_value(shape.sustain_loop);
+        Ok(())
+    }
+
+    // Takes shape of other envelope without allocating, other gets replaced segments
+    // when their count differs
+    pub fn swap_shape(&mut self, other: &mut ADSREnvelope) -> Result<(), Error> {
+        match other.segments.len() == self.segments.len() {
+            true => self
+                .segments
+                .iter_mut()
+                .zip(other.segments.iter())
+                .for_each(|(segment, other)| {
+                    segment.kind = other.kind;
+                    *segment.curve.curve.write().unwrap() =
+                        other.curve.curve.read().unwrap().clone();
+                }),
+            false => std::mem::swap(&mut self.segments, &mut other.segments),
+        }
+        self.set_sustain_point(other.sustain_point)?;
+        self.set_loop_points(other.loop_points.0, other.loop_points.1)?;
+        let sustain_loop = other.is_sustain_looped();
+        self.sustain_loop.write().unwrap().set_value(sustain_loop);
+        Ok(())
+    }
+
+    // Breakpoint n is the start of segment n, the last one is the end of envelope
+    pub fn sustain_point(&self) -> Option<usize> {
+        self.sustain_point
+    }
+
+    pub fn set_sustain_point(&mut self, point: Option<usize>) -> Result<(), Error> {
+        if point.is_some_and(|p| p > self.segments.len()) {
+            return Err("Sustain point out of envelope".into());
+        }
+        self.sustain_point = point;
+        let (start, end) = self.loop_points;
+        let limit = self.loop_limit();
+        self.loop_points = (start.min(limit), end.min(limit));
+        Ok(())
+    }
+
+    pub fn loop_points(&self) -> (usize, usize) {
+        self.loop_points
+    }
+
+    pub fn set_loop_points(&mut self, start: usize, end: usize) -> Result<(), Error> {
+        if start > end || end > self.loop_limit() {
+            return Err("Loop should end before sustain point".into());
+        }
+        self.loop_points = (start, end);
+        Ok(())
+    }
+
+    fn loop_limit(&self) -> usize {
+        self.sustain_point.unwrap_or(self.segments.len())
+    }
+
+    pub fn is_gated(&self) -> bool {
+        self.sustain_point.is_some()
+    }
+
+    pub fn is_sustain_looped(&self) -> bool {
+        self.sustain_loop.read().unwrap().get_value()
+    }
+
+    // Time at which breakpoint is reached
+    pub fn time_at(&self, point: usize) -> f32 {
+        self.segments[..point]
+            .iter()
+            .map(|s| s.curve.curve.read().unwrap().difference().x)
+            .sum()
+    }
+
+    pub fn level_at_point(&self, point: usize) -> f32 {
+        match point {
+            0 => self
+                .segments
+                .first()
+                .map_or(0.0, |s| s.curve.curve.read().unwrap().start().y),
+            _ => self.segments[point - 1].curve.curve.read().unwrap().end().y,
+        }
+    }
+
+    pub fn length(&self) -> f32 {
+        self.time_at(self.segments.len())
+    }
+
+    // Level at time t since note on, release is the time key went up
+    pub fn level_at(&self, t: f32, release: Option<f32>) -> f32 {
+        match release {
+            Some(release) if self.is_gated() => {
+                self.release_from(self.held(release), (t - release).max(0.0))
+            }
+            _ => self.held(t),
+        }
+    }
+
+    // Level while the key is down
+    pub fn held(&self, t: f32) -> f32 {
+        let Some(sustain) = self.sustain_point else {
+            return self.evaluate(t);
+        };
+        let (start, end) = self.loop_points;
+        let loop_start = self.time_at(start);
+        let loop_length = self.time_at(end) - loop_start;
+        if self.is_sustain_looped() && loop_length > 0.0 && t >= loop_start {
+            return self.play((t - loop_start) % loop_length, start, end);
+        }
+        self.play(t, 0, sustain)
+    }
+
+    // Whole envelope of note released at sustain point
     pub fn evaluate(&self, t: f32) -> f32 {
-        let attack_time = self.time_range_of(State::Attack).1;
-        if t < attack_time {
-            self.attack
-                .curve
-                .read()
-                .unwrap()
-                .evaluate(t / attack_time)
-                .y
-        } else {
-            let decay_time = self.time_range_of(State::Decay).1;
-            if t < decay_time {
-                self.decay
-                    .curve
-                    .read()
-                    .unwrap()
-                    .evaluate((t - attack_time) / self.decay.curve.read().unwrap().difference().x)
-                    .y
-            } else {
-                let sustain_time = self.time_range_of(State::Sustain).1;
-                if t < sustain_time {
-                    self.sustain
-                        .curve
-                        .read()
-                        .unwrap()
-                        .evaluate(
-                            (t - decay_time) / self.sustain.curve.read().unwrap().difference().x,
-                        )
-                        .y
-                } else {
-                    let release_time = self.time_range_of(State::Release).1;
-                    if t < release_time {
-                        self.release
-                            .curve
-                            .read()
-                            .unwrap()
-                            .evaluate(
-                                (t - sustain_time)
-                                    / self.release.curve.read().unwrap().difference().x,
-                            )
-                            .y
-                    } else {
-                        0.0
-                    }
-                }
+        match t < self.length() {
+            true => self.play(t, 0, self.segments.len()),
+            false => 0.0,
+        }
+    }
+
+    // Segments between breakpoints, t is time since the first one
+    fn play(&self, t: f32, from: usize, to: usize) -> f32 {
+        let mut t = t.max(0.0);
+        for segment in &self.segments[from..to] {
+            let curve = segment.curve.curve.read().unwrap();
+            let length = curve.difference().x;
+            if t < length {
+                return curve.evaluate(t / length).y;
             }
+            t -= length;
         }
+        self.level_at_point(to)
     }
 
-    pub fn peak_at(&self, state: State) -> f32 {
-        match state {
-            State::None => 0.0,
-            State::Attack => self.attack.curve.read().unwrap().evaluate(1.0).y,
-            State::Decay => self.decay.curve.read().unwrap().evaluate(1.0).y,
-            State::Sustain => self.sustain.curve.read().unwrap().evaluate(1.0).y,
-            State::Release => self.release.curve.read().unwrap().evaluate(1.0).y,
+    // Segments after sustain point scaled to start at given level, t is time since release
+    pub fn release_from(&self, level: f32, t: f32) -> f32 {
+        let length = self.release_length();
+        if t >= length {
+            return 0.0;
+        }
+        let point = self.loop_limit();
+        let start = self.level_at_point(point);
+        match start > 0.0 {
+            true => self.play(t, point, self.segments.len()) / start * level,
+            false => level * (1.0 - t / length),
         }
     }
 
+    pub fn release_length(&self) -> f32 {
+        self.length() - self.time_at(self.loop_limit())
+    }
+
+    // Time since note on at which envelope of note released at given time is finished
+    pub fn end_after(&self, release: f32) -> f32 {
+        match self.is_gated() {
+            true => release + self.release_length(),
+            false => self.length(),
+        }
+    }
+
+    pub fn peak_at(&self, state: State) -> f32 {
+        self.segments
+            .iter()
+            .rposition(|s| s.kind == state)
+            .map_or(0.0, |i| self.level_at_point(i + 1))
+    }
+
     pub fn time_range_of(&self, state: State) -> (f32, f32) {
-        match state {
-            State::None => (0., 0.),
-            State::Attack => (
-                self.attack.curve.read().unwrap().start().x,
-                self.attack.curve.read().unwrap().difference().x,
-            ),
-            State::Decay => {
-                let attack = self.time_range_of(State::Attack);
-                (
-                    attack.1,
-                    attack.1 + self.decay.curve.read().unwrap().difference().x,
-                )
-            }
-            State::Sustain => {
-                let decay = self.time_range_of(State::Decay);
-                (
-                    decay.1,
-                    decay.1 + self.sustain.curve.read().unwrap().difference().x,
-                )
-            }
-            State::Release => {
-                let sustain = self.time_range_of(State::Sustain);
-                (
-                    sustain.1,
-                    sustain.1 + self.release.curve.read().unwrap().difference().x,
-                )
-            }
+        let first = self.segments.iter().position(|s| s.kind == state);
+        let last = self.segments.iter().rposition(|s| s.kind == state);
+        match (first, last) {
+            (Some(first), Some(last)) => (self.time_at(first), self.time_at(last + 1)),
+            _ => (0.0, 0.0),
+        }
+    }
+
+    // Divides segment into two halves of the same stage and shape
+    pub fn split_segment(&mut self, index: usize) -> Result<(), Error> {
+        let mut curves = self.curves();
+        let (kind, curve) = curves.get(index).ok_or("Segment out of envelope")?.clone();
+        let (first, second) = curve.split(0.5);
+        let offset = Vector2::new(second.a.x, 0.0);
+        let second = CubicBezierCurve::new(
+            second.a - offset,
+            second.b - offset,
+            second.c - offset,
+            second.d - offset,
+        );
+        curves[index] = (kind, first);
+        curves.insert(index + 1, (kind, second));
+        let shift = |p: usize| if p > index { p + 1 } else { p };
+        self.sustain_point = self.sustain_point.map(shift);
+        self.loop_points = (shift(self.loop_points.0), shift(self.loop_points.1));
+        self.segments = ADSREnvelopeBuilder::link_segments(curves);
+        Ok(())
+    }
+
+    // Removes segment, the next one starts where the previous one ends
+    pub fn remove_segment(&mut self, index: usize) -> Result<(), Error> {
+        if index >= self.segments.len() {
+            return Err("Segment out of envelope".into());
+        }
+        if self.segments.len() == 1 {
+            return Err("Envelope should have at least one segment".into());
         }
+        let mut curves = self.curves();
+        curves.remove(index);
+        if index > 0 && index < curves.len() {
+            curves[index].1.a.y = curves[index - 1].1.d.y;
+        }
+        let shift = |p: usize| if p > index { p - 1 } else { p };
+        self.sustain_point = self.sustain_point.map(shift);
+        self.loop_points = (shift(self.loop_points.0), shift(self.loop_points.1));
+        self.segments = ADSREnvelopeBuilder::link_segments(curves);
+        Ok(())
+    }
+
+    fn curves(&self) -> Vec<(State, CubicBezierCurve)> {
+        self.segments
+            .iter()
+            .map(|s| (s.kind, s.curve.curve.read().unwrap().clone()))
+            .collect()
+    }
+}
+
+// Free running modulation source with key held down
+impl Evaluate<f32> for ADSREnvelope {
+    fn evaluate(&self, t: f32) -> Result<f32, Error> {
+        Ok(self.held(t))
     }
 }
 
 pub struct ADSREnvelopeBuilder {
-    attack: Option<CubicBezierCurve>,
-    decay: Option<CubicBezierCurve>,
-    sustain: Option<CubicBezierCurve>,
-    release: Option<CubicBezierCurve>,
+    segments: Vec<(State, CubicBezierCurve)>,
+    sustain_point: Option<usize>,
+    loop_points: Option<(usize, usize)>,
+    one_shot: bool,
+    sustain_loop: bool,
 }
 
 impl ADSREnvelopeBuilder {
     pub fn new() -> Self {
         Self {
-            attack: None,
-            decay: None,
-            sustain: None,
-            release: None,
+            segments: vec![],
+            sustain_point: None,
+            loop_points: None,
+            one_shot: false,
+            sustain_loop: false,
         }
     }
 
@@ -146,95 +465,138 @@ impl ADSREnvelopeBuilder {
         sustain: CubicBezierCurve,
         release: CubicBezierCurve,
     ) -> Self {
-        Self {
-            attack: Some(attack),
-            decay: Some(decay),
-            sustain: Some(sustain),
-            release: Some(release),
-        }
+        let mut builder = Self::new();
+        builder.segments = vec![
+            (State::Attack, attack),
+            (State::Decay, decay),
+            (State::Sustain, sustain),
+            (State::Release, release),
+        ];
+        builder
     }
 
-    pub fn attack(&mut self, length: f32, amplitude: f32) -> Result<&mut Self, Error> {
-        self.attack = Some(CubicBezierCurve::new_linear(
-            Vector2 { x: 0.0, y: 0.0 },
-            Vector2 {
-                x: length,
-                y: amplitude,
-            },
-        ));
-        Ok(self)
+    fn last_level(&self) -> f32 {
+        self.segments.last().map_or(0.0, |(_, c)| c.end().y)
     }
 
-    pub fn decay(&mut self, length: f32, amplitude_percent: f32) -> Result<&mut Self, Error> {
-        let attack = self
-            .attack
-            .as_ref()
-            .expect("Attack should be initialized before Decay")
-            .end();
-        self.decay = Some(CubicBezierCurve::new_linear(
+    fn linear(&mut self, kind: State, length: f32, amplitude: f32) -> Result<&mut Self, Error> {
+        let curve = CubicBezierCurve::new_linear(
             Vector2 {
                 x: 0.0,
-                y: attack.y,
+                y: self.last_level(),
             },
             Vector2 {
                 x: length,
-                y: attack.y * amplitude_percent,
+                y: amplitude,
             },
-        ));
+        );
+        self.segment(kind, curve)
+    }
+
+    pub fn segment(&mut self, kind: State, curve: CubicBezierCurve) -> Result<&mut Self, Error> {
+        self.segments.push((kind, curve));
         Ok(self)
     }
 
+    pub fn delay(&mut self, length: f32) -> Result<&mut Self, Error> {
+        self.linear(State::Delay, length, 0.0)
+    }
+
+    pub fn attack(&mut self, length: f32, amplitude: f32) -> Result<&mut Self, Error> {
+        self.linear(State::Attack, length, amplitude)
+    }
+
+    pub fn hold(&mut self, length: f32) -> Result<&mut Self, Error> {
+        self.linear(State::Hold, length, self.last_level())
+    }
+
+    pub fn decay(&mut self, length: f32, amplitude_percent: f32) -> Result<&mut Self, Error> {
+        self.linear(State::Decay, length, self.last_level() * amplitude_percent)
+    }
+
     pub fn sustain(&mut self, length: f32, amplitude_percent: f32) -> Result<&mut Self, Error> {
-        let decay = self
-            .decay
-            .as_ref()
-            .expect("Decay should be initialized before Sustain")
-            .end();
-        self.sustain = Some(CubicBezierCurve::new_linear(
-            Vector2 { x: 0.0, y: decay.y },
-            Vector2 {
-                x: length,
-                y: decay.y * amplitude_percent,
-            },
-        ));
-        Ok(self)
+        self.linear(
+            State::Sustain,
+            length,
+            self.last_level() * amplitude_percent,
+        )
     }
 
     pub fn release(&mut self, length: f32) -> Result<&mut Self, Error> {
-        let sustain = self
-            .sustain
-            .as_ref()
-            .expect("Sustain should be initialized before Release")
-            .end();
-        self.release = Some(CubicBezierCurve::new_linear(
-            Vector2 {
-                x: 0.0,
-                y: sustain.y,
-            },
-            Vector2 { x: length, y: 0.0 },
-        ));
+        self.linear(State::Release, length, 0.0)
+    }
+
+    // By default envelope is sustained before the first release segment
+    pub fn sustain_point(&mut self, point: usize) -> Result<&mut Self, Error> {
+        self.sustain_point = Some(point);
+        Ok(self)
+    }
+
+    pub fn one_shot(&mut self) -> Result<&mut Self, Error> {
+        self.one_shot = true;
+        Ok(self)
+    }
+
+    // By default loop covers sustain segments
+    pub fn loop_points(&mut self, start: usize, end: usize) -> Result<&mut Self, Error> {
+        self.loop_points = Some((start, end));
+        Ok(self)
+    }
+
+    pub fn sustain_loop(&mut self, sustain_loop: bool) -> Result<&mut Self, Error> {
+        self.sustain_loop = sustain_loop;
         Ok(self)
     }
 
     pub fn build(&mut self) -> Result<ADSREnvelope, Error> {
-        let attack = make_shared(self.attack.take().ok_or("Attack not specified")?);
-        let decay = make_shared(self.decay.take().ok_or("Decay not specified")?);
-        let sustain = make_shared(self.sustain.take().ok_or("Sustain not specified")?);
-        let release = make_shared(self.release.take().ok_or("Release not specified")?);
-        let attack = Self::create_shared_curve(attack, Some(decay.clone()));
-        let decay = Self::create_shared_curve(decay, Some(sustain.clone()));
-        let sustain = Self::create_shared_curve(sustain, Some(release.clone()));
-        let release = Self::create_shared_curve(release, None);
-
-        let adsr = ADSREnvelope {
-            attack,
-            decay,
-            sustain,
-            release,
+        if self.segments.is_empty() {
+            return Err("Envelope has no segments".into());
+        }
+        let segments = take(&mut self.segments);
+        let kinds = segments.iter().map(|(k, _)| *k).collect::<Vec<_>>();
+        let sustain_point = match take(&mut self.one_shot) {
+            true => None,
+            false => self.sustain_point.take().or_else(|| {
+                let release = kinds.iter().position(|k| *k == State::Release);
+                Some(release.unwrap_or(kinds.len()))
+            }),
+        };
+        let loop_points = self.loop_points.take().unwrap_or_else(|| {
+            let first = kinds.iter().position(|k| *k == State::Sustain);
+            let last = kinds.iter().rposition(|k| *k == State::Sustain);
+            match (first, last) {
+                (Some(first), Some(last)) => (first, last + 1),
+                _ => (0, 0),
+            }
+        });
+        let mut adsr = ADSREnvelope {
+            segments: Self::link_segments(segments),
+            sustain_point: None,
+            loop_points: (0, 0),
+            sustain_loop: make_shared(ValueParameter::new(self.sustain_loop, (false, true))),
         };
+        adsr.set_sustain_point(sustain_point)?;
+        adsr.set_loop_points(loop_points.0, loop_points.1)?;
         Ok(adsr)
     }
 
+    // Amplitude of each segment moves the start of the next one
+    fn link_segments(segments: Vec<(State, CubicBezierCurve)>) -> Vec<Segment> {
+        let curves = segments
+            .iter()
+            .map(|(_, c)| make_shared(c.clone()))
+            .collect::<Vec<_>>();
+        segments
+            .iter()
+            .zip(curves.iter())
+            .enumerate()
+            .map(|(i, ((kind, _), curve))| Segment {
+                kind: *kind,
+                curve: Self::create_shared_curve(curve.clone(), curves.get(i + 1).cloned()),
+            })
+            .collect()
+    }
+
     fn create_shared_curve(
         curve: Shared<CubicBezierCurve>,
         next_curve: Option<Shared<CubicBezierCurve>>,
@@ -330,8 +692,54 @@ impl Default for ADSREnvelope {
 
 #[cfg(test)]
 mod tests {
-    use crate::utils::adsr_envelope::{ADSREnvelopeBuilder, State};
+    use crate::{
+        core::parameter::ValueParameter,
+        utils::{
+            adsr_envelope::{ADSREnvelope, ADSREnvelopeBuilder, EnvelopeShape, State},
+            cubic_bezier::CubicBezierCurve,
+            evaluate::{Evaluate, Modulation},
+            make_shared, Shared,
+        },
+    };
     use assert_approx_eq::assert_approx_eq;
+    use cgmath::Vector2;
+
+    fn linear(from: f32, to: f32) -> CubicBezierCurve {
+        CubicBezierCurve::new_linear(Vector2::new(0.0, from), Vector2::new(1.0, to))
+    }
+
+    #[test]
+    fn test_envelope_shape() {
+        let mut adsr = ADSREnvelopeBuilder::new()
+            .attack(0.5, 0.8)
+            .unwrap()
+            .sustain(0.2, 1.0)
+            .unwrap()
+            .release(1.0)
+            .unwrap()
+            .sustain_loop(true)
+            .unwrap()
+            .build()
+            .unwrap();
+        let text = adsr.shape().to_text();
+        let shape = EnvelopeShape::parse(&text).unwrap();
+        assert_eq!(shape.to_text(), text);
+        assert_eq!(shape.sustain_point, Some(2));
+        assert_eq!(shape.loop_points, (1, 2));
+        assert!(shape.sustain_loop);
+        assert!(EnvelopeShape::parse("sustain_point:none").is_err());
+        assert!(EnvelopeShape::parse("attack:0,0,1").is_err());
+
+        let curve = adsr.segments()[0].curve.curve.clone();
+        let mut other = ADSREnvelope::default();
+        other.set_shape(&shape).unwrap();
+        assert_eq!(other.shape().to_text(), text);
+        adsr.set_shape(&ADSREnvelope::default().shape()).unwrap();
+        assert_eq!(adsr.segments().len(), 4);
+        // Curve of replaced segment is not shared by envelope any more
+        assert_approx_eq!(curve.read().unwrap().d.y, 0.8);
+        assert_approx_eq!(adsr.segments()[0].curve.curve.read().unwrap().d.y, 1.0);
+    }
 
     #[test]
     fn test_builder() {
@@ -370,4 +778,172 @@ mod tests {
         assert_approx_eq!(adsr.evaluate(1.5), 0.2);
         assert_approx_eq!(adsr.evaluate(2.0), 0.0);
     }
+
+    #[test]
+    fn test_release_from() {
+        let adsr = ADSREnvelopeBuilder::new()
+            .attack(0.5, 0.8)
+            .unwrap()
+            .decay(0.5, 0.5)
+            .unwrap()
+            .sustain(0.0, 1.0)
+            .unwrap()
+            .release(1.0)
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_approx_eq!(adsr.release_length(), 1.0);
+        assert_approx_eq!(adsr.release_from(0.4, 0.0), 0.4);
+        assert_approx_eq!(adsr.release_from(0.8, 0.5), 0.4);
+        assert_approx_eq!(adsr.release_from(0.8, 1.0), 0.0);
+    }
+
+    #[test]
+    fn test_gated_sustain() {
+        let mut builder = ADSREnvelopeBuilder::new();
+        builder
+            .attack(0.5, 0.8)
+            .unwrap()
+            .decay(0.5, 0.5)
+            .unwrap()
+            .sustain(1.0, 0.5)
+            .unwrap()
+            .release(1.0)
+            .unwrap();
+        let adsr = builder.build().unwrap();
+        // Sustain curve is played once, then its end level is held while key is down
+        assert_approx_eq!(adsr.held(0.25), 0.4);
+        assert_approx_eq!(adsr.held(1.5), 0.3);
+        assert_approx_eq!(adsr.held(2.0), 0.2);
+        assert_approx_eq!(adsr.held(100.0), 0.2);
+        assert_approx_eq!(adsr.level_at(100.0, None), 0.2);
+        assert_approx_eq!(adsr.level_at(100.5, Some(100.0)), 0.1);
+        assert_approx_eq!(adsr.level_at(101.0, Some(100.0)), 0.0);
+        // Released during decay
+        assert_approx_eq!(adsr.level_at(0.75, Some(0.75)), 0.6);
+        assert_approx_eq!(adsr.level_at(1.25, Some(0.75)), 0.3);
+
+        // Looped sustain repeats its curve while key is down
+        adsr.sustain_loop.write().unwrap().set_value(true);
+        assert!(adsr.is_sustain_looped());
+        assert_approx_eq!(adsr.held(1.5), 0.3);
+        assert_approx_eq!(adsr.held(10.5), 0.3);
+        assert_approx_eq!(adsr.held(11.0), 0.4);
+        assert_approx_eq!(adsr.level_at(11.0, Some(10.5)), 0.15);
+        // Single pass of sustain is shown regardless of looping
+        assert_approx_eq!(adsr.evaluate(2.5), 0.1);
+
+        let adsr = builder
+            .attack(0.5, 0.8)
+            .unwrap()
+            .decay(0.5, 0.5)
+            .unwrap()
+            .sustain(0.0, 1.0)
+            .unwrap()
+            .release(1.0)
+            .unwrap()
+            .sustain_loop(true)
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_approx_eq!(adsr.held(5.0), 0.4);
+    }
+
+    #[test]
+    fn test_dahdsr() {
+        let adsr = ADSREnvelopeBuilder::new()
+            .delay(0.5)
+            .unwrap()
+            .attack(0.5, 1.0)
+            .unwrap()
+            .hold(0.5)
+            .unwrap()
+            .decay(0.5, 0.5)
+            .unwrap()
+            .release(1.0)
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(adsr.sustain_point(), Some(4));
+        assert_eq!(adsr.time_range_of(State::Hold), (1.0, 1.5));
+        assert_approx_eq!(adsr.held(0.25), 0.0);
+        assert_approx_eq!(adsr.held(0.75), 0.5);
+        assert_approx_eq!(adsr.held(1.25), 1.0);
+        assert_approx_eq!(adsr.held(1.75), 0.75);
+        assert_approx_eq!(adsr.held(10.0), 0.5);
+        assert_approx_eq!(adsr.level_at(10.5, Some(10.0)), 0.25);
+        assert_approx_eq!(adsr.end_after(10.0), 11.0);
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let mut adsr = ADSREnvelopeBuilder::new()
+            .segment(State::Attack, linear(0.0, 1.0))
+            .unwrap()
+            .segment(State::Decay, linear(1.0, 0.5))
+            .unwrap()
+            .segment(State::Decay, linear(0.5, 1.0))
+            .unwrap()
+            .release(1.0)
+            .unwrap()
+            .loop_points(1, 3)
+            .unwrap()
+            .sustain_loop(true)
+            .unwrap()
+            .build()
+            .unwrap();
+        // Down and up segments repeat while key is down
+        assert_approx_eq!(adsr.held(1.5), 0.75);
+        assert_approx_eq!(adsr.held(2.5), 0.75);
+        assert_approx_eq!(adsr.held(3.5), 0.75);
+        assert_approx_eq!(adsr.held(4.0), 0.5);
+        assert_approx_eq!(adsr.held(4.25), 0.625);
+        assert_approx_eq!(adsr.level_at(4.75, Some(4.25)), 0.3125);
+        assert!(adsr.set_loop_points(1, 4).is_err());
+        assert!(adsr.set_sustain_point(Some(5)).is_err());
+
+        // Split segment keeps shape of envelope, points move with segments
+        adsr.split_segment(1).unwrap();
+        assert_eq!(adsr.segments().len(), 5);
+        assert_eq!(adsr.sustain_point(), Some(4));
+        assert_eq!(adsr.loop_points(), (1, 4));
+        assert_approx_eq!(adsr.time_at(2), 1.5);
+        assert_approx_eq!(adsr.evaluate(1.25), 0.875);
+        assert_approx_eq!(adsr.evaluate(1.75), 0.625);
+
+        adsr.remove_segment(3).unwrap();
+        assert_eq!(adsr.sustain_point(), Some(3));
+        assert_eq!(adsr.loop_points(), (1, 3));
+        assert_approx_eq!(adsr.length(), 3.0);
+        assert_approx_eq!(adsr.level_at_point(3), 0.5);
+        (0..3).for_each(|_| adsr.remove_segment(0).unwrap());
+        assert!(adsr.remove_segment(0).is_err());
+        assert_eq!(adsr.sustain_point(), Some(0));
+    }
+
+    #[test]
+    fn test_envelope_modulation() {
+        let adsr = ADSREnvelopeBuilder::new()
+            .attack(1.0, 1.0)
+            .unwrap()
+            .release(1.0)
+            .unwrap()
+            .one_shot()
+            .unwrap()
+            .build()
+            .unwrap();
+        // One-shot envelope ignores release
+        assert!(!adsr.is_gated());
+        assert_approx_eq!(adsr.level_at(0.5, Some(0.2)), 0.5);
+        assert_approx_eq!(adsr.level_at(1.5, Some(0.2)), 0.5);
+        assert_approx_eq!(adsr.end_after(0.2), 2.0);
+
+        let source: Shared<dyn Evaluate<f32>> = make_shared(adsr);
+        let mut parameter = ValueParameter::new(0.0, (0.0, 1.0));
+        parameter.set_evaluate_range((0.0, 1.0));
+        parameter.container_mut().modulators.push(source);
+        assert_approx_eq!(parameter.container_mut().next_value(0.5).unwrap(), 0.0);
+        assert_approx_eq!(parameter.container_mut().next_value(0.5).unwrap(), 0.5);
+        assert_approx_eq!(parameter.container_mut().next_value(0.5).unwrap(), 1.0);
+    }
 }
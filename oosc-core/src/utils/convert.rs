@@ -7,10 +7,15 @@ pub fn cents_to_freq(cents: i32) -> f32 {
     2.0_f32.powf(cents as f32 / 1200.0)
 }
 
+// Linear, the square response notes used to have is the default velocity curve now
 #[inline]
 pub fn velocity_to_float(velocity: u32) -> f32 {
-    let velocity_f32 = velocity as f32;
-    velocity_f32 * velocity_f32 / (127.0 * 127.0)
+    velocity as f32 / 127.0
+}
+
+#[inline]
+pub fn controller_to_float(value: u32) -> f32 {
+    value as f32 / 127.0
 }
 
 #[inline]
@@ -18,6 +23,12 @@ pub fn note_to_freq(note: u32) -> f32 {
     8.175_799_f32 * 1.059_463_1_f32.powi(note as i32)
 }
 
+// Fractional note number to frequency
+#[inline]
+pub fn pitch_to_freq(pitch: f32) -> f32 {
+    8.175_799_f32 * 2.0_f32.powf(pitch / 12.0)
+}
+
 #[inline]
 pub fn linear_to_power(value: f32) -> f32 {
     10. * value.log10()
@@ -55,6 +66,11 @@ pub fn cents_to_freq_coefficient(cents: f32) -> f32 {
     2.0_f32.powf(cents / 1200.0)
 }
 
+#[inline]
+pub fn semitones_to_freq_coefficient(semitones: f32) -> f32 {
+    2.0_f32.powf(semitones / 12.0)
+}
+
 #[inline]
 pub fn octave_offset_to_notes(octave: i32) -> i32 {
     octave * 12
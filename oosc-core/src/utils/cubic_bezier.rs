@@ -2,6 +2,9 @@ use cgmath::Vector2;
 
 type Point = Vector2<f32>;
 
+const BISECTION_ITERATIONS: usize = 24;
+
+#[derive(Clone)]
 pub struct CubicBezierCurve {
     pub a: Point,
     pub b: Point,
@@ -50,6 +53,34 @@ impl CubicBezierCurve {
         self.c = points.1;
     }
 
+    // Curve is parametrized by t, so t of given x is found by bisection,
+    // x of points should be increasing
+    pub fn y_at(&self, x: f32) -> f32 {
+        let (mut low, mut high) = (0.0, 1.0);
+        (0..BISECTION_ITERATIONS).for_each(|_| {
+            let t = (low + high) * 0.5;
+            match self.evaluate(t).x < x {
+                true => low = t,
+                false => high = t,
+            }
+        });
+        self.evaluate((low + high) * 0.5).y
+    }
+
+    // Two halves of curve divided at t, both keep the shape of the original
+    pub fn split(&self, t: f32) -> (Self, Self) {
+        let ab = self.a + (self.b - self.a) * t;
+        let bc = self.b + (self.c - self.b) * t;
+        let cd = self.c + (self.d - self.c) * t;
+        let abc = ab + (bc - ab) * t;
+        let bcd = bc + (cd - bc) * t;
+        let point = abc + (bcd - abc) * t;
+        (
+            Self::new(self.a, ab, abc, point),
+            Self::new(point, bcd, cd, self.d),
+        )
+    }
+
     fn get_linear_points(a: &Point, d: &Point) -> (Point, Point) {
         let part = (d - a) / 3.0;
         (a + part, d - part)
@@ -82,4 +113,19 @@ mod tests {
         assert_eq!(eval.x, 0.75);
         assert_eq!(eval.y, 0.6);
     }
+
+    #[test]
+    fn test_split() {
+        let bez = CubicBezierCurve::new(
+            Vector2::from([0.0, 0.0]),
+            Vector2::from([0.0, 1.0]),
+            Vector2::from([0.5, 1.0]),
+            Vector2::from([1.0, 0.0]),
+        );
+        let (first, second) = bez.split(0.5);
+        assert_eq!(first.end(), bez.evaluate(0.5));
+        assert_eq!(second.start(), bez.evaluate(0.5));
+        assert_eq!(first.evaluate(0.5), bez.evaluate(0.25));
+        assert_eq!(second.evaluate(0.5), bez.evaluate(0.75));
+    }
 }
@@ -0,0 +1,86 @@
+use crate::error::Error;
+
+use super::consts::PI_2M;
+
+// In-place radix-2 FFT, inverse transform is not normalized
+pub fn fft(re: &mut [f32], im: &mut [f32], inverse: bool) -> Result<(), Error> {
+    let n = re.len();
+    if n != im.len() || !n.is_power_of_two() {
+        return Err("FFT size must be a power of two".into());
+    }
+    let bits = n.trailing_zeros();
+    (0..n).for_each(|i| {
+        let j = i.reverse_bits() >> (usize::BITS - bits);
+        if j > i {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    });
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut size = 2;
+    while size <= n {
+        let half = size / 2;
+        let angle = sign * PI_2M / size as f32;
+        (0..n).step_by(size).for_each(|start| {
+            (0..half).for_each(|k| {
+                let (w_im, w_re) = (angle * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + half);
+                let t_re = re[b] * w_re - im[b] * w_im;
+                let t_im = re[b] * w_im + im[b] * w_re;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            });
+        });
+        size *= 2;
+    }
+    Ok(())
+}
+
+// Single cycle of sum of sines, harmonics are (number, amplitude, phase in cycles)
+pub fn harmonics_to_table(harmonics: &[(usize, f32, f32)], size: usize) -> Result<Vec<f32>, Error> {
+    let mut re = vec![0.0; size];
+    let mut im = vec![0.0; size];
+    harmonics
+        .iter()
+        .filter(|(k, _, _)| *k > 0 && *k < size / 2)
+        .for_each(|(k, amplitude, phase)| {
+            let (sin, cos) = (PI_2M * phase).sin_cos();
+            re[*k] += amplitude * cos;
+            im[*k] += amplitude * sin;
+        });
+    fft(&mut re, &mut im, true)?;
+    // Imaginary part of the positive bins is the sum of sines
+    Ok(im)
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_approx_eq::assert_approx_eq;
+
+    use crate::utils::consts::PI_2M;
+
+    use super::{fft, harmonics_to_table};
+
+    #[test]
+    fn test_fft() {
+        let size = 64;
+        let mut re = (0..size)
+            .map(|i| (PI_2M * 3.0 * i as f32 / size as f32).cos())
+            .collect::<Vec<_>>();
+        let mut im = vec![0.0; size];
+        fft(&mut re, &mut im, false).unwrap();
+        assert_approx_eq!(re[3], size as f32 / 2.0, 1e-3);
+        assert_approx_eq!(re[size - 3], size as f32 / 2.0, 1e-3);
+        assert_approx_eq!(re[5], 0.0, 1e-3);
+        assert!(fft(&mut [0.0; 3], &mut [0.0; 3], false).is_err());
+
+        let table = harmonics_to_table(&[(1, 1.0, 0.0), (4, 0.5, 0.25)], size).unwrap();
+        (0..size).for_each(|i| {
+            let t = i as f32 / size as f32;
+            let expected = (PI_2M * t).sin() + 0.5 * (PI_2M * (4.0 * t + 0.25)).sin();
+            assert_approx_eq!(table[i], expected, 1e-4);
+        });
+    }
+}
@@ -10,7 +10,7 @@ pub enum InterpolateMethod {
     Exponential(f32),
 }
 
-pub fn interpolate_lagrange(fx: &Vec<InPoint>, xm: f32) -> f32 {
+pub fn interpolate_lagrange(fx: &[InPoint], xm: f32) -> f32 {
     let n = fx.len();
     let mut result = 0.0;
 
@@ -79,7 +79,7 @@ pub fn interpolate_sample(
         }
         InterpolateMethod::LaGrange => {
             let left_index = (index.floor() - 1.) as i32;
-            let vec = get_samples_points_ranged(slice, slice.len(), left_index, 4).collect();
+            let vec: Vec<_> = get_samples_points_ranged(slice, slice.len(), left_index, 4).collect();
             interpolate_lagrange(&vec, index)
         }
         InterpolateMethod::Exponential(c) => {
@@ -5,8 +5,10 @@ pub mod consts;
 pub mod convert;
 pub mod cubic_bezier;
 pub mod evaluate;
+pub mod fft;
 pub mod interpolation;
 pub mod math;
+pub mod random;
 pub mod sample_buffer;
 
 pub type Shared<T> = Arc<RwLock<T>>;
@@ -1,10 +1,16 @@
-use crate::ui::components::{root::Root, Component};
+use crate::ui::components::{root::Root, Component, Focus};
 use anyhow::{Context, Result};
-use std::{thread, time::Duration};
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
 
-use super::{config::Config, context};
+use super::{
+    config::{Config, MIDI_MAPPINGS_FILE, MIDI_REFRESH_INTERVAL},
+    context,
+};
 use cpal::{traits::DeviceTrait, Device};
-use crossterm::event::{self, Event, KeyCode};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
 
 pub struct Application {
     pub ctx: context::Context,
@@ -38,7 +44,14 @@ impl Application {
     }
 
     pub fn detach_stream(&mut self) -> Result<cpal::Stream> {
-        let err_fn = |err| println!("An error occurred on stream: {}", err);
+        let status = self.ctx.status.clone();
+        let err_fn = move |err| {
+            status
+                .write()
+                .unwrap()
+                .report(format!("An error occurred on stream: {}", err))
+        };
+        let status = self.ctx.status.clone();
         let callbacks = self.ctx.callbacks.get_callbacks();
         let mut total_playback_seconds = 0.;
         let sample_rate = self.config.sample_rate as f32;
@@ -46,13 +59,15 @@ impl Application {
         Ok(self.device.build_output_stream(
             &self.config.into(),
             move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
-                callbacks
-                    .iter()
-                    .try_for_each(|callback| -> Result<()> {
-                        let mut callback = callback.lock().unwrap();
-                        Ok(callback.process_stream(data, total_playback_seconds, sample_rate)?)
-                    })
-                    .unwrap();
+                let result = callbacks.iter().try_for_each(|callback| -> Result<()> {
+                    let mut callback = callback.lock().unwrap();
+                    Ok(callback.process_stream(data, total_playback_seconds, sample_rate)?)
+                });
+                // Failed buffer is silenced, stream keeps running
+                if let Err(e) = result {
+                    data.fill(0.0);
+                    status.write().unwrap().report(e);
+                }
                 total_playback_seconds += data.len() as f32 / channels_rate;
             },
             err_fn,
@@ -64,8 +79,14 @@ impl Application {
         self.ctx.terminal.write().unwrap().clear()?;
         let area = self.ctx.terminal.write().unwrap().current_buffer_mut().area;
         self.root.resize(area)?;
+        let mut last_refresh = Instant::now();
         loop {
             thread::sleep(Duration::from_millis(16));
+            if last_refresh.elapsed() >= MIDI_REFRESH_INTERVAL {
+                self.ctx.midi_inputs.lock().unwrap().refresh();
+                last_refresh = Instant::now();
+            }
+            self.root.keyboard.write().unwrap().release_expired();
             if !self.read_events()? {
                 break;
             }
@@ -73,17 +94,31 @@ impl Application {
                 let _ = self.root.draw(f, f.size());
             })?;
         }
-        context::restore_terminal()
+        // Terminal is restored first so save error is printed on usable terminal
+        context::restore_terminal()?;
+        self.ctx
+            .midi_learn
+            .read()
+            .unwrap()
+            .save(MIDI_MAPPINGS_FILE)
+            .context("saving MIDI mappings failed")
     }
 
+    // Synthesizer is not locked here, keyboard component plays notes on it
     fn read_events(&mut self) -> Result<bool> {
-        let _syn = self.ctx.synthesizer.lock().unwrap();
         if event::poll(Duration::from_millis(0)).context("event poll failed")? {
             let event = event::read().context("event read failed")?;
+            let playing = self.root.keyboard.read().unwrap().is_focused();
+            // Only keyboard needs release events, other components handle presses
+            if let Event::Key(key) = event {
+                if key.kind == KeyEventKind::Release && !playing {
+                    return Ok(true);
+                }
+            }
             let event_copy = event.clone();
             self.root.handle_events(Some(event_copy))?;
             if let Event::Key(key) = event {
-                return Ok(!matches!(key.code, KeyCode::Char('q')));
+                return Ok(playing || !matches!(key.code, KeyCode::Char('q')));
             }
         }
         Ok(true)
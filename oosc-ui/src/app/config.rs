@@ -1,5 +1,18 @@
+use std::time::Duration;
+
 use cpal::StreamConfig;
 
+pub const MIDI_MAPPINGS_FILE: &str = "midi_mappings.txt";
+pub const PARTS_FILE: &str = "parts.txt";
+pub const MODULATIONS_FILE: &str = "modulations.txt";
+pub const MPE_ZONES_FILE: &str = "mpe.txt";
+pub const PRESETS_DIR: &str = "presets";
+pub const SAMPLES_DIR: &str = "samples";
+pub const TUNING_SCALE_FILE: &str = "tuning.scl";
+pub const TUNING_MAPPING_FILE: &str = "tuning.kbm";
+pub const OSC_TUNINGS_DIR: &str = "tunings";
+pub const MIDI_REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
 #[derive(Copy, Clone)]
 pub struct Config {
     pub channels: u32,
@@ -1,13 +1,12 @@
 use anyhow::Result;
 use crossterm::{
-    event::{KeyboardEnhancementFlags, PushKeyboardEnhancementFlags},
+    event::{KeyboardEnhancementFlags, PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags},
     execute,
     terminal::{self, enable_raw_mode, EnterAlternateScreen},
 };
-use midir::{Ignore, MidiInput, MidiInputConnection};
-use midly::live::LiveEvent;
 use std::{
     io::Stdout,
+    path::Path,
     sync::{Arc, Mutex, RwLock},
 };
 
@@ -18,22 +17,40 @@ use cpal::{
 
 use oosc_core::{
     callbacks::{
-        stream_callback::{MidiStreamCallback, SynthesizerStreamCallback},
+        stream_callback::{
+            ArpeggiatorStreamCallback, MidiStreamCallback, SequencerStreamCallback,
+            SynthesizerStreamCallback, TransportStreamCallback,
+        },
         stream_renderer::{RenderStreamCallback, StreamWavRenderer},
         StreamCallback,
     },
     core::{
-        oscillator::{OscillatorBuilder, WavetableOscillator},
-        synthesizer::{Synthesizer, SynthesizerBuilder},
+        additive::AdditiveOscillatorBuilder,
+        lfo::{LfoPolarity, LFO},
+        modulation_config::ModulationConfig,
+        noise::NoiseOscillatorBuilder,
+        oscillator::{ExpressionSource, ExpressionTarget, OscillatorBuilder, WavetableOscillator},
+        part::{Part, PartAssignment, PartBuilder, PartSource},
+        preset::PresetBank,
+        sampler::{load_zones, SamplerOscillatorBuilder},
+        sub_oscillator::SubOscillatorBuilder,
+        synthesizer::{LockedOscillator, Synthesizer, SynthesizerBuilder},
+        transport::Transport,
+        tuning::{KeyboardMapping, Scale, Tuning},
         waveshape::WaveShape,
         wavetable::WaveTableBuilder,
     },
     effects::{amplifier::Amplifier, chorus::Chorus, compressor::Compressor, delay::Delay},
     error::Error,
     midi::{
-        mediator::{MidiEventReceiver, MidiSynthesizerMediator},
+        arpeggiator::Arpeggiator,
+        learn::MidiLearn,
+        mediator::MidiSynthesizerMediator,
+        mpe::MpeZone,
+        output::{MidiClockOutput, MidiThru},
         playback::{MidiPlayback, SmfPlayback},
-        smf_extensions::OwnedTrackEvent,
+        recorder::MidiRecorder,
+        sequencer::StepSequencer,
     },
     utils::{
         adsr_envelope::ADSREnvelope,
@@ -45,33 +62,59 @@ use oosc_core::{
 };
 use ratatui::{prelude::CrosstermBackend, Terminal};
 
-use super::config::Config;
+use super::{
+    config::{
+        Config, MIDI_MAPPINGS_FILE, MODULATIONS_FILE, MPE_ZONES_FILE, OSC_TUNINGS_DIR, PARTS_FILE,
+        PRESETS_DIR, SAMPLES_DIR, TUNING_MAPPING_FILE, TUNING_SCALE_FILE,
+    },
+    midi_input::MidiInputs,
+    midi_output::MidiOutputPort,
+    status::Status,
+};
 
 type AppTerminal = Shared<Terminal<CrosstermBackend<Stdout>>>;
 
+const PART_LFOS: usize = 2;
+
 pub struct CallbacksData {
     pub output: SharedMutex<SynthesizerStreamCallback>,
+    pub transport: SharedMutex<TransportStreamCallback>,
     pub smf: SharedMutex<MidiStreamCallback>,
     pub render: SharedMutex<RenderStreamCallback>,
+    pub arpeggiator: SharedMutex<ArpeggiatorStreamCallback>,
+    pub sequencer: SharedMutex<SequencerStreamCallback>,
 }
 
 impl CallbacksData {
     pub fn get_callbacks(&self) -> Vec<Arc<Mutex<dyn StreamCallback>>> {
-        vec![self.output.clone(), self.smf.clone(), self.render.clone()]
+        vec![
+            self.output.clone(),
+            self.transport.clone(),
+            self.sequencer.clone(),
+            self.smf.clone(),
+            self.arpeggiator.clone(),
+            self.render.clone(),
+        ]
     }
 }
 
-pub struct MidiContext {
-    pub input: Shared<MidiInputConnection<()>>,
-}
-
 pub struct Context {
     pub synthesizer: SharedMutex<Synthesizer>,
     pub callbacks: CallbacksData,
     pub midi_control: SharedMutex<dyn MidiPlayback>,
+    pub transport: Shared<Transport>,
     pub render_control: SharedMutex<StreamWavRenderer>,
+    pub midi_recorder: SharedMutex<MidiRecorder>,
+    pub midi_learn: Shared<MidiLearn>,
     pub terminal: AppTerminal,
-    pub midi: Option<MidiContext>,
+    pub midi_inputs: SharedMutex<MidiInputs>,
+    pub midi_output: SharedMutex<MidiOutputPort>,
+    pub midi_thru: SharedMutex<MidiThru>,
+    pub midi_clock: SharedMutex<MidiClockOutput>,
+    pub arpeggiator: SharedMutex<Arpeggiator>,
+    pub sequencer: SharedMutex<StepSequencer>,
+    pub status: Shared<Status>,
+    pub presets: Shared<PresetBank>,
 }
 
 impl Context {
@@ -81,53 +124,98 @@ impl Context {
             channels: config.channels as usize,
             sample_rate: config.sample_rate as f32,
         };
-        let osc1 = Self::build_osc(config, WaveShape::Sin)?;
-        let osc2 = Self::build_osc(config, WaveShape::Triangle)?;
-        let chorus = make_shared(Chorus::default(&settings));
-        let compressor = make_shared(Compressor::default(&settings));
-        let delay = make_shared(Delay::default(&settings));
-        let amplifier = make_shared(Amplifier::default());
-        let synthesizer = Arc::new(Mutex::new(
-            SynthesizerBuilder::new()
-                .set_buffer(config.buffer_size)?
-                .add_osc(osc1)
-                .add_osc(osc2)
-                .add_effect(amplifier)
-                .add_effect(chorus)
-                // .add_effect(delay)
-                // .add_effect(compressor)
-                .set_sample_rate(config.sample_rate)
-                .build()?,
-        ));
+        // LFOs synced to tempo follow the same transport as sequencer and arpeggiator
+        let transport = make_shared(Transport::default());
+        let mut builder = SynthesizerBuilder::new();
+        builder.set_buffer(config.buffer_size)?;
+        // Line of parts file adds part with sources it lists, without it the only part
+        // listens to all channels and plays samples directory if it has any
+        let mut assignments = PartAssignment::load(PARTS_FILE).unwrap_or_default();
+        if assignments.is_empty() {
+            assignments.push(PartAssignment {
+                sources: vec![PartSource::Sampler],
+                ..Default::default()
+            });
+        }
+        for (index, assignment) in assignments.iter().enumerate() {
+            let sources = Self::build_sources(config, &assignment.sources)?;
+            let mut part = Self::build_part(config, &settings, &transport, assignment, sources)?;
+            Self::add_part_lfos(&mut part, index, &transport);
+            builder.add_part(part);
+        }
+        if let Ok(scale) = Scale::load(TUNING_SCALE_FILE) {
+            let mapping = KeyboardMapping::load(TUNING_MAPPING_FILE).unwrap_or_default();
+            builder.set_tuning(Tuning::from_scale(&scale, &mapping)?);
+        }
+        let mut synthesizer = builder.set_sample_rate(config.sample_rate).build()?;
+        Self::load_oscillator_tunings(&mut synthesizer)?;
+        let synthesizer = Arc::new(Mutex::new(synthesizer));
+        // Mediator owns MIDI controllers, so it connects modulations before MIDI learn
+        // collects parameters
+        let mut midi_mediator = MidiSynthesizerMediator::new(synthesizer.clone());
+        ModulationConfig::load(MODULATIONS_FILE)
+            .unwrap_or_default()
+            .iter()
+            .try_for_each(|config| midi_mediator.apply_modulation(config))?;
+        // Zones from file are active from start, MCM from controller can change them later
+        MpeZone::load(MPE_ZONES_FILE)
+            .unwrap_or_default()
+            .into_iter()
+            .for_each(|zone| {
+                midi_mediator.set_mpe_zone(zone);
+            });
 
         let synthesizer_callback =
             make_shared_mutex(SynthesizerStreamCallback(synthesizer.clone()));
 
-        let midi_mediator = make_shared_mutex(MidiSynthesizerMediator::new(synthesizer.clone()));
+        let mut midi_learn = {
+            let synthesizer = synthesizer.lock().unwrap();
+            let mut learn = MidiLearn::new(synthesizer.get_named_parameters());
+            learn.set_parameters_i32(synthesizer.get_named_parameters_i32());
+            learn
+        };
+        let _ = midi_learn.load(MIDI_MAPPINGS_FILE);
+        let midi_learn = make_shared(midi_learn);
+        let presets = make_shared(PresetBank::load_dir(PRESETS_DIR).unwrap_or_default());
+        midi_mediator
+            .set_learn(midi_learn.clone())
+            .set_transport(transport.clone())
+            .set_presets(presets.clone());
+        let midi_mediator = make_shared_mutex(midi_mediator);
+        let arpeggiator = make_shared_mutex(Arpeggiator::new(midi_mediator, transport.clone()));
+        let midi_recorder = make_shared_mutex(MidiRecorder::new(arpeggiator.clone()));
+        let midi_output = make_shared_mutex(MidiOutputPort::new());
+        let midi_thru =
+            make_shared_mutex(MidiThru::new(midi_recorder.clone(), midi_output.clone()));
+        let midi_clock = make_shared_mutex(MidiClockOutput::new(midi_output.clone()));
+        let sequencer = make_shared_mutex(StepSequencer::new(
+            midi_thru.clone(),
+            transport.clone(),
+            synthesizer.lock().unwrap().get_named_parameters(),
+        ));
         let midi_control = make_shared_mutex(SmfPlayback::default());
         let midi_control_cloned = midi_control.clone();
-        let midi_callback = make_shared_mutex(MidiStreamCallback(
-            midi_control_cloned,
-            midi_mediator.clone(),
-        ));
+        let midi_callback =
+            make_shared_mutex(MidiStreamCallback(midi_control_cloned, midi_thru.clone()));
+
+        let mut transport_callback =
+            TransportStreamCallback::new(transport.clone(), midi_control.clone());
+        transport_callback.set_clock(midi_clock.clone());
+        let transport_callback = make_shared_mutex(transport_callback);
 
         let render_control = make_shared_mutex(StreamWavRenderer::from(&settings));
         let render_callback = make_shared_mutex(RenderStreamCallback(render_control.clone()));
 
         let callbacks = CallbacksData {
             output: synthesizer_callback,
+            transport: transport_callback,
             smf: midi_callback,
             render: render_callback,
+            arpeggiator: make_shared_mutex(ArpeggiatorStreamCallback(arpeggiator.clone())),
+            sequencer: make_shared_mutex(SequencerStreamCallback(sequencer.clone())),
         };
 
-        let midi_input = Self::build_midi_input(midi_mediator);
-        let midi = match midi_input.is_ok() {
-            true => {
-                let input = make_shared(midi_input.unwrap());
-                Some(MidiContext { input })
-            }
-            false => None,
-        };
+        let midi_inputs = make_shared_mutex(MidiInputs::new(midi_thru.clone()));
 
         let terminal = build_terminal()?;
         setup_panic_hook();
@@ -136,9 +224,19 @@ impl Context {
             synthesizer,
             callbacks,
             midi_control,
+            transport,
             render_control,
+            midi_recorder,
+            midi_learn,
             terminal,
-            midi,
+            midi_inputs,
+            midi_output,
+            midi_thru,
+            midi_clock,
+            arpeggiator,
+            sequencer,
+            status: make_shared(Status::new()),
+            presets,
         })
     }
 
@@ -176,8 +274,55 @@ impl Context {
         Ok((host, device, default_config))
     }
 
+    // Oscillator N of part is retuned by oscN.scl with optional oscN.kbm
+    fn load_oscillator_tunings(synthesizer: &mut Synthesizer) -> Result<(), Error> {
+        for part in synthesizer.get_parts_mut() {
+            let count = part.oscillators().count();
+            for index in 0..count {
+                let path = Path::new(OSC_TUNINGS_DIR).join(format!("osc{}", index + 1));
+                if let Ok(scale) = Scale::load(path.with_extension("scl")) {
+                    let mapping =
+                        KeyboardMapping::load(path.with_extension("kbm")).unwrap_or_default();
+                    let tuning = Tuning::from_scale(&scale, &mapping)?;
+                    part.set_oscillator_tuning(index, Some(make_shared(tuning)))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn build_part(
+        config: &Config,
+        settings: &BufferSettings,
+        transport: &Shared<Transport>,
+        assignment: &PartAssignment,
+        sources: Vec<LockedOscillator>,
+    ) -> Result<Part, Error> {
+        let chorus = make_shared(Chorus::default(settings));
+        // Compressor and delay are not added to chain yet
+        let _compressor = make_shared(Compressor::default(settings));
+        let _delay = make_shared(Delay::default(settings));
+        let amplifier = make_shared(Amplifier::default());
+        let mut builder = PartBuilder::new();
+        builder
+            .set_buffer(config.buffer_size)?
+            .assign(assignment)
+            .add_osc(Self::build_osc(config, transport, WaveShape::Sin)?)
+            .add_osc(Self::build_osc(config, transport, WaveShape::Triangle)?);
+        sources.into_iter().for_each(|osc| {
+            builder.add_osc(osc);
+        });
+        builder
+            .add_effect(amplifier)
+            .add_effect(chorus)
+            // .add_effect(_delay)
+            // .add_effect(_compressor)
+            .build()
+    }
+
     fn build_osc(
         config: &Config,
+        transport: &Shared<Transport>,
         shape: WaveShape,
     ) -> Result<Arc<RwLock<WavetableOscillator>>, Error> {
         let adsr = ADSREnvelope::default();
@@ -189,42 +334,87 @@ impl Context {
             .from_shape(shape, config.buffer_size)
             .set_interpolation(InterpolateMethod::Linear)
             .build()?;
-        Ok(make_shared(
-            OscillatorBuilder::new()
-                .set_buffer(buffer)
-                .set_envelope(adsr)
-                .set_wavetable(table)
-                .build()?,
-        ))
+        let mut osc = OscillatorBuilder::new()
+            .set_buffer(buffer)
+            .set_envelope(adsr)
+            .set_wavetable(table)
+            .build()?;
+        // MPE pressure, has no effect for notes from non-MPE controllers
+        osc.add_expression_route(ExpressionSource::Pressure, ExpressionTarget::Gain, 6.0);
+        osc.vibrato()
+            .write()
+            .unwrap()
+            .set_transport(transport.clone());
+        Ok(make_shared(osc))
     }
 
-    fn build_midi_input(
-        mediator: SharedMutex<dyn MidiEventReceiver>,
-    ) -> Result<MidiInputConnection<()>, anyhow::Error> {
-        let mut midi_in = MidiInput::new("oosc")?;
-        midi_in.ignore(Ignore::None);
-        let binding = midi_in.ports();
-        let in_port = anyhow::Context::context(binding.get(0), "Cannot get MIDI-IN port")?;
-        let mediator = mediator.clone();
-        let connection = midi_in
-            .connect(
-                in_port,
-                "oosc-input",
-                move |_stamp, message, _| {
-                    let event =
-                        OwnedTrackEvent::try_from(&LiveEvent::parse(message).unwrap()).unwrap();
-                    let mut receiver = mediator.lock().unwrap();
-                    receiver.receive_event(&event).unwrap();
-                },
-                (),
-            )
-            .map_err(|e| {
-                anyhow::anyhow!(format!(
-                    "Cannot connect to MIDI-IN port. Reason: {}",
-                    e.to_string()
-                ))
-            })?;
-        Ok(connection)
+    // LFOs modulate oscillators by lfo lines of modulations file, seeds differ between
+    // parts so their random shapes do not move together
+    fn add_part_lfos(part: &mut Part, index: usize, transport: &Shared<Transport>) {
+        (0..PART_LFOS).for_each(|i| {
+            let mut lfo = LFO::new(WaveShape::Sin, 1.0);
+            lfo.set_polarity(LfoPolarity::Bipolar)
+                .set_transport(transport.clone())
+                .set_seed((index * PART_LFOS + i + 1) as u32);
+            part.add_lfo(make_shared(lfo));
+        });
+    }
+
+    // Sampler is added only when samples directory has WAV files
+    fn build_sources(
+        config: &Config,
+        kinds: &[PartSource],
+    ) -> Result<Vec<LockedOscillator>, Error> {
+        let buffer = || {
+            SampleBufferBuilder::new()
+                .set_channels(2)
+                .set_samples(config.buffer_size)
+                .build()
+        };
+        let mut sources: Vec<LockedOscillator> = vec![];
+        for kind in kinds {
+            match kind {
+                PartSource::Noise => {
+                    let noise = NoiseOscillatorBuilder::new()
+                        .set_buffer(buffer()?)
+                        .set_envelope(ADSREnvelope::default())
+                        .build()?;
+                    sources.push(make_shared(noise));
+                }
+                PartSource::Sub => {
+                    let sub = SubOscillatorBuilder::new()
+                        .set_buffer(buffer()?)
+                        .set_envelope(ADSREnvelope::default())
+                        .build()?;
+                    sources.push(make_shared(sub));
+                }
+                PartSource::Additive => {
+                    // Sawtooth spectrum to start editing harmonics from
+                    let amplitudes = (1..=16).map(|k| 1.0 / k as f32).collect::<Vec<_>>();
+                    let additive = AdditiveOscillatorBuilder::new()
+                        .set_buffer(buffer()?)
+                        .set_envelope(ADSREnvelope::default())
+                        .set_amplitudes(&amplitudes)
+                        .build()?;
+                    sources.push(make_shared(additive));
+                }
+                PartSource::Sampler => {
+                    let zones = load_zones(SAMPLES_DIR).unwrap_or_default();
+                    if zones.is_empty() {
+                        continue;
+                    }
+                    let mut builder = SamplerOscillatorBuilder::new();
+                    builder
+                        .set_buffer(buffer()?)
+                        .set_envelope(ADSREnvelope::default());
+                    zones.into_iter().for_each(|zone| {
+                        builder.add_zone(zone);
+                    });
+                    sources.push(make_shared(builder.build()?));
+                }
+            }
+        }
+        Ok(sources)
     }
 }
 
@@ -232,7 +422,11 @@ fn build_terminal() -> Result<AppTerminal> {
     let mut stdout = std::io::stdout();
     execute!(
         stdout,
-        PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES)
+        // Key release events are reported only by terminals supporting kitty protocol
+        PushKeyboardEnhancementFlags(
+            KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES
+                | KeyboardEnhancementFlags::REPORT_EVENT_TYPES
+        )
     )?;
     anyhow::Context::context(enable_raw_mode(), "Failed to enable raw mode")?;
     anyhow::Context::context(
@@ -246,7 +440,11 @@ fn build_terminal() -> Result<AppTerminal> {
 }
 
 pub fn restore_terminal() -> Result<(), anyhow::Error> {
-    execute!(std::io::stderr(), crossterm::terminal::LeaveAlternateScreen)?;
+    execute!(
+        std::io::stderr(),
+        PopKeyboardEnhancementFlags,
+        crossterm::terminal::LeaveAlternateScreen
+    )?;
     terminal::disable_raw_mode()?;
     Ok(())
 }
@@ -0,0 +1,173 @@
+use anyhow::{anyhow, Result};
+use midir::{Ignore, MidiInput, MidiInputConnection, MidiInputPort};
+use midly::live::LiveEvent;
+use oosc_core::{
+    midi::{mediator::MidiEventReceiver, smf_extensions::OwnedTrackEvent},
+    utils::SharedMutex,
+};
+
+pub const CLIENT_NAME: &str = "oosc";
+pub const VIRTUAL_PORT_NAME: &str = "oosc";
+
+pub struct MidiInputPortState {
+    pub name: String,
+    pub connected: bool,
+}
+
+struct Connection {
+    name: String,
+    port: MidiInputPort,
+    _connection: MidiInputConnection<()>,
+}
+
+pub struct MidiInputs {
+    receiver: SharedMutex<dyn MidiEventReceiver>,
+    scanner: Option<MidiInput>,
+    connections: Vec<Connection>,
+    // Ports disconnected by user, they are not reconnected on refresh
+    ignored: Vec<String>,
+    available: Vec<String>,
+    virtual_port: Option<MidiInputConnection<()>>,
+}
+
+impl MidiInputs {
+    pub fn new(receiver: SharedMutex<dyn MidiEventReceiver>) -> Self {
+        let mut inputs = Self {
+            receiver,
+            scanner: MidiInput::new(CLIENT_NAME).ok(),
+            connections: vec![],
+            ignored: vec![],
+            available: vec![],
+            virtual_port: None,
+        };
+        inputs.virtual_port = inputs.create_virtual().ok();
+        inputs.refresh();
+        inputs
+    }
+
+    pub fn ports(&self) -> Vec<MidiInputPortState> {
+        self.available
+            .iter()
+            .map(|name| MidiInputPortState {
+                name: name.clone(),
+                connected: self.is_connected(name),
+            })
+            .collect()
+    }
+
+    pub fn is_connected(&self, name: &str) -> bool {
+        self.connections.iter().any(|c| c.name == name)
+    }
+
+    pub fn has_virtual_port(&self) -> bool {
+        self.virtual_port.is_some()
+    }
+
+    pub fn connect(&mut self, name: &str) -> Result<()> {
+        self.ignored.retain(|n| n != name);
+        if self.is_connected(name) {
+            return Ok(());
+        }
+        let port = self
+            .find_port(name)
+            .ok_or(anyhow!("MIDI-IN port \"{}\" not found", name))?;
+        self.open(name.to_owned(), port)
+    }
+
+    pub fn disconnect(&mut self, name: &str) {
+        if !self.ignored.iter().any(|n| n == name) {
+            self.ignored.push(name.to_owned());
+        }
+        self.connections.retain(|c| c.name != name);
+    }
+
+    pub fn toggle(&mut self, name: &str) -> Result<()> {
+        if self.is_connected(name) {
+            self.disconnect(name);
+            Ok(())
+        } else {
+            self.connect(name)
+        }
+    }
+
+    // Drops connections of unplugged devices and connects new or replugged ones
+    pub fn refresh(&mut self) {
+        let ports = match &self.scanner {
+            Some(scanner) => scanner
+                .ports()
+                .into_iter()
+                .filter_map(|port| scanner.port_name(&port).ok().map(|name| (name, port)))
+                .collect::<Vec<_>>(),
+            None => vec![],
+        };
+        self.connections
+            .retain(|c| ports.iter().any(|(name, port)| *name == c.name && *port == c.port));
+        self.available = ports.iter().map(|(name, _)| name.clone()).collect();
+        for (name, port) in ports {
+            if !self.is_connected(&name) && !self.ignored.contains(&name) {
+                let _ = self.open(name, port);
+            }
+        }
+    }
+
+    fn find_port(&self, name: &str) -> Option<MidiInputPort> {
+        let scanner = self.scanner.as_ref()?;
+        scanner
+            .ports()
+            .into_iter()
+            .find(|port| scanner.port_name(port).is_ok_and(|n| n == name))
+    }
+
+    fn open(&mut self, name: String, port: MidiInputPort) -> Result<()> {
+        let midi_in = Self::build_input()?;
+        let receiver = self.receiver.clone();
+        let connection = midi_in
+            .connect(
+                &port,
+                "oosc-input",
+                move |_stamp, message, _| forward(&receiver, message),
+                (),
+            )
+            .map_err(|e| anyhow!("Cannot connect to MIDI-IN port. Reason: {}", e))?;
+        self.connections.push(Connection {
+            name,
+            port,
+            _connection: connection,
+        });
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    fn create_virtual(&self) -> Result<MidiInputConnection<()>> {
+        use midir::os::unix::VirtualInput;
+        let receiver = self.receiver.clone();
+        Self::build_input()?
+            .create_virtual(
+                VIRTUAL_PORT_NAME,
+                move |_stamp, message, _| forward(&receiver, message),
+                (),
+            )
+            .map_err(|e| anyhow!("Cannot create virtual MIDI-IN port. Reason: {}", e))
+    }
+
+    #[cfg(not(unix))]
+    fn create_virtual(&self) -> Result<MidiInputConnection<()>> {
+        Err(anyhow!("Virtual MIDI ports are not supported on this platform"))
+    }
+
+    fn build_input() -> Result<MidiInput> {
+        let mut midi_in = MidiInput::new(CLIENT_NAME)?;
+        midi_in.ignore(Ignore::None);
+        Ok(midi_in)
+    }
+}
+
+// Errors cannot be returned from midir callback, so broken messages are skipped
+fn forward(receiver: &SharedMutex<dyn MidiEventReceiver>, message: &[u8]) {
+    let event = LiveEvent::parse(message)
+        .ok()
+        .and_then(|event| OwnedTrackEvent::try_from(&event).ok());
+    if let Some(event) = event {
+        let _ = receiver.lock().unwrap().receive_event(&event);
+    }
+}
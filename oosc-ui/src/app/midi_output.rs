@@ -0,0 +1,100 @@
+use anyhow::{anyhow, Result};
+use midir::{MidiOutput, MidiOutputConnection};
+use oosc_core::{error::Error, midi::output::MidiSender};
+
+use super::midi_input::{CLIENT_NAME, VIRTUAL_PORT_NAME};
+
+// Single external MIDI-OUT port, messages are dropped while it is not connected
+pub struct MidiOutputPort {
+    scanner: Option<MidiOutput>,
+    connection: Option<(String, MidiOutputConnection)>,
+    // Reason of the last dropped connection, taken by UI
+    error: Option<String>,
+}
+
+impl MidiOutputPort {
+    pub fn new() -> Self {
+        Self {
+            scanner: MidiOutput::new(CLIENT_NAME).ok(),
+            connection: None,
+            error: None,
+        }
+    }
+
+    // Own virtual input is skipped to avoid feedback loop
+    pub fn ports(&self) -> Vec<String> {
+        match &self.scanner {
+            Some(scanner) => scanner
+                .ports()
+                .iter()
+                .filter_map(|port| scanner.port_name(port).ok())
+                .filter(|name| !name.contains(VIRTUAL_PORT_NAME))
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    pub fn connected(&self) -> Option<&str> {
+        self.connection.as_ref().map(|(name, _)| name.as_str())
+    }
+
+    pub fn connect(&mut self, name: &str) -> Result<()> {
+        let output = MidiOutput::new(CLIENT_NAME)?;
+        let port = output
+            .ports()
+            .into_iter()
+            .find(|port| output.port_name(port).is_ok_and(|n| n == name))
+            .ok_or(anyhow!("MIDI-OUT port \"{}\" not found", name))?;
+        let connection = output
+            .connect(&port, "oosc-output")
+            .map_err(|e| anyhow!("Cannot connect to MIDI-OUT port. Reason: {}", e))?;
+        self.connection = Some((name.to_owned(), connection));
+        Ok(())
+    }
+
+    pub fn disconnect(&mut self) {
+        if let Some((_, connection)) = self.connection.take() {
+            connection.close();
+        }
+    }
+
+    pub fn take_error(&mut self) -> Option<String> {
+        self.error.take()
+    }
+
+    pub fn toggle(&mut self, name: &str) -> Result<()> {
+        if self.connected() == Some(name) {
+            self.disconnect();
+            Ok(())
+        } else {
+            self.connect(name)
+        }
+    }
+}
+
+impl Default for MidiOutputPort {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MidiSender for MidiOutputPort {
+    fn send(&mut self, message: &[u8]) -> Result<(), Error> {
+        if let Some((_, connection)) = self.connection.as_mut() {
+            connection
+                .send(message)
+                .map_err(|e| format!("Cannot send MIDI message. Reason: {}", e))?;
+        }
+        Ok(())
+    }
+
+    fn fail(&mut self, error: Error) {
+        if let Some(name) = self.connected() {
+            self.error = Some(format!(
+                "MIDI-OUT port \"{}\" disconnected. {}",
+                name, error
+            ));
+        }
+        self.disconnect();
+    }
+}
@@ -1,3 +1,6 @@
 pub mod application;
 pub mod config;
 pub mod context;
+pub mod midi_input;
+pub mod midi_output;
+pub mod status;
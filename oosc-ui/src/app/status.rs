@@ -0,0 +1,29 @@
+use std::{
+    fmt::Display,
+    time::{Duration, Instant},
+};
+
+const MESSAGE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Terminal is in raw mode, so errors of audio thread and actions are shown in status line
+#[derive(Default)]
+pub struct Status {
+    message: Option<(String, Instant)>,
+}
+
+impl Status {
+    pub fn new() -> Self {
+        Self { message: None }
+    }
+
+    pub fn report<T: Display>(&mut self, message: T) {
+        self.message = Some((message.to_string(), Instant::now()));
+    }
+
+    pub fn message(&self) -> Option<&str> {
+        self.message
+            .as_ref()
+            .filter(|(_, time)| time.elapsed() < MESSAGE_TIMEOUT)
+            .map(|(message, _)| message.as_str())
+    }
+}
@@ -0,0 +1,162 @@
+use crossterm::event::{KeyCode, KeyEvent};
+use oosc_core::{
+    midi::arpeggiator::{Arpeggiator, ARP_RATES, MAX_ARP_OCTAVES},
+    utils::SharedMutex,
+};
+use ratatui::{
+    prelude::{Alignment, Margin, Rect},
+    style::Style,
+    widgets::{Block, BorderType, Borders, Paragraph, Wrap},
+};
+
+use crate::ui::utils::keycode_to_string;
+
+use super::{AutoFocus, Component, Focus, FocusableComponent, FocusableComponentContext};
+
+const GATE_STEP: f32 = 0.05;
+const SWING_STEP: f32 = 0.05;
+
+struct ArpeggiatorLayout {
+    pub rect: Rect,
+    pub inner: Rect,
+}
+
+pub struct ArpeggiatorComponent {
+    arpeggiator: SharedMutex<Arpeggiator>,
+    ctx: FocusableComponentContext,
+    layout: Option<ArpeggiatorLayout>,
+}
+
+impl ArpeggiatorComponent {
+    pub fn new(arpeggiator: SharedMutex<Arpeggiator>, keymap: KeyCode) -> Self {
+        let ctx = FocusableComponentContext::new().keymap(keymap);
+        Self {
+            arpeggiator,
+            ctx,
+            layout: None,
+        }
+    }
+}
+
+fn rate_to_string(rate: u32) -> String {
+    // Triplet rates are shown as fraction of triplet note
+    match rate % 3 {
+        0 => format!("1/{}t", rate / 3 * 4),
+        _ => format!("1/{}", rate * 4),
+    }
+}
+
+impl Component for ArpeggiatorComponent {
+    fn draw(
+        &mut self,
+        f: &mut ratatui::Frame<'_>,
+        _rect: ratatui::prelude::Rect,
+    ) -> anyhow::Result<()> {
+        let layout = self.layout.as_ref().unwrap();
+        let text = {
+            let arp = self.arpeggiator.lock().unwrap();
+            format!(
+                "e:{} m:{} o:{} r:{} g/G:{:.0}% s/S:{:.0}% l:{}",
+                if arp.is_enabled() { "on" } else { "off" },
+                arp.mode(),
+                arp.octaves(),
+                rate_to_string(arp.rate()),
+                arp.gate() * 100.0,
+                arp.swing() * 100.0,
+                if arp.is_latch() { "on" } else { "off" },
+            )
+        };
+        let p = Paragraph::new(text)
+            .wrap(Wrap { trim: true })
+            .alignment(Alignment::Center);
+        f.render_widget(p, layout.inner);
+        let b = Block::default()
+            .borders(Borders::ALL)
+            .title(format!(
+                "arp[{}]",
+                keycode_to_string(self.keymap().unwrap_or(KeyCode::Null))
+            ))
+            .border_type(BorderType::Rounded)
+            .title_alignment(Alignment::Center)
+            .style(Style::default().fg(self.color()));
+        f.render_widget(b, layout.rect);
+        Ok(())
+    }
+
+    fn resize(&mut self, rect: Rect) -> anyhow::Result<()> {
+        let inner = rect.inner(&Margin {
+            horizontal: 1,
+            vertical: 1,
+        });
+        self.layout = Some(ArpeggiatorLayout { rect, inner });
+        Ok(())
+    }
+
+    fn handle_key_events(&mut self, key: KeyEvent) -> anyhow::Result<()> {
+        let mut arp = self.arpeggiator.lock().unwrap();
+        match key.code {
+            KeyCode::Esc => {
+                drop(arp);
+                self.unfocus();
+            }
+            KeyCode::Char('e') => {
+                let value = !arp.is_enabled();
+                arp.set_enabled(value)?;
+            }
+            KeyCode::Char('m') => {
+                let mode = arp.mode().next();
+                arp.set_mode(mode);
+            }
+            KeyCode::Char('o') => {
+                let octaves = arp.octaves() % MAX_ARP_OCTAVES + 1;
+                arp.set_octaves(octaves);
+            }
+            KeyCode::Char('r') => {
+                let index = ARP_RATES.iter().position(|r| *r == arp.rate()).unwrap_or(0);
+                arp.set_rate(ARP_RATES[(index + 1) % ARP_RATES.len()]);
+            }
+            KeyCode::Char('g') => {
+                let gate = arp.gate() - GATE_STEP;
+                arp.set_gate(gate);
+            }
+            KeyCode::Char('G') => {
+                let gate = arp.gate() + GATE_STEP;
+                arp.set_gate(gate);
+            }
+            KeyCode::Char('s') => {
+                let swing = arp.swing() - SWING_STEP;
+                arp.set_swing(swing);
+            }
+            KeyCode::Char('S') => {
+                let swing = arp.swing() + SWING_STEP;
+                arp.set_swing(swing);
+            }
+            KeyCode::Char('l') => {
+                let value = !arp.is_latch();
+                arp.set_latch(value)?;
+            }
+            _ => (),
+        };
+        Ok(())
+    }
+}
+
+impl AutoFocus for ArpeggiatorComponent {}
+
+impl FocusableComponent for ArpeggiatorComponent {
+    fn context(&self) -> &FocusableComponentContext {
+        &self.ctx
+    }
+
+    fn context_mut(&mut self) -> &mut FocusableComponentContext {
+        &mut self.ctx
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
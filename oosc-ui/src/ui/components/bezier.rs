@@ -6,7 +6,6 @@ use oosc_core::utils::{
     adsr_envelope::SharedCurve, cubic_bezier::CubicBezierCurve, interpolation::InterpolateMethod,
     make_shared, Shared,
 };
-use ratatui::style::Style;
 use ratatui::{
     prelude::*,
     widgets::{canvas::Canvas, *},
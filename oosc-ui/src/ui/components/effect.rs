@@ -3,6 +3,7 @@ use std::rc::Rc;
 use crossterm::event::KeyCode;
 use oosc_core::{
     effects::Effect,
+    midi::learn::MidiLearn,
     utils::{make_shared, Shared},
 };
 use ratatui::{
@@ -32,7 +33,7 @@ pub struct EffectComponent {
 }
 
 impl EffectComponent {
-    pub fn new(effect: Shared<dyn Effect>, keymap: KeyCode) -> Self {
+    pub fn new(effect: Shared<dyn Effect>, keymap: KeyCode, learn: Shared<MidiLearn>) -> Self {
         let parameters = {
             let mut effect_guard = effect.write().unwrap();
             let parameters_container = effect_guard.parameters().unwrap().parameters_f32().unwrap();
@@ -41,13 +42,16 @@ impl EffectComponent {
                 .enumerate()
                 .map(|(i, p)| {
                     let map = KeyCode::Char(char::from_digit(i as u32 + 1, 10).unwrap());
-                    make_shared(ParameterComponentF32::from_named(
-                        p,
-                        Direction::Vertical,
-                        20,
-                        oosc_core::utils::interpolation::InterpolateMethod::Linear,
-                        map,
-                    )) as Shared<dyn FocusableComponent>
+                    make_shared(
+                        ParameterComponentF32::from_named(
+                            p,
+                            Direction::Vertical,
+                            20,
+                            oosc_core::utils::interpolation::InterpolateMethod::Linear,
+                            map,
+                        )
+                        .learn(learn.clone()),
+                    ) as Shared<dyn FocusableComponent>
                 })
                 .collect::<Vec<Shared<dyn FocusableComponent>>>();
             ComponentsContainer::from(parameters)
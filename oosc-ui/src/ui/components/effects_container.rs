@@ -2,6 +2,7 @@ use anyhow::anyhow;
 use crossterm::event::KeyCode;
 use oosc_core::{
     effects::Effect,
+    midi::learn::MidiLearn,
     utils::{make_shared, Shared},
 };
 use ratatui::prelude::*;
@@ -24,13 +25,16 @@ pub struct EffectsContainer {
 }
 
 impl EffectsContainer {
-    pub fn new(effects: impl Iterator<Item = Shared<dyn Effect>>) -> Self {
+    pub fn new(
+        effects: impl Iterator<Item = Shared<dyn Effect>>,
+        learn: Shared<MidiLearn>,
+    ) -> Self {
         let components = ComponentsContainer::from(
             effects
                 .enumerate()
                 .map(|(i, effect)| {
                     let map = KeyCode::Char(char::from_digit(i as u32 + 1, 10).unwrap());
-                    make_shared(EffectComponent::new(effect, map))
+                    make_shared(EffectComponent::new(effect, map, learn.clone()))
                         as Shared<dyn NamedFocusableComponent>
                 })
                 .collect::<Vec<Shared<dyn NamedFocusableComponent>>>(),
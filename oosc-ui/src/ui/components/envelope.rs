@@ -1,9 +1,9 @@
-use std::{collections::HashMap, rc::Rc};
+use std::rc::Rc;
 
 use crossterm::event::KeyCode;
-use oosc_core::utils::{
-    adsr_envelope::{ADSREnvelope, State},
-    Shared,
+use oosc_core::{
+    error::Error,
+    utils::{adsr_envelope::ADSREnvelope, Shared},
 };
 use ratatui::{
     prelude::*,
@@ -16,13 +16,10 @@ use super::{
     bezier::BezierComponent, Component, Focus, FocusableComponent, FocusableComponentContext, AutoFocus,
 };
 
-#[derive(Eq, PartialEq, Hash, Clone, Copy)]
+#[derive(Eq, PartialEq, Clone, Copy)]
 enum ShowState {
     Info,
-    Attack,
-    Decay,
-    Sustain,
-    Release,
+    Segment,
 }
 
 struct EnvelopeLayout {
@@ -34,9 +31,11 @@ pub struct EnvelopeComponent {
     pub envelope: Shared<ADSREnvelope>,
     pub samples: usize,
     ctx: FocusableComponentContext,
-    envelope_keymaps: HashMap<ShowState, KeyCode>,
     bezier: BezierComponent,
     state: ShowState,
+    selected: usize,
+    // Reason of rejected edit, shown instead of key help until next key
+    message: Option<String>,
     line: Vec<canvas::Line>,
     layout: Option<EnvelopeLayout>,
 }
@@ -63,21 +62,17 @@ impl FocusableComponent for EnvelopeComponent {
 
 impl EnvelopeComponent {
     pub fn new(envelope: Shared<ADSREnvelope>) -> Self {
-        let bezier = BezierComponent::new(&envelope.read().unwrap().attack);
+        let bezier = BezierComponent::new(&envelope.read().unwrap().segments()[0].curve);
         let ctx = FocusableComponentContext::new().keymap(KeyCode::Char('e'));
-        let mut envelope_keymaps = HashMap::new();
-        envelope_keymaps.insert(ShowState::Attack, KeyCode::Char('a'));
-        envelope_keymaps.insert(ShowState::Decay, KeyCode::Char('d'));
-        envelope_keymaps.insert(ShowState::Sustain, KeyCode::Char('s'));
-        envelope_keymaps.insert(ShowState::Release, KeyCode::Char('r'));
 
         Self {
             envelope,
             samples: 0,
             ctx,
-            envelope_keymaps,
             bezier,
             state: ShowState::Info,
+            selected: 0,
+            message: None,
             line: vec![],
             layout: None,
         }
@@ -94,11 +89,19 @@ impl EnvelopeComponent {
 
     pub fn render_line(&self) -> Vec<canvas::Line> {
         let table = self.envelope.read().unwrap();
-        let max_time = table.time_range_of(State::Release).1;
+        let max_time = table.length();
         let rate = max_time / self.samples as f32;
+        // Selected segment is highlighted
+        let start = table.time_at(self.selected);
+        let end = table.time_at(self.selected + 1);
         (1..=self.samples)
             .map(|t| {
-                let mut line = canvas::Line::new(0.0, 0.0, 0.0, 0.0, self.color());
+                let middle = (t as f32 - 0.5) * rate;
+                let color = match (start..end).contains(&middle) {
+                    true => Color::Yellow,
+                    false => self.color(),
+                };
+                let mut line = canvas::Line::new(0.0, 0.0, 0.0, 0.0, color);
                 let x1 = (t - 1) as f32 * rate;
                 let x2 = t as f32 * rate;
                 line.x1 = x1 as f64;
@@ -110,6 +113,68 @@ impl EnvelopeComponent {
             .collect()
     }
 
+    fn info(&self) -> String {
+        if let Some(message) = &self.message {
+            return message.clone();
+        }
+        let envelope = self.envelope.read().unwrap();
+        let segments = envelope.segments();
+        let sustain = envelope
+            .sustain_point()
+            .map_or("off".to_owned(), |p| p.to_string());
+        let (start, end) = envelope.loop_points();
+        format!(
+            "Segment [left/right]: {:?} {}/{} Edit [enter] Add [+] Remove [-] Sustain [p]: {} Loop [l]: {} {}-{} [[/]]",
+            segments[self.selected].kind,
+            self.selected + 1,
+            segments.len(),
+            sustain,
+            if envelope.is_sustain_looped() { "on" } else { "off" },
+            start,
+            end,
+        )
+    }
+
+    fn edit(&mut self, key: KeyCode) -> Result<(), Error> {
+        let mut envelope = self.envelope.write().unwrap();
+        let count = envelope.segments().len();
+        match key {
+            KeyCode::Left => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Right => self.selected = (self.selected + 1).min(count - 1),
+            KeyCode::Enter => {
+                self.state = ShowState::Segment;
+                self.bezier
+                    .new_curve(&envelope.segments()[self.selected].curve);
+                self.bezier.focus();
+            }
+            KeyCode::Char('+') => envelope.split_segment(self.selected)?,
+            KeyCode::Char('-') => {
+                envelope.remove_segment(self.selected)?;
+                self.selected = self.selected.min(count - 2);
+            }
+            // Sustain at the end of selected segment, again for one-shot envelope
+            KeyCode::Char('p') => {
+                let point = Some(self.selected + 1);
+                let point = if envelope.sustain_point() == point { None } else { point };
+                envelope.set_sustain_point(point)?;
+            }
+            KeyCode::Char('[') => {
+                let end = envelope.loop_points().1;
+                envelope.set_loop_points(self.selected, end)?;
+            }
+            KeyCode::Char(']') => {
+                let start = envelope.loop_points().0;
+                envelope.set_loop_points(start, self.selected + 1)?;
+            }
+            KeyCode::Char('l') => {
+                let looped = envelope.is_sustain_looped();
+                envelope.sustain_loop.write().unwrap().set_value(!looped);
+            }
+            _ => (),
+        };
+        Ok(())
+    }
+
     fn build_main_layout(rect: Rect) -> Rc<[Rect]> {
         Layout::default()
             .direction(Direction::Vertical)
@@ -133,12 +198,7 @@ impl Component for EnvelopeComponent {
         match self.state {
             ShowState::Info => {
                 let layout = self.layout.as_ref().unwrap();
-                let max_time = self
-                    .envelope
-                    .read()
-                    .unwrap()
-                    .time_range_of(State::Release)
-                    .1;
+                let max_time = self.envelope.read().unwrap().length();
                 self.line = self.render_line();
                 let canvas = Canvas::default()
                     .marker(Marker::Braille)
@@ -148,7 +208,7 @@ impl Component for EnvelopeComponent {
                         self.line.iter().for_each(|line| ctx.draw(line));
                     });
                 canvas.render(layout.main[0], f.buffer_mut());
-                let p = Paragraph::new("Attack [a] Decay [d] Sustain [s] Release [r]")
+                let p = Paragraph::new(self.info())
                     .wrap(Wrap { trim: true })
                     .alignment(Alignment::Center);
                 f.render_widget(p, layout.main[1]);
@@ -180,34 +240,13 @@ impl Component for EnvelopeComponent {
             self.bezier.unfocus();
             return Ok(())
         }
-        self.bezier.handle_key_events(key)?;
-        match key.code {
-            KeyCode::Esc => {
-                self.state = ShowState::Info;
-                self.bezier.unfocus();
-            }
-            c => {
-                if c == self.envelope_keymaps[&ShowState::Attack] {
-                    self.state = ShowState::Attack;
-                    self.bezier.new_curve(&self.envelope.read().unwrap().attack);
-                    self.bezier.focus();
-                }
-                if c == self.envelope_keymaps[&ShowState::Decay] {
-                    self.state = ShowState::Decay;
-                    self.bezier.new_curve(&self.envelope.read().unwrap().decay);
-                    self.bezier.focus();
-                }
-                if c == self.envelope_keymaps[&ShowState::Sustain] {
-                    self.state = ShowState::Sustain;
-                    self.bezier
-                        .new_curve(&self.envelope.read().unwrap().sustain);
-                    self.bezier.focus();
-                }
-                if c == self.envelope_keymaps[&ShowState::Release] {
-                    self.state = ShowState::Release;
-                    self.bezier
-                        .new_curve(&self.envelope.read().unwrap().release);
-                    self.bezier.focus();
+        match self.state {
+            ShowState::Info => self.message = self.edit(key.code).err().map(|e| e.to_string()),
+            ShowState::Segment => {
+                self.bezier.handle_key_events(key)?;
+                if key.code == KeyCode::Esc {
+                    self.state = ShowState::Info;
+                    self.bezier.unfocus();
                 }
             }
         };
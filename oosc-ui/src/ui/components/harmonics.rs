@@ -0,0 +1,164 @@
+use crossterm::event::KeyCode;
+use oosc_core::core::{additive::Partial, parameter::SharedParameter};
+use ratatui::{prelude::*, widgets::*};
+
+use crate::ui::utils::keycode_to_string_prefixed;
+
+use super::{AutoFocus, Component, Focus, FocusableComponent, FocusableComponentContext};
+
+const AMPLITUDE_STEP: f32 = 0.05;
+const RATIO_STEP: f32 = 0.5;
+const PHASE_STEP: f32 = 0.05;
+const BAR_WIDTH: u16 = 2;
+
+struct HarmonicsLayout {
+    pub rect: Rect,
+    pub bars: Rect,
+    pub help: Rect,
+}
+
+// Bar graph of partial amplitudes, selected partial is edited with arrows
+pub struct HarmonicsComponent {
+    partials: Vec<Partial>,
+    selected: usize,
+    ctx: FocusableComponentContext,
+    layout: Option<HarmonicsLayout>,
+}
+
+impl HarmonicsComponent {
+    pub fn new(partials: Vec<Partial>, keymap: KeyCode) -> Self {
+        Self {
+            partials,
+            selected: 0,
+            ctx: FocusableComponentContext::new().keymap(keymap),
+            layout: None,
+        }
+    }
+
+    fn step(parameter: SharedParameter<f32>, step: f32) {
+        let mut parameter = parameter.write().unwrap();
+        let (min, max) = parameter.range();
+        let value = (parameter.get_value() + step).clamp(min, max);
+        parameter.set_value(value);
+    }
+
+    fn selected(&self) -> &Partial {
+        &self.partials[self.selected]
+    }
+
+    // Keeps selected bar visible when not all bars fit
+    fn visible_range(&self, width: u16) -> std::ops::Range<usize> {
+        let count = ((width / (BAR_WIDTH + 1)) as usize).clamp(1, self.partials.len());
+        let start = self
+            .selected
+            .saturating_sub(count - 1)
+            .min(self.partials.len() - count);
+        start..start + count
+    }
+}
+
+impl Component for HarmonicsComponent {
+    fn draw(&mut self, f: &mut Frame<'_>, _rect: Rect) -> anyhow::Result<()> {
+        let layout = self.layout.as_ref().unwrap();
+        let bars = self
+            .visible_range(layout.bars.width)
+            .map(|i| {
+                let amplitude = self.partials[i].amplitude().read().unwrap().get_value();
+                let color = if i == self.selected {
+                    Color::Yellow
+                } else {
+                    self.color()
+                };
+                Bar::default()
+                    .value((amplitude * 100.0).round() as u64)
+                    .text_value(String::new())
+                    .label(Line::from(format!("{}", (i + 1) % 100)))
+                    .style(Style::default().fg(color))
+            })
+            .collect::<Vec<_>>();
+        let chart = BarChart::default()
+            .data(BarGroup::default().bars(&bars))
+            .bar_width(BAR_WIDTH)
+            .bar_gap(1)
+            .max(100);
+        f.render_widget(chart, layout.bars);
+
+        let partial = self.selected();
+        let info = format!(
+            "#{} amp:{:.2} ratio:{:.2} phase:{:.2}{} [up/down amp, +/- ratio, [/] phase]",
+            self.selected + 1,
+            partial.amplitude().read().unwrap().get_value(),
+            partial.ratio().read().unwrap().get_value(),
+            partial.phase().read().unwrap().get_value(),
+            if partial.envelope().is_some() {
+                " env"
+            } else {
+                ""
+            },
+        );
+        let p = Paragraph::new(info)
+            .wrap(Wrap { trim: true })
+            .alignment(Alignment::Center);
+        f.render_widget(p, layout.help);
+        let b = Block::default()
+            .borders(Borders::TOP | Borders::BOTTOM | Borders::RIGHT)
+            .title(format!(
+                "Harmonics{}",
+                keycode_to_string_prefixed(self.keymap(), "[", "]")
+            ))
+            .style(Style::default().fg(self.color()));
+        f.render_widget(b, layout.rect);
+        Ok(())
+    }
+
+    fn resize(&mut self, rect: Rect) -> anyhow::Result<()> {
+        let main = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(0), Constraint::Length(2)])
+            .margin(1)
+            .split(rect);
+        self.layout = Some(HarmonicsLayout {
+            rect,
+            bars: main[0],
+            help: main[1],
+        });
+        Ok(())
+    }
+
+    fn handle_key_events(&mut self, key: crossterm::event::KeyEvent) -> anyhow::Result<()> {
+        let partial = self.selected().clone();
+        match key.code {
+            KeyCode::Esc => self.unfocus(),
+            KeyCode::Left => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Right => self.selected = (self.selected + 1).min(self.partials.len() - 1),
+            KeyCode::Up => Self::step(partial.amplitude(), AMPLITUDE_STEP),
+            KeyCode::Down => Self::step(partial.amplitude(), -AMPLITUDE_STEP),
+            KeyCode::Char('+') => Self::step(partial.ratio(), RATIO_STEP),
+            KeyCode::Char('-') => Self::step(partial.ratio(), -RATIO_STEP),
+            KeyCode::Char(']') => Self::step(partial.phase(), PHASE_STEP),
+            KeyCode::Char('[') => Self::step(partial.phase(), -PHASE_STEP),
+            _ => (),
+        };
+        Ok(())
+    }
+}
+
+impl AutoFocus for HarmonicsComponent {}
+
+impl FocusableComponent for HarmonicsComponent {
+    fn context(&self) -> &FocusableComponentContext {
+        &self.ctx
+    }
+
+    fn context_mut(&mut self) -> &mut FocusableComponentContext {
+        &mut self.ctx
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
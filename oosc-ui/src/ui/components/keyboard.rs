@@ -1,40 +1,120 @@
-use anyhow::Result;
+use std::time::{Duration, Instant};
+
 use crossterm::event::{KeyCode, KeyEvent, KeyEventKind};
-use midir::{MidiOutput, MidiOutputConnection};
+use oosc_core::{
+    core::note::{Note, NoteEventReceiver},
+    utils::SharedMutex,
+};
 use ratatui::{
     prelude::{Alignment, Margin, Rect},
     style::Style,
     widgets::{Block, BorderType, Borders, Paragraph, Wrap},
 };
 
-use crate::ui::utils::keycode_to_string;
+use crate::ui::utils::{keycode_to_string, note_name};
 
 use super::{AutoFocus, Component, Focus, FocusableComponent, FocusableComponentContext};
 
+// Longer than usual key repeat delay, so held key is not released between repeats
+const KEY_RELEASE_TIMEOUT: Duration = Duration::from_millis(600);
+// Tracker layout, lower row starts at current octave, upper row one octave higher
+const LOWER_ROW: &str = "zsxdcvgbhnjm,l.;/";
+const UPPER_ROW: &str = "q2w3er5t6y7ui9o0p[=]";
+
+fn key_offset(c: char) -> Option<u32> {
+    let c = c.to_ascii_lowercase();
+    LOWER_ROW
+        .find(c)
+        .or_else(|| UPPER_ROW.find(c).map(|i| i + 12))
+        .map(|i| i as u32)
+}
+
+struct HeldKey {
+    key: char,
+    note: u32,
+    deadline: Instant,
+}
+
 struct KeyboardLayout {
     pub rect: Rect,
     pub inner: Rect,
 }
 
 pub struct KeyboardComponent {
-    output: MidiOutputConnection,
+    receiver: SharedMutex<dyn NoteEventReceiver>,
     ctx: FocusableComponentContext,
     layout: Option<KeyboardLayout>,
-    last_note: Option<i32>,
+    octave: u32,
+    velocity: u32,
+    held: Vec<HeldKey>,
+    // Set after first key release event, before that notes are released by timeout
+    release_events: bool,
 }
 
 impl KeyboardComponent {
-    pub fn new(keymap: KeyCode) -> Result<Self> {
+    pub fn new(receiver: SharedMutex<dyn NoteEventReceiver>, keymap: KeyCode) -> Self {
         let ctx = FocusableComponentContext::new().keymap(keymap);
-        let output = MidiOutput::new("oosc")?;
-        let out_ports = output.ports();
-        let output = output.connect(&out_ports[0], "oosc-output").unwrap();
-        Ok(Self {
-            output,
+        Self {
+            receiver,
             ctx,
             layout: None,
-            last_note: None,
-        })
+            octave: 4,
+            velocity: 100,
+            held: vec![],
+            release_events: false,
+        }
+    }
+
+    // Releases notes of keys without repeat, used when terminal does not report key release
+    pub fn release_expired(&mut self) {
+        if self.release_events {
+            return;
+        }
+        let now = Instant::now();
+        let expired = self
+            .held
+            .iter()
+            .filter(|k| k.deadline <= now)
+            .map(|k| k.key)
+            .collect::<Vec<_>>();
+        expired.into_iter().for_each(|key| self.release(key));
+    }
+
+    pub fn release_all(&mut self) {
+        let keys = self.held.iter().map(|k| k.key).collect::<Vec<_>>();
+        keys.into_iter().for_each(|key| self.release(key));
+    }
+
+    fn press(&mut self, key: char) {
+        let deadline = Instant::now() + KEY_RELEASE_TIMEOUT;
+        if let Some(held) = self.held.iter_mut().find(|k| k.key == key) {
+            held.deadline = deadline;
+            return;
+        }
+        let Some(offset) = key_offset(key) else {
+            return;
+        };
+        let note = (self.octave + 1) * 12 + offset;
+        if note > 127 {
+            return;
+        }
+        let _ = self
+            .receiver
+            .lock()
+            .unwrap()
+            .note_on(Note::new(note, self.velocity));
+        self.held.push(HeldKey {
+            key,
+            note,
+            deadline,
+        });
+    }
+
+    fn release(&mut self, key: char) {
+        if let Some(index) = self.held.iter().position(|k| k.key == key) {
+            let held = self.held.remove(index);
+            let _ = self.receiver.lock().unwrap().note_off(held.note);
+        }
     }
 }
 
@@ -45,16 +125,24 @@ impl Component for KeyboardComponent {
         _rect: ratatui::prelude::Rect,
     ) -> anyhow::Result<()> {
         let layout = self.layout.as_ref().unwrap();
-        if let Some(last_note) = self.last_note {
-            let p = Paragraph::new(format!("{}", last_note))
-                .wrap(Wrap { trim: true })
-                .alignment(Alignment::Center);
-            f.render_widget(p, layout.inner);
-        }
+        let notes = self
+            .held
+            .iter()
+            .map(|k| note_name(k.note))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let text = format!(
+            "oct:{} vel:{} [up/down octave, left/right velocity] {}",
+            self.octave, self.velocity, notes
+        );
+        let p = Paragraph::new(text)
+            .wrap(Wrap { trim: true })
+            .alignment(Alignment::Center);
+        f.render_widget(p, layout.inner);
         let b = Block::default()
             .borders(Borders::ALL)
             .title(format!(
-                "midi[{}]",
+                "keyboard[{}]",
                 keycode_to_string(self.keymap().unwrap_or(KeyCode::Null))
             ))
             .border_type(BorderType::Rounded)
@@ -74,18 +162,23 @@ impl Component for KeyboardComponent {
     }
 
     fn handle_key_events(&mut self, key: KeyEvent) -> anyhow::Result<()> {
+        if key.kind == KeyEventKind::Release {
+            self.release_events = true;
+            if let KeyCode::Char(c) = key.code {
+                self.release(c.to_ascii_lowercase());
+            }
+            return Ok(());
+        }
         match key.code {
-            KeyCode::Esc => self.unfocus(),
-            KeyCode::Char(_c) => {
-                if key.kind == KeyEventKind::Press {
-                    let _ = self.output.send(&[0x90, 60, 0x64]);
-                    self.last_note = Some(60);
-                }
-                if key.kind == KeyEventKind::Release {
-                    let _ = self.output.send(&[0x80, 60, 0x64]);
-                    self.last_note = None;
-                }
+            KeyCode::Esc => {
+                self.release_all();
+                self.unfocus();
             }
+            KeyCode::Up => self.octave = (self.octave + 1).min(8),
+            KeyCode::Down => self.octave = self.octave.saturating_sub(1),
+            KeyCode::Right => self.velocity = (self.velocity + 8).min(127),
+            KeyCode::Left => self.velocity = self.velocity.saturating_sub(8).max(1),
+            KeyCode::Char(c) => self.press(c.to_ascii_lowercase()),
             _ => (),
         };
         Ok(())
@@ -0,0 +1,121 @@
+use crossterm::event::{KeyCode, KeyEvent};
+use oosc_core::utils::SharedMutex;
+use ratatui::{
+    prelude::{Alignment, Margin, Rect},
+    style::Style,
+    widgets::{Block, BorderType, Borders, Paragraph, Wrap},
+};
+
+use crate::{app::midi_input::MidiInputs, ui::utils::keycode_to_string};
+
+use super::{AutoFocus, Component, Focus, FocusableComponent, FocusableComponentContext};
+
+struct MidiInputLayout {
+    pub rect: Rect,
+    pub inner: Rect,
+}
+
+pub struct MidiInputComponent {
+    inputs: SharedMutex<MidiInputs>,
+    ctx: FocusableComponentContext,
+    layout: Option<MidiInputLayout>,
+    current: usize,
+}
+
+impl MidiInputComponent {
+    pub fn new(inputs: SharedMutex<MidiInputs>, keymap: KeyCode) -> Self {
+        let ctx = FocusableComponentContext::new().keymap(keymap);
+        Self {
+            inputs,
+            ctx,
+            layout: None,
+            current: 0,
+        }
+    }
+}
+
+impl Component for MidiInputComponent {
+    fn draw(
+        &mut self,
+        f: &mut ratatui::Frame<'_>,
+        _rect: ratatui::prelude::Rect,
+    ) -> anyhow::Result<()> {
+        let layout = self.layout.as_ref().unwrap();
+        let text = {
+            let inputs = self.inputs.lock().unwrap();
+            let ports = inputs.ports();
+            self.current = self.current.min(ports.len().saturating_sub(1));
+            match ports.get(self.current) {
+                Some(port) => format!(
+                    "< {} [{}] > {}/{}",
+                    port.name,
+                    if port.connected { "on" } else { "off" },
+                    self.current + 1,
+                    ports.len()
+                ),
+                None if inputs.has_virtual_port() => "No ports, connect to \"oosc\"".to_owned(),
+                None => "No ports".to_owned(),
+            }
+        };
+        let p = Paragraph::new(text)
+            .wrap(Wrap { trim: true })
+            .alignment(Alignment::Center);
+        f.render_widget(p, layout.inner);
+        let b = Block::default()
+            .borders(Borders::ALL)
+            .title(format!(
+                "midi-in[{}]",
+                keycode_to_string(self.keymap().unwrap_or(KeyCode::Null))
+            ))
+            .border_type(BorderType::Rounded)
+            .title_alignment(Alignment::Center)
+            .style(Style::default().fg(self.color()));
+        f.render_widget(b, layout.rect);
+        Ok(())
+    }
+
+    fn resize(&mut self, rect: Rect) -> anyhow::Result<()> {
+        let inner = rect.inner(&Margin {
+            horizontal: 1,
+            vertical: 1,
+        });
+        self.layout = Some(MidiInputLayout { rect, inner });
+        Ok(())
+    }
+
+    fn handle_key_events(&mut self, key: KeyEvent) -> anyhow::Result<()> {
+        match key.code {
+            KeyCode::Esc => self.unfocus(),
+            KeyCode::Char('h') => self.current = self.current.saturating_sub(1),
+            KeyCode::Char('l') => self.current += 1,
+            KeyCode::Char(' ') | KeyCode::Enter => {
+                let mut inputs = self.inputs.lock().unwrap();
+                if let Some(port) = inputs.ports().get(self.current) {
+                    let _ = inputs.toggle(&port.name);
+                }
+            }
+            _ => (),
+        };
+        Ok(())
+    }
+}
+
+impl AutoFocus for MidiInputComponent {}
+
+impl FocusableComponent for MidiInputComponent {
+    fn context(&self) -> &FocusableComponentContext {
+        &self.ctx
+    }
+
+    fn context_mut(&mut self) -> &mut FocusableComponentContext {
+        &mut self.ctx
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
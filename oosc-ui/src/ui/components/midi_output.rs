@@ -0,0 +1,197 @@
+use crossterm::event::{KeyCode, KeyEvent};
+use oosc_core::{
+    core::transport::Transport,
+    midi::output::{MidiClockOutput, MidiThru},
+    utils::{Shared, SharedMutex},
+};
+use ratatui::{
+    prelude::{Alignment, Margin, Rect},
+    style::Style,
+    widgets::{Block, BorderType, Borders, Paragraph, Wrap},
+};
+
+use crate::{
+    app::{midi_output::MidiOutputPort, status::Status},
+    ui::utils::keycode_to_string,
+};
+
+use super::{AutoFocus, Component, Focus, FocusableComponent, FocusableComponentContext};
+
+struct MidiOutputLayout {
+    pub rect: Rect,
+    pub inner: Rect,
+}
+
+pub struct MidiOutputComponent {
+    output: SharedMutex<MidiOutputPort>,
+    thru: SharedMutex<MidiThru>,
+    clock: SharedMutex<MidiClockOutput>,
+    transport: Shared<Transport>,
+    status: Shared<Status>,
+    ctx: FocusableComponentContext,
+    layout: Option<MidiOutputLayout>,
+    current: usize,
+}
+
+impl MidiOutputComponent {
+    pub fn new(
+        output: SharedMutex<MidiOutputPort>,
+        thru: SharedMutex<MidiThru>,
+        clock: SharedMutex<MidiClockOutput>,
+        transport: Shared<Transport>,
+        status: Shared<Status>,
+        keymap: KeyCode,
+    ) -> Self {
+        let ctx = FocusableComponentContext::new().keymap(keymap);
+        Self {
+            output,
+            thru,
+            clock,
+            transport,
+            status,
+            ctx,
+            layout: None,
+            current: 0,
+        }
+    }
+}
+
+fn flag(name: &str, value: bool) -> String {
+    format!("{}:{}", name, if value { "on" } else { "off" })
+}
+
+impl Component for MidiOutputComponent {
+    fn draw(
+        &mut self,
+        f: &mut ratatui::Frame<'_>,
+        _rect: ratatui::prelude::Rect,
+    ) -> anyhow::Result<()> {
+        let layout = self.layout.as_ref().unwrap();
+        let port = {
+            let mut output = self.output.lock().unwrap();
+            if let Some(error) = output.take_error() {
+                self.status.write().unwrap().report(error);
+            }
+            let ports = output.ports();
+            self.current = self.current.min(ports.len().saturating_sub(1));
+            match ports.get(self.current) {
+                Some(port) => format!(
+                    "< {} [{}] >",
+                    port,
+                    if output.connected() == Some(port) {
+                        "on"
+                    } else {
+                        "off"
+                    }
+                ),
+                None => "No ports".to_owned(),
+            }
+        };
+        let text = {
+            let thru = self.thru.lock().unwrap();
+            let clock = self.clock.lock().unwrap();
+            let transport = self.transport.read().unwrap();
+            format!(
+                "{} {} {} {} {} {:.1}",
+                port,
+                flag("t-thru", thru.is_thru()),
+                flag("p-smf", thru.is_playback()),
+                flag("c-clock", clock.is_master()),
+                flag("g/G-play", transport.is_playing()),
+                transport.position()
+            )
+        };
+        let p = Paragraph::new(text)
+            .wrap(Wrap { trim: true })
+            .alignment(Alignment::Center);
+        f.render_widget(p, layout.inner);
+        let b = Block::default()
+            .borders(Borders::ALL)
+            .title(format!(
+                "midi-out[{}]",
+                keycode_to_string(self.keymap().unwrap_or(KeyCode::Null))
+            ))
+            .border_type(BorderType::Rounded)
+            .title_alignment(Alignment::Center)
+            .style(Style::default().fg(self.color()));
+        f.render_widget(b, layout.rect);
+        Ok(())
+    }
+
+    fn resize(&mut self, rect: Rect) -> anyhow::Result<()> {
+        let inner = rect.inner(&Margin {
+            horizontal: 1,
+            vertical: 1,
+        });
+        self.layout = Some(MidiOutputLayout { rect, inner });
+        Ok(())
+    }
+
+    fn handle_key_events(&mut self, key: KeyEvent) -> anyhow::Result<()> {
+        match key.code {
+            KeyCode::Esc => self.unfocus(),
+            KeyCode::Char('h') => self.current = self.current.saturating_sub(1),
+            KeyCode::Char('l') => self.current += 1,
+            KeyCode::Char(' ') | KeyCode::Enter => {
+                let mut output = self.output.lock().unwrap();
+                if let Some(port) = output.ports().get(self.current) {
+                    if let Err(e) = output.toggle(port) {
+                        self.status.write().unwrap().report(e);
+                    }
+                }
+            }
+            KeyCode::Char('t') => {
+                let mut thru = self.thru.lock().unwrap();
+                let value = !thru.is_thru();
+                thru.set_thru(value);
+                if !value {
+                    thru.all_notes_off();
+                }
+            }
+            KeyCode::Char('p') => {
+                let mut thru = self.thru.lock().unwrap();
+                let value = !thru.is_playback();
+                thru.set_playback(value);
+                if !value {
+                    thru.all_notes_off();
+                }
+            }
+            KeyCode::Char('c') => {
+                let mut clock = self.clock.lock().unwrap();
+                let value = !clock.is_master();
+                clock.set_master(value);
+            }
+            // Transport drives clock, arpeggiator sync and sequencer, g continues and G restarts
+            KeyCode::Char('g') => {
+                let mut transport = self.transport.write().unwrap();
+                match transport.is_playing() {
+                    true => transport.stop(),
+                    false => transport.resume(),
+                }
+            }
+            KeyCode::Char('G') => self.transport.write().unwrap().start(),
+            _ => (),
+        };
+        Ok(())
+    }
+}
+
+impl AutoFocus for MidiOutputComponent {}
+
+impl FocusableComponent for MidiOutputComponent {
+    fn context(&self) -> &FocusableComponentContext {
+        &self.ctx
+    }
+
+    fn context_mut(&mut self) -> &mut FocusableComponentContext {
+        &mut self.ctx
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
@@ -0,0 +1,119 @@
+use anyhow::anyhow;
+use crossterm::event::KeyCode;
+use oosc_core::{
+    core::synthesizer::Synthesizer,
+    utils::{make_shared, Shared},
+};
+use ratatui::prelude::*;
+
+use crate::{app::context::Context, ui::utils::keycode_to_string_prefixed};
+
+use super::{
+    components_container::ComponentsContainer, part::PartComponent, AutoFocus, Component, Focus,
+    FocusableComponent, FocusableComponentContext, Named, NamedFocusableComponent,
+};
+
+struct MixerLayout {
+    rect: Rect,
+}
+
+pub struct MixerComponent {
+    pub components: Shared<ComponentsContainer<dyn NamedFocusableComponent>>,
+    context: FocusableComponentContext,
+    layout: Option<MixerLayout>,
+}
+
+impl MixerComponent {
+    pub fn new(synthesizer: &Synthesizer, ctx: &Context) -> Self {
+        let components = ComponentsContainer::from(
+            synthesizer
+                .get_parts()
+                .iter()
+                .enumerate()
+                .map(|(i, part)| {
+                    let map = KeyCode::Char(char::from_digit(i as u32 + 1, 10).unwrap());
+                    make_shared(PartComponent::new(i, part, map, ctx))
+                        as Shared<dyn NamedFocusableComponent>
+                })
+                .collect::<Vec<Shared<dyn NamedFocusableComponent>>>(),
+        );
+        let components = make_shared(components);
+        let context = FocusableComponentContext::new().keymap(KeyCode::Char('x'));
+        Self {
+            components,
+            context,
+            layout: None,
+        }
+    }
+}
+
+impl FocusableComponent for MixerComponent {
+    fn context(&self) -> &FocusableComponentContext {
+        &self.context
+    }
+
+    fn context_mut(&mut self) -> &mut FocusableComponentContext {
+        &mut self.context
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
+impl Component for MixerComponent {
+    fn draw(
+        &mut self,
+        f: &mut ratatui::Frame<'_>,
+        rect: ratatui::prelude::Rect,
+    ) -> anyhow::Result<()> {
+        if self.layout.is_none() {
+            return Err(anyhow!("Cannot draw mixer").context("Create layout before draw"));
+        }
+        let _rect = self.layout.as_ref().unwrap().rect;
+        let mut components = self.components.write().unwrap();
+        components.draw(f, rect)?;
+        Ok(())
+    }
+
+    fn resize(&mut self, rect: Rect) -> anyhow::Result<()> {
+        let inner = {
+            let len = self.components.read().unwrap().components.len();
+            let size = 100 / len;
+            Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints(vec![Constraint::Percentage(size as u16); len])
+                .split(rect)
+        };
+        let mut components = self.components.write().unwrap();
+        components.resize_in_layout(&inner)?;
+        self.layout = Some(MixerLayout { rect });
+        Ok(())
+    }
+
+    fn handle_key_events(&mut self, key: crossterm::event::KeyEvent) -> anyhow::Result<()> {
+        if !self.components.read().unwrap().is_any_focused() && key.code == KeyCode::Esc {
+            self.unfocus()
+        }
+        let mut components = self.components.write().unwrap();
+        components.handle_key_events(key)
+    }
+}
+
+impl Named for MixerComponent {
+    fn name(&self) -> Vec<Span<'static>> {
+        vec![
+            Span::styled("Mixer", Style::default().fg(self.color())),
+            Span::styled(
+                keycode_to_string_prefixed(self.keymap(), "[", "]"),
+                Style::default().fg(Color::Red),
+            ),
+        ]
+    }
+}
+
+impl AutoFocus for MixerComponent {}
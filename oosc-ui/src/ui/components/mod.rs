@@ -1,12 +1,21 @@
+pub mod arpeggiator;
 pub mod bezier;
 pub mod components_container;
 pub mod envelope;
+pub mod harmonics;
 pub mod keyboard;
 pub mod menu_bar;
+pub mod midi_input;
+pub mod midi_output;
+pub mod mixer;
 pub mod oscillator;
 pub mod parameter;
+pub mod part;
 pub mod record;
 pub mod root;
+pub mod sequencer;
+pub mod source;
+pub mod status;
 pub mod synthesizer;
 pub mod wavetable;
 pub mod effect;
@@ -4,6 +4,7 @@ use anyhow::Context;
 use crossterm::event::KeyCode;
 use oosc_core::{
     core::{oscillator::WavetableOscillator, synthesizer::LockedOscillator},
+    midi::learn::MidiLearn,
     utils::{interpolation::InterpolateMethod, make_shared, Shared},
 };
 use ratatui::{prelude::*, widgets::*};
@@ -37,21 +38,25 @@ pub struct OscillatorComponent {
 impl AutoFocus for OscillatorComponent {}
 
 impl OscillatorComponent {
-    pub fn new(oscillator: LockedOscillator, keymap: KeyCode) -> Self {
+    pub fn new(oscillator: LockedOscillator, keymap: KeyCode, learn: Shared<MidiLearn>) -> Self {
         let mut osc = oscillator.write().unwrap();
 
         let osc = osc
             .as_any_mut()
             .downcast_mut::<WavetableOscillator>()
             .unwrap();
-        let mut parametrs = ComponentsContainer::from(Self::build_parametr_components(osc));
+        let mut parametrs =
+            ComponentsContainer::from(Self::build_parametr_components(osc, learn.clone()));
         let wavetable = make_shared(WavetableComponent::from(osc.wavetable()));
-        let wt_pos = make_shared(ParameterComponentI32::new(
-            "Wt Pos".to_owned(),
-            osc.wavetable_position(),
-            Direction::Vertical,
-            KeyCode::Char('w'),
-        ));
+        let wt_pos = make_shared(
+            ParameterComponentI32::new(
+                "Wt Pos".to_owned(),
+                osc.wavetable_position(),
+                Direction::Vertical,
+                KeyCode::Char('w'),
+            )
+            .learn(learn),
+        );
         wt_pos
             .write()
             .unwrap()
@@ -76,38 +81,157 @@ impl OscillatorComponent {
         }
     }
 
-    fn build_parametr_components(osc: &WavetableOscillator) -> Vec<Shared<dyn FocusableComponent>> {
+    fn build_parametr_components(
+        osc: &WavetableOscillator,
+        learn: Shared<MidiLearn>,
+    ) -> Vec<Shared<dyn FocusableComponent>> {
         vec![
-            make_shared(ParameterComponentF32::new(
-                "Pan".to_owned(),
-                osc.pan(),
-                Direction::Horizontal,
-                10,
-                InterpolateMethod::Linear,
-                KeyCode::Char('p'),
-            )),
-            make_shared(ParameterComponentI32::new(
-                "Octave".to_owned(),
-                osc.octave_offset(),
-                Direction::Vertical,
-                KeyCode::Char('o'),
-            )),
-            make_shared(ParameterComponentF32::new(
-                "Cents".to_owned(),
-                osc.cents_offset(),
-                Direction::Vertical,
-                200,
-                InterpolateMethod::Linear,
-                KeyCode::Char('c'),
-            )),
-            make_shared(ParameterComponentF32::new(
-                "Gain".to_owned(),
-                osc.gain(),
-                Direction::Vertical,
-                20,
-                InterpolateMethod::Exponential(0.001),
-                KeyCode::Char('g'),
-            )),
+            make_shared(
+                ParameterComponentF32::new(
+                    "Pan".to_owned(),
+                    osc.pan(),
+                    Direction::Horizontal,
+                    10,
+                    InterpolateMethod::Linear,
+                    KeyCode::Char('p'),
+                )
+                .learn(learn.clone()),
+            ),
+            make_shared(
+                ParameterComponentI32::new(
+                    "Octave".to_owned(),
+                    osc.octave_offset(),
+                    Direction::Vertical,
+                    KeyCode::Char('o'),
+                )
+                .learn(learn.clone()),
+            ),
+            make_shared(
+                ParameterComponentF32::new(
+                    "Cents".to_owned(),
+                    osc.cents_offset(),
+                    Direction::Vertical,
+                    200,
+                    InterpolateMethod::Linear,
+                    KeyCode::Char('c'),
+                )
+                .learn(learn.clone()),
+            ),
+            make_shared(
+                ParameterComponentF32::new(
+                    "Gain".to_owned(),
+                    osc.gain(),
+                    Direction::Vertical,
+                    20,
+                    InterpolateMethod::Exponential(0.001),
+                    KeyCode::Char('g'),
+                )
+                .learn(learn.clone()),
+            ),
+            make_shared(
+                ParameterComponentI32::new(
+                    "Bend".to_owned(),
+                    osc.pitch_bend_range(),
+                    Direction::Vertical,
+                    KeyCode::Char('b'),
+                )
+                .learn(learn.clone()),
+            ),
+            make_shared(
+                ParameterComponentI32::new(
+                    "Voices".to_owned(),
+                    osc.unison_voices(),
+                    Direction::Vertical,
+                    KeyCode::Char('u'),
+                )
+                .learn(learn.clone()),
+            ),
+            make_shared(
+                ParameterComponentF32::new(
+                    "Detune".to_owned(),
+                    osc.unison_detune(),
+                    Direction::Vertical,
+                    100,
+                    InterpolateMethod::Linear,
+                    KeyCode::Char('d'),
+                )
+                .learn(learn.clone()),
+            ),
+            make_shared(
+                ParameterComponentF32::new(
+                    "Spread".to_owned(),
+                    osc.unison_spread(),
+                    Direction::Vertical,
+                    20,
+                    InterpolateMethod::Linear,
+                    KeyCode::Char('s'),
+                )
+                .learn(learn.clone()),
+            ),
+            make_shared(
+                ParameterComponentF32::new(
+                    "Blend".to_owned(),
+                    osc.unison_blend(),
+                    Direction::Vertical,
+                    20,
+                    InterpolateMethod::Linear,
+                    KeyCode::Char('n'),
+                )
+                .learn(learn.clone()),
+            ),
+            make_shared(
+                ParameterComponentI32::new(
+                    "Mode".to_owned(),
+                    osc.voice_mode(),
+                    Direction::Vertical,
+                    KeyCode::Char('v'),
+                )
+                .learn(learn.clone()),
+            ),
+            make_shared(
+                ParameterComponentF32::new(
+                    "Glide".to_owned(),
+                    osc.glide_time(),
+                    Direction::Vertical,
+                    50,
+                    InterpolateMethod::Exponential(0.001),
+                    KeyCode::Char('t'),
+                )
+                .learn(learn.clone()),
+            ),
+            make_shared(
+                ParameterComponentF32::new(
+                    "Velocity".to_owned(),
+                    osc.velocity().sensitivity(),
+                    Direction::Vertical,
+                    20,
+                    InterpolateMethod::Linear,
+                    KeyCode::Char('l'),
+                )
+                .learn(learn.clone()),
+            ),
+            make_shared(
+                ParameterComponentF32::new(
+                    "Vib Rate".to_owned(),
+                    osc.vibrato().read().unwrap().frequency(),
+                    Direction::Vertical,
+                    50,
+                    InterpolateMethod::Exponential(0.001),
+                    KeyCode::Char('r'),
+                )
+                .learn(learn.clone()),
+            ),
+            make_shared(
+                ParameterComponentF32::new(
+                    "Vib Depth".to_owned(),
+                    osc.vibrato_depth(),
+                    Direction::Vertical,
+                    100,
+                    InterpolateMethod::Linear,
+                    KeyCode::Char('i'),
+                )
+                .learn(learn),
+            ),
         ]
     }
 
@@ -3,7 +3,11 @@ use std::rc::Rc;
 use crossterm::event::KeyCode;
 use oosc_core::{
     core::parameter::{NamedParameter, SharedParameter},
-    utils::interpolation::{interpolate_range, time_at, InterpolateMethod},
+    midi::learn::MidiLearn,
+    utils::{
+        interpolation::{interpolate_range, time_at, InterpolateMethod},
+        Shared,
+    },
 };
 use ratatui::style::Style;
 use ratatui::{prelude::*, widgets::*};
@@ -40,6 +44,7 @@ pub trait AnyParameterComponent {
     fn format_value(&self) -> String;
     fn increment(&mut self);
     fn decrement(&mut self);
+    fn toggle_learn(&mut self) {}
     fn resize(&mut self, rect: Rect);
     fn layout(&self) -> &Option<ParameterLayout>;
 }
@@ -70,6 +75,7 @@ pub struct ParameterComponentF32 {
     current_step: f32,
     interpolation_method: InterpolateMethod,
     events: EventContainer<f32>,
+    learn: Option<Shared<MidiLearn>>,
     context: FocusableComponentContext,
     layout: Option<ParameterLayout>,
 }
@@ -98,14 +104,29 @@ impl ParameterComponentF32 {
             interpolation_method,
             context,
             events: EventContainer::<f32>::default(),
+            learn: None,
             layout: None,
         }
     }
 
+    pub fn learn(self, learn: Shared<MidiLearn>) -> Self {
+        Self {
+            learn: Some(learn),
+            ..self
+        }
+    }
+
     pub fn events(&mut self) -> &mut impl Notifier<ParameterEvent<f32>> {
         &mut self.events
     }
 
+    fn is_learning(&self) -> bool {
+        self.learn
+            .as_ref()
+            .map(|learn| learn.read().unwrap().is_armed(&self.parametr))
+            .unwrap_or(false)
+    }
+
     pub fn from_named(
         named: &NamedParameter<f32>,
         direction: Direction,
@@ -150,6 +171,7 @@ pub struct ParameterComponentI32 {
     direction: Direction,
     events: EventContainer<i32>,
     context: FocusableComponentContext,
+    learn: Option<Shared<MidiLearn>>,
     layout: Option<ParameterLayout>,
 }
 
@@ -187,13 +209,28 @@ impl ParameterComponentI32 {
             direction,
             events: EventContainer::<i32>::default(),
             context,
+            learn: None,
             layout: None,
         }
     }
 
+    pub fn learn(self, learn: Shared<MidiLearn>) -> Self {
+        Self {
+            learn: Some(learn),
+            ..self
+        }
+    }
+
     pub fn events(&mut self) -> &mut impl Notifier<ParameterEvent<i32>> {
         &mut self.events
     }
+
+    fn is_learning(&self) -> bool {
+        self.learn
+            .as_ref()
+            .map(|learn| learn.read().unwrap().is_armed(&self.parametr))
+            .unwrap_or(false)
+    }
 }
 
 impl AnyParameterComponent for ParameterComponentF32 {
@@ -214,7 +251,22 @@ impl AnyParameterComponent for ParameterComponentF32 {
     }
 
     fn format_value(&self) -> String {
-        format!("{:.2}", self.value())
+        if self.is_learning() {
+            "learn".to_owned()
+        } else {
+            format!("{:.2}", self.value())
+        }
+    }
+
+    fn toggle_learn(&mut self) {
+        if let Some(learn) = &self.learn {
+            let mut learn = learn.write().unwrap();
+            if learn.is_armed(&self.parametr) {
+                learn.disarm();
+            } else {
+                let _ = learn.arm(&self.parametr, self.interpolation_method);
+            }
+        }
     }
 
     fn increment(&mut self) {
@@ -269,7 +321,22 @@ impl AnyParameterComponent for ParameterComponentI32 {
     }
 
     fn format_value(&self) -> String {
-        format!("{:.0}", self.value().round())
+        if self.is_learning() {
+            "learn".to_owned()
+        } else {
+            format!("{:.0}", self.value().round())
+        }
+    }
+
+    fn toggle_learn(&mut self) {
+        if let Some(learn) = &self.learn {
+            let mut learn = learn.write().unwrap();
+            if learn.is_armed(&self.parametr) {
+                learn.disarm();
+            } else {
+                let _ = learn.arm(&self.parametr, InterpolateMethod::Linear);
+            }
+        }
     }
 
     fn increment(&mut self) {
@@ -339,6 +406,7 @@ impl<T: AnyParameterComponent + Focus> Component for T {
         match key.code {
             KeyCode::Char('h') => self.decrement(),
             KeyCode::Char('l') => self.increment(),
+            KeyCode::Char('m') => self.toggle_learn(),
             KeyCode::Esc => self.unfocus(),
             _ => (),
         };
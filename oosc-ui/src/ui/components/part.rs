@@ -0,0 +1,231 @@
+use std::rc::Rc;
+
+use anyhow::anyhow;
+use crossterm::event::KeyCode;
+use oosc_core::{
+    core::{
+        cross_modulation::{CrossModulation, CrossModulationKind},
+        parameter::NamedParametersContainer,
+        part::Part,
+        preset::{Preset, PresetBank},
+        synthesizer::Synthesizer,
+    },
+    utils::{interpolation::InterpolateMethod, make_shared, Shared, SharedMutex},
+};
+use ratatui::{
+    prelude::{Alignment, Constraint, Direction, Layout, Margin, Rect},
+    style::{Color, Style},
+    text::Span,
+    widgets::{Block, BorderType, Borders},
+};
+
+use crate::{
+    app::{config::PRESETS_DIR, context::Context, status::Status},
+    ui::{components::Focus, utils::keycode_to_string_prefixed},
+};
+
+use super::{
+    components_container::ComponentsContainer, parameter::ParameterComponentF32, AutoFocus,
+    Component, FocusableComponent, FocusableComponentContext, Named,
+};
+
+struct PartLayout {
+    pub rect: Rect,
+    pub inner: Rc<[Rect]>,
+}
+
+pub struct PartComponent {
+    index: usize,
+    title: String,
+    synthesizer: SharedMutex<Synthesizer>,
+    presets: Shared<PresetBank>,
+    status: Shared<Status>,
+    parameters: ComponentsContainer<dyn FocusableComponent>,
+    ctx: FocusableComponentContext,
+    layout: Option<PartLayout>,
+}
+
+impl PartComponent {
+    pub fn new(index: usize, part: &Part, keymap: KeyCode, ctx: &Context) -> Self {
+        let mixer = part
+            .parameters_f32()
+            .unwrap_or_default()
+            .iter()
+            .map(|p| (p.name.to_owned(), p.parameter.clone()));
+        let lfos = part.lfos().iter().enumerate().map(|(i, lfo)| {
+            (
+                format!("LFO{} Rate", i + 1),
+                lfo.read().unwrap().frequency(),
+            )
+        });
+        // Indices of cross modulations from modulations file follow the mixer and LFOs
+        let modulations = part
+            .modulations()
+            .iter()
+            .map(|m| (Self::format_modulation(m), m.index()));
+        let parameters = mixer
+            .chain(lfos)
+            .chain(modulations)
+            .take(9)
+            .enumerate()
+            .map(|(i, (name, parameter))| {
+                let map = KeyCode::Char(char::from_digit(i as u32 + 1, 10).unwrap());
+                make_shared(
+                    ParameterComponentF32::new(
+                        name,
+                        parameter,
+                        Direction::Vertical,
+                        20,
+                        InterpolateMethod::Linear,
+                        map,
+                    )
+                    .learn(ctx.midi_learn.clone()),
+                ) as Shared<dyn FocusableComponent>
+            })
+            .collect::<Vec<Shared<dyn FocusableComponent>>>();
+        Self {
+            index,
+            title: format!("Part{} {} Save [w]", index + 1, Self::format_input(part)),
+            synthesizer: ctx.synthesizer.clone(),
+            presets: ctx.presets.clone(),
+            status: ctx.status.clone(),
+            parameters: ComponentsContainer::from(parameters),
+            ctx: FocusableComponentContext::new().keymap(keymap),
+            layout: None,
+        }
+    }
+
+    // Part is stored as next free program of first bank
+    fn save_preset(&self) -> anyhow::Result<String> {
+        let name = format!("Part{}", self.index + 1);
+        let preset = {
+            let synthesizer = self.synthesizer.lock().unwrap();
+            let part = synthesizer
+                .get_parts()
+                .get(self.index)
+                .ok_or(anyhow!("{} is missing", name))?;
+            Preset::capture(&name, part)
+        };
+        let mut presets = self.presets.write().unwrap();
+        let program = presets
+            .free_program(0)
+            .ok_or(anyhow!("No free program in bank 0"))?;
+        presets.insert(0, program, preset);
+        presets.save_dir(PRESETS_DIR)?;
+        Ok(format!("{} saved as program {}", name, program))
+    }
+
+    fn format_modulation(modulation: &CrossModulation) -> String {
+        let kind = match modulation.kind {
+            CrossModulationKind::Frequency => "FM",
+            CrossModulationKind::Phase => "PM",
+            CrossModulationKind::Ring => "Ring",
+            CrossModulationKind::Amplitude => "AM",
+            CrossModulationKind::Sync => "Sync",
+        };
+        format!(
+            "{}>{} {}",
+            modulation.source + 1,
+            modulation.target + 1,
+            kind
+        )
+    }
+
+    fn format_input(part: &Part) -> String {
+        let join = |values: Vec<String>| values.join(",");
+        let channels = join(
+            part.channels()
+                .iter()
+                .map(|c| (c + 1).to_string())
+                .collect(),
+        );
+        let tracks = join(part.tracks().iter().map(|t| t.to_string()).collect());
+        match (channels.is_empty(), tracks.is_empty()) {
+            (true, true) => "omni".to_owned(),
+            (false, true) => format!("ch:{}", channels),
+            (true, false) => format!("tr:{}", tracks),
+            (false, false) => format!("ch:{} tr:{}", channels, tracks),
+        }
+    }
+}
+
+impl Component for PartComponent {
+    fn draw(
+        &mut self,
+        f: &mut ratatui::Frame<'_>,
+        _rect: ratatui::prelude::Rect,
+    ) -> anyhow::Result<()> {
+        let layout = self.layout.as_ref().unwrap();
+        let b = Block::default()
+            .borders(Borders::ALL)
+            .title(self.name())
+            .style(Style::default().fg(self.color()))
+            .border_type(BorderType::Rounded)
+            .title_alignment(Alignment::Center);
+        f.render_widget(b, layout.rect);
+        self.parameters.draw_in_layout(f, &layout.inner)?;
+        Ok(())
+    }
+
+    fn resize(&mut self, rect: ratatui::prelude::Rect) -> anyhow::Result<()> {
+        let inner = {
+            let inner = rect.inner(&Margin::new(1, 1));
+            let len = self.parameters.components.len();
+            let size = 100 / len;
+            Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints(vec![Constraint::Percentage(size as u16); len])
+                .split(inner)
+        };
+        self.parameters.resize_in_layout(&inner)?;
+        self.layout = Some(PartLayout { rect, inner });
+        Ok(())
+    }
+
+    fn handle_key_events(&mut self, key: crossterm::event::KeyEvent) -> anyhow::Result<()> {
+        if !self.parameters.is_any_focused() {
+            match key.code {
+                KeyCode::Esc => self.unfocus(),
+                KeyCode::Char('w') => {
+                    let message = self.save_preset().unwrap_or_else(|e| e.to_string());
+                    self.status.write().unwrap().report(message);
+                    return Ok(());
+                }
+                _ => (),
+            }
+        }
+        self.parameters.handle_key_events(key)
+    }
+}
+
+impl AutoFocus for PartComponent {}
+
+impl FocusableComponent for PartComponent {
+    fn context(&self) -> &FocusableComponentContext {
+        &self.ctx
+    }
+
+    fn context_mut(&mut self) -> &mut FocusableComponentContext {
+        &mut self.ctx
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
+impl Named for PartComponent {
+    fn name(&self) -> Vec<Span<'static>> {
+        vec![
+            Span::styled(self.title.clone(), Style::default().fg(self.color())),
+            Span::styled(
+                keycode_to_string_prefixed(self.keymap(), "[", "]"),
+                Style::default().fg(Color::Red),
+            ),
+        ]
+    }
+}
@@ -1,6 +1,7 @@
 use crossterm::event::{KeyCode, KeyEvent};
 use oosc_core::{
     callbacks::stream_renderer::{RenderState, StreamRenderer, StreamWavRenderer},
+    midi::recorder::MidiRecorder,
     utils::SharedMutex,
 };
 use ratatui::{
@@ -20,6 +21,8 @@ struct RecordLayout {
 
 pub struct RecordComponent {
     control: SharedMutex<StreamWavRenderer>,
+    midi: Option<SharedMutex<MidiRecorder>>,
+    midi_armed: bool,
     ctx: FocusableComponentContext,
     layout: Option<RecordLayout>,
 }
@@ -29,10 +32,27 @@ impl RecordComponent {
         let ctx = FocusableComponentContext::new().keymap(keymap);
         Self {
             control,
+            midi: None,
+            midi_armed: false,
             ctx,
             layout: None,
         }
     }
+
+    pub fn midi(self, midi: SharedMutex<MidiRecorder>) -> Self {
+        Self {
+            midi: Some(midi),
+            ..self
+        }
+    }
+
+    fn midi_status(&self) -> &'static str {
+        match (&self.midi, self.midi_armed) {
+            (Some(_), true) => " MIDI [m]: on",
+            (Some(_), false) => " MIDI [m]: off",
+            (None, _) => "",
+        }
+    }
 }
 
 impl Component for RecordComponent {
@@ -45,14 +65,18 @@ impl Component for RecordComponent {
         let p = {
             let control = self.control.lock().unwrap();
             match control.get_state() {
-                RenderState::None => Paragraph::new("Record [r] Stop [s]")
-                    .wrap(Wrap { trim: true })
-                    .alignment(Alignment::Center),
-                RenderState::Rendering => {
-                    Paragraph::new(format!("Recording {:.2}s Stop [s]", control.time()))
+                RenderState::None => {
+                    Paragraph::new(format!("Record [r] Stop [s]{}", self.midi_status()))
                         .wrap(Wrap { trim: true })
                         .alignment(Alignment::Center)
                 }
+                RenderState::Rendering => Paragraph::new(format!(
+                    "Recording {:.2}s Stop [s]{}",
+                    control.time(),
+                    self.midi_status()
+                ))
+                .wrap(Wrap { trim: true })
+                .alignment(Alignment::Center),
             }
         };
         f.render_widget(p, layout.inner);
@@ -87,11 +111,18 @@ impl Component for RecordComponent {
                         let mut control = self.control.lock().unwrap();
                         control.to_file("record.wav")?;
                         let _ = control.start();
+                        if let Some(midi) = self.midi.as_ref().filter(|_| self.midi_armed) {
+                            let _ = midi.lock().unwrap().to_file("record.mid").start();
+                        }
                     }
                     's' => {
                         let mut control = self.control.lock().unwrap();
                         let _ = control.stop();
+                        if let Some(midi) = &self.midi {
+                            midi.lock().unwrap().stop()?;
+                        }
                     }
+                    'm' => self.midi_armed = !self.midi_armed,
                     _ => (),
                 };
             }
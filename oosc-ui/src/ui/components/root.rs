@@ -5,32 +5,88 @@ use ratatui::prelude::*;
 use crate::app::context::Context;
 
 use super::{
-    components_container::ComponentsContainer, record::RecordComponent,
-    synthesizer::SynthesizerComponent, Component, FocusableComponent,
+    arpeggiator::ArpeggiatorComponent, components_container::ComponentsContainer, keyboard::KeyboardComponent,
+    midi_input::MidiInputComponent,
+    midi_output::MidiOutputComponent, record::RecordComponent, sequencer::SequencerComponent, status::StatusComponent, synthesizer::SynthesizerComponent, Component, FocusableComponent,
 };
 
 pub struct Root {
     pub synthesizer: Shared<SynthesizerComponent>,
     pub recorder: Shared<RecordComponent>,
+    pub midi_input: Shared<MidiInputComponent>,
+    pub midi_output: Shared<MidiOutputComponent>,
+    pub arpeggiator: Shared<ArpeggiatorComponent>,
+    pub sequencer: Shared<SequencerComponent>,
+    pub keyboard: Shared<KeyboardComponent>,
+    status: StatusComponent,
     components: ComponentsContainer<dyn FocusableComponent>,
 }
 
 impl Root {
     pub fn new(ctx: &Context) -> Self {
         let synthesizer = ctx.synthesizer.lock().unwrap();
-        let synthesizer = make_shared(SynthesizerComponent::new(&synthesizer));
+        let synthesizer = make_shared(SynthesizerComponent::new(&synthesizer, ctx));
         let recorder = ctx.render_control.clone();
-        let recorder = make_shared(RecordComponent::new(recorder, KeyCode::Char('r')));
+        let recorder = make_shared(
+            RecordComponent::new(recorder, KeyCode::Char('r')).midi(ctx.midi_recorder.clone()),
+        );
+        let midi_input = make_shared(MidiInputComponent::new(
+            ctx.midi_inputs.clone(),
+            KeyCode::Char('i'),
+        ));
+        let midi_output = make_shared(MidiOutputComponent::new(
+            ctx.midi_output.clone(),
+            ctx.midi_thru.clone(),
+            ctx.midi_clock.clone(),
+            ctx.transport.clone(),
+            ctx.status.clone(),
+            KeyCode::Char('n'),
+        ));
+        let arpeggiator = make_shared(ArpeggiatorComponent::new(
+            ctx.arpeggiator.clone(),
+            KeyCode::Char('a'),
+        ));
+        let sequencer = make_shared(SequencerComponent::new(
+            ctx.sequencer.clone(),
+            ctx.transport.clone(),
+            ctx.status.clone(),
+            KeyCode::Char('s'),
+        ));
+        let keyboard = make_shared(KeyboardComponent::new(
+            ctx.synthesizer.clone(),
+            KeyCode::Char('y'),
+        ));
         let mut components = ComponentsContainer::new();
         components
             .container()
             .push(synthesizer.clone() as Shared<dyn FocusableComponent>);
+        components
+            .container()
+            .push(sequencer.clone() as Shared<dyn FocusableComponent>);
         components
             .container()
             .push(recorder.clone() as Shared<dyn FocusableComponent>);
+        components
+            .container()
+            .push(midi_input.clone() as Shared<dyn FocusableComponent>);
+        components
+            .container()
+            .push(midi_output.clone() as Shared<dyn FocusableComponent>);
+        components
+            .container()
+            .push(arpeggiator.clone() as Shared<dyn FocusableComponent>);
+        components
+            .container()
+            .push(keyboard.clone() as Shared<dyn FocusableComponent>);
         Self {
             synthesizer,
             recorder,
+            midi_input,
+            midi_output,
+            arpeggiator,
+            sequencer,
+            keyboard,
+            status: StatusComponent::new(ctx.status.clone()),
             components,
         }
     }
@@ -42,15 +98,34 @@ impl Component for Root {
         f: &mut ratatui::Frame<'_>,
         rect: ratatui::prelude::Rect,
     ) -> anyhow::Result<()> {
-        self.components.draw(f, rect)
+        self.components.draw(f, rect)?;
+        self.status.draw(f, rect)
     }
 
     fn resize(&mut self, rect: ratatui::prelude::Rect) -> anyhow::Result<()> {
         let layout = Layout::default()
             .direction(Direction::Vertical)
-            .constraints([Constraint::Min(0), Constraint::Length(3)])
+            .constraints([
+                Constraint::Min(0),
+                Constraint::Length(4),
+                Constraint::Length(3),
+                Constraint::Length(3),
+                Constraint::Length(1),
+            ])
             .split(rect);
-        self.components.resize_in_layout(&layout)
+        self.status.resize(layout[4])?;
+        let bottom = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([
+                Constraint::Percentage(20),
+                Constraint::Percentage(20),
+                Constraint::Percentage(30),
+                Constraint::Percentage(30),
+            ])
+            .split(layout[2]);
+        self.components.resize_in_layout(&[
+            layout[0], layout[1], bottom[0], bottom[1], bottom[2], bottom[3], layout[3],
+        ])
     }
 
     fn handle_key_events(&mut self, key: crossterm::event::KeyEvent) -> anyhow::Result<()> {
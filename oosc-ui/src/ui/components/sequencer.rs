@@ -0,0 +1,258 @@
+use crossterm::event::{KeyCode, KeyEvent};
+use oosc_core::{
+    core::transport::Transport,
+    midi::sequencer::{Step, StepSequencer, MAX_STEPS, MIN_STEPS},
+    utils::{Shared, SharedMutex},
+};
+use ratatui::{prelude::*, widgets::*};
+
+use crate::{
+    app::status::Status,
+    ui::utils::{keycode_to_string, note_name},
+};
+
+use super::{AutoFocus, Component, Focus, FocusableComponent, FocusableComponentContext};
+
+const SEQUENCE_FILE: &str = "sequence.mid";
+const PAGE_STEPS: usize = 16;
+const CELL_WIDTH: usize = 5;
+const VELOCITY_STEP: i32 = 8;
+const GATE_STEP: f32 = 0.1;
+const PROBABILITY_STEP: f32 = 0.1;
+
+struct SequencerLayout {
+    pub rect: Rect,
+    pub grid: Rect,
+    pub info: Rect,
+}
+
+// One page of 16 steps, page follows selected step
+pub struct SequencerComponent {
+    sequencer: SharedMutex<StepSequencer>,
+    transport: Shared<Transport>,
+    status: Shared<Status>,
+    selected: usize,
+    // Index of parameter locked by l
+    target: usize,
+    ctx: FocusableComponentContext,
+    layout: Option<SequencerLayout>,
+}
+
+impl SequencerComponent {
+    pub fn new(
+        sequencer: SharedMutex<StepSequencer>,
+        transport: Shared<Transport>,
+        status: Shared<Status>,
+        keymap: KeyCode,
+    ) -> Self {
+        let ctx = FocusableComponentContext::new().keymap(keymap);
+        Self {
+            sequencer,
+            transport,
+            status,
+            selected: 0,
+            target: 0,
+            ctx,
+            layout: None,
+        }
+    }
+
+    fn cell(&self, index: usize, step: &Step, current: Option<usize>) -> Span<'static> {
+        let text = match step.active {
+            true => format!(
+                "{}{}",
+                note_name(step.note as u32),
+                if step.tie { "~" } else { "" }
+            ),
+            false => "-".to_owned(),
+        };
+        let mut style = match step.active {
+            true => Style::default().fg(self.color()),
+            false => Style::default().fg(Color::DarkGray),
+        };
+        if current == Some(index) {
+            style = style.fg(Color::Green);
+        }
+        if index == self.selected {
+            style = style.bg(Color::Yellow).fg(Color::Black);
+        }
+        Span::styled(format!("{:<width$}", text, width = CELL_WIDTH), style)
+    }
+
+    fn action(&mut self, key: KeyCode) -> anyhow::Result<()> {
+        match key {
+            KeyCode::Esc => self.unfocus(),
+            KeyCode::Left => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Right => {
+                let length = self.sequencer.lock().unwrap().length();
+                self.selected = (self.selected + 1).min(length - 1);
+            }
+            KeyCode::Char('[') => self.target = self.target.saturating_sub(1),
+            KeyCode::Char(']') => {
+                let count = self.sequencer.lock().unwrap().parameters().len();
+                self.target = (self.target + 1).min(count.saturating_sub(1));
+            }
+            KeyCode::Char('n') => {
+                let mut sequencer = self.sequencer.lock().unwrap();
+                let length = sequencer.length().saturating_sub(PAGE_STEPS);
+                sequencer.set_length(length.max(MIN_STEPS))?;
+            }
+            KeyCode::Char('N') => {
+                let mut sequencer = self.sequencer.lock().unwrap();
+                let length = sequencer.length() + PAGE_STEPS;
+                sequencer.set_length(length.min(MAX_STEPS))?;
+            }
+            KeyCode::Char('e') => {
+                let mut sequencer = self.sequencer.lock().unwrap();
+                let value = !sequencer.is_enabled();
+                sequencer.set_enabled(value)?;
+            }
+            KeyCode::Char('x') => self.sequencer.lock().unwrap().save(SEQUENCE_FILE, 1)?,
+            // Steps follow transport, s starts it from the first step or stops it
+            KeyCode::Char('s') => {
+                let mut transport = self.transport.write().unwrap();
+                match transport.is_playing() {
+                    true => transport.stop(),
+                    false => transport.start(),
+                }
+            }
+            code => self.edit(code)?,
+        };
+        Ok(())
+    }
+
+    fn edit(&mut self, key: KeyCode) -> anyhow::Result<()> {
+        let mut sequencer = self.sequencer.lock().unwrap();
+        let target = sequencer
+            .parameters()
+            .get(self.target)
+            .map(|p| (p.path.clone(), p.parameter.read().unwrap().get_value()));
+        let Some(step) = sequencer.step_mut(self.selected) else {
+            return Ok(());
+        };
+        match key {
+            KeyCode::Char(' ') => step.active = !step.active,
+            KeyCode::Up => step.note = (step.note + 1).min(127),
+            KeyCode::Down => step.note = step.note.saturating_sub(1),
+            KeyCode::Char('>') => step.note = (step.note + 12).min(127),
+            KeyCode::Char('<') => step.note = step.note.saturating_sub(12),
+            KeyCode::Char('v') => {
+                step.velocity = (step.velocity as i32 - VELOCITY_STEP).clamp(1, 127) as u8
+            }
+            KeyCode::Char('V') => {
+                step.velocity = (step.velocity as i32 + VELOCITY_STEP).clamp(1, 127) as u8
+            }
+            KeyCode::Char('g') => step.gate = (step.gate - GATE_STEP).max(GATE_STEP),
+            KeyCode::Char('G') => step.gate = (step.gate + GATE_STEP).min(1.0),
+            KeyCode::Char('p') => step.probability = (step.probability - PROBABILITY_STEP).max(0.0),
+            KeyCode::Char('P') => step.probability = (step.probability + PROBABILITY_STEP).min(1.0),
+            KeyCode::Char('t') => step.tie = !step.tie,
+            // Locks current value of target parameter
+            KeyCode::Char('l') => {
+                if let Some((path, value)) = target {
+                    step.locks.retain(|(p, _)| *p != path);
+                    step.locks.push((path, value));
+                }
+            }
+            KeyCode::Char('L') => step.locks.clear(),
+            _ => (),
+        };
+        Ok(())
+    }
+}
+
+impl Component for SequencerComponent {
+    fn draw(
+        &mut self,
+        f: &mut ratatui::Frame<'_>,
+        _rect: ratatui::prelude::Rect,
+    ) -> anyhow::Result<()> {
+        let layout = self.layout.as_ref().unwrap();
+        let sequencer = self.sequencer.lock().unwrap();
+        self.selected = self.selected.min(sequencer.length() - 1);
+        let page = self.selected / PAGE_STEPS;
+        let current = sequencer.current_step();
+        let cells = sequencer
+            .steps()
+            .iter()
+            .enumerate()
+            .skip(page * PAGE_STEPS)
+            .take(PAGE_STEPS)
+            .map(|(i, step)| self.cell(i, step, current))
+            .collect::<Vec<_>>();
+        f.render_widget(Paragraph::new(Line::from(cells)), layout.grid);
+
+        let step = &sequencer.steps()[self.selected];
+        let target = sequencer
+            .parameters()
+            .get(self.target)
+            .map(|p| p.path.as_str())
+            .unwrap_or("-");
+        let info = format!(
+            "{} {} {}/{} vel:{} gate:{:.0}% prob:{:.0}% locks:{} target:{} [space,up/down,</>,v/V,g/G,p/P,t,l/L,[/],n/N,e,x,s]",
+            if sequencer.is_enabled() { "on" } else { "off" },
+            if self.transport.read().unwrap().is_playing() { "playing" } else { "stopped" },
+            self.selected + 1,
+            sequencer.length(),
+            step.velocity,
+            step.gate * 100.0,
+            step.probability * 100.0,
+            step.locks.len(),
+            target,
+        );
+        f.render_widget(Paragraph::new(info), layout.info);
+        let b = Block::default()
+            .borders(Borders::ALL)
+            .title(format!(
+                "seq[{}]",
+                keycode_to_string(self.keymap().unwrap_or(KeyCode::Null))
+            ))
+            .border_type(BorderType::Rounded)
+            .title_alignment(Alignment::Center)
+            .style(Style::default().fg(self.color()));
+        f.render_widget(b, layout.rect);
+        Ok(())
+    }
+
+    fn resize(&mut self, rect: Rect) -> anyhow::Result<()> {
+        let main = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(1), Constraint::Length(1)])
+            .margin(1)
+            .split(rect);
+        self.layout = Some(SequencerLayout {
+            rect,
+            grid: main[0],
+            info: main[1],
+        });
+        Ok(())
+    }
+
+    fn handle_key_events(&mut self, key: KeyEvent) -> anyhow::Result<()> {
+        // Failed action is reported, sequencer stays as it was
+        if let Err(e) = self.action(key.code) {
+            self.status.write().unwrap().report(e);
+        }
+        Ok(())
+    }
+}
+
+impl AutoFocus for SequencerComponent {}
+
+impl FocusableComponent for SequencerComponent {
+    fn context(&self) -> &FocusableComponentContext {
+        &self.ctx
+    }
+
+    fn context_mut(&mut self) -> &mut FocusableComponentContext {
+        &mut self.ctx
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
@@ -0,0 +1,369 @@
+use std::rc::Rc;
+
+use anyhow::Context;
+use crossterm::event::KeyCode;
+use oosc_core::{
+    core::{
+        additive::AdditiveOscillator, noise::NoiseOscillator, parameter::SharedParameter,
+        sampler::SamplerOscillator, sub_oscillator::SubOscillator, synthesizer::LockedOscillator,
+    },
+    midi::learn::MidiLearn,
+    utils::{interpolation::InterpolateMethod, make_shared, Shared},
+};
+use ratatui::{prelude::*, widgets::*};
+
+use crate::ui::utils::keycode_to_string_prefixed;
+
+use super::{
+    components_container::ComponentsContainer,
+    envelope::EnvelopeComponent,
+    harmonics::HarmonicsComponent,
+    parameter::{ParameterComponentF32, ParameterComponentI32},
+    AutoFocus, Component, Focus, FocusableComponent, FocusableComponentContext, Named,
+};
+
+struct SourceLayout {
+    pub rect: Rect,
+    pub components: Rc<[Rect]>,
+    pub parametrs: Rc<[Rect]>,
+}
+
+// Noise, sub, sampler and additive oscillators, which have envelope and parameters but no wavetable
+pub struct SourceComponent {
+    pub oscillator: LockedOscillator,
+    pub envelope: Shared<EnvelopeComponent>,
+    pub components: ComponentsContainer<dyn FocusableComponent>,
+    pub parametrs: ComponentsContainer<dyn FocusableComponent>,
+    name: &'static str,
+    context: FocusableComponentContext,
+    layout: Option<SourceLayout>,
+}
+
+impl AutoFocus for SourceComponent {}
+
+impl SourceComponent {
+    // None if oscillator is not noise, sub, sampler or additive oscillator
+    pub fn new(
+        oscillator: LockedOscillator,
+        keymap: KeyCode,
+        learn: Shared<MidiLearn>,
+    ) -> Option<Self> {
+        let (name, envelope, parametrs, harmonics) = {
+            let osc = oscillator.read().unwrap();
+            let osc = osc.as_any();
+            if let Some(noise) = osc.downcast_ref::<NoiseOscillator>() {
+                (
+                    "noise",
+                    noise.envelope(),
+                    Self::noise_parametrs(noise, learn),
+                    None,
+                )
+            } else if let Some(sub) = osc.downcast_ref::<SubOscillator>() {
+                ("sub", sub.envelope(), Self::sub_parametrs(sub, learn), None)
+            } else if let Some(sampler) = osc.downcast_ref::<SamplerOscillator>() {
+                (
+                    "sampler",
+                    sampler.envelope(),
+                    Self::sampler_parametrs(sampler, learn),
+                    None,
+                )
+            } else {
+                let additive = osc.downcast_ref::<AdditiveOscillator>()?;
+                let harmonics =
+                    HarmonicsComponent::new(additive.partials().to_vec(), KeyCode::Char('h'));
+                (
+                    "additive",
+                    additive.envelope(),
+                    Self::additive_parametrs(additive, learn),
+                    Some(make_shared(harmonics) as Shared<dyn FocusableComponent>),
+                )
+            }
+        };
+        let mut parametrs = ComponentsContainer::from(parametrs);
+        parametrs.focus();
+        let envelope = make_shared(EnvelopeComponent::from(envelope));
+        let mut components = ComponentsContainer::from(
+            std::iter::once(envelope.clone() as Shared<dyn FocusableComponent>)
+                .chain(harmonics)
+                .collect::<Vec<_>>(),
+        );
+        components.focus();
+        Some(Self {
+            oscillator,
+            envelope,
+            components,
+            parametrs,
+            name,
+            context: FocusableComponentContext::new().keymap(keymap),
+            layout: None,
+        })
+    }
+
+    fn noise_parametrs(
+        noise: &NoiseOscillator,
+        learn: Shared<MidiLearn>,
+    ) -> Vec<Shared<dyn FocusableComponent>> {
+        vec![
+            make_shared(
+                ParameterComponentI32::new(
+                    "Color".to_owned(),
+                    noise.color(),
+                    Direction::Vertical,
+                    KeyCode::Char('c'),
+                )
+                .learn(learn.clone()),
+            ),
+            make_shared(
+                ParameterComponentF32::new(
+                    "Low Cut".to_owned(),
+                    noise.low_cut(),
+                    Direction::Vertical,
+                    50,
+                    InterpolateMethod::Exponential(1000.0),
+                    KeyCode::Char('l'),
+                )
+                .learn(learn.clone()),
+            ),
+            make_shared(
+                ParameterComponentF32::new(
+                    "High Cut".to_owned(),
+                    noise.high_cut(),
+                    Direction::Vertical,
+                    50,
+                    InterpolateMethod::Exponential(1000.0),
+                    KeyCode::Char('h'),
+                )
+                .learn(learn.clone()),
+            ),
+            Self::pan_parametr(noise.pan(), learn.clone()),
+            Self::gain_parametr(noise.gain(), learn.clone()),
+            Self::velocity_parametr(noise.velocity().sensitivity(), learn),
+        ]
+    }
+
+    fn sub_parametrs(
+        sub: &SubOscillator,
+        learn: Shared<MidiLearn>,
+    ) -> Vec<Shared<dyn FocusableComponent>> {
+        vec![
+            make_shared(
+                ParameterComponentI32::new(
+                    "Shape".to_owned(),
+                    sub.shape(),
+                    Direction::Vertical,
+                    KeyCode::Char('w'),
+                )
+                .learn(learn.clone()),
+            ),
+            make_shared(
+                ParameterComponentI32::new(
+                    "Octave".to_owned(),
+                    sub.octave(),
+                    Direction::Vertical,
+                    KeyCode::Char('o'),
+                )
+                .learn(learn.clone()),
+            ),
+            make_shared(
+                ParameterComponentI32::new(
+                    "Bend".to_owned(),
+                    sub.pitch_bend_range(),
+                    Direction::Vertical,
+                    KeyCode::Char('b'),
+                )
+                .learn(learn.clone()),
+            ),
+            Self::pan_parametr(sub.pan(), learn.clone()),
+            Self::gain_parametr(sub.gain(), learn.clone()),
+            Self::velocity_parametr(sub.velocity().sensitivity(), learn),
+        ]
+    }
+
+    fn sampler_parametrs(
+        sampler: &SamplerOscillator,
+        learn: Shared<MidiLearn>,
+    ) -> Vec<Shared<dyn FocusableComponent>> {
+        vec![
+            make_shared(
+                ParameterComponentI32::new(
+                    "Bend".to_owned(),
+                    sampler.pitch_bend_range(),
+                    Direction::Vertical,
+                    KeyCode::Char('b'),
+                )
+                .learn(learn.clone()),
+            ),
+            Self::pan_parametr(sampler.pan(), learn.clone()),
+            Self::gain_parametr(sampler.gain(), learn.clone()),
+            Self::velocity_parametr(sampler.velocity().sensitivity(), learn),
+        ]
+    }
+
+    fn additive_parametrs(
+        additive: &AdditiveOscillator,
+        learn: Shared<MidiLearn>,
+    ) -> Vec<Shared<dyn FocusableComponent>> {
+        vec![
+            make_shared(
+                ParameterComponentI32::new(
+                    "Bend".to_owned(),
+                    additive.pitch_bend_range(),
+                    Direction::Vertical,
+                    KeyCode::Char('b'),
+                )
+                .learn(learn.clone()),
+            ),
+            Self::pan_parametr(additive.pan(), learn.clone()),
+            Self::gain_parametr(additive.gain(), learn.clone()),
+            Self::velocity_parametr(additive.velocity().sensitivity(), learn),
+        ]
+    }
+
+    fn pan_parametr(
+        pan: SharedParameter<f32>,
+        learn: Shared<MidiLearn>,
+    ) -> Shared<dyn FocusableComponent> {
+        make_shared(
+            ParameterComponentF32::new(
+                "Pan".to_owned(),
+                pan,
+                Direction::Horizontal,
+                10,
+                InterpolateMethod::Linear,
+                KeyCode::Char('p'),
+            )
+            .learn(learn),
+        )
+    }
+
+    fn velocity_parametr(
+        sensitivity: SharedParameter<f32>,
+        learn: Shared<MidiLearn>,
+    ) -> Shared<dyn FocusableComponent> {
+        make_shared(
+            ParameterComponentF32::new(
+                "Velocity".to_owned(),
+                sensitivity,
+                Direction::Vertical,
+                20,
+                InterpolateMethod::Linear,
+                KeyCode::Char('v'),
+            )
+            .learn(learn),
+        )
+    }
+
+    fn gain_parametr(
+        gain: SharedParameter<f32>,
+        learn: Shared<MidiLearn>,
+    ) -> Shared<dyn FocusableComponent> {
+        make_shared(
+            ParameterComponentF32::new(
+                "Gain".to_owned(),
+                gain,
+                Direction::Vertical,
+                20,
+                InterpolateMethod::Exponential(0.001),
+                KeyCode::Char('g'),
+            )
+            .learn(learn),
+        )
+    }
+}
+
+impl FocusableComponent for SourceComponent {
+    fn context(&self) -> &FocusableComponentContext {
+        &self.context
+    }
+
+    fn context_mut(&mut self) -> &mut FocusableComponentContext {
+        &mut self.context
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
+impl Named for SourceComponent {
+    fn name(&self) -> Vec<Span<'static>> {
+        vec![
+            Span::styled(self.name, Style::default().fg(self.color())),
+            Span::styled(
+                keycode_to_string_prefixed(self.keymap(), "[", "]"),
+                Style::default().fg(Color::Red),
+            ),
+        ]
+    }
+}
+
+impl Component for SourceComponent {
+    fn draw(
+        &mut self,
+        f: &mut ratatui::Frame<'_>,
+        _rect: ratatui::prelude::Rect,
+    ) -> anyhow::Result<()> {
+        let layout = self
+            .layout
+            .as_ref()
+            .context("Cannot get SourceComponent layout")?;
+        let b = Block::default()
+            .borders(Borders::ALL)
+            .title(self.name())
+            .border_type(BorderType::Rounded)
+            .title_alignment(Alignment::Center)
+            .style(Style::default().fg(self.color()));
+        b.render(layout.rect, f.buffer_mut());
+        self.components.draw_in_layout(f, &layout.components)?;
+        self.parametrs.draw_in_layout(f, &layout.parametrs)?;
+        Ok(())
+    }
+
+    fn resize(&mut self, rect: Rect) -> anyhow::Result<()> {
+        let main = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+            .margin(1)
+            .split(rect);
+        let split = |rect: Rect, len: usize| {
+            Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints(
+                    std::iter::repeat_with(|| Constraint::Percentage(100 / len as u16))
+                        .take(len)
+                        .collect::<Vec<_>>(),
+                )
+                .split(rect)
+        };
+        let components = split(main[0], self.components.components.len());
+        let parametrs = split(main[1], self.parametrs.components.len());
+        self.parametrs.resize_in_layout(&parametrs)?;
+        self.components.resize_in_layout(&components)?;
+        self.layout = Some(SourceLayout {
+            rect,
+            components,
+            parametrs,
+        });
+        Ok(())
+    }
+
+    fn handle_key_events(&mut self, key: crossterm::event::KeyEvent) -> anyhow::Result<()> {
+        if !self.parametrs.is_any_focused()
+            && !self.components.is_any_focused()
+            && key.code == KeyCode::Esc
+        {
+            self.unfocus()
+        }
+        if !self.parametrs.is_any_focused() {
+            self.components.handle_key_events(key)?;
+        }
+        if !self.components.is_any_focused() {
+            self.parametrs.handle_key_events(key)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,36 @@
+use oosc_core::utils::Shared;
+use ratatui::{
+    prelude::Rect,
+    style::{Color, Style},
+    widgets::Paragraph,
+};
+
+use crate::app::status::Status;
+
+use super::Component;
+
+pub struct StatusComponent {
+    status: Shared<Status>,
+    rect: Option<Rect>,
+}
+
+impl StatusComponent {
+    pub fn new(status: Shared<Status>) -> Self {
+        Self { status, rect: None }
+    }
+}
+
+impl Component for StatusComponent {
+    fn draw(&mut self, f: &mut ratatui::Frame<'_>, _rect: Rect) -> anyhow::Result<()> {
+        if let (Some(rect), Some(message)) = (self.rect, self.status.read().unwrap().message()) {
+            let p = Paragraph::new(message.to_owned()).style(Style::default().fg(Color::Red));
+            f.render_widget(p, rect);
+        }
+        Ok(())
+    }
+
+    fn resize(&mut self, rect: Rect) -> anyhow::Result<()> {
+        self.rect = Some(rect);
+        Ok(())
+    }
+}
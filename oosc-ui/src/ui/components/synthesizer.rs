@@ -1,13 +1,18 @@
 use crossterm::event::KeyCode;
 use oosc_core::{
-    core::{oscillator::WavetableOscillator, synthesizer::Synthesizer},
+    core::{
+        additive::AdditiveOscillator, noise::NoiseOscillator, oscillator::WavetableOscillator,
+        sampler::SamplerOscillator, sub_oscillator::SubOscillator, synthesizer::Synthesizer,
+    },
     utils::{make_shared, Shared},
 };
 use ratatui::prelude::*;
 
+use crate::app::context::Context;
+
 use super::{
     components_container::ComponentsContainer, effects_container::EffectsContainer,
-    menu_bar::MenuBar, oscillator::OscillatorComponent, Component, Focus, FocusableComponent,
+    menu_bar::MenuBar, mixer::MixerComponent, oscillator::OscillatorComponent, source::SourceComponent, Component, Focus, FocusableComponent,
     FocusableComponentContext, NamedFocusableComponent,
 };
 
@@ -23,21 +28,42 @@ pub struct SynthesizerComponent {
 }
 
 impl SynthesizerComponent {
-    pub fn new(synthesizer: &Synthesizer) -> Self {
+    pub fn new(synthesizer: &Synthesizer, ctx: &Context) -> Self {
+        let learn = ctx.midi_learn.clone();
         let mut oscillators = ComponentsContainer::from(
             synthesizer
                 .get_oscillators::<WavetableOscillator>()
+                // Oscillators are selected by digits
+                .take(9)
                 .enumerate()
                 .map(|(i, osc)| {
                     let map = KeyCode::Char(char::from_digit(i as u32 + 1, 10).unwrap());
-                    make_shared(OscillatorComponent::new(osc, map))
+                    make_shared(OscillatorComponent::new(osc, map, learn.clone()))
                         as Shared<dyn NamedFocusableComponent>
                 })
                 .collect::<Vec<Shared<dyn NamedFocusableComponent>>>(),
         );
+        // Noise, sub, additive and sampler oscillators take next digits after wavetable ones
+        let sources = synthesizer
+            .get_oscillators::<NoiseOscillator>()
+            .chain(synthesizer.get_oscillators::<SubOscillator>())
+            .chain(synthesizer.get_oscillators::<AdditiveOscillator>())
+            .chain(synthesizer.get_oscillators::<SamplerOscillator>())
+            .collect::<Vec<_>>();
+        sources.into_iter().for_each(|osc| {
+            let index = oscillators.components.len() as u32 + 1;
+            let Some(map) = char::from_digit(index, 10) else {
+                return;
+            };
+            if let Some(source) = SourceComponent::new(osc, KeyCode::Char(map), learn.clone()) {
+                oscillators.components.push(make_shared(source));
+            }
+        });
         let effects = synthesizer.get_named_effects();
-        let effects = make_shared(EffectsContainer::new(effects));
+        let effects = make_shared(EffectsContainer::new(effects, learn.clone()));
         oscillators.components.push(effects);
+        let mixer = make_shared(MixerComponent::new(synthesizer, ctx));
+        oscillators.components.push(mixer);
         oscillators.draw_only_focused(true);
         let oscillators = make_shared(oscillators);
         let menu = MenuBar::new(oscillators.clone(), "Menu");
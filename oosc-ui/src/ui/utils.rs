@@ -2,6 +2,14 @@ use std::fmt::Display;
 
 use crossterm::event::KeyCode;
 
+const NOTE_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+pub fn note_name(note: u32) -> String {
+    format!("{}{}", NOTE_NAMES[note as usize % 12], note as i32 / 12 - 1)
+}
+
 pub fn keycode_to_string(code: KeyCode) -> String {
     match code {
         KeyCode::Char(c) => format!("{}", c),